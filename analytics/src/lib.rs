@@ -25,7 +25,8 @@ use tower::timeout::Timeout;
 
 use crate::postgres_adapter::{PostgresAdapter, PostgresAdapterBuilder};
 use massbit::firehose::bstream::{
-    stream_client::StreamClient, BlockRequest, BlockResponse, ChainType,
+    stream_client::StreamClient, BlockRequest, BlockResponse, ChainType, PayloadCompression,
+    PayloadEncoding,
 };
 use massbit_common::NetworkType;
 
@@ -55,10 +56,13 @@ pub async fn try_create_stream(
     log::info!("Create new stream from block {:?}", start_block);
     let filter = vec![];
     let get_blocks_request = BlockRequest {
+        indexer_hash: Default::default(),
         start_block_number: start_block,
         chain_type: chain_type as i32,
         network: network.clone().unwrap_or_default(),
         filter,
+        encoding: PayloadEncoding::Json as i32,
+        compression: PayloadCompression::Uncompressed as i32,
     };
     match client
         .blocks(Request::new(get_blocks_request.clone()))
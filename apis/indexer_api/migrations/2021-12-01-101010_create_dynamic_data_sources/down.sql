@@ -0,0 +1 @@
+drop table if exists dynamic_data_sources;
\ No newline at end of file
@@ -0,0 +1,13 @@
+create table dynamic_data_sources
+(
+    vid bigserial
+        constraint dynamic_data_sources_pk
+            primary key,
+    indexer_hash varchar not null,  -- hash of the indexer which created the data source
+    name varchar not null,          -- template name from manifest
+    address varchar not null,       -- interested address of data source
+    creation_block bigint not null  -- block in which the data source was created
+);
+
+create index dynamic_data_sources_indexer_hash_index
+    on dynamic_data_sources (indexer_hash);
@@ -0,0 +1,3 @@
+alter table indexers
+    drop column health,
+    drop column fatal_error;
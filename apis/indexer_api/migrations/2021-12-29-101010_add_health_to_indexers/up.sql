@@ -0,0 +1,6 @@
+-- Plugin indexers have no deployment, their health is kept on their own row
+alter table indexers
+    add column health      varchar not null default 'healthy'
+        constraint indexers_health_check
+            check (health in ('healthy', 'unhealthy', 'failed')),
+    add column fatal_error bigint; -- vid of the error in indexer_error that stopped the indexer
@@ -0,0 +1,81 @@
+//! Health of the indexers. Indexers with WASM mappings have a deployment
+//! that keeps their health; plugin indexers keep it on their own row in
+//! `indexers`. The errors of both are recorded in `indexer_error`.
+
+use crate::orm::schema::indexers::dsl as idx;
+use massbit::data::indexer::schema::IndexerError;
+use massbit::data::indexer::status::{DeploymentHealth, HealthStatus};
+use massbit::prelude::StoreError;
+use massbit_common::prelude::diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+};
+use massbit_store_postgres::{
+    delete_error, deployment_health, find_error, has_errors_besides, insert_error,
+};
+
+/// Marks the plugin indexer `hash` as failed because of `error`. An earlier
+/// fatal error is replaced by the new one
+pub fn fail(conn: &PgConnection, hash: &str, error: IndexerError) -> Result<(), StoreError> {
+    conn.transaction(|| {
+        let previous = fatal_error_vid(conn, hash)?;
+        let vid = insert_error(conn, hash, error)?;
+        diesel::update(idx::indexers.filter(idx::hash.eq(hash)))
+            .set((
+                idx::health.eq(DeploymentHealth::Failed.as_str()),
+                idx::fatal_error.eq(vid),
+            ))
+            .execute(conn)?;
+        if let Some(previous) = previous {
+            delete_error(conn, previous)?;
+        }
+        Ok(())
+    })
+}
+
+/// Returns the health of the indexer `hash` together with the error that
+/// stopped it, or `None` if there is no such indexer
+pub fn health(conn: &PgConnection, hash: &str) -> Result<Option<HealthStatus>, StoreError> {
+    if let Some(health) = deployment_health(conn, hash)? {
+        return Ok(Some(health));
+    }
+    let (health, fatal) = match idx::indexers
+        .filter(idx::hash.eq(hash))
+        .select((idx::health, idx::fatal_error))
+        .first::<(String, Option<i64>)>(conn)
+        .optional()?
+    {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let health = health
+        .parse::<DeploymentHealth>()
+        .map_err(StoreError::Unknown)?;
+    let fatal_error = match fatal {
+        Some(vid) => find_error(conn, vid)?,
+        None => None,
+    };
+    Ok(Some(HealthStatus {
+        deployment: hash.to_string(),
+        health,
+        fatal_error,
+    }))
+}
+
+/// Returns `true` if handlers of the indexer `hash` failed and their
+/// changes are missing from its data
+pub fn has_non_fatal_errors(conn: &PgConnection, hash: &str) -> Result<bool, StoreError> {
+    match fatal_error_vid(conn, hash)? {
+        Some(fatal) => has_errors_besides(conn, hash, Some(fatal)),
+        None => massbit_store_postgres::has_non_fatal_errors(conn, hash),
+    }
+}
+
+/// The fatal error recorded on the row of a plugin indexer
+fn fatal_error_vid(conn: &PgConnection, hash: &str) -> Result<Option<i64>, StoreError> {
+    let vid = idx::indexers
+        .filter(idx::hash.eq(hash))
+        .select(idx::fatal_error)
+        .first::<Option<i64>>(conn)
+        .optional()?;
+    Ok(vid.flatten())
+}
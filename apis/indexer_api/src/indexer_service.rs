@@ -1,35 +1,55 @@
 use crate::git_helper::GitHelper;
+use crate::indexer_health;
+use crate::indexing_status::{self, EntityCounts, IndexingStatus, ProgressSample};
 use crate::manager::{IndexerManager, IndexerRuntime};
-use crate::model::{IndexerData, ListOptions};
+use crate::model::{IndexerData, ListOptions, RedeployOptions, StatusOptions};
 use crate::orm::models::Indexer;
+use crate::orm::models_impl::IndexerStatus;
 use crate::orm::schema::indexers;
 use crate::orm::schema::indexers::dsl;
+use crate::store::StoreBuilder;
 use crate::API_LIST_LIMIT;
 use chain_solana::SolanaIndexerManifest;
 use diesel::sql_types::BigInt;
 use futures::lock::Mutex;
 use log::debug;
 //use massbit::components::link_resolver::LinkResolver as _;
+use massbit::data::indexer::status::DeploymentHealth;
+use massbit::data::query::QueryError;
 use massbit::ipfs_client::IpfsClient;
 use massbit::ipfs_link_resolver::LinkResolver;
 use massbit::prelude::prost::bytes::BufMut;
-use massbit::prelude::{anyhow, TryStreamExt};
+use massbit::prelude::{anyhow, DeploymentHash, Schema, TryStreamExt};
 use massbit::slog::Logger;
 use massbit_common::prelude::diesel::r2d2::ConnectionManager;
 use massbit_common::prelude::diesel::{
-    r2d2, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+    r2d2, BoolExpressionMethods, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
+    RunQueryDsl,
 };
 use massbit_common::prelude::r2d2::PooledConnection;
+use massbit_graphql::{execute_query, QueryRequest, QueryResult, QuerySchema};
+use massbit_store_postgres::StoreEventListener;
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::Arc;
 use warp::{
+    http::StatusCode,
     multipart::{FormData, Part},
+    reply::{Json, WithStatus},
     Rejection, Reply,
 };
 
 pub struct IndexerService {
     pub ipfs_client: Arc<IpfsClient>,
     pub connection_pool: Arc<r2d2::Pool<ConnectionManager<PgConnection>>>,
+    /// Query schemas by indexer hash, with the IPFS hash of the GraphQL schema they were built from
+    pub query_schemas: Mutex<HashMap<String, (String, Arc<QuerySchema>)>>,
+    /// The entity changes of each block, for the GraphQL subscriptions
+    pub store_events: StoreEventListener,
+    /// How far behind each indexer was at the previous indexing status request
+    pub progress: Mutex<HashMap<String, ProgressSample>>,
+    /// The entity counts of each indexer at the previous indexing status requests
+    pub entity_counts: Mutex<HashMap<String, EntityCounts>>,
     pub logger: Logger,
 }
 
@@ -42,10 +62,21 @@ impl IndexerService {
     > {
         self.connection_pool.get()
     }
+    /// Returns the indexers to start: the ones which are not deleted, paused, stopped or invalid.
     pub fn get_indexers(&self) -> Option<Vec<Indexer>> {
+        let inactive_statuses = vec![
+            IndexerStatus::Invalid.as_str(),
+            IndexerStatus::Paused.as_str(),
+            IndexerStatus::Stopped.as_str(),
+        ];
         self.get_connection().ok().and_then(|conn| {
             dsl::indexers
                 .filter(dsl::deleted.eq(false))
+                .filter(
+                    dsl::status
+                        .is_null()
+                        .or(dsl::status.ne_all(inactive_statuses)),
+                )
                 .load::<Indexer>(conn.deref())
                 .ok()
         })
@@ -57,11 +88,28 @@ impl IndexerService {
         indexer_manager: Arc<Mutex<IndexerManager>>,
     ) -> Result<impl Reply, Rejection> {
         log::info!("Deploy new indexer");
+        let mut indexer = Indexer::new();
+        let manifest = self.upload_files(form, &mut indexer).await?;
+        if let Some(manifest) = &manifest {
+            if let Ok(indexer) = self.store_indexer(manifest, indexer).await {
+                if let Err(err) = indexer_manager.lock().await.start_indexer(indexer).await {
+                    log::error!("{:?}", &err);
+                };
+            }
+        };
+        Ok("success")
+    }
+    /// Adds the files of the form to IPFS and stores their hashes in `indexer`.
+    /// Returns the manifest if it could be parsed.
+    async fn upload_files(
+        &self,
+        form: FormData,
+        indexer: &mut Indexer,
+    ) -> Result<Option<SolanaIndexerManifest>, Rejection> {
         let parts: Vec<Part> = form.try_collect().await.map_err(|e| {
             eprintln!("form error: {}", e);
             warp::reject::reject()
         })?;
-        let mut indexer = Indexer::new();
         let mut manifest: Option<SolanaIndexerManifest> = None;
         for p in parts {
             log::info!("Receive file: {}/{}", &p.name(), p.filename().unwrap());
@@ -106,14 +154,7 @@ impl IndexerService {
                 _ => {}
             }
         }
-        if let Some(manifest) = &manifest {
-            if let Ok(indexer) = self.store_indexer(manifest, indexer).await {
-                if let Err(err) = indexer_manager.lock().await.start_indexer(indexer).await {
-                    log::error!("{:?}", &err);
-                };
-            }
-        };
-        Ok("success")
+        Ok(manifest)
     }
     /// for api deploy indexer from front-end
     pub async fn deploy_git_indexer(
@@ -173,12 +214,11 @@ impl IndexerService {
         };
         Ok("success")
     }
-    async fn store_indexer(
-        &self,
-        manifest: &SolanaIndexerManifest,
-        mut indexer: Indexer,
-    ) -> Result<Indexer, anyhow::Error> {
+    /// Resets the progress and the health of `indexer` and takes its settings from `manifest`.
+    fn apply_manifest(manifest: &SolanaIndexerManifest, indexer: &mut Indexer) {
         indexer.got_block = -1_i64;
+        indexer.health = DeploymentHealth::Healthy.as_str().to_string();
+        indexer.fatal_error = None;
         if let Some(datasource) = manifest.data_sources.get(0) {
             indexer.address = datasource.source.address.clone();
             indexer.start_block = datasource.source.start_block.clone() as i64;
@@ -186,10 +226,17 @@ impl IndexerService {
             indexer.name = datasource.name.clone();
         }
         if IndexerRuntime::verify_manifest(manifest) {
-            indexer.status = Some(String::from("Deploying"))
+            indexer.set_status(IndexerStatus::Deploying)
         } else {
-            indexer.status = Some(String::from("Invalid"))
+            indexer.set_status(IndexerStatus::Invalid)
         }
+    }
+    async fn store_indexer(
+        &self,
+        manifest: &SolanaIndexerManifest,
+        mut indexer: Indexer,
+    ) -> Result<Indexer, anyhow::Error> {
+        Self::apply_manifest(manifest, &mut indexer);
         match self.get_connection() {
             Ok(conn) => {
                 indexer.v_id = self.get_next_sequence(&conn, "indexers", "v_id");
@@ -241,6 +288,433 @@ impl IndexerService {
             Ok(warp::reply::json(&String::from("")))
         }
     }
+    /// for api get the sync progress of indexers: /indexingStatuses?hashes=:hash,:hash
+    pub async fn indexing_statuses(
+        &self,
+        options: StatusOptions,
+    ) -> Result<WithStatus<Json>, Rejection> {
+        let conn = match self.get_connection() {
+            Ok(conn) => conn,
+            Err(err) => {
+                log::error!("{:?}", &err);
+                return Ok(reply_message(
+                    "Database unavailable",
+                    StatusCode::SERVICE_UNAVAILABLE,
+                ));
+            }
+        };
+        let mut query = dsl::indexers.filter(dsl::deleted.eq(false)).into_boxed();
+        if let Some(hashes) = options.hashes.as_ref() {
+            let hashes: Vec<&str> = hashes.split(',').map(str::trim).collect();
+            query = query.filter(dsl::hash.eq_any(hashes));
+        }
+        let indexers = match query.order(dsl::v_id.asc()).load::<Indexer>(conn.deref()) {
+            Ok(indexers) => indexers,
+            Err(err) => {
+                log::error!("{:?}", &err);
+                return Ok(reply_message(
+                    "Cannot load indexers",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+        };
+        // Ask the chain reader once per network
+        let mut chain_heads: HashMap<String, Option<i64>> = HashMap::new();
+        for network in indexers
+            .iter()
+            .filter_map(|indexer| indexer.network.as_ref())
+        {
+            if !chain_heads.contains_key(network) {
+                let head = match indexing_status::chain_head(network).await {
+                    Ok(head) => Some(head),
+                    Err(err) => {
+                        log::error!("Cannot get the chain head of {}: {:?}", network, &err);
+                        None
+                    }
+                };
+                chain_heads.insert(network.clone(), head);
+            }
+        }
+        let mut progress = self.progress.lock().await;
+        let mut entity_counts = self.entity_counts.lock().await;
+        let mut statuses = Vec::with_capacity(indexers.len());
+        for indexer in indexers.iter() {
+            let chain_head = indexer
+                .network
+                .as_ref()
+                .and_then(|network| chain_heads.get(network).copied().flatten());
+            let previous = progress.get(&indexer.hash).copied();
+            let status = EntityCounts::cached(conn.deref(), indexer, &mut entity_counts).and_then(
+                |counts| IndexingStatus::new(conn.deref(), indexer, chain_head, previous, counts),
+            );
+            match status {
+                Ok((status, sample)) => {
+                    if let Some(sample) = sample {
+                        progress.insert(indexer.hash.clone(), sample);
+                    }
+                    statuses.push(status);
+                }
+                Err(err) => {
+                    log::error!("{:?}", &err);
+                    return Ok(reply_message(
+                        "Cannot load indexing statuses",
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                    ));
+                }
+            }
+        }
+        Ok(warp::reply::with_status(
+            warp::reply::json(&statuses),
+            StatusCode::OK,
+        ))
+    }
+    /// for api pause indexer: /indexers/:hash/pause
+    pub async fn pause_indexer(
+        &self,
+        hash: String,
+        indexer_manager: Arc<Mutex<IndexerManager>>,
+    ) -> Result<WithStatus<Json>, Rejection> {
+        self.change_status(hash, IndexerStatus::Paused, indexer_manager)
+            .await
+    }
+    /// for api resume indexer: /indexers/:hash/resume
+    pub async fn resume_indexer(
+        &self,
+        hash: String,
+        indexer_manager: Arc<Mutex<IndexerManager>>,
+    ) -> Result<WithStatus<Json>, Rejection> {
+        self.change_status(hash, IndexerStatus::Deploying, indexer_manager)
+            .await
+    }
+    /// for api stop indexer: /indexers/:hash/stop
+    pub async fn stop_indexer(
+        &self,
+        hash: String,
+        indexer_manager: Arc<Mutex<IndexerManager>>,
+    ) -> Result<WithStatus<Json>, Rejection> {
+        self.change_status(hash, IndexerStatus::Stopped, indexer_manager)
+            .await
+    }
+    /// Moves the indexer to `to` if its status is one `to` can be changed from. Its runtime
+    /// is stopped, and started again if `to` is `Deploying`.
+    async fn change_status(
+        &self,
+        hash: String,
+        to: IndexerStatus,
+        indexer_manager: Arc<Mutex<IndexerManager>>,
+    ) -> Result<WithStatus<Json>, Rejection> {
+        let mut indexer = match self.load_indexer(&hash) {
+            Ok(Some(indexer)) => indexer,
+            Ok(None) => return Ok(reply_message("Indexer not found", StatusCode::NOT_FOUND)),
+            Err(err) => {
+                log::error!("{:?}", &err);
+                return Ok(reply_message(
+                    "Cannot load indexer",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+        };
+        let status = indexer.get_status();
+        if !status
+            .map(|status| to.changeable_from().contains(&status))
+            .unwrap_or(false)
+        {
+            return Ok(reply_message(
+                &format!(
+                    "Cannot change indexer from {:?} to {:?}",
+                    &indexer.status, to
+                ),
+                StatusCode::CONFLICT,
+            ));
+        }
+        let mut manager = indexer_manager.lock().await;
+        manager.stop_indexer(&hash);
+        indexer.set_status(to);
+        if let Err(err) = self.update_status(&hash, to) {
+            log::error!("{:?}", &err);
+            return Ok(reply_message(
+                "Cannot update indexer",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+        log::info!("Indexer {} is {}", &hash, to.as_str());
+        if to == IndexerStatus::Deploying {
+            if let Err(err) = manager.start_indexer(indexer.clone()).await {
+                log::error!("{:?}", &err);
+            }
+        }
+        Ok(warp::reply::with_status(
+            warp::reply::json(&indexer),
+            StatusCode::OK,
+        ))
+    }
+    /// for api delete indexer: DELETE /indexers/:hash
+    /// Drops the tables of the indexer and their Hasura tracking, the indexer is kept as deleted.
+    pub async fn delete_indexer(
+        &self,
+        hash: String,
+        indexer_manager: Arc<Mutex<IndexerManager>>,
+    ) -> Result<impl Reply, Rejection> {
+        let mut indexer = match self.load_indexer(&hash) {
+            Ok(Some(indexer)) => indexer,
+            Ok(None) => return Ok(reply_message("Indexer not found", StatusCode::NOT_FOUND)),
+            Err(err) => {
+                log::error!("{:?}", &err);
+                return Ok(reply_message(
+                    "Cannot load indexer",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+        };
+        indexer_manager.lock().await.stop_indexer(&hash);
+        if let Err(err) = self.drop_indexer_data(&indexer).await {
+            log::error!("{:?}", &err);
+            return Ok(reply_message(
+                "Cannot drop indexer data",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+        let result = self
+            .get_connection()
+            .map_err(|err| anyhow!("{:?}", err))
+            .and_then(|conn| {
+                diesel::update(dsl::indexers.filter(dsl::hash.eq(&hash)))
+                    .set((
+                        dsl::deleted.eq(true),
+                        dsl::status.eq(IndexerStatus::Deleted.as_str()),
+                    ))
+                    .execute(conn.deref())
+                    .map_err(|err| anyhow!("{:?}", err))
+            });
+        if let Err(err) = result {
+            log::error!("{:?}", &err);
+            return Ok(reply_message(
+                "Cannot update indexer",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+        log::info!("Indexer {} is deleted", &hash);
+        indexer.deleted = true;
+        indexer.set_status(IndexerStatus::Deleted);
+        Ok(warp::reply::with_status(
+            warp::reply::json(&indexer),
+            StatusCode::OK,
+        ))
+    }
+    /// for api redeploy indexer from massbit-sol cli: /indexers/:hash/redeploy
+    /// Replaces the files of the indexer, keeping the hash and the namespace of the indexer.
+    /// The tables are kept and migrated to the new schema when the store is created, and
+    /// indexing continues from the last indexed block. With `reset` the data is dropped and
+    /// the indexer indexes again from the start block of the new manifest.
+    pub async fn redeploy_indexer(
+        &self,
+        hash: String,
+        options: RedeployOptions,
+        form: FormData,
+        indexer_manager: Arc<Mutex<IndexerManager>>,
+    ) -> Result<impl Reply, Rejection> {
+        log::info!("Redeploy indexer {}", &hash);
+        let mut indexer = match self.load_indexer(&hash) {
+            Ok(Some(indexer)) => indexer,
+            Ok(None) => return Ok(reply_message("Indexer not found", StatusCode::NOT_FOUND)),
+            Err(err) => {
+                log::error!("{:?}", &err);
+                return Ok(reply_message(
+                    "Cannot load indexer",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+        };
+        let manifest = match self.upload_files(form, &mut indexer).await? {
+            Some(manifest) => manifest,
+            None => return Ok(reply_message("Invalid manifest", StatusCode::BAD_REQUEST)),
+        };
+        let reset = options.reset.unwrap_or(false);
+        let mut manager = indexer_manager.lock().await;
+        manager.stop_indexer(&hash);
+        if reset {
+            if let Err(err) = self.drop_indexer_data(&indexer).await {
+                log::error!("{:?}", &err);
+                return Ok(reply_message(
+                    "Cannot drop indexer data",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+        }
+        let got_block = indexer.got_block;
+        Self::apply_manifest(&manifest, &mut indexer);
+        if !reset {
+            indexer.got_block = got_block;
+        }
+        let version = indexer
+            .version
+            .as_ref()
+            .and_then(|version| version.parse::<u32>().ok())
+            .unwrap_or_default();
+        indexer.version = Some((version + 1).to_string());
+        let result = self
+            .get_connection()
+            .map_err(|err| anyhow!("{:?}", err))
+            .and_then(|conn| {
+                diesel::update(dsl::indexers.filter(dsl::hash.eq(&hash)))
+                    .set((
+                        dsl::manifest.eq(&indexer.manifest),
+                        dsl::mapping.eq(&indexer.mapping),
+                        dsl::graphql.eq(&indexer.graphql),
+                        dsl::address.eq(&indexer.address),
+                        dsl::start_block.eq(indexer.start_block),
+                        dsl::got_block.eq(indexer.got_block),
+                        dsl::network.eq(&indexer.network),
+                        dsl::name.eq(&indexer.name),
+                        dsl::version.eq(&indexer.version),
+                        dsl::status.eq(&indexer.status),
+                        dsl::health.eq(&indexer.health),
+                        dsl::fatal_error.eq(indexer.fatal_error),
+                    ))
+                    .execute(conn.deref())
+                    .map_err(|err| anyhow!("{:?}", err))
+            });
+        if let Err(err) = result {
+            log::error!("{:?}", &err);
+            return Ok(reply_message(
+                "Cannot update indexer",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+        if indexer.get_status() == Some(IndexerStatus::Deploying) {
+            if let Err(err) = manager.start_indexer(indexer.clone()).await {
+                log::error!("{:?}", &err);
+            }
+        }
+        Ok(warp::reply::with_status(
+            warp::reply::json(&indexer),
+            StatusCode::OK,
+        ))
+    }
+    /// for api query indexer data: /indexers/:hash/graphql
+    pub async fn query_indexer(
+        &self,
+        hash: String,
+        request: QueryRequest,
+    ) -> Result<WithStatus<Json>, Rejection> {
+        let schema = match self.query_schema(&hash).await {
+            Ok(Some(schema)) => schema,
+            Ok(None) => return Ok(reply_message("Indexer not found", StatusCode::NOT_FOUND)),
+            Err(err) => {
+                log::error!("{:?}", &err);
+                return Ok(reply_message(
+                    "Cannot load indexer schema",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+        };
+        let result = match self.get_connection() {
+            Ok(conn) => {
+                let mut result = execute_query(conn.deref(), &schema, &request);
+                add_indexing_errors(conn.deref(), &hash, &mut result);
+                result
+            }
+            Err(err) => {
+                log::error!("{:?}", &err);
+                return Ok(reply_message(
+                    "Database unavailable",
+                    StatusCode::SERVICE_UNAVAILABLE,
+                ));
+            }
+        };
+        Ok(warp::reply::with_status(
+            warp::reply::json(&result),
+            StatusCode::OK,
+        ))
+    }
+    /// for api get indexer health: /indexers/:hash/health
+    pub async fn get_indexer_health(&self, hash: String) -> Result<WithStatus<Json>, Rejection> {
+        let conn = match self.get_connection() {
+            Ok(conn) => conn,
+            Err(err) => {
+                log::error!("{:?}", &err);
+                return Ok(reply_message(
+                    "Database unavailable",
+                    StatusCode::SERVICE_UNAVAILABLE,
+                ));
+            }
+        };
+        match indexer_health::health(conn.deref(), &hash) {
+            Ok(Some(health)) => Ok(warp::reply::with_status(
+                warp::reply::json(&health),
+                StatusCode::OK,
+            )),
+            Ok(None) => Ok(reply_message("Indexer not found", StatusCode::NOT_FOUND)),
+            Err(err) => {
+                log::error!("{:?}", &err);
+                Ok(reply_message(
+                    "Cannot load indexer health",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ))
+            }
+        }
+    }
+    /// Returns the query schema of the indexer, which is built again when the indexer
+    /// was redeployed with another GraphQL schema.
+    pub async fn query_schema(
+        &self,
+        hash: &str,
+    ) -> Result<Option<Arc<QuerySchema>>, anyhow::Error> {
+        let indexer = match self.load_indexer(hash)? {
+            Some(indexer) => indexer,
+            None => return Ok(None),
+        };
+        let mut query_schemas = self.query_schemas.lock().await;
+        if let Some((graphql, schema)) = query_schemas.get(hash) {
+            if graphql == &indexer.graphql {
+                return Ok(Some(schema.clone()));
+            }
+        }
+        let content = self.ipfs_client.cat_all(&indexer.graphql, None).await?;
+        let deployment_hash = DeploymentHash::new("_indexer").unwrap();
+        let schema = Schema::parse(
+            String::from_utf8(content.to_vec())?.as_str(),
+            deployment_hash.clone(),
+        )?;
+        let layout = StoreBuilder::create_layout(
+            self.get_connection()?.deref(),
+            &schema,
+            &indexer.namespace,
+            deployment_hash,
+            indexer.network.as_deref().unwrap_or_default(),
+        )?;
+        let schema = Arc::new(QuerySchema::new(schema, Arc::new(layout))?);
+        query_schemas.insert(hash.to_string(), (indexer.graphql, schema.clone()));
+        Ok(Some(schema))
+    }
+    /// Loads the indexer with `hash` unless it is deleted.
+    fn load_indexer(&self, hash: &str) -> Result<Option<Indexer>, anyhow::Error> {
+        let conn = self.get_connection()?;
+        let indexer = dsl::indexers
+            .filter(dsl::hash.eq(hash))
+            .filter(dsl::deleted.eq(false))
+            .first::<Indexer>(conn.deref())
+            .optional()?;
+        Ok(indexer)
+    }
+    fn update_status(&self, hash: &str, status: IndexerStatus) -> Result<usize, anyhow::Error> {
+        let conn = self.get_connection()?;
+        let count = diesel::update(dsl::indexers.filter(dsl::hash.eq(hash)))
+            .set(dsl::status.eq(status.as_str()))
+            .execute(conn.deref())?;
+        Ok(count)
+    }
+    /// Drops the tables of the indexer, after they were untracked in Hasura.
+    async fn drop_indexer_data(&self, indexer: &Indexer) -> Result<(), anyhow::Error> {
+        let tables = StoreBuilder::get_tables(self.get_connection()?.deref(), &indexer.namespace)?;
+        StoreBuilder::untrack_hasura_tables(&indexer.namespace, &tables).await;
+        StoreBuilder::drop_store(
+            self.get_connection()?.deref(),
+            &indexer.namespace,
+            &indexer.hash,
+        )?;
+        Ok(())
+    }
     fn get_next_sequence(
         &self,
         conn: &PooledConnection<ConnectionManager<PgConnection>>,
@@ -261,3 +735,17 @@ impl IndexerService {
         next_seq.unwrap_or_default().value
     }
 }
+
+/// Tells the client that the data of the indexer `hash` is missing the
+/// changes of handlers that failed, if the indexer skipped such handlers.
+pub(crate) fn add_indexing_errors(conn: &PgConnection, hash: &str, result: &mut QueryResult) {
+    match indexer_health::has_non_fatal_errors(conn, hash) {
+        Ok(true) => result.errors.push(QueryError::IndexingError),
+        Ok(false) => {}
+        Err(err) => log::error!("{:?}", &err),
+    }
+}
+
+fn reply_message(message: &str, code: StatusCode) -> WithStatus<Json> {
+    warp::reply::with_status(warp::reply::json(&message), code)
+}
@@ -0,0 +1,304 @@
+use crate::indexer_health;
+use crate::orm::models::Indexer;
+use crate::{CHAIN_READER_URL, SYNCED_MAX_BLOCKS_BEHIND};
+use massbit::data::indexer::status::{DeploymentHealth, ErrorStatus};
+use massbit::prelude::{anyhow, StoreError};
+use massbit_common::prelude::diesel::sql_types::{BigInt, Text};
+use massbit_common::prelude::diesel::{sql_query, PgConnection, RunQueryDsl};
+use massbit_grpc::firehose::bstream::stream_client::StreamClient;
+use massbit_grpc::firehose::bstream::{ChainHeadRequest, ChainType};
+use massbit_store_postgres::last_error;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// How long the entity counts of an indexer are reused; counting scans
+/// all the tables of the indexer
+const ENTITY_COUNTS_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// How far an indexer got, for the dashboards: /indexingStatuses
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexingStatus {
+    pub hash: String,
+    pub name: String,
+    pub network: Option<String>,
+    pub status: Option<String>,
+    pub start_block: i64,
+    /// The last block the indexer processed, if it processed one yet
+    pub latest_block: Option<i64>,
+    /// The last block the chain reader has seen on the network of the indexer
+    pub chain_head_block: Option<i64>,
+    pub blocks_behind: Option<i64>,
+    /// Seconds until the indexer reaches the chain head at the pace it
+    /// caught up since the previous request
+    pub estimated_seconds_to_catch_up: Option<u64>,
+    pub synced: bool,
+    pub health: DeploymentHealth,
+    pub last_error: Option<ErrorStatus>,
+    /// The number of current entities of each table, counted at most
+    /// `ENTITY_COUNTS_TTL` ago
+    pub entity_counts: BTreeMap<String, i64>,
+}
+
+/// When an indexer was how many blocks behind, to estimate when it catches up
+#[derive(Clone, Copy, Debug)]
+pub struct ProgressSample {
+    at: Instant,
+    blocks_behind: i64,
+}
+
+/// The entity counts of an indexer, and when they were counted
+#[derive(Clone, Debug)]
+pub struct EntityCounts {
+    at: Instant,
+    counts: BTreeMap<String, i64>,
+}
+
+impl EntityCounts {
+    /// Returns the counts of `indexer` from `cache`, and counts again if
+    /// they are older than `ENTITY_COUNTS_TTL`
+    pub fn cached(
+        conn: &PgConnection,
+        indexer: &Indexer,
+        cache: &mut HashMap<String, EntityCounts>,
+    ) -> Result<BTreeMap<String, i64>, StoreError> {
+        if let Some(cached) = cache.get(&indexer.hash) {
+            if cached.at.elapsed() < ENTITY_COUNTS_TTL {
+                return Ok(cached.counts.clone());
+            }
+        }
+        let counts = entity_counts(conn, &indexer.namespace)?;
+        cache.insert(
+            indexer.hash.clone(),
+            EntityCounts {
+                at: Instant::now(),
+                counts: counts.clone(),
+            },
+        );
+        Ok(counts)
+    }
+}
+
+impl IndexingStatus {
+    pub fn new(
+        conn: &PgConnection,
+        indexer: &Indexer,
+        chain_head_block: Option<i64>,
+        previous: Option<ProgressSample>,
+        entity_counts: BTreeMap<String, i64>,
+    ) -> Result<(Self, Option<ProgressSample>), StoreError> {
+        let latest_block = Some(indexer.got_block).filter(|block| *block >= 0);
+        let blocks_behind = match (latest_block, chain_head_block) {
+            (Some(latest), Some(head)) => Some((head - latest).max(0)),
+            (None, Some(head)) => Some((head - indexer.start_block).max(0)),
+            _ => None,
+        };
+        let sample = blocks_behind.map(|blocks_behind| ProgressSample {
+            at: Instant::now(),
+            blocks_behind,
+        });
+        let estimated_seconds_to_catch_up = match (previous, sample) {
+            (Some(previous), Some(sample)) => estimate_catch_up(previous, sample),
+            _ => None,
+        };
+        let health = match indexer_health::health(conn, &indexer.hash)? {
+            Some(health) => health.health,
+            None => DeploymentHealth::Healthy,
+        };
+        let status = IndexingStatus {
+            hash: indexer.hash.clone(),
+            name: indexer.name.clone(),
+            network: indexer.network.clone(),
+            status: indexer.status.clone(),
+            start_block: indexer.start_block,
+            latest_block,
+            chain_head_block,
+            blocks_behind,
+            estimated_seconds_to_catch_up,
+            synced: matches!(blocks_behind, Some(behind) if behind <= SYNCED_MAX_BLOCKS_BEHIND),
+            health,
+            last_error: last_error(conn, &indexer.hash)?,
+            entity_counts,
+        };
+        Ok((status, sample))
+    }
+}
+
+/// Extrapolates the pace at which the indexer closed the distance to the
+/// chain head between two samples
+fn estimate_catch_up(previous: ProgressSample, current: ProgressSample) -> Option<u64> {
+    if current.blocks_behind == 0 {
+        return Some(0);
+    }
+    let elapsed = current.at.duration_since(previous.at).as_secs_f64();
+    let caught_up = previous.blocks_behind - current.blocks_behind;
+    if elapsed <= 0.0 || caught_up <= 0 {
+        return None;
+    }
+    let rate = caught_up as f64 / elapsed;
+    Some((current.blocks_behind as f64 / rate).ceil() as u64)
+}
+
+/// Counts the current versions of the entities in each table of the schema
+/// `db_schema`
+fn entity_counts(
+    conn: &PgConnection,
+    db_schema: &str,
+) -> Result<BTreeMap<String, i64>, StoreError> {
+    #[derive(QueryableByName)]
+    struct EntityCount {
+        #[sql_type = "Text"]
+        table_name: String,
+        #[sql_type = "BigInt"]
+        count: i64,
+    }
+    #[derive(QueryableByName)]
+    struct TableName {
+        #[sql_type = "Text"]
+        table_name: String,
+    }
+    // Only the tables of entities keep versions in a `block_range`
+    let tables = sql_query(
+        "select table_name from information_schema.columns \
+         where table_schema = $1 and column_name = 'block_range'",
+    )
+    .bind::<Text, _>(db_schema)
+    .load::<TableName>(conn)?;
+    if tables.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let query = tables
+        .iter()
+        .map(|table| {
+            format!(
+                "select '{table}'::text as table_name, count(*) as count \
+                 from \"{schema}\".\"{table}\" where upper_inf(block_range)",
+                schema = db_schema,
+                table = table.table_name
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" union all ");
+    let counts = sql_query(query).load::<EntityCount>(conn)?;
+    Ok(counts
+        .into_iter()
+        .map(|count| (count.table_name, count.count))
+        .collect())
+}
+
+/// Asks the chain reader for the latest block it has seen on `network`
+pub async fn chain_head(network: &str) -> Result<i64, anyhow::Error> {
+    let mut client = StreamClient::connect(CHAIN_READER_URL.as_str()).await?;
+    let response = client
+        .chain_head(ChainHeadRequest {
+            // The indexer api only deploys Solana indexers
+            chain_type: ChainType::Solana as i32,
+            network: network.to_string(),
+        })
+        .await?;
+    Ok(response.into_inner().block_number as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use massbit::prelude::serde_json::{self, json};
+
+    fn sample(at: Instant, blocks_behind: i64) -> ProgressSample {
+        ProgressSample { at, blocks_behind }
+    }
+
+    #[test]
+    fn catch_up_is_extrapolated_from_the_pace() {
+        let start = Instant::now();
+        let later = start + Duration::from_secs(10);
+        // 100 blocks in 10 seconds leave 30 seconds for the remaining 300
+        assert_eq!(
+            estimate_catch_up(sample(start, 400), sample(later, 300)),
+            Some(30)
+        );
+        // Partial seconds are rounded up
+        assert_eq!(
+            estimate_catch_up(sample(start, 400), sample(later, 301)),
+            Some(31)
+        );
+    }
+
+    #[test]
+    fn caught_up_indexer_needs_no_time() {
+        let start = Instant::now();
+        assert_eq!(
+            estimate_catch_up(sample(start, 10), sample(start, 0)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn no_estimate_without_progress() {
+        let start = Instant::now();
+        let later = start + Duration::from_secs(10);
+        // Falling behind, standing still, or no time between the samples
+        assert_eq!(
+            estimate_catch_up(sample(start, 10), sample(later, 20)),
+            None
+        );
+        assert_eq!(
+            estimate_catch_up(sample(start, 10), sample(later, 10)),
+            None
+        );
+        assert_eq!(
+            estimate_catch_up(sample(start, 20), sample(start, 10)),
+            None
+        );
+    }
+
+    #[test]
+    fn status_is_serialized_in_camel_case() {
+        let status = IndexingStatus {
+            hash: String::from("hash"),
+            name: String::from("serum"),
+            network: Some(String::from("mainnet")),
+            status: Some(String::from("Running")),
+            start_block: 10,
+            latest_block: Some(90),
+            chain_head_block: Some(100),
+            blocks_behind: Some(10),
+            estimated_seconds_to_catch_up: Some(5),
+            synced: false,
+            health: DeploymentHealth::Unhealthy,
+            last_error: Some(ErrorStatus {
+                message: String::from("handler failed"),
+                block_number: Some(42),
+                block_hash: None,
+                handler: Some(String::from("handle_trade")),
+                deterministic: true,
+            }),
+            entity_counts: vec![(String::from("trade"), 3)].into_iter().collect(),
+        };
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            json!({
+                "hash": "hash",
+                "name": "serum",
+                "network": "mainnet",
+                "status": "Running",
+                "startBlock": 10,
+                "latestBlock": 90,
+                "chainHeadBlock": 100,
+                "blocksBehind": 10,
+                "estimatedSecondsToCatchUp": 5,
+                "synced": false,
+                "health": "unhealthy",
+                "lastError": {
+                    "message": "handler failed",
+                    "blockNumber": 42,
+                    "blockHash": null,
+                    "handler": "handle_trade",
+                    "deterministic": true,
+                },
+                "entityCounts": { "trade": 3 },
+            })
+        );
+    }
+}
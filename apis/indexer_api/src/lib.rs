@@ -3,12 +3,15 @@ extern crate diesel;
 extern crate diesel_migrations;
 
 pub mod git_helper;
+pub mod indexer_health;
 pub mod indexer_service;
+pub mod indexing_status;
 pub mod manager;
 pub mod model;
 pub mod orm;
 pub mod server_builder;
 pub mod store;
+pub mod subscription;
 
 use lazy_static::lazy_static;
 use std::collections::HashMap;
@@ -21,6 +24,10 @@ pub const API_LIST_LIMIT: i64 = 100_i64;
 pub const GET_BLOCK_TIMEOUT_SEC: u64 = 600;
 pub const GET_STREAM_TIMEOUT_SEC: u64 = 30;
 pub const MAX_JSON_BODY_SIZE: u64 = 1024 * 1024;
+/// An indexer at most this many blocks behind the chain head counts as synced
+pub const SYNCED_MAX_BLOCKS_BEHIND: i64 = 10;
+/// Slots of history whose transactions are fetched and handled at once by a backfill
+pub const BACKFILL_RANGE_SLOTS: u64 = 10_000;
 lazy_static! {
     pub static ref COMPONENT_NAME: String = String::from("[IndexerApi]");
     pub static ref CONNECTION_POOL_SIZE: u32 = env::var("CONNECTION_POOL_SIZE")
@@ -41,6 +48,12 @@ lazy_static! {
     pub static ref GENERATED_FOLDER: String = String::from("index-manager/generated");
     pub static ref INDEXER_UPLOAD_DIR: String =
         env::var("INDEXER_UPLOAD_DIR").unwrap_or(String::from("."));
+    /// Make the changes a new schema requires to the tables of an indexer even
+    /// when they drop data
+    pub static ref FORCE_SCHEMA_MIGRATION: bool = env::var("FORCE_SCHEMA_MIGRATION")
+        .ok()
+        .and_then(|val| val.parse().ok())
+        .unwrap_or(false);
     pub static ref MAX_UPLOAD_FILE_SIZE: u64 = 10_000_000_u64;
     pub static ref FILES: HashMap<String, String> = HashMap::from_iter([
         (String::from("libblock.so"), String::from("mapping")),
@@ -0,0 +1,636 @@
+//! Backfill of an indexer: the blocks of its data sources from its start block up to
+//! the latest finalized block, handled before the indexer receives live blocks.
+//!
+//! The history is fetched forward, a range of slots at a time, and each range is
+//! handled as soon as it is fetched. Data sources with an address are backfilled from
+//! the signatures of their address, a data source without address needs every block.
+
+use super::data_sources::IndexerDataSources;
+use crate::{BACKFILL_RANGE_SLOTS, COMPONENT_NAME, GET_STREAM_TIMEOUT_SEC};
+use chain_solana::adapter::SolanaAdapter;
+use chain_solana::types::Pubkey;
+use chain_solana::LIMIT_FILTER_RESULT;
+use log::info;
+use massbit::prelude::{Arc, CancelHandle, CancelToken};
+use massbit_common::prelude::anyhow;
+use massbit_common::prelude::tokio::task;
+use massbit_common::prelude::tokio::time::{sleep, Duration};
+use massbit_solana_sdk::plugin::proxy::SolanaHandlerProxy;
+use massbit_solana_sdk::store::IndexStore;
+use massbit_solana_sdk::types::ExtBlock;
+use solana_sdk::signature::Signature;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// The history of a network. The calls block until the node answers, the backfill
+/// makes them on the blocking threads of the runtime.
+pub trait BlockHistory: Send + Sync {
+    /// Returns the slot of the latest finalized block.
+    fn get_slot(&self) -> Result<u64, anyhow::Error>;
+    /// Returns the slots of the blocks in `[first_slot, last_slot]`.
+    fn get_blocks(&self, first_slot: u64, last_slot: u64) -> Result<Vec<u64>, anyhow::Error>;
+    /// Fetches the block at `slot` with all its transactions.
+    fn get_block(&self, slot: u64) -> Result<ExtBlock, anyhow::Error>;
+    /// Returns the signature of the first transaction of the first block in
+    /// `[first_slot, last_slot]`.
+    fn first_signature(
+        &self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Option<Signature>, anyhow::Error>;
+    /// Returns the slots and signatures of the transactions of `address` in
+    /// `[first_slot, last_slot]` between `before` and `until`, oldest first.
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        first_slot: u64,
+        last_slot: u64,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    ) -> Result<Vec<(u64, String)>, anyhow::Error>;
+}
+
+impl BlockHistory for SolanaAdapter {
+    fn get_slot(&self) -> Result<u64, anyhow::Error> {
+        SolanaAdapter::get_slot(self)
+    }
+    fn get_blocks(&self, first_slot: u64, last_slot: u64) -> Result<Vec<u64>, anyhow::Error> {
+        SolanaAdapter::get_blocks(self, first_slot, last_slot)
+    }
+    fn get_block(&self, slot: u64) -> Result<ExtBlock, anyhow::Error> {
+        SolanaAdapter::get_block(self, slot)
+    }
+    fn first_signature(
+        &self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Option<Signature>, anyhow::Error> {
+        SolanaAdapter::first_signature(self, first_slot, last_slot)
+    }
+    fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        first_slot: u64,
+        last_slot: u64,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    ) -> Result<Vec<(u64, String)>, anyhow::Error> {
+        SolanaAdapter::get_signatures_for_address(
+            self, address, first_slot, last_slot, before, until,
+        )
+    }
+}
+
+pub struct Backfill {
+    history: Arc<dyn BlockHistory>,
+    /// Canceled when the indexer is paused, stopped or deleted
+    cancel_handle: CancelHandle,
+    /// Number of slots whose history is fetched at once
+    range_slots: u64,
+    /// Number of transactions after which the fetched blocks are handled
+    page_transactions: usize,
+}
+
+impl Backfill {
+    pub fn new(history: Arc<dyn BlockHistory>, cancel_handle: CancelHandle) -> Self {
+        Backfill {
+            history,
+            cancel_handle,
+            range_slots: BACKFILL_RANGE_SLOTS,
+            page_transactions: LIMIT_FILTER_RESULT,
+        }
+    }
+
+    /// Handles the blocks of `data_sources` from `from_slot` to the latest finalized slot,
+    /// in slot order. The store checkpoints each handled block when it flushes it, and
+    /// `checkpoint` is called with the end of each range whose blocks were all handled.
+    /// A block which creates data sources ends the range, the history is fetched again
+    /// from the next block with the addresses of the new data sources.
+    /// Returns the slot to stream from, or None if the indexer was canceled.
+    pub async fn run(
+        &self,
+        proxy: &SolanaHandlerProxy,
+        data_sources: &mut IndexerDataSources,
+        store: &Mutex<Box<dyn IndexStore>>,
+        from_slot: u64,
+        checkpoint: &(dyn Fn(i64) + Sync),
+    ) -> Result<Option<u64>, Box<dyn Error>> {
+        let mut next_slot = from_slot;
+        'backfill: loop {
+            let last_slot = match self.retry(|history| history.get_slot()).await {
+                Some(last_slot) => last_slot,
+                None => return Ok(None),
+            };
+            let addresses = history_addresses(data_sources);
+            // The latest signature of each address before the current range
+            let mut untils: HashMap<Pubkey, Signature> = HashMap::new();
+            while next_slot <= last_slot {
+                let first_slot = next_slot;
+                let range_end = last_slot.min(first_slot + self.range_slots - 1);
+                let slots: Vec<(u64, Option<HashSet<String>>)> = match &addresses {
+                    Some(addresses) => {
+                        let addresses = addresses.clone();
+                        let previous = untils.clone();
+                        let result = self
+                            .retry(move |history| {
+                                let mut untils = previous.clone();
+                                let signatures = history_signatures(
+                                    history,
+                                    &addresses,
+                                    &mut untils,
+                                    first_slot,
+                                    range_end,
+                                    last_slot,
+                                )?;
+                                Ok((signatures, untils))
+                            })
+                            .await;
+                        match result {
+                            Some((signatures, latest)) => {
+                                untils = latest;
+                                signatures
+                                    .into_iter()
+                                    .map(|(slot, signatures)| (slot, Some(signatures)))
+                                    .collect()
+                            }
+                            None => return Ok(None),
+                        }
+                    }
+                    None => {
+                        let result = self
+                            .retry(move |history| history.get_blocks(first_slot, range_end))
+                            .await;
+                        match result {
+                            Some(slots) => slots.into_iter().map(|slot| (slot, None)).collect(),
+                            None => return Ok(None),
+                        }
+                    }
+                };
+                info!(
+                    "{} Backfills {} blocks from block {} to block {}",
+                    &*COMPONENT_NAME,
+                    slots.len(),
+                    first_slot,
+                    range_end
+                );
+                let mut blocks = vec![];
+                let mut transactions = 0;
+                let mut slots = slots.into_iter().peekable();
+                while let Some((slot, signatures)) = slots.next() {
+                    let block = match self.retry(move |history| history.get_block(slot)).await {
+                        Some(block) => block,
+                        None => return Ok(None),
+                    };
+                    let block = match &signatures {
+                        Some(signatures) => history_block(block, signatures),
+                        None => block,
+                    };
+                    // A page ends with a whole block, so that the checkpoint covers all its
+                    // transactions
+                    transactions += block.block.transactions.len();
+                    blocks.push(block);
+                    if transactions < self.page_transactions && slots.peek().is_some() {
+                        continue;
+                    }
+                    transactions = 0;
+                    let (block_slot, created_data_sources) =
+                        data_sources.handle_blocks(proxy, std::mem::take(&mut blocks), store)?;
+                    //Get the history of new data sources from the next block on
+                    if created_data_sources {
+                        next_slot = block_slot as u64 + 1;
+                        continue 'backfill;
+                    }
+                }
+                // Every block of the range was handled
+                checkpoint(range_end as i64);
+                next_slot = range_end + 1;
+            }
+            return Ok(Some(next_slot));
+        }
+    }
+
+    /// Calls `f` on a blocking thread until it succeeds. Returns None once the indexer is
+    /// canceled.
+    async fn retry<R, F>(&self, f: F) -> Option<R>
+    where
+        R: Send + 'static,
+        F: Fn(&dyn BlockHistory) -> Result<R, anyhow::Error> + Clone + Send + 'static,
+    {
+        loop {
+            if self.cancel_handle.is_canceled() {
+                return None;
+            }
+            let history = self.history.clone();
+            let call = f.clone();
+            let result = task::spawn_blocking(move || call(history.as_ref()))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result);
+            match result {
+                Ok(value) => return Some(value),
+                Err(err) => {
+                    log::error!("{} Cannot get history: {:?}", &*COMPONENT_NAME, &err);
+                    sleep(Duration::from_secs(GET_STREAM_TIMEOUT_SEC)).await;
+                }
+            }
+        }
+    }
+}
+
+/// The addresses of the data sources, each of them once. None if a data source has no
+/// address: its transactions can only be found in the blocks.
+fn history_addresses(data_sources: &IndexerDataSources) -> Option<Vec<Pubkey>> {
+    let mut addresses: Vec<Pubkey> = vec![];
+    for data_source in data_sources.data_sources.iter() {
+        let address = match &data_source.source.address {
+            Some(address) => address,
+            None => return None,
+        };
+        if let Ok(address) = Pubkey::from_str(address.as_str()) {
+            if !addresses.contains(&address) {
+                addresses.push(address);
+            }
+        }
+    }
+    Some(addresses)
+}
+
+/// Returns the signatures of the transactions of `addresses` in `[first_slot, range_end]`
+/// grouped by slot. The signatures are paged before the first block after the range and
+/// after the latest signature of each address in `untils`, which moves to the range.
+fn history_signatures(
+    history: &dyn BlockHistory,
+    addresses: &[Pubkey],
+    untils: &mut HashMap<Pubkey, Signature>,
+    first_slot: u64,
+    range_end: u64,
+    last_slot: u64,
+) -> Result<BTreeMap<u64, HashSet<String>>, anyhow::Error> {
+    let before = match range_end < last_slot {
+        true => history.first_signature(range_end + 1, last_slot)?,
+        false => None,
+    };
+    let mut signatures: BTreeMap<u64, HashSet<String>> = BTreeMap::new();
+    let mut latest = vec![];
+    for address in addresses.iter() {
+        let address_signatures = history.get_signatures_for_address(
+            address,
+            first_slot,
+            range_end,
+            before,
+            untils.get(address).cloned(),
+        )?;
+        if let Some((_, signature)) = address_signatures.last() {
+            latest.push((*address, Signature::from_str(signature)?));
+        }
+        for (slot, signature) in address_signatures {
+            signatures.entry(slot).or_default().insert(signature);
+        }
+    }
+    untils.extend(latest);
+    Ok(signatures)
+}
+
+/// Keeps the transactions of `block` with one of `signatures`, in their order in the block
+fn history_block(block: ExtBlock, signatures: &HashSet<String>) -> ExtBlock {
+    let ExtBlock {
+        version,
+        timestamp,
+        block_number,
+        mut block,
+        list_log_messages,
+        account_updates,
+    } = block;
+    let (transactions, list_log_messages) = block
+        .transactions
+        .into_iter()
+        .zip(list_log_messages)
+        .filter(|(tran, _)| {
+            tran.transaction
+                .signatures
+                .first()
+                .map_or(false, |signature| {
+                    signatures.contains(&signature.to_string())
+                })
+        })
+        .unzip();
+    block.transactions = transactions;
+    ExtBlock {
+        version,
+        timestamp,
+        block_number,
+        block,
+        list_log_messages,
+        account_updates,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::data_sources::tests::{block, data_source, data_sources, template};
+    use massbit::prelude::CancelGuard;
+    use massbit_solana_sdk::entity::Entity;
+    use massbit_solana_sdk::plugin::handler::SolanaHandler;
+    use massbit_solana_sdk::store::DynamicDataSource;
+
+    /// A finalized chain of `blocks` whose transactions are signed by their slot and index
+    struct MockHistory {
+        blocks: BTreeMap<u64, ExtBlock>,
+    }
+
+    impl MockHistory {
+        fn new(blocks: Vec<(u64, Vec<Pubkey>)>) -> Self {
+            let blocks = blocks
+                .into_iter()
+                .map(|(slot, keys)| {
+                    let mut block = block(slot, &keys, vec![]);
+                    for (index, tran) in block.block.transactions.iter_mut().enumerate() {
+                        tran.transaction.signatures = vec![signature(slot, index)];
+                    }
+                    (slot, block)
+                })
+                .collect();
+            MockHistory { blocks }
+        }
+    }
+
+    fn signature(slot: u64, index: usize) -> Signature {
+        let mut bytes = [0_u8; 64];
+        bytes[..8].copy_from_slice(&slot.to_be_bytes());
+        bytes[8..16].copy_from_slice(&(index as u64).to_be_bytes());
+        Signature::new(&bytes)
+    }
+
+    impl BlockHistory for MockHistory {
+        fn get_slot(&self) -> Result<u64, anyhow::Error> {
+            Ok(*self.blocks.keys().last().unwrap())
+        }
+        fn get_blocks(&self, first_slot: u64, last_slot: u64) -> Result<Vec<u64>, anyhow::Error> {
+            Ok(self
+                .blocks
+                .range(first_slot..=last_slot)
+                .map(|(slot, _)| *slot)
+                .collect())
+        }
+        fn get_block(&self, slot: u64) -> Result<ExtBlock, anyhow::Error> {
+            self.blocks
+                .get(&slot)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("no block at slot {}", slot))
+        }
+        fn first_signature(
+            &self,
+            first_slot: u64,
+            last_slot: u64,
+        ) -> Result<Option<Signature>, anyhow::Error> {
+            Ok(self
+                .blocks
+                .range(first_slot..=last_slot)
+                .find_map(|(_, block)| block.block.transactions.first())
+                .map(|tran| tran.transaction.signatures[0]))
+        }
+        fn get_signatures_for_address(
+            &self,
+            address: &Pubkey,
+            first_slot: u64,
+            last_slot: u64,
+            _before: Option<Signature>,
+            _until: Option<Signature>,
+        ) -> Result<Vec<(u64, String)>, anyhow::Error> {
+            let mut signatures = vec![];
+            for (slot, block) in self.blocks.range(first_slot..=last_slot) {
+                for tran in block.block.transactions.iter() {
+                    if tran.transaction.message.account_keys.contains(address) {
+                        signatures.push((*slot, tran.transaction.signatures[0].to_string()));
+                    }
+                }
+            }
+            Ok(signatures)
+        }
+    }
+
+    /// Records the slot and the first account of the transactions of each handled block
+    struct RecordingHandler {
+        blocks: Arc<Mutex<Vec<(u64, Vec<Pubkey>)>>>,
+    }
+
+    impl SolanaHandler for RecordingHandler {
+        fn handle_block(&self, block: &ExtBlock) -> Result<(), Box<dyn Error>> {
+            let keys = block
+                .block
+                .transactions
+                .iter()
+                .map(|tran| tran.transaction.message.account_keys[0])
+                .collect();
+            self.blocks.lock().unwrap().push((block.block_number, keys));
+            Ok(())
+        }
+    }
+
+    /// Records the flushed slots and creates `created` when the block at its slot is flushed
+    #[derive(Default)]
+    struct MockStore {
+        flushed: Arc<Mutex<Vec<u64>>>,
+        created: Option<DynamicDataSource>,
+        taken: Vec<DynamicDataSource>,
+    }
+
+    impl IndexStore for MockStore {
+        fn save(&mut self, _entity_name: String, _data: Entity) {}
+        fn get(&mut self, _entity_name: String, _entity_id: &String) -> Option<Entity> {
+            None
+        }
+        fn flush(&mut self, _block_hash: &String, block_slot: u64) -> Result<(), Box<dyn Error>> {
+            self.flushed.lock().unwrap().push(block_slot);
+            if self.created.as_ref().map(|created| created.creation_block) == Some(block_slot) {
+                self.taken.extend(self.created.take());
+            }
+            Ok(())
+        }
+        fn create_data_source(&mut self, _name: &str, _address: &str) {}
+        fn take_data_sources(&mut self) -> Vec<DynamicDataSource> {
+            std::mem::take(&mut self.taken)
+        }
+    }
+
+    struct Run {
+        result: Option<u64>,
+        handled: Vec<(u64, Vec<Pubkey>)>,
+        flushed: Vec<u64>,
+        checkpoints: Vec<i64>,
+    }
+
+    async fn run(
+        history: MockHistory,
+        data_sources: &mut IndexerDataSources,
+        created: Option<DynamicDataSource>,
+        range_slots: u64,
+        page_transactions: usize,
+    ) -> Run {
+        let guard = CancelGuard::new();
+        let backfill = Backfill {
+            history: Arc::new(history),
+            cancel_handle: guard.handle(),
+            range_slots,
+            page_transactions,
+        };
+        let handled = Arc::new(Mutex::new(vec![]));
+        let proxy = SolanaHandlerProxy::new(Box::new(RecordingHandler {
+            blocks: handled.clone(),
+        }));
+        let flushed = Arc::new(Mutex::new(vec![]));
+        let store: Mutex<Box<dyn IndexStore>> = Mutex::new(Box::new(MockStore {
+            flushed: flushed.clone(),
+            created,
+            ..Default::default()
+        }));
+        let checkpoints = Mutex::new(vec![]);
+        let checkpoint = |block_slot: i64| checkpoints.lock().unwrap().push(block_slot);
+        let result = backfill
+            .run(&proxy, data_sources, &store, 0, &checkpoint)
+            .await
+            .unwrap();
+        let handled = handled.lock().unwrap().clone();
+        let flushed = flushed.lock().unwrap().clone();
+        Run {
+            result,
+            handled,
+            flushed,
+            checkpoints: checkpoints.into_inner().unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn blocks_are_handled_in_slot_order_across_pages_and_ranges() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let history = MockHistory::new(vec![
+            (1, vec![a, b]),
+            (2, vec![a, a, a]),
+            (3, vec![b]),
+            (4, vec![a]),
+            (5, vec![a]),
+            (7, vec![b, a]),
+            (9, vec![a]),
+        ]);
+        let mut data_sources = data_sources(vec![data_source("a", Some(a), false)], vec![]);
+
+        let run = run(history, &mut data_sources, None, 4, 2).await;
+
+        assert_eq!(run.result, Some(10));
+        // The blocks without transactions of `a` are not fetched
+        assert_eq!(run.flushed, vec![1, 2, 4, 5, 7, 9]);
+        assert_eq!(
+            run.handled,
+            vec![
+                (1, vec![a]),
+                (2, vec![a, a, a]),
+                (4, vec![a]),
+                (5, vec![a]),
+                (7, vec![a]),
+                (9, vec![a]),
+            ]
+        );
+        assert_eq!(run.checkpoints, vec![3, 7, 9]);
+    }
+
+    #[tokio::test]
+    async fn history_is_fetched_again_after_a_block_which_creates_data_sources() {
+        let (factory, pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let history = MockHistory::new(vec![
+            (1, vec![factory]),
+            (2, vec![factory, pool]),
+            (3, vec![pool]),
+            (4, vec![factory, pool]),
+        ]);
+        let mut data_sources = data_sources(
+            vec![data_source("factory", Some(factory), false)],
+            vec![template("pool")],
+        );
+        let created = DynamicDataSource {
+            name: String::from("pool"),
+            address: pool.to_string(),
+            creation_block: 2,
+        };
+
+        let run = run(history, &mut data_sources, Some(created), 10, 10).await;
+
+        assert_eq!(run.result, Some(5));
+        assert_eq!(run.flushed, vec![1, 2, 3, 4]);
+        // The pool handles the blocks after its creation block
+        assert_eq!(
+            run.handled,
+            vec![
+                (1, vec![factory]),
+                (2, vec![factory]),
+                (3, vec![pool]),
+                (4, vec![factory, pool]),
+            ]
+        );
+        // The first range ended with the creation block without a checkpoint
+        assert_eq!(run.checkpoints, vec![4]);
+        assert_eq!(data_sources.data_sources.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn blocks_are_scanned_for_data_sources_without_address() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let history = MockHistory::new(vec![(2, vec![a, b]), (5, vec![b]), (6, vec![])]);
+        let mut data_sources = data_sources(
+            vec![
+                data_source("a", Some(a), false),
+                data_source("all", None, false),
+            ],
+            vec![],
+        );
+
+        let run = run(history, &mut data_sources, None, 4, 10).await;
+
+        assert_eq!(run.result, Some(7));
+        // Every block is handled, the data source without address gets them whole
+        assert_eq!(run.flushed, vec![2, 5, 6]);
+        assert_eq!(
+            run.handled,
+            vec![(2, vec![a, b]), (5, vec![b]), (6, vec![])]
+        );
+        assert_eq!(run.checkpoints, vec![3, 6]);
+    }
+
+    #[tokio::test]
+    async fn canceled_backfill_handles_nothing() {
+        let a = Pubkey::new_unique();
+        let backfill = Backfill::new(
+            Arc::new(MockHistory::new(vec![(1, vec![a])])),
+            CancelGuard::new().handle(),
+        );
+        let proxy = SolanaHandlerProxy::new(Box::new(RecordingHandler {
+            blocks: Default::default(),
+        }));
+        let mut data_sources = data_sources(vec![data_source("a", Some(a), false)], vec![]);
+        let store: Mutex<Box<dyn IndexStore>> = Mutex::new(Box::new(MockStore::default()));
+
+        let result = backfill
+            .run(&proxy, &mut data_sources, &store, 0, &|_| {
+                panic!("a canceled backfill has no checkpoint")
+            })
+            .await
+            .unwrap();
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn empty_batch_has_no_block_slot() {
+        let proxy = SolanaHandlerProxy::new(Box::new(RecordingHandler {
+            blocks: Default::default(),
+        }));
+        let mut data_sources = data_sources(vec![data_source("all", None, false)], vec![]);
+        let store: Mutex<Box<dyn IndexStore>> = Mutex::new(Box::new(MockStore::default()));
+
+        let (block_slot, created_data_sources) =
+            data_sources.handle_blocks(&proxy, vec![], &store).unwrap();
+        assert_eq!(block_slot, -1);
+        assert!(!created_data_sources);
+    }
+}
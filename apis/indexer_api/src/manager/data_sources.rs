@@ -0,0 +1,581 @@
+use super::metrics::IndexerMetrics;
+use crate::store::dynds;
+use crate::COMPONENT_NAME;
+use chain_solana::data_source::{DataSource, DataSourceTemplate};
+use chain_solana::types::{Pubkey, SolanaFilter};
+use chain_solana::SolanaIndexerManifest;
+use massbit::components::indexer::DataSourceTemplateInfo;
+use massbit::prelude::{anyhow, BlockNumber, StoreError};
+use massbit_common::prelude::diesel::PgConnection;
+use massbit_solana_sdk::plugin::handler::SolanaHandler;
+use massbit_solana_sdk::plugin::proxy::SolanaHandlerProxy;
+use massbit_solana_sdk::store::{DynamicDataSource, IndexStore};
+use massbit_solana_sdk::types::SolanaBlock;
+use solana_transaction_status::ConfirmedBlock;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The data sources of an indexer: the ones from the manifest followed by the ones
+/// its mapping created from the manifest templates.
+#[derive(Clone)]
+pub struct IndexerDataSources {
+    pub data_sources: Vec<DataSource>,
+    pub templates: Vec<DataSourceTemplate>,
+    pub metrics: IndexerMetrics,
+}
+
+impl IndexerDataSources {
+    pub fn new(manifest: &SolanaIndexerManifest, indexer_hash: &str) -> Self {
+        IndexerDataSources {
+            data_sources: manifest.data_sources.clone(),
+            templates: manifest.templates.clone(),
+            metrics: IndexerMetrics::new(indexer_hash),
+        }
+    }
+    /// Adds the data sources which were created by the indexer before it was restarted.
+    pub fn load_dynamic_data_sources(
+        &mut self,
+        conn: &PgConnection,
+        indexer_hash: &str,
+    ) -> Result<(), StoreError> {
+        for data_source in dynds::load(conn, indexer_hash)? {
+            self.add_dynamic_data_source(data_source);
+        }
+        Ok(())
+    }
+    /// Creates a data source from its template. It handles the blocks after its creation block.
+    fn add_dynamic_data_source(&mut self, created: DynamicDataSource) {
+        let result = self
+            .templates
+            .iter()
+            .find(|template| template.name == created.name)
+            .cloned()
+            .ok_or_else(|| anyhow!("no template named `{}` was found", &created.name))
+            .and_then(|template| {
+                DataSource::try_from(DataSourceTemplateInfo {
+                    template,
+                    params: vec![created.address.clone()],
+                    context: None,
+                    creation_block: created.creation_block as BlockNumber,
+                })
+            });
+        match result {
+            Ok(mut data_source) => {
+                data_source.source.start_block = created.creation_block as BlockNumber + 1;
+                log::info!(
+                    "{} Add data source {} for address {} from block {}",
+                    &*COMPONENT_NAME,
+                    &data_source.name,
+                    &created.address,
+                    data_source.source.start_block
+                );
+                self.data_sources.push(data_source);
+            }
+            Err(err) => {
+                log::error!(
+                    "{} Cannot create data source {:?}: {:?}",
+                    &*COMPONENT_NAME,
+                    &created,
+                    &err
+                );
+            }
+        }
+    }
+    /// Returns the filter which matches the transactions of all data sources.
+    /// The accounts of the programs of data sources with account handlers are
+    /// delivered as well.
+    pub fn filter(&self) -> SolanaFilter {
+        let mut addresses: Vec<&str> = vec![];
+        let mut wildcard = false;
+        let mut account_owners: Vec<Pubkey> = vec![];
+        for data_source in self.data_sources.iter() {
+            match &data_source.source.address {
+                Some(address) => {
+                    if !addresses.contains(&address.as_str()) {
+                        addresses.push(address.as_str());
+                    }
+                    if !data_source.mapping.account_handlers.is_empty() {
+                        if let Ok(owner) = Pubkey::from_str(address) {
+                            if !account_owners.contains(&owner) {
+                                account_owners.push(owner);
+                            }
+                        }
+                    }
+                }
+                // A data source without address handles all transactions
+                None => wildcard = true,
+            }
+        }
+        if wildcard {
+            addresses.clear();
+        }
+        SolanaFilter {
+            account_owners,
+            ..SolanaFilter::new(addresses)
+        }
+    }
+    /// Calls the handler of each data source with the transactions of `block` which touch
+    /// its address. The data sources without a handler of their own share the handler of
+    /// the plugin, which is called once with the transactions of all of them. Handlers
+    /// without transactions in the block are not called.
+    pub fn handle_block(
+        &self,
+        proxy: &SolanaHandlerProxy,
+        block: &SolanaBlock,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut shared_data_sources = vec![];
+        for data_source in self.data_sources.iter() {
+            if (block.block_number as i64) < data_source.source.start_block as i64 {
+                continue;
+            }
+            match proxy.data_source_handlers.get(&data_source.name) {
+                Some(handler) => call_handler(handler.as_ref(), &[data_source], block)?,
+                None => shared_data_sources.push(data_source),
+            }
+        }
+        match &proxy.handler {
+            Some(handler) if !shared_data_sources.is_empty() => {
+                call_handler(handler.as_ref(), &shared_data_sources, block)
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Handles `blocks` in order and flushes the store after each of them.
+    /// Stops after the first block which creates data sources, since the following blocks
+    /// were filtered without their addresses.
+    /// Returns the slot of the last handled block and whether data sources were created.
+    pub fn handle_blocks(
+        &mut self,
+        proxy: &SolanaHandlerProxy,
+        blocks: Vec<SolanaBlock>,
+        store: &Mutex<Box<dyn IndexStore>>,
+    ) -> Result<(i64, bool), Box<dyn Error>> {
+        let mut block_slot = -1_i64;
+        for block in blocks {
+            log::info!(
+                "{} Received SOLANA BLOCK with block slot: {:?} and hash {:?}, with {} TRANSACTIONs",
+                &*COMPONENT_NAME,
+                &block.block_number,
+                &block.block.blockhash,
+                &block.block.transactions.len()
+            );
+            let start = Instant::now();
+            if let Err(err) = self.handle_block(proxy, &block) {
+                self.metrics.handler_errors.inc();
+                return Err(err);
+            }
+            let created = {
+                let mut store = store.lock().unwrap();
+                store.flush(&block.block.blockhash, block.block_number)?;
+                store.take_data_sources()
+            };
+            self.metrics
+                .block_handling_duration
+                .observe(start.elapsed().as_secs_f64());
+            self.metrics.blocks_handled.inc();
+            self.metrics.latest_block.set(block.block_number as i64);
+            block_slot = block_slot.max(block.block_number as i64);
+            if !created.is_empty() {
+                for data_source in created {
+                    self.add_dynamic_data_source(data_source);
+                }
+                return Ok((block_slot, true));
+            }
+        }
+        Ok((block_slot, false))
+    }
+}
+
+/// Calls `handler` with the transactions of `block` which touch the addresses of
+/// `data_sources`, then with the accounts of the data sources with account handlers.
+fn call_handler(
+    handler: &dyn SolanaHandler,
+    data_sources: &[&DataSource],
+    block: &SolanaBlock,
+) -> Result<(), Box<dyn Error>> {
+    let block = match filter_block(block, data_sources) {
+        Some(block) => block,
+        None => return Ok(()),
+    };
+    handler.handle_block(&block)?;
+    let account_data_sources: Vec<&DataSource> = data_sources
+        .iter()
+        .filter(|data_source| !data_source.mapping.account_handlers.is_empty())
+        .copied()
+        .collect();
+    if !account_data_sources.is_empty() {
+        let owners = addresses(&account_data_sources);
+        for account_update in block.account_updates.iter() {
+            if owners
+                .as_ref()
+                .map_or(true, |owners| owners.contains(&account_update.owner))
+            {
+                handler.handle_account(account_update)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the addresses of `data_sources`, or None if one of them has no address and
+/// handles all transactions.
+fn addresses(data_sources: &[&DataSource]) -> Option<Vec<Pubkey>> {
+    let mut keys = vec![];
+    for data_source in data_sources.iter() {
+        let key = Pubkey::from_str(data_source.source.address.as_ref()?).ok();
+        if let Some(key) = key {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    Some(keys)
+}
+
+/// Returns `block` with the transactions which touch the addresses of `data_sources` and
+/// the accounts owned by them, or None if there are no such transactions.
+fn filter_block<'a>(
+    block: &'a SolanaBlock,
+    data_sources: &[&DataSource],
+) -> Option<Cow<'a, SolanaBlock>> {
+    let keys = match addresses(data_sources) {
+        Some(keys) => keys,
+        None => return Some(Cow::Borrowed(block)),
+    };
+    let indexes: Vec<usize> = block
+        .block
+        .transactions
+        .iter()
+        .enumerate()
+        .filter(|(_, tran)| {
+            tran.transaction
+                .message
+                .account_keys
+                .iter()
+                .any(|key| keys.contains(key))
+        })
+        .map(|(index, _)| index)
+        .collect();
+    if indexes.is_empty() {
+        return None;
+    }
+    Some(Cow::Owned(SolanaBlock {
+        version: block.version.clone(),
+        timestamp: block.timestamp,
+        block_number: block.block_number,
+        block: ConfirmedBlock {
+            previous_blockhash: block.block.previous_blockhash.clone(),
+            blockhash: block.block.blockhash.clone(),
+            parent_slot: block.block.parent_slot,
+            transactions: indexes
+                .iter()
+                .map(|index| block.block.transactions[*index].clone())
+                .collect(),
+            rewards: block.block.rewards.clone(),
+            block_time: block.block.block_time,
+            block_height: block.block.block_height,
+        },
+        list_log_messages: indexes
+            .iter()
+            .filter_map(|index| block.list_log_messages.get(*index).cloned())
+            .collect(),
+        account_updates: block
+            .account_updates
+            .iter()
+            .filter(|account_update| keys.contains(&account_update.owner))
+            .cloned()
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chain_solana::data_source::{Mapping, MappingAccountHandler, Source, TemplateSource};
+    use massbit::semver::Version;
+    use massbit_solana_sdk::types::AccountUpdate;
+    use solana_transaction_status::TransactionWithStatusMeta;
+    use std::sync::Arc;
+
+    /// The calls a handler received: the first account of the transactions of each
+    /// block, and the owner of each account update
+    #[derive(Default)]
+    struct Calls {
+        blocks: Vec<(&'static str, Vec<Pubkey>)>,
+        accounts: Vec<(&'static str, Pubkey)>,
+    }
+
+    struct RecordingHandler {
+        name: &'static str,
+        calls: Arc<Mutex<Calls>>,
+    }
+
+    impl SolanaHandler for RecordingHandler {
+        fn handle_block(&self, block: &SolanaBlock) -> Result<(), Box<dyn Error>> {
+            let keys = block
+                .block
+                .transactions
+                .iter()
+                .map(|tran| tran.transaction.message.account_keys[0])
+                .collect();
+            self.calls.lock().unwrap().blocks.push((self.name, keys));
+            Ok(())
+        }
+        fn handle_account(&self, update: &AccountUpdate) -> Result<(), Box<dyn Error>> {
+            self.calls
+                .lock()
+                .unwrap()
+                .accounts
+                .push((self.name, update.owner));
+            Ok(())
+        }
+    }
+
+    fn handler(
+        name: &'static str,
+        calls: &Arc<Mutex<Calls>>,
+    ) -> Box<dyn SolanaHandler + Send + Sync> {
+        Box::new(RecordingHandler {
+            name,
+            calls: calls.clone(),
+        })
+    }
+
+    pub(crate) fn mapping(account_handlers: bool) -> Mapping {
+        let account_handlers = match account_handlers {
+            true => vec![MappingAccountHandler {
+                handler: String::from("handle_account"),
+            }],
+            false => vec![],
+        };
+        Mapping {
+            kind: String::from("solana/BlockHandler"),
+            api_version: Version::new(0, 0, 1),
+            language: String::from("rust"),
+            entities: vec![],
+            block_handlers: vec![],
+            transaction_handlers: vec![],
+            instruction_handlers: vec![],
+            log_handlers: vec![],
+            account_handlers,
+            runtime: Arc::new(vec![]),
+            link: None,
+        }
+    }
+
+    pub(crate) fn data_source(name: &str, address: Option<Pubkey>, account_handlers: bool) -> DataSource {
+        DataSource {
+            kind: String::from("solana"),
+            network: Some(String::from("mainnet")),
+            name: name.to_string(),
+            source: Source {
+                address: address.map(|address| address.to_string()),
+                start_block: 0,
+            },
+            mapping: mapping(account_handlers),
+            context: Arc::new(None),
+            creation_block: None,
+        }
+    }
+
+    pub(crate) fn template(name: &str) -> DataSourceTemplate {
+        DataSourceTemplate {
+            kind: String::from("solana"),
+            network: Some(String::from("mainnet")),
+            name: name.to_string(),
+            source: TemplateSource { abi: String::new() },
+            mapping: mapping(false),
+        }
+    }
+
+    pub(crate) fn data_sources(
+        data_sources: Vec<DataSource>,
+        templates: Vec<DataSourceTemplate>,
+    ) -> IndexerDataSources {
+        IndexerDataSources {
+            data_sources,
+            templates,
+            metrics: IndexerMetrics::new("test"),
+        }
+    }
+
+    /// A block at `slot` with a transaction for each of `keys`, the key being its
+    /// first account
+    pub(crate) fn block(slot: u64, keys: &[Pubkey], account_updates: Vec<AccountUpdate>) -> SolanaBlock {
+        let transactions: Vec<TransactionWithStatusMeta> = keys
+            .iter()
+            .map(|key| {
+                let mut tran = TransactionWithStatusMeta {
+                    transaction: Default::default(),
+                    meta: None,
+                };
+                tran.transaction.message.account_keys = vec![*key];
+                tran
+            })
+            .collect();
+        SolanaBlock {
+            version: String::from("1.7.0"),
+            timestamp: 0,
+            block_number: slot,
+            list_log_messages: vec![None; transactions.len()],
+            block: ConfirmedBlock {
+                previous_blockhash: String::new(),
+                blockhash: String::new(),
+                parent_slot: slot.saturating_sub(1),
+                transactions,
+                rewards: vec![],
+                block_time: None,
+                block_height: None,
+            },
+            account_updates,
+        }
+    }
+
+    fn account_update(owner: Pubkey, slot: u64) -> AccountUpdate {
+        AccountUpdate {
+            pubkey: Pubkey::new_unique(),
+            owner,
+            lamports: 0,
+            data: vec![],
+            slot,
+            write_version: 0,
+        }
+    }
+
+    #[test]
+    fn filter_has_addresses_and_account_owners() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data_sources = data_sources(
+            vec![
+                data_source("a", Some(a), true),
+                data_source("b", Some(b), false),
+                data_source("a2", Some(a), true),
+            ],
+            vec![],
+        );
+        let filter = data_sources.filter();
+        assert_eq!(filter.keys, vec![a, b]);
+        assert_eq!(filter.account_owners, vec![a]);
+    }
+
+    #[test]
+    fn filter_of_wildcard_data_source_has_no_addresses() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data_sources = data_sources(
+            vec![
+                data_source("a", Some(a), true),
+                data_source("all", None, false),
+                data_source("b", Some(b), false),
+            ],
+            vec![],
+        );
+        let filter = data_sources.filter();
+        assert!(filter.keys.is_empty());
+        // The accounts are still only the ones of the account handlers
+        assert_eq!(filter.account_owners, vec![a]);
+    }
+
+    #[test]
+    fn handle_block_routes_transactions_to_handlers() {
+        let keys: Vec<Pubkey> = (0..5).map(|_| Pubkey::new_unique()).collect();
+        let mut late = data_source("late", Some(keys[3]), false);
+        late.source.start_block = 100;
+        let data_sources = data_sources(
+            vec![
+                data_source("own", Some(keys[0]), true),
+                data_source("shared", Some(keys[1]), false),
+                data_source("other", Some(keys[2]), false),
+                late,
+                data_source("quiet", Some(keys[4]), false),
+            ],
+            vec![],
+        );
+        let calls = Arc::new(Mutex::new(Calls::default()));
+        let mut proxy = SolanaHandlerProxy::new(handler("plugin", &calls));
+        for name in &["own", "quiet"] {
+            proxy
+                .data_source_handlers
+                .insert(name.to_string(), handler(*name, &calls));
+        }
+
+        let block = block(
+            10,
+            &[keys[0], keys[1], keys[3], Pubkey::new_unique()],
+            vec![account_update(keys[0], 10), account_update(keys[1], 10)],
+        );
+        data_sources.handle_block(&proxy, &block).unwrap();
+
+        let calls = calls.lock().unwrap();
+        // `quiet` has no transaction in the block, `late` starts after it and
+        // the shared handler is called once for `shared` and `other`
+        assert_eq!(
+            calls.blocks,
+            vec![("own", vec![keys[0]]), ("plugin", vec![keys[1]])]
+        );
+        // Only `own` has account handlers
+        assert_eq!(calls.accounts, vec![("own", keys[0])]);
+    }
+
+    #[test]
+    fn handle_block_of_wildcard_data_source_has_all_transactions() {
+        let keys: Vec<Pubkey> = (0..2).map(|_| Pubkey::new_unique()).collect();
+        let data_sources = data_sources(
+            vec![
+                data_source("one", Some(keys[0]), false),
+                data_source("all", None, false),
+            ],
+            vec![],
+        );
+        let calls = Arc::new(Mutex::new(Calls::default()));
+        let proxy = SolanaHandlerProxy::new(handler("plugin", &calls));
+
+        data_sources
+            .handle_block(&proxy, &block(1, &keys, vec![]))
+            .unwrap();
+        assert_eq!(calls.lock().unwrap().blocks, vec![("plugin", keys)]);
+    }
+
+    #[test]
+    fn dynamic_data_source_handles_blocks_after_its_creation() {
+        let factory = Pubkey::new_unique();
+        let pool = Pubkey::new_unique();
+        let mut data_sources = data_sources(
+            vec![data_source("factory", Some(factory), false)],
+            vec![template("pool")],
+        );
+
+        data_sources.add_dynamic_data_source(DynamicDataSource {
+            name: String::from("pool"),
+            address: pool.to_string(),
+            creation_block: 7,
+        });
+        // A data source of an unknown template is not added
+        data_sources.add_dynamic_data_source(DynamicDataSource {
+            name: String::from("unknown"),
+            address: Pubkey::new_unique().to_string(),
+            creation_block: 7,
+        });
+
+        assert_eq!(data_sources.data_sources.len(), 2);
+        let created = &data_sources.data_sources[1];
+        assert_eq!(created.name, "pool");
+        assert_eq!(created.source.address, Some(pool.to_string()));
+        assert_eq!(created.source.start_block, 8);
+        assert_eq!(created.creation_block, Some(7));
+        assert_eq!(data_sources.filter().keys, vec![factory, pool]);
+
+        let calls = Arc::new(Mutex::new(Calls::default()));
+        let proxy = SolanaHandlerProxy::new(handler("plugin", &calls));
+        data_sources
+            .handle_block(&proxy, &block(7, &[pool], vec![]))
+            .unwrap();
+        data_sources
+            .handle_block(&proxy, &block(8, &[pool], vec![]))
+            .unwrap();
+        assert_eq!(calls.lock().unwrap().blocks, vec![("plugin", vec![pool])]);
+    }
+}
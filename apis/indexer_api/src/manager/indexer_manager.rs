@@ -1,16 +1,20 @@
 use super::IndexerRuntime;
 use crate::orm::models::Indexer;
+use crate::COMPONENT_NAME;
 use massbit::ipfs_client::IpfsClient;
+use massbit::prelude::{CancelGuard, FutureExtension};
 use massbit::slog::Logger;
 use massbit_common::prelude::anyhow;
 use massbit_common::prelude::diesel::r2d2::ConnectionManager;
 use massbit_common::prelude::diesel::{r2d2, PgConnection};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 pub struct IndexerManager {
     pub ipfs_client: Arc<IpfsClient>,
     pub connection_pool: Arc<r2d2::Pool<ConnectionManager<PgConnection>>>,
-    //pub runtimes: HashMap<String, JoinHandle<_>>,
+    /// Guards of the running indexers by hash, dropping one cancels the runtime of its indexer
+    pub runtimes: HashMap<String, CancelGuard>,
     pub logger: Logger,
 }
 
@@ -26,13 +30,39 @@ impl IndexerManager {
         let connection_pool = self.connection_pool.clone();
         let logger = self.logger.clone();
         let ipfs_client = self.ipfs_client.clone();
-        let join_handle = tokio::spawn(async move {
+        let guard = CancelGuard::new();
+        let cancel_handle = guard.handle();
+        let indexer_hash = hash.clone();
+        let indexer_future = Box::pin(async move {
             if let Some(mut runtime) =
-                IndexerRuntime::new(indexer, ipfs_client, connection_pool, logger).await
+                IndexerRuntime::new(indexer, ipfs_client, connection_pool, logger, cancel_handle)
+                    .await
             {
-                runtime.start().await;
+                if let Err(err) = runtime.start().await {
+                    log::error!(
+                        "{} Indexer {} failed: {:?}",
+                        &*COMPONENT_NAME,
+                        &indexer_hash,
+                        &err
+                    );
+                }
             }
         });
+        tokio::spawn(indexer_future.cancelable(&guard, || ()));
+        // A runtime which is still running for the indexer is canceled with its old guard
+        self.runtimes.insert(hash, guard);
         Ok(())
     }
+    /// Cancels the runtime of the indexer, which unloads its mapping library.
+    /// Returns false if the indexer was not started.
+    pub fn stop_indexer(&mut self, hash: &str) -> bool {
+        match self.runtimes.remove(hash) {
+            Some(guard) => {
+                log::info!("Stop indexer {}", hash);
+                guard.cancel();
+                true
+            }
+            None => false,
+        }
+    }
 }
@@ -1,21 +1,25 @@
+use super::backfill::{Backfill, BlockHistory};
+use super::data_sources::IndexerDataSources;
+use crate::indexer_health;
 use crate::orm::models::Indexer;
+use crate::orm::models_impl::IndexerStatus;
 use crate::orm::schema::indexers::dsl as idx;
 use crate::store::StoreBuilder;
 use crate::{CHAIN_READER_URL, COMPONENT_NAME, GET_BLOCK_TIMEOUT_SEC, GET_STREAM_TIMEOUT_SEC};
 use chain_solana::adapter::SolanaNetworkAdapter;
-use chain_solana::data_source::{DataSource, DataSourceTemplate};
+use chain_solana::data_source::DataSource;
 use chain_solana::manifest::ManifestResolve;
-use chain_solana::types::{Pubkey, SolanaFilter};
+use chain_solana::types::SolanaFilter;
 use chain_solana::SolanaIndexerManifest;
 use libloading::Library;
-use log::info;
 use massbit::components::link_resolver::LinkResolver as _;
+use massbit::data::indexer::schema::IndexerError;
 use massbit::data::indexer::MAX_SPEC_VERSION;
 use massbit::ipfs_client::IpfsClient;
 use massbit::ipfs_link_resolver::LinkResolver;
 use massbit::prelude::anyhow::Context;
 use massbit::prelude::Arc;
-use massbit::prelude::{DeploymentHash, Logger};
+use massbit::prelude::{CancelHandle, DeploymentHash, Logger};
 use massbit_common::prelude::diesel::{
     r2d2::{self, ConnectionManager},
     ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
@@ -24,44 +28,53 @@ use massbit_common::prelude::r2d2::PooledConnection;
 use massbit_common::prelude::tokio::time::{sleep, timeout, Duration};
 use massbit_common::prelude::{anyhow, serde_json};
 use massbit_grpc::firehose::bstream::stream_client::StreamClient;
-use massbit_grpc::firehose::bstream::{BlockRequest, ChainType};
-use massbit_solana_sdk::plugin::handler::SolanaHandler;
-use massbit_solana_sdk::plugin::proxy::SolanaHandlerProxy;
-use massbit_solana_sdk::plugin::{
-    AdapterDeclaration, BlockResponse, MessageHandler, PluginRegistrar,
+use massbit_grpc::firehose::bstream::{
+    BlockRequest, ChainType, PayloadCompression, PayloadEncoding,
 };
+use massbit_solana_sdk::plugin::proxy::SolanaHandlerProxy;
+use massbit_solana_sdk::plugin::{load_declaration, AdapterDeclaration, BlockResponse};
 use massbit_solana_sdk::store::IndexStore;
-use massbit_solana_sdk::types::{ExtBlock, SolanaBlock};
-use solana_sdk::signature::Signature;
+use massbit_solana_sdk::types::decode_blocks;
 use std::env::temp_dir;
 use std::error::Error;
 use std::fs;
 use std::ops::Deref;
 use std::path::PathBuf;
-use std::str::FromStr;
 use std::sync::Mutex;
 use tonic::transport::Channel;
 use tonic::{Request, Streaming};
 use tower::timeout::Timeout;
 use uuid::Uuid;
 
+/// Statuses which the runtime moves to `Backfilling` while it processes the history, and
+/// to `Running` once it streams live blocks. An indexer paused or stopped meanwhile keeps
+/// its status.
+const BACKFILLING_FROM: &[IndexerStatus] = &[IndexerStatus::Deploying, IndexerStatus::Running];
+const RUNNING_FROM: &[IndexerStatus] = &[IndexerStatus::Deploying, IndexerStatus::Backfilling];
+
+/// The slot to stream from after the blocks of a response were handled up to
+/// `block_slot`, which is -1 if the response had no block.
+fn next_start_block(start_block: Option<u64>, block_slot: i64) -> Option<u64> {
+    match block_slot >= 0 {
+        true => Some(block_slot as u64 + 1),
+        false => start_block,
+    }
+}
+
+#[derive(Clone)]
 pub struct IndexerHandler {
-    pub lib: Arc<Library>,
+    // Declared before `lib` so that the handlers are dropped before their library is unloaded
     pub handler_proxies: Option<Arc<SolanaHandlerProxy>>,
+    pub lib: Arc<Library>,
 }
 impl IndexerHandler {
-    fn new(lib: Arc<Library>) -> IndexerHandler {
+    fn new(lib: Arc<Library>, proxy: SolanaHandlerProxy) -> IndexerHandler {
         IndexerHandler {
+            handler_proxies: Some(Arc::new(proxy)),
             lib,
-            handler_proxies: None,
         }
     }
 }
-impl PluginRegistrar for IndexerHandler {
-    fn register_solana_handler(&mut self, handler: Box<dyn SolanaHandler + Send + Sync>) {
-        self.handler_proxies = Some(Arc::new(SolanaHandlerProxy::new(handler)));
-    }
-}
 
 pub struct IndexerRuntime {
     pub indexer: Indexer,
@@ -71,6 +84,8 @@ pub struct IndexerRuntime {
     pub indexer_handler: Option<IndexerHandler>,
     pub network_adapter: Arc<SolanaNetworkAdapter>,
     pub connection_pool: Arc<r2d2::Pool<ConnectionManager<PgConnection>>>,
+    /// Canceled when the indexer is paused, stopped or deleted
+    pub cancel_handle: CancelHandle,
 }
 /// Static methods
 impl IndexerRuntime {
@@ -79,6 +94,7 @@ impl IndexerRuntime {
         ipfs_client: Arc<IpfsClient>,
         connection_pool: Arc<r2d2::Pool<ConnectionManager<PgConnection>>>,
         logger: Logger,
+        cancel_handle: CancelHandle,
     ) -> Option<Self> {
         let link_resolver = LinkResolver::from(ipfs_client.clone());
         let mapping_path = Self::get_ipfs_file(ipfs_client.clone(), &indexer.mapping, "so").await;
@@ -109,6 +125,7 @@ impl IndexerRuntime {
                 indexer_handler: None,
                 network_adapter: Arc::new(adapter),
                 connection_pool,
+                cancel_handle,
             };
             return Some(runtime);
         } else {
@@ -165,11 +182,8 @@ impl IndexerRuntime {
             })
     }
     pub fn verify_manifest(manifest: &SolanaIndexerManifest) -> bool {
-        // Manifest must contain single datasource
-        if manifest.data_sources.len() != 1 {
-            return false;
-        }
-        true
+        // Manifest must contain at least one datasource
+        !manifest.data_sources.is_empty()
     }
 }
 impl<'a> IndexerRuntime {
@@ -183,14 +197,6 @@ impl<'a> IndexerRuntime {
     }
 
     pub async fn start(&mut self) -> Result<(), Box<dyn Error>> {
-        let templates = self
-            .manifest
-            .templates
-            .iter()
-            .map(|tpl| tpl.clone())
-            .collect::<Vec<DataSourceTemplate>>();
-
-        let _arc_templates = Arc::new(templates);
         let data_source = self.manifest.data_sources.get(0).unwrap();
         let mut network = String::default();
         if let Some(val) = &data_source.network {
@@ -208,7 +214,7 @@ impl<'a> IndexerRuntime {
         let db_schema = self.indexer.namespace.as_str();
         let schema_path = self.schema_path.clone().unwrap();
         let deployment_hash = DeploymentHash::new("_indexer").unwrap();
-        if let Ok(mut store) = StoreBuilder::create_store(
+        match StoreBuilder::create_store(
             connection_pool,
             db_schema,
             network,
@@ -216,44 +222,147 @@ impl<'a> IndexerRuntime {
             schema_path,
             deployment_hash,
         ) {
-            unsafe {
-                match self.load_mapping_library(&mut store).await {
-                    Ok(_) => {
-                        log::info!("{} Load library successfully", &*COMPONENT_NAME);
-                    }
-                    Err(err) => {
-                        log::error!("Load library with error {:?}", &err);
-                        return Err(err);
-                    }
-                };
+            Ok(mut store) => {
+                unsafe {
+                    match self.load_mapping_library(&mut store).await {
+                        Ok(_) => {
+                            log::info!("{} Load library successfully", &*COMPONENT_NAME);
+                        }
+                        Err(err) => {
+                            log::error!("Load library with error {:?}", &err);
+                            self.fail_mapping_library(err.as_ref());
+                            return Err(err);
+                        }
+                    };
+                }
+                {
+                    let store: Arc<Mutex<Box<dyn IndexStore>>> =
+                        Arc::new(Mutex::new(Box::new(store)));
+                    self.start_mapping(store).await;
+                }
             }
-            {
-                let store: Arc<Mutex<Box<dyn IndexStore>>> = Arc::new(Mutex::new(Box::new(store)));
-                self.start_mapping(store).await;
+            Err(err) => {
+                log::error!("{} Create store with error {:?}", &*COMPONENT_NAME, &err);
+                return Err(err.into());
             }
         }
         Ok(())
     }
+    /// Moves the indexer from one of the `from` statuses to `to`, unless its status was
+    /// changed since, e.g. it was paused.
+    fn update_status(&self, from: &[IndexerStatus], to: IndexerStatus) {
+        let from: Vec<&str> = from.iter().map(IndexerStatus::as_str).collect();
+        if let Ok(conn) = self.get_connection() {
+            if let Err(err) = diesel::update(
+                idx::indexers
+                    .filter(idx::hash.eq(&self.indexer.hash))
+                    .filter(idx::status.eq_any(from)),
+            )
+            .set(idx::status.eq(to.as_str()))
+            .execute(conn.deref())
+            {
+                log::error!("{:?}", &err);
+            }
+        }
+    }
+    /// Stores the last processed block, the indexer restarts after it.
+    fn save_got_block(&self, block_slot: i64) {
+        if let Ok(conn) = self.get_connection() {
+            if let Err(err) = diesel::update(idx::indexers.filter(idx::hash.eq(&self.indexer.hash)))
+                .set(idx::got_block.eq(block_slot))
+                .execute(conn.deref())
+            {
+                log::error!("{:?}", &err);
+            }
+        }
+    }
+    /// Stops the indexer after its mapping failed to handle a block, and records `err` as
+    /// its fatal error. The block is not checkpointed, so it is handled again when the
+    /// indexer is redeployed.
+    fn stop_failed_indexer(&self, err: &dyn Error) {
+        log::error!(
+            "{} Indexer {} is stopped after a mapping error",
+            &*COMPONENT_NAME,
+            &self.indexer.hash
+        );
+        self.update_status(
+            &[IndexerStatus::Backfilling, IndexerStatus::Running],
+            IndexerStatus::Stopped,
+        );
+        let indexer_id = match DeploymentHash::new(self.indexer.hash.clone()) {
+            Ok(indexer_id) => indexer_id,
+            Err(_) => return,
+        };
+        let error = IndexerError {
+            indexer_id,
+            message: err.to_string(),
+            block_ptr: None,
+            handler: None,
+            deterministic: false,
+        };
+        if let Ok(conn) = self.get_connection() {
+            if let Err(err) = indexer_health::fail(conn.deref(), &self.indexer.hash, error) {
+                log::error!("{:?}", &err);
+            }
+        }
+    }
+    /// Records why the mapping library can not be loaded, e.g. it is missing or was built
+    /// by another compiler or SDK, as the fatal error of the indexer, which is not started
+    /// again until it is redeployed with a rebuilt library.
+    fn fail_mapping_library(&self, err: &dyn Error) {
+        self.update_status(
+            &[
+                IndexerStatus::Deploying,
+                IndexerStatus::Backfilling,
+                IndexerStatus::Running,
+            ],
+            IndexerStatus::Invalid,
+        );
+        let indexer_id = match DeploymentHash::new(self.indexer.hash.clone()) {
+            Ok(indexer_id) => indexer_id,
+            Err(_) => return,
+        };
+        let error = IndexerError {
+            indexer_id,
+            message: err.to_string(),
+            block_ptr: None,
+            handler: None,
+            deterministic: true,
+        };
+        if let Ok(conn) = self.get_connection() {
+            if let Err(err) = indexer_health::fail(conn.deref(), &self.indexer.hash, error) {
+                log::error!("{:?}", &err);
+            }
+        }
+    }
     /// Load a plugin library
     /// A plugin library **must** be implemented using the
-    /// [`model::adapter_declaration!()`] macro. Trying manually implement
+    /// [`massbit_solana_sdk::export_plugin!()`] macro. Trying manually implement
     /// a plugin without going through that macro will result in undefined
-    /// behaviour.use massbit::ipfs_link_resolver::LinkResolver;
+    /// behaviour. Libraries built by another compiler or SDK version are refused.
     pub async unsafe fn load_mapping_library(
         &mut self,
         store: &mut dyn IndexStore,
     ) -> Result<(), Box<dyn Error>> {
         let library_path = self.mapping_path.as_ref().unwrap().as_os_str();
         let lib = Arc::new(Library::new(library_path)?);
+        let adapter_decl = load_declaration::<AdapterDeclaration>(&lib)?;
         // inject store to plugin
         lib.get::<*mut Option<&dyn IndexStore>>(b"STORE\0")?
             .write(Some(store));
-        let adapter_decl = lib
-            .get::<*mut AdapterDeclaration>(b"adapter_declaration\0")?
-            .read();
-        let mut registrar = IndexerHandler::new(lib);
-        (adapter_decl.register)(&mut registrar);
-        self.indexer_handler = Some(registrar);
+        let names = self
+            .manifest
+            .data_sources
+            .iter()
+            .map(|data_source| data_source.name.as_str())
+            .chain(
+                self.manifest
+                    .templates
+                    .iter()
+                    .map(|template| template.name.as_str()),
+            );
+        let proxy = SolanaHandlerProxy::for_plugin(&adapter_decl, lib.clone(), names);
+        self.indexer_handler = Some(IndexerHandler::new(lib, proxy));
         Ok(())
     }
     async fn start_mapping(
@@ -263,17 +372,42 @@ impl<'a> IndexerRuntime {
         if let Some(adapter) = &self.indexer_handler {
             if let Some(proxy) = &adapter.handler_proxies {
                 let data_source = self.manifest.data_sources.get(0).unwrap();
+                let mut data_sources = IndexerDataSources::new(&self.manifest, &self.indexer.hash);
+                data_sources.load_dynamic_data_sources(
+                    self.get_connection()?.deref(),
+                    &self.indexer.hash,
+                )?;
                 let mut opt_stream: Option<Streaming<BlockResponse>> = None;
                 let mut start_block = if self.indexer.got_block >= 0 {
                     Some(self.indexer.got_block.clone() as u64 + 1)
+                } else if self.indexer.start_block > 0 {
+                    Some(self.indexer.start_block as u64)
                 } else {
                     None
                 };
+                //Process the history of the indexer before its live blocks
+                if let Some(from_slot) = start_block {
+                    match self
+                        .backfill(proxy, &mut data_sources, &store, from_slot)
+                        .await
+                    {
+                        Some(next_slot) => {
+                            self.indexer.got_block = next_slot as i64 - 1;
+                            start_block = Some(next_slot);
+                        }
+                        None => return Ok(()),
+                    }
+                }
+                self.update_status(RUNNING_FROM, IndexerStatus::Running);
                 loop {
                     match opt_stream {
                         None => {
                             opt_stream = self
-                                .try_create_block_stream(data_source, start_block.clone())
+                                .try_create_block_stream(
+                                    data_source,
+                                    data_sources.filter(),
+                                    start_block.clone(),
+                                )
                                 .await;
                             if opt_stream.is_none() {
                                 //Sleep for a while and reconnect
@@ -288,53 +422,41 @@ impl<'a> IndexerRuntime {
                             .await;
                             match response {
                                 Ok(Ok(res)) => {
-                                    if let Some(mut data) = res {
-                                        let blocks: Vec<SolanaBlock> =
-                                            serde_json::from_slice(&mut data.payload).unwrap();
-                                        //Get history block from first transaction in first block
-                                        if let Some(block) = blocks.get(0) {
-                                            if let Some(start_block_number) = start_block {
-                                                if block.block_number > start_block_number {
-                                                    let from_signature = block
-                                                        .block
-                                                        .transactions
-                                                        .first()
-                                                        .unwrap()
-                                                        .transaction
-                                                        .signatures
-                                                        .first()
-                                                        .and_then(|sig| Some(sig.clone()));
-                                                    self.mapping_history_data(
-                                                        store.clone(),
-                                                        start_block_number,
-                                                        from_signature,
-                                                    )
-                                                    .await;
-                                                }
+                                    if let Some(data) = res {
+                                        let blocks = match decode_blocks(&data) {
+                                            Ok(blocks) => blocks,
+                                            Err(err) => {
+                                                log::error!(
+                                                    "{} Cannot decode blocks: {:?}. Recreate stream",
+                                                    &*COMPONENT_NAME,
+                                                    &err
+                                                );
+                                                opt_stream = None;
+                                                continue;
                                             }
-                                        }
-                                        match proxy.handle_block_mapping(blocks, store.clone()) {
+                                        };
+                                        match data_sources.handle_blocks(proxy, blocks, &store) {
                                             Err(err) => {
                                                 log::error!(
-                                                    "{} Error while handle received message",
-                                                    err
+                                                    "{} Error while handle received message: {:?}",
+                                                    &*COMPONENT_NAME,
+                                                    &err
                                                 );
+                                                self.stop_failed_indexer(err.as_ref());
+                                                return Err(err);
                                             }
-                                            Ok(block_slot) => {
-                                                self.indexer.got_block = block_slot;
-                                                start_block = Some(block_slot as u64 + 1);
-                                                //Store got_block to db
-                                                if let Ok(conn) = self.get_connection() {
-                                                    if let Err(err) =
-                                                        diesel::update(idx::indexers.filter(
-                                                            idx::hash.eq(&self.indexer.hash),
-                                                        ))
-                                                        .set(idx::got_block.eq(block_slot))
-                                                        .execute(conn.deref())
-                                                    {
-                                                        log::error!("{:?}", &err);
-                                                    }
+                                            Ok((block_slot, created_data_sources)) => {
+                                                //Get the transactions of new data sources
+                                                //from the next block on
+                                                if created_data_sources {
+                                                    opt_stream = None;
                                                 }
+                                                //The store checkpointed the handled blocks
+                                                if block_slot >= 0 {
+                                                    self.indexer.got_block = block_slot;
+                                                }
+                                                start_block =
+                                                    next_start_block(start_block, block_slot);
                                             }
                                         }
                                     }
@@ -354,81 +476,46 @@ impl<'a> IndexerRuntime {
         }
         Ok(())
     }
-    /// Collect history blocks in range [from_block, to_block)
-    async fn mapping_history_data(
+    /// Processes the history of the data sources from `from_slot` to the latest finalized
+    /// slot before the indexer receives live blocks. Returns the slot to stream from, or
+    /// None if the indexer was canceled or stopped by an error of its handlers.
+    async fn backfill(
         &self,
-        store: Arc<Mutex<Box<dyn IndexStore>>>,
-        from_block: u64,
-        last_signature: Option<Signature>,
-    ) {
-        //******************* Backward check ***************************//
-        info!(
-            "Start get transaction backward with filter address: {:?}",
-            &self.indexer
-        );
-        let chain_adapter = self.network_adapter.get_adapter();
-        let address = self
-            .manifest
-            .data_sources
-            .get(0)
-            .unwrap()
-            .source
-            .address
-            .as_ref()
-            .and_then(|addr| Pubkey::from_str(addr.as_str()).ok());
-        //Create a cloned reference to proxy for sub thread
-        let proxy = self
-            .indexer_handler
-            .as_ref()
-            .unwrap()
-            .handler_proxies
-            .as_ref()
-            .unwrap()
-            .clone();
-        tokio::spawn(async move {
-            if let Some(pubkey) = address {
-                let signatures = chain_adapter.get_signatures_for_address(
-                    &pubkey,
-                    Some(from_block),
-                    last_signature,
+        proxy: &SolanaHandlerProxy,
+        data_sources: &mut IndexerDataSources,
+        store: &Mutex<Box<dyn IndexStore>>,
+        from_slot: u64,
+    ) -> Option<u64> {
+        self.update_status(BACKFILLING_FROM, IndexerStatus::Backfilling);
+        let history: Arc<dyn BlockHistory> = self.network_adapter.get_adapter();
+        let backfill = Backfill::new(history, self.cancel_handle.clone());
+        let checkpoint = |block_slot| self.save_got_block(block_slot);
+        match backfill
+            .run(proxy, data_sources, store, from_slot, &checkpoint)
+            .await
+        {
+            Ok(next_slot) => next_slot,
+            Err(err) => {
+                log::error!(
+                    "{} Error while handle history blocks: {:?}",
+                    &*COMPONENT_NAME,
+                    &err
                 );
-                //******************* Forward run ***************************//
-                info!("Start get {} transaction forward.", signatures.len());
-                //Get all transactions by history signatures
-                let confirmed_blocks = chain_adapter.get_confirmed_blocks(&signatures);
-                let ext_blocks = confirmed_blocks
-                    .into_iter()
-                    .filter_map(|confirmed_block| {
-                        let block_number = confirmed_block.block_slot;
-                        confirmed_block.block.and_then(|block| {
-                            Some(ExtBlock {
-                                version: "".to_string(),
-                                timestamp: 0,
-                                block_number,
-                                block,
-                                list_log_messages: vec![],
-                            })
-                        })
-                    })
-                    .collect();
-                proxy.handle_block_mapping(ext_blocks, store);
+                self.stop_failed_indexer(err.as_ref());
+                None
             }
-        });
+        }
     }
 
     async fn try_create_block_stream(
         &self,
         data_source: &DataSource,
+        filter: SolanaFilter,
         start_block: Option<u64>,
     ) -> Option<Streaming<BlockResponse>> {
         //Todo: if remove this line, debug will be broken
         // let _filter =
         //     <chain_solana::Chain as Blockchain>::TriggerFilter::from_data_sources(vec![].iter());
-        let addresses = match &data_source.source.address {
-            Some(addr) => vec![addr.as_str()],
-            None => vec![],
-        };
-        let filter = SolanaFilter::new(addresses);
         let encoded_filter = serde_json::to_vec(&filter).unwrap();
         log::info!(
             "Indexer {:?} get new stream from block {:?}.",
@@ -445,6 +532,8 @@ impl<'a> IndexerRuntime {
             chain_type: chain_type as i32,
             network: data_source.network.clone().unwrap_or(Default::default()),
             filter: encoded_filter,
+            encoding: PayloadEncoding::Bincode as i32,
+            compression: PayloadCompression::Zstd as i32,
         };
         if let Ok(channel) = Channel::from_static(CHAIN_READER_URL.as_str())
             .connect()
@@ -471,3 +560,32 @@ impl<'a> IndexerRuntime {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_restarts_after_the_last_handled_block() {
+        assert_eq!(next_start_block(Some(10), 42), Some(43));
+        assert_eq!(next_start_block(None, 0), Some(1));
+    }
+
+    #[test]
+    fn empty_batch_keeps_the_start_block() {
+        assert_eq!(next_start_block(Some(10), -1), Some(10));
+        assert_eq!(next_start_block(None, -1), None);
+    }
+
+    #[test]
+    fn backfilled_indexer_moves_to_running() {
+        assert!(BACKFILLING_FROM.contains(&IndexerStatus::Deploying));
+        assert!(RUNNING_FROM.contains(&IndexerStatus::Backfilling));
+        assert!(RUNNING_FROM.contains(&IndexerStatus::Deploying));
+        // A paused or stopped indexer is not resumed by its runtime
+        for status in [IndexerStatus::Paused, IndexerStatus::Stopped].iter() {
+            assert!(!BACKFILLING_FROM.contains(status));
+            assert!(!RUNNING_FROM.contains(status));
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use lazy_static::lazy_static;
+
+use massbit::components::metrics::{
+    self, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+};
+
+lazy_static! {
+    static ref BLOCKS_HANDLED: IntCounterVec = metrics::int_counter_vec(
+        "plugin_indexer_blocks_handled_total",
+        "Number of blocks the mapping library of an indexer handled",
+        &["indexer"]
+    );
+    static ref BLOCK_HANDLING_DURATION: HistogramVec = metrics::histogram_vec(
+        "plugin_indexer_block_handling_duration_seconds",
+        "Time an indexer took to handle a block and write its changes",
+        &["indexer"]
+    );
+    static ref HANDLER_ERRORS: IntCounterVec = metrics::int_counter_vec(
+        "plugin_indexer_handler_errors_total",
+        "Number of blocks the mapping library of an indexer failed to handle",
+        &["indexer"]
+    );
+    static ref LATEST_BLOCK: IntGaugeVec = metrics::int_gauge_vec(
+        "plugin_indexer_latest_block",
+        "Slot of the last block an indexer handled",
+        &["indexer"]
+    );
+}
+
+/// The metrics of an indexer whose mapping is a plugin library
+#[derive(Clone)]
+pub struct IndexerMetrics {
+    pub blocks_handled: IntCounter,
+    pub block_handling_duration: Histogram,
+    pub handler_errors: IntCounter,
+    pub latest_block: IntGauge,
+}
+
+impl IndexerMetrics {
+    pub fn new(indexer: &str) -> Self {
+        IndexerMetrics {
+            blocks_handled: BLOCKS_HANDLED.with_label_values(&[indexer]),
+            block_handling_duration: BLOCK_HANDLING_DURATION.with_label_values(&[indexer]),
+            handler_errors: HANDLER_ERRORS.with_label_values(&[indexer]),
+            latest_block: LATEST_BLOCK.with_label_values(&[indexer]),
+        }
+    }
+}
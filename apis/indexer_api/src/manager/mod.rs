@@ -1,5 +1,8 @@
+pub mod backfill;
+pub mod data_sources;
 pub mod indexer_manager;
 pub mod indexer_runtime;
+pub mod metrics;
 
 pub use indexer_manager::IndexerManager;
 pub use indexer_runtime::IndexerRuntime;
@@ -6,6 +6,19 @@ pub struct ListOptions {
     pub limit: Option<i64>,
 }
 
+// The query parameters for indexing statuses, a comma separated list of indexer hashes.
+#[derive(Debug, Deserialize)]
+pub struct StatusOptions {
+    pub hashes: Option<String>,
+}
+
+// The query parameters for redeploy, `reset=true` drops the data of the indexer and indexes
+// again from the start block.
+#[derive(Debug, Deserialize)]
+pub struct RedeployOptions {
+    pub reset: Option<bool>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct IndexerData {
     pub name: Option<String>,
@@ -23,4 +23,6 @@ pub struct Indexer {
     pub version: Option<String>,
     pub hash: String,
     pub v_id: i64,
+    pub health: String,
+    pub fatal_error: Option<i64>,
 }
@@ -1,10 +1,173 @@
 use super::models::Indexer;
+use massbit::data::indexer::status::DeploymentHealth;
+use std::str::FromStr;
 use uuid::Uuid;
 impl Indexer {
     ///Always to call this function to create indexer for init hash value
     pub fn new() -> Self {
         let mut indexer = Indexer::default();
         indexer.hash = Uuid::new_v4().to_string().replace("-", "");
+        indexer.health = DeploymentHealth::Healthy.as_str().to_string();
         indexer
     }
+    pub fn get_status(&self) -> Option<IndexerStatus> {
+        self.status
+            .as_ref()
+            .and_then(|status| IndexerStatus::from_str(status).ok())
+    }
+    pub fn set_status(&mut self, status: IndexerStatus) {
+        self.status = Some(String::from(status.as_str()));
+    }
+}
+
+/// Values of the `status` column of `indexers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexerStatus {
+    /// Waiting for its runtime to start
+    Deploying,
+    /// Its manifest or its mapping library can not be used, it is never started
+    Invalid,
+    /// Processing the transactions of its addresses from its start block, in slot order
+    Backfilling,
+    /// Receiving the blocks from the chain reader
+    Running,
+    /// Not running, it can be resumed
+    Paused,
+    /// Not running, only a redeploy starts it again
+    Stopped,
+    Deleted,
+}
+
+impl IndexerStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexerStatus::Deploying => "Deploying",
+            IndexerStatus::Invalid => "Invalid",
+            IndexerStatus::Backfilling => "Backfilling",
+            IndexerStatus::Running => "Running",
+            IndexerStatus::Paused => "Paused",
+            IndexerStatus::Stopped => "Stopped",
+            IndexerStatus::Deleted => "Deleted",
+        }
+    }
+    /// The statuses from which the pause, resume and stop routes move an indexer to `self`
+    pub fn changeable_from(&self) -> &'static [IndexerStatus] {
+        match self {
+            IndexerStatus::Paused => &[
+                IndexerStatus::Deploying,
+                IndexerStatus::Backfilling,
+                IndexerStatus::Running,
+            ],
+            IndexerStatus::Deploying => &[IndexerStatus::Paused],
+            IndexerStatus::Stopped => &[
+                IndexerStatus::Deploying,
+                IndexerStatus::Backfilling,
+                IndexerStatus::Running,
+                IndexerStatus::Paused,
+            ],
+            _ => &[],
+        }
+    }
+}
+
+impl FromStr for IndexerStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Deploying" => Ok(IndexerStatus::Deploying),
+            "Invalid" => Ok(IndexerStatus::Invalid),
+            "Backfilling" => Ok(IndexerStatus::Backfilling),
+            "Running" => Ok(IndexerStatus::Running),
+            "Paused" => Ok(IndexerStatus::Paused),
+            "Stopped" => Ok(IndexerStatus::Stopped),
+            "Deleted" => Ok(IndexerStatus::Deleted),
+            _ => Err(format!("unknown indexer status `{}`", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUSES: [IndexerStatus; 7] = [
+        IndexerStatus::Deploying,
+        IndexerStatus::Invalid,
+        IndexerStatus::Backfilling,
+        IndexerStatus::Running,
+        IndexerStatus::Paused,
+        IndexerStatus::Stopped,
+        IndexerStatus::Deleted,
+    ];
+
+    /// Whether an indexer in `from` can be moved to `to`
+    fn can_change(from: IndexerStatus, to: IndexerStatus) -> bool {
+        to.changeable_from().contains(&from)
+    }
+
+    #[test]
+    fn running_indexers_can_be_paused() {
+        for status in STATUSES.iter() {
+            let expected = matches!(
+                status,
+                IndexerStatus::Deploying | IndexerStatus::Backfilling | IndexerStatus::Running
+            );
+            assert_eq!(can_change(*status, IndexerStatus::Paused), expected);
+        }
+    }
+
+    #[test]
+    fn only_paused_indexers_can_be_resumed() {
+        for status in STATUSES.iter() {
+            let expected = *status == IndexerStatus::Paused;
+            assert_eq!(can_change(*status, IndexerStatus::Deploying), expected);
+        }
+    }
+
+    #[test]
+    fn running_and_paused_indexers_can_be_stopped() {
+        for status in STATUSES.iter() {
+            let expected = matches!(
+                status,
+                IndexerStatus::Deploying
+                    | IndexerStatus::Backfilling
+                    | IndexerStatus::Running
+                    | IndexerStatus::Paused
+            );
+            assert_eq!(can_change(*status, IndexerStatus::Stopped), expected);
+        }
+    }
+
+    #[test]
+    fn no_route_changes_to_other_statuses() {
+        for to in [
+            IndexerStatus::Invalid,
+            IndexerStatus::Backfilling,
+            IndexerStatus::Running,
+            IndexerStatus::Deleted,
+        ]
+        .iter()
+        {
+            assert!(to.changeable_from().is_empty());
+        }
+    }
+
+    #[test]
+    fn status_round_trip() {
+        for status in STATUSES.iter() {
+            assert_eq!(IndexerStatus::from_str(status.as_str()), Ok(*status));
+        }
+        assert!(IndexerStatus::from_str("running").is_err());
+    }
+
+    #[test]
+    fn unknown_status_is_none() {
+        let mut indexer = Indexer::default();
+        assert_eq!(indexer.get_status(), None);
+        indexer.status = Some(String::from("Unknown"));
+        assert_eq!(indexer.get_status(), None);
+        indexer.set_status(IndexerStatus::Paused);
+        assert_eq!(indexer.get_status(), Some(IndexerStatus::Paused));
+    }
 }
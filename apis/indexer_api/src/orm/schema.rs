@@ -17,5 +17,7 @@ table! {
         version -> Nullable<Varchar>,
         hash -> Varchar,
         v_id -> Int8,
+        health -> Varchar,
+        fatal_error -> Nullable<Int8>,
     }
 }
@@ -1,15 +1,19 @@
-use super::model::ListOptions;
+use super::model::{ListOptions, RedeployOptions, StatusOptions};
 use super::MAX_UPLOAD_FILE_SIZE;
 use crate::indexer_service::IndexerService;
 use crate::manager::IndexerManager;
 use crate::model::IndexerData;
-use crate::MAX_JSON_BODY_SIZE;
+use crate::subscription::{serve_connection, GRAPHQL_WS_PROTOCOL};
+use crate::{DATABASE_URL, MAX_JSON_BODY_SIZE};
 use futures::lock::Mutex;
+use massbit::components::metrics::{encode_metrics, METRICS_CONTENT_TYPE};
 use massbit::ipfs_client::IpfsClient;
 use massbit::slog::Logger;
 use massbit_common::prelude::diesel::r2d2::ConnectionManager;
 use massbit_common::prelude::diesel::PgConnection;
 use massbit_common::prelude::r2d2;
+use massbit_graphql::QueryRequest;
+use massbit_store_postgres::StoreEventListener;
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -40,7 +44,10 @@ impl<'a> IndexerServer {
         };
     }
     pub async fn serve(&self) {
-        let cors = warp::cors().allow_any_origin();
+        let cors = warp::cors()
+            .allow_any_origin()
+            .allow_methods(vec!["GET", "POST", "DELETE"])
+            .allow_header("content-type");
         let router = self
             .create_route_indexer_cli_deploy(
                 self.indexer_service.clone(),
@@ -56,6 +63,35 @@ impl<'a> IndexerServer {
             .or(self
                 .create_route_indexer_detail(self.indexer_service.clone())
                 .with(&cors))
+            .or(self
+                .create_route_indexer_health(self.indexer_service.clone())
+                .with(&cors))
+            .or(self
+                .create_route_indexing_statuses(self.indexer_service.clone())
+                .with(&cors))
+            .or(Self::create_route_metrics())
+            .or(self.create_route_indexer_subscription(self.indexer_service.clone()))
+            .or(self
+                .create_route_indexer_query(self.indexer_service.clone())
+                .with(&cors))
+            .or(self
+                .create_route_indexer_action(
+                    self.indexer_service.clone(),
+                    self.indexer_manager.clone(),
+                )
+                .with(&cors))
+            .or(self
+                .create_route_indexer_redeploy(
+                    self.indexer_service.clone(),
+                    self.indexer_manager.clone(),
+                )
+                .with(&cors))
+            .or(self
+                .create_route_indexer_delete(
+                    self.indexer_service.clone(),
+                    self.indexer_manager.clone(),
+                )
+                .with(&cors))
             .recover(handle_rejection);
         let socket_addr: SocketAddr = self.entry_point.parse().unwrap();
 
@@ -135,6 +171,135 @@ impl<'a> IndexerServer {
                 async move { clone_service.get_indexer(hash).await }
             })
     }
+    /// Indexer health api
+    fn create_route_indexer_health(
+        &self,
+        service: Arc<IndexerService>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("indexers" / String / "health")
+            .and(warp::get())
+            .and_then(move |hash: String| {
+                let clone_service = service.clone();
+                async move { clone_service.get_indexer_health(hash).await }
+            })
+    }
+    /// Sync progress of the indexers api
+    fn create_route_indexing_statuses(
+        &self,
+        service: Arc<IndexerService>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("indexingStatuses")
+            .and(warp::get())
+            .and(warp::query::<StatusOptions>())
+            .and_then(move |options: StatusOptions| {
+                let clone_service = service.clone();
+                async move { clone_service.indexing_statuses(options).await }
+            })
+    }
+    /// Prometheus metrics api
+    fn create_route_metrics(
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("metrics")
+            .and(warp::get())
+            .map(|| match encode_metrics() {
+                Ok(metrics) => warp::http::Response::builder()
+                    .header("content-type", METRICS_CONTENT_TYPE)
+                    .body(metrics)
+                    .unwrap(),
+                Err(err) => {
+                    log::error!("{:?}", &err);
+                    warp::http::Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(vec![])
+                        .unwrap()
+                }
+            })
+    }
+    /// Indexer GraphQL query api
+    fn create_route_indexer_query(
+        &self,
+        service: Arc<IndexerService>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("indexers" / String / "graphql")
+            .and(warp::post())
+            .and(warp::body::content_length_limit(MAX_JSON_BODY_SIZE))
+            .and(warp::body::json())
+            .and_then(move |hash: String, request: QueryRequest| {
+                let clone_service = service.clone();
+                async move { clone_service.query_indexer(hash, request).await }
+            })
+    }
+    /// Indexer GraphQL subscription api, over a websocket
+    fn create_route_indexer_subscription(
+        &self,
+        service: Arc<IndexerService>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("indexers" / String / "graphql")
+            .and(warp::ws())
+            .map(move |hash: String, ws: warp::ws::Ws| {
+                let clone_service = service.clone();
+                let reply =
+                    ws.on_upgrade(move |socket| serve_connection(socket, hash, clone_service));
+                warp::reply::with_header(reply, "Sec-WebSocket-Protocol", GRAPHQL_WS_PROTOCOL)
+            })
+    }
+    /// Indexer lifecycle api: /indexers/:hash/pause, /indexers/:hash/resume and /indexers/:hash/stop
+    fn create_route_indexer_action(
+        &self,
+        service: Arc<IndexerService>,
+        manager: Arc<Mutex<IndexerManager>>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("indexers" / String / String)
+            .and(warp::post())
+            .and_then(move |hash: String, action: String| {
+                let clone_service = service.clone();
+                let clone_manager = manager.clone();
+                async move {
+                    match action.as_str() {
+                        "pause" => clone_service.pause_indexer(hash, clone_manager).await,
+                        "resume" => clone_service.resume_indexer(hash, clone_manager).await,
+                        "stop" => clone_service.stop_indexer(hash, clone_manager).await,
+                        _ => Err(warp::reject::not_found()),
+                    }
+                }
+            })
+    }
+    /// Indexer redeploy from cli api: /indexers/:hash/redeploy?reset=true
+    fn create_route_indexer_redeploy(
+        &self,
+        service: Arc<IndexerService>,
+        manager: Arc<Mutex<IndexerManager>>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("indexers" / String / "redeploy")
+            .and(warp::post())
+            .and(warp::query::<RedeployOptions>())
+            .and(warp::multipart::form().max_length(MAX_UPLOAD_FILE_SIZE.clone()))
+            .and_then(
+                move |hash: String, options: RedeployOptions, form: FormData| {
+                    let clone_service = service.clone();
+                    let clone_manager = manager.clone();
+                    async move {
+                        clone_service
+                            .redeploy_indexer(hash, options, form, clone_manager)
+                            .await
+                    }
+                },
+            )
+    }
+    /// Indexer delete api
+    fn create_route_indexer_delete(
+        &self,
+        service: Arc<IndexerService>,
+        manager: Arc<Mutex<IndexerManager>>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        warp::path!("indexers" / String)
+            .and(warp::delete())
+            .and_then(move |hash: String| {
+                let clone_service = service.clone();
+                let clone_manager = manager.clone();
+                async move { clone_service.delete_indexer(hash, clone_manager).await }
+            })
+    }
 }
 impl ServerBuilder {
     pub fn with_entry_point(mut self, entry_point: &str) -> Self {
@@ -171,12 +336,19 @@ impl ServerBuilder {
             indexer_service: Arc::new(IndexerService {
                 ipfs_client: ipfs_client.clone(),
                 connection_pool: self.connection_pool.as_ref().unwrap().clone(),
+                query_schemas: Default::default(),
+                progress: Default::default(),
+                entity_counts: Default::default(),
+                store_events: StoreEventListener::start(
+                    self.logger.as_ref().unwrap().clone(),
+                    DATABASE_URL.clone(),
+                ),
                 logger: self.logger.as_ref().unwrap().clone(),
             }),
             indexer_manager: Arc::new(Mutex::new(IndexerManager {
                 ipfs_client: ipfs_client.clone(),
                 connection_pool: self.connection_pool.as_ref().unwrap().clone(),
-                //runtimes: Default::default(),
+                runtimes: Default::default(),
                 logger: self.logger.as_ref().unwrap().clone(),
             })),
         }
@@ -203,3 +375,44 @@ async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply, Inf
 
     Ok(warp::reply::with_status(message, code))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use massbit::components::metrics::int_counter_vec;
+
+    #[tokio::test]
+    async fn metrics_route_serves_registered_metrics() {
+        let counter = int_counter_vec("test_api_requests_total", "Served by the api", &[]);
+        counter.with_label_values(&[]).inc();
+
+        let response = warp::test::request()
+            .method("GET")
+            .path("/metrics")
+            .reply(&IndexerServer::create_route_metrics())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], METRICS_CONTENT_TYPE);
+        let metrics = String::from_utf8(response.body().to_vec()).unwrap();
+        assert!(metrics.contains("test_api_requests_total 1"));
+    }
+
+    #[tokio::test]
+    async fn metrics_route_only_answers_get() {
+        let route = IndexerServer::create_route_metrics();
+        assert!(
+            !warp::test::request()
+                .method("POST")
+                .path("/metrics")
+                .matches(&route)
+                .await
+        );
+        assert!(
+            !warp::test::request()
+                .method("GET")
+                .path("/metrics/indexers")
+                .matches(&route)
+                .await
+        );
+    }
+}
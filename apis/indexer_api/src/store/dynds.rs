@@ -0,0 +1,75 @@
+use chain_solana::types::BlockPtr;
+use massbit::prelude::StoreError;
+use massbit_common::prelude::diesel::{
+    delete, insert_into, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
+use massbit_solana_sdk::store::DynamicDataSource;
+
+table! {
+    dynamic_data_sources (vid) {
+        vid -> BigInt,
+        indexer_hash -> Text,
+        name -> Text,
+        address -> Text,
+        creation_block -> BigInt,
+    }
+}
+
+pub fn load(conn: &PgConnection, indexer_hash: &str) -> Result<Vec<DynamicDataSource>, StoreError> {
+    use dynamic_data_sources as dds;
+
+    // Ordering by the creation block and `vid` makes sure they are in insertion order,
+    // which is the order their handlers are called in.
+    let data_sources = dds::table
+        .filter(dds::indexer_hash.eq(indexer_hash))
+        .select((dds::name, dds::address, dds::creation_block))
+        .order_by((dds::creation_block, dds::vid))
+        .load::<(String, String, i64)>(conn)?
+        .into_iter()
+        .map(|(name, address, creation_block)| DynamicDataSource {
+            name,
+            address,
+            creation_block: creation_block as u64,
+        })
+        .collect();
+    Ok(data_sources)
+}
+
+pub fn insert(
+    conn: &PgConnection,
+    indexer_hash: &str,
+    data_sources: &[DynamicDataSource],
+    block_ptr: &BlockPtr,
+) -> Result<usize, StoreError> {
+    use dynamic_data_sources as dds;
+
+    if data_sources.is_empty() {
+        // Avoids a roundtrip to the DB.
+        return Ok(0);
+    }
+
+    let values: Vec<_> = data_sources
+        .iter()
+        .map(|ds| {
+            (
+                dds::indexer_hash.eq(indexer_hash),
+                dds::name.eq(&ds.name),
+                dds::address.eq(&ds.address),
+                dds::creation_block.eq(block_ptr.number as i64),
+            )
+        })
+        .collect();
+
+    insert_into(dds::table)
+        .values(values)
+        .execute(conn)
+        .map_err(|e| e.into())
+}
+
+pub fn remove(conn: &PgConnection, indexer_hash: &str) -> Result<usize, StoreError> {
+    use dynamic_data_sources as dds;
+
+    delete(dds::table.filter(dds::indexer_hash.eq(indexer_hash)))
+        .execute(conn)
+        .map_err(|e| e.into())
+}
@@ -1,23 +1,28 @@
 use super::postgres_queries::{ClampRangeQuery, FindManyQuery, FindQuery, InsertQuery};
 use crate::diesel::OptionalExtension;
+use crate::orm::schema::indexers;
+use crate::store::dynds;
 use crate::store::entity_cache::ModificationsAndCache;
 use crate::store::entity_data::EntityData;
 use crate::store::postgres_queries::DELETE_OPERATION_CHUNK_SIZE;
 use crate::store::{EntityCache, POSTGRES_MAX_PARAMETERS};
 use async_trait::async_trait;
 use chain_solana::types::BlockPtr;
-use massbit::components::store::EntityType;
+use massbit::components::store::{EntityType, StoreEvent};
 use massbit::data::query::{CloneableAnyhowError, QueryExecutionError};
-use massbit::prelude::Logger;
 use massbit::prelude::StoreError;
+use massbit::prelude::{BlockNumber, Logger};
 use massbit_common::prelude::diesel::r2d2::{ConnectionManager, PooledConnection};
-use massbit_common::prelude::diesel::{Connection, PgConnection, RunQueryDsl};
+use massbit_common::prelude::diesel::{
+    Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
+};
 use massbit_common::prelude::tokio::time::Instant;
 use massbit_common::prelude::{anyhow, r2d2};
 use massbit_solana_sdk::entity::Entity;
 use massbit_solana_sdk::model::{EntityKey, EntityModification, BLOCK_NUMBER_MAX};
-use massbit_solana_sdk::store::IndexStore;
+use massbit_solana_sdk::store::{DynamicDataSource, IndexStore};
 use massbit_store_postgres::relational::Layout;
+use massbit_store_postgres::send_store_event;
 //use massbit_store_postgres::relational_queries::EntityData;
 use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
@@ -34,18 +39,20 @@ pub trait IndexerStoreTrait: Sync + Send {
         ids_for_type: BTreeMap<&String, Vec<&str>>,
     ) -> Result<BTreeMap<String, Vec<Entity>>, StoreError>;
 
-    /// Transact the entity changes from a single block atomically into the store, and update the
-    /// indexer block pointer to `block_ptr_to`.
+    /// Transact the entity changes and the data sources created by a single block atomically
+    /// into the store, and update the indexer block pointer to `block_ptr_to`.
     ///
     /// `block_ptr_to` must point to a child block of the current indexer block pointer.
     fn transact_block_operations(
         &self,
         block_ptr_to: BlockPtr,
         mods: Vec<EntityModification>,
+        data_sources: &[DynamicDataSource],
     ) -> Result<(), StoreError>;
 }
 #[derive(Clone)]
 pub struct IndexerStore {
+    pub indexer_hash: String,
     pub connection_pool: Arc<r2d2::Pool<ConnectionManager<PgConnection>>>,
     pub logger: Logger,
     pub layout: Layout,
@@ -111,22 +118,40 @@ impl IndexerStoreTrait for IndexerStore {
         &self,
         block_ptr_to: BlockPtr,
         mods: Vec<EntityModification>,
+        data_sources: &[DynamicDataSource],
     ) -> Result<(), StoreError> {
         let conn = self.get_conn()?;
         conn.transaction(|| -> Result<_, StoreError> {
-            // Emit a store event for the changes we are about to make. We
-            // wait with sending it until we have done all our other work
-            // so that we do not hold a lock on the notification queue
-            // for longer than we have to
-            //let event: StoreEvent = mods.iter().collect();
+            if !mods.is_empty() {
+                // Subscribers are notified of the changes when the transaction commits
+                let event = StoreEvent::from_changes(
+                    self.indexer_hash.clone(),
+                    block_ptr_to.number as BlockNumber,
+                    mods.iter().map(|modification| {
+                        let key = modification.entity_key();
+                        (
+                            key.entity_type.as_str(),
+                            key.entity_id.as_str(),
+                            modification.is_remove(),
+                        )
+                    }),
+                );
+                send_store_event(&conn, &event)?;
+            }
             //let section = stopwatch.start_section("apply_entity_modifications");
             let _count = self.apply_entity_modifications(&conn, mods, &block_ptr_to)?;
             //section.end();
+            dynds::insert(&conn, &self.indexer_hash, data_sources, &block_ptr_to)?;
+            // The checkpoint is written with the changes of the block, so that after a
+            // restart the indexer resumes right after the last block it stored
+            diesel::update(indexers::table.filter(indexers::hash.eq(&self.indexer_hash)))
+                .set(indexers::got_block.eq(block_ptr_to.number as i64))
+                .execute(&conn)?;
             Ok(())
         })
-        //log::info!("{:?}", &event);
     }
 }
+
 impl IndexerStore {
     fn get_conn(
         &self,
@@ -287,6 +312,10 @@ pub struct CacheableStore {
     pub store: Arc<dyn IndexerStoreTrait>,
     pub entity_cache: EntityCache,
     pub indexer_id: String,
+    /// Data sources created by the block which is not flushed yet
+    pending_data_sources: Vec<DynamicDataSource>,
+    /// Data sources stored by flush, not yet taken by the runtime
+    created_data_sources: Vec<DynamicDataSource>,
 }
 
 impl CacheableStore {
@@ -296,6 +325,8 @@ impl CacheableStore {
             store,
             entity_cache,
             indexer_id,
+            pending_data_sources: vec![],
+            created_data_sources: vec![],
         }
     }
 }
@@ -328,39 +359,57 @@ impl IndexStore for CacheableStore {
         })
     }
 
+    /// Transacts the changes and the data sources of the block with the checkpoint of the
+    /// indexer. They are kept if the transaction fails.
     fn flush(&mut self, block_hash: &String, block_slot: u64) -> Result<(), Box<dyn Error>> {
-        //let mut data = self.entity_cache.lock().unwrap();
-        let entity_cache =
-            std::mem::replace(&mut self.entity_cache, EntityCache::new(self.store.clone()));
-        if let Ok(ModificationsAndCache {
+        let ModificationsAndCache {
             modifications: mods,
             entity_lfu_cache: _cache,
-        }) = entity_cache.as_modifications().map_err(|e| {
+        } = self.entity_cache.clone().as_modifications().map_err(|e| {
             log::error!("Error {:?}", e);
             StoreError::Unknown(e.into())
-        }) {
-            // Transact entity modifications into the store
-            let length = mods.len();
-            if length > 0 {
-                let start = Instant::now();
-                let block_ptr = BlockPtr {
-                    hash: block_hash.clone(),
-                    number: block_slot as i32,
-                };
-                match self.store.transact_block_operations(block_ptr, mods) {
-                    Ok(_) => {
-                        log::info!(
-                            "Transact block operation with {} records successfully in {:?}",
-                            length,
-                            start.elapsed()
-                        );
-                    }
-                    Err(err) => {
-                        log::error!("Transact block operation with error {:?}", &err);
-                    }
-                }
+        })?;
+        let mut data_sources = self.pending_data_sources.clone();
+        for data_source in data_sources.iter_mut() {
+            data_source.creation_block = block_slot;
+        }
+        // Transact entity modifications into the store, a block without changes only moves
+        // the checkpoint
+        let length = mods.len();
+        {
+            let start = Instant::now();
+            let block_ptr = BlockPtr {
+                hash: block_hash.clone(),
+                number: block_slot as i32,
+            };
+            if let Err(err) = self
+                .store
+                .transact_block_operations(block_ptr, mods, &data_sources)
+            {
+                log::error!("Transact block operation with error {:?}", &err);
+                return Err(err.into());
             }
+            log::info!(
+                "Transact block operation with {} records successfully in {:?}",
+                length,
+                start.elapsed()
+            );
         }
+        self.entity_cache = EntityCache::new(self.store.clone());
+        self.pending_data_sources.clear();
+        self.created_data_sources.append(&mut data_sources);
         Ok(())
     }
+
+    fn create_data_source(&mut self, name: &str, address: &str) {
+        self.pending_data_sources.push(DynamicDataSource {
+            name: name.to_string(),
+            address: address.to_string(),
+            creation_block: 0,
+        });
+    }
+
+    fn take_data_sources(&mut self) -> Vec<DynamicDataSource> {
+        std::mem::take(&mut self.created_data_sources)
+    }
 }
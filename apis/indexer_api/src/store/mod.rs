@@ -1,5 +1,6 @@
 pub mod block_range;
 pub mod converter;
+pub mod dynds;
 pub mod entity_cache;
 pub mod entity_data;
 pub mod indexer_store;
@@ -1,17 +1,18 @@
-use crate::store::{CacheableStore, IndexerStore};
-use crate::HASURA_URL;
+use crate::store::{dynds, CacheableStore, IndexerStore};
+use crate::{FORCE_SCHEMA_MIGRATION, HASURA_URL};
 use log::error;
 use massbit::prelude::{anyhow, CheapClone, DeploymentHash, Logger, Schema, StoreError};
-use massbit_common::prelude::diesel::connection::SimpleConnection;
+use massbit_common::prelude::diesel::sql_types::Text;
 use massbit_common::prelude::diesel::{
     r2d2::{self, ConnectionManager},
-    sql_query, PgConnection, RunQueryDsl,
+    sql_query, Connection, PgConnection, RunQueryDsl,
 };
 use massbit_common::prelude::reqwest::Client;
 use massbit_common::prelude::tokio_compat_02::FutureExt;
 use massbit_common::prelude::{serde_json, slog};
 use massbit_solana_sdk::store::IndexStore;
 use massbit_store_postgres::command_support::catalog::Site;
+use massbit_store_postgres::migration;
 use massbit_store_postgres::relational::Catalog;
 use massbit_store_postgres::relational::Layout;
 use std::fs::File;
@@ -34,28 +35,31 @@ impl StoreBuilder {
         let conn = conn.deref();
         match sql_query(format!("create schema if not exists {}", db_schema)).execute(conn) {
             Ok(_) => {
-                match Self::create_relational_layout(
+                let raw_schema = Self::read_schema(schema_path);
+                let schema = Schema::parse(&raw_schema, deployment_hash.cheap_clone())?;
+                match Self::create_layout(
                     conn,
-                    schema_path,
+                    &schema,
                     db_schema,
                     deployment_hash.cheap_clone(),
                     network.as_str(),
                 ) {
                     Ok(layout) => {
-                        let sql = layout.as_ddl().map_err(|_| {
-                            StoreError::Unknown(anyhow!("failed to generate DDL for layout"))
-                        })?;
-                        match conn.batch_execute(&sql) {
-                            Ok(_) => {}
-                            Err(e) => {
-                                log::error!("{:?}", e);
-                            }
+                        let changes = migration::migrate(
+                            conn,
+                            &layout,
+                            &raw_schema,
+                            *FORCE_SCHEMA_MIGRATION,
+                        )?;
+                        for change in changes {
+                            log::info!("Indexer {}: {}", &indexer_hash, change);
                         }
                         if let Err(err) = Self::create_hasura_relations(&layout) {
                             error!("{:?}", &err);
                         }
                         let logger = Logger::root(slog::Discard, slog::o!());
                         let store = Arc::new(IndexerStore {
+                            indexer_hash: indexer_hash.clone(),
                             connection_pool,
                             layout,
                             logger,
@@ -77,17 +81,87 @@ impl StoreBuilder {
         deployment_hash: DeploymentHash,
         network: &str,
     ) -> Result<Layout, StoreError> {
+        let schema_buffer = Self::read_schema(path);
+        let schema = Schema::parse(schema_buffer.as_str(), deployment_hash.cheap_clone()).unwrap();
+        Self::create_layout(conn, &schema, schema_name, deployment_hash, network)
+    }
+    fn read_schema<P: AsRef<Path>>(path: P) -> String {
         let mut schema_buffer = String::new();
         let mut file = File::open(path).expect("Unable to open file"); // Refactor: Config to download config file from IPFS instead of just reading from local
         file.read_to_string(&mut schema_buffer)
             .expect("Unable to read string");
-        let schema = Schema::parse(schema_buffer.as_str(), deployment_hash.cheap_clone()).unwrap();
+        schema_buffer
+    }
+    /// Creates the layout of the tables of `schema` in the database schema `schema_name`.
+    pub fn create_layout(
+        conn: &PgConnection,
+        schema: &Schema,
+        schema_name: &str,
+        deployment_hash: DeploymentHash,
+        network: &str,
+    ) -> Result<Layout, StoreError> {
         //let logger = Logger::root(slog::Discard, slog::o!());
         //Create simple site
         let site = Site::new(&deployment_hash, schema_name, network);
         let arc_site = Arc::new(site);
         let catalog = Catalog::new(conn, arc_site.clone()).unwrap();
-        Layout::new(arc_site, &schema, catalog)
+        Layout::new(arc_site, schema, catalog)
+    }
+    /// Returns the names of the tables in the schema `db_schema`.
+    pub fn get_tables(conn: &PgConnection, db_schema: &str) -> Result<Vec<String>, StoreError> {
+        #[derive(QueryableByName)]
+        struct TableName {
+            #[sql_type = "Text"]
+            table_name: String,
+        }
+        let tables =
+            sql_query("select table_name from information_schema.tables where table_schema = $1")
+                .bind::<Text, _>(db_schema)
+                .load::<TableName>(conn)?;
+        Ok(tables.into_iter().map(|table| table.table_name).collect())
+    }
+    /// Stops tracking `tables` of the schema `db_schema` and their relationships in Hasura.
+    pub async fn untrack_hasura_tables(db_schema: &str, tables: &[String]) {
+        let untrack_tables: Vec<serde_json::Value> = tables
+            .iter()
+            .map(|table| {
+                serde_json::json!({
+                    "type": "untrack_table",
+                    "args": {
+                        "table" : {
+                            "schema": db_schema,
+                            "name": table
+                        },
+                        "source": "default",
+                        "cascade": true
+                    },
+                })
+            })
+            .collect();
+        let payload = serde_json::json!({
+            "type": "bulk",
+            "args" : untrack_tables
+        });
+        let response = Client::new()
+            .post(&*HASURA_URL)
+            .json(&payload)
+            .send()
+            .compat()
+            .await;
+        log::info!("Hasura {:?}", response);
+    }
+    /// Drops the schema of an indexer together with the data sources it created.
+    pub fn drop_store(
+        conn: &PgConnection,
+        db_schema: &str,
+        indexer_hash: &str,
+    ) -> Result<(), StoreError> {
+        conn.transaction(|| {
+            sql_query(format!("drop schema if exists {} cascade", db_schema)).execute(conn)?;
+            dynds::remove(conn, indexer_hash)?;
+            migration::remove_schema_versions(conn, db_schema)?;
+            Ok(())
+        })
     }
     fn create_hasura_relations(layout: &Layout) -> Result<(), anyhow::Error> {
         let (track_tables, _) = layout.create_hasura_tracking_tables();
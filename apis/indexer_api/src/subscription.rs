@@ -0,0 +1,193 @@
+//! GraphQL subscriptions over websockets, with the `graphql-ws` protocol of
+//! subscriptions-transport-ws. Each subscription sends the result of its
+//! query when it starts, and again after every block that changes one of
+//! the entities the query reads.
+
+use crate::indexer_service::{add_indexing_errors, IndexerService};
+use futures::{SinkExt, StreamExt};
+use massbit_common::prelude::serde_json;
+use massbit_graphql::{QueryRequest, QueryResult, Subscription};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use warp::ws::{Message, WebSocket};
+
+/// The websocket subprotocol of the subscription connections
+pub const GRAPHQL_WS_PROTOCOL: &str = "graphql-ws";
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum IncomingMessage {
+    ConnectionInit {},
+    Start { id: String, payload: QueryRequest },
+    Stop { id: String },
+    ConnectionTerminate {},
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OutgoingMessage {
+    ConnectionAck,
+    ConnectionError {
+        payload: serde_json::Value,
+    },
+    Data {
+        id: String,
+        payload: QueryResult,
+    },
+    Error {
+        id: String,
+        payload: serde_json::Value,
+    },
+    Complete {
+        id: String,
+    },
+}
+
+fn error_payload(message: &str) -> serde_json::Value {
+    serde_json::json!({ "message": message })
+}
+
+/// Serves the subscriptions a client starts on the connection `ws` to the
+/// indexer `hash`, until the client closes it.
+pub async fn serve_connection(ws: WebSocket, hash: String, service: Arc<IndexerService>) {
+    let (mut ws_sender, mut ws_receiver) = ws.split();
+    let (sender, mut outgoing) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            let text = match serde_json::to_string(&message) {
+                Ok(text) => text,
+                Err(err) => {
+                    log::error!("{:?}", &err);
+                    continue;
+                }
+            };
+            if ws_sender.send(Message::text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut subscriptions: HashMap<String, JoinHandle<()>> = HashMap::new();
+    while let Some(Ok(message)) = ws_receiver.next().await {
+        if message.is_close() {
+            break;
+        }
+        // Pings and binary messages are not part of the protocol
+        let text = match message.to_str() {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let message = match serde_json::from_str::<IncomingMessage>(text) {
+            Ok(message) => message,
+            Err(err) => {
+                let payload = error_payload(&format!("Invalid message: {}", err));
+                let _ = sender.send(OutgoingMessage::ConnectionError { payload });
+                continue;
+            }
+        };
+        match message {
+            IncomingMessage::ConnectionInit {} => {
+                let _ = sender.send(OutgoingMessage::ConnectionAck);
+            }
+            IncomingMessage::Start { id, payload } => {
+                let task = run_subscription(
+                    service.clone(),
+                    hash.clone(),
+                    id.clone(),
+                    payload,
+                    sender.clone(),
+                );
+                if let Some(previous) = subscriptions.insert(id, tokio::spawn(task)) {
+                    previous.abort();
+                }
+            }
+            IncomingMessage::Stop { id } => {
+                if let Some(subscription) = subscriptions.remove(&id) {
+                    subscription.abort();
+                    let _ = sender.send(OutgoingMessage::Complete { id });
+                }
+            }
+            IncomingMessage::ConnectionTerminate {} => break,
+        }
+    }
+    for subscription in subscriptions.values() {
+        subscription.abort();
+    }
+}
+
+async fn run_subscription(
+    service: Arc<IndexerService>,
+    hash: String,
+    id: String,
+    request: QueryRequest,
+    sender: mpsc::UnboundedSender<OutgoingMessage>,
+) {
+    // Listen before the first query, so that no block is missed in between
+    let mut events = service.store_events.subscribe();
+    let schema = match service.query_schema(&hash).await {
+        Ok(Some(schema)) => schema,
+        Ok(None) => {
+            let payload = error_payload("Indexer not found");
+            let _ = sender.send(OutgoingMessage::Error { id, payload });
+            return;
+        }
+        Err(err) => {
+            log::error!("{:?}", &err);
+            let payload = error_payload("Cannot load indexer schema");
+            let _ = sender.send(OutgoingMessage::Error { id, payload });
+            return;
+        }
+    };
+    let subscription = match service.get_connection() {
+        Ok(conn) => Subscription::new(&conn, &schema, request)
+            .map_err(|err| serde_json::to_value(&err).unwrap_or_default()),
+        Err(err) => {
+            log::error!("{:?}", &err);
+            Err(error_payload("Database unavailable"))
+        }
+    };
+    let subscription = match subscription {
+        Ok(subscription) => subscription,
+        Err(payload) => {
+            let _ = sender.send(OutgoingMessage::Error { id, payload });
+            return;
+        }
+    };
+    loop {
+        let payload = match service.get_connection() {
+            Ok(conn) => {
+                let mut payload = subscription.execute(&conn, &schema);
+                add_indexing_errors(&conn, &hash, &mut payload);
+                payload
+            }
+            Err(err) => {
+                log::error!("{:?}", &err);
+                let payload = error_payload("Database unavailable");
+                let _ = sender.send(OutgoingMessage::Error { id, payload });
+                return;
+            }
+        };
+        let message = OutgoingMessage::Data {
+            id: id.clone(),
+            payload,
+        };
+        if sender.send(message).is_err() {
+            return;
+        }
+
+        // Wait for a block that changes an entity the subscription reads
+        loop {
+            match events.recv().await {
+                Ok(event) if event.indexer == hash && subscription.matches(&event) => break,
+                Ok(_) => {}
+                // Events were missed, so the result may have changed
+                Err(RecvError::Lagged(_)) => break,
+                Err(RecvError::Closed) => return,
+            }
+        }
+    }
+}
@@ -4,7 +4,8 @@ use log::{debug, info};
 use massbit::ipfs_client::IpfsClient;
 use massbit_chain_solana::data_type::{decode as solana_decode, SolanaBlock, SolanaFilter};
 use massbit_grpc::firehose::bstream::{
-    stream_client::StreamClient, BlockRequest, BlockResponse, ChainType,
+    stream_client::StreamClient, BlockRequest, BlockResponse, ChainType, PayloadCompression,
+    PayloadEncoding,
 };
 use std::time::Instant;
 
@@ -50,6 +51,8 @@ pub async fn print_blocks(
         chain_type: chain_type as i32,
         network,
         filter: encoded_filter,
+        encoding: PayloadEncoding::Bincode as i32,
+        compression: PayloadCompression::Zstd as i32,
     };
     println!("Creating Stream with {:?}", &get_blocks_request);
     let mut stream = Some(
@@ -61,11 +64,12 @@ pub async fn print_blocks(
 
     println!("Waitting for data...");
     while let Some(data) = stream.as_mut().unwrap().message().await? {
-        let mut data = data as BlockResponse;
+        let data = data as BlockResponse;
         match chain_type {
             ChainType::Solana => {
                 let now = Instant::now();
-                let blocks: Vec<SolanaBlock> = solana_decode(&mut data.payload).unwrap();
+                let blocks: Vec<SolanaBlock> =
+                    solana_decode(&data.payload, data.encoding, data.compression).unwrap();
                 // Decode
                 // let block = convert_solana_encoded_block_to_solana_block(encoded_block);
                 // let mut print_flag = true;
@@ -0,0 +1,317 @@
+//! State of the program accounts written by a block, for the indexers whose
+//! filter has `account_owners`.
+//!
+//! The accounts are read with `getMultipleAccounts` once per block for all
+//! the indexers, then each indexer receives the accounts of its own account
+//! owners. The RPC nodes only serve the latest state, so account triggers are
+//! live only: the accounts of a block are delivered if a provider answers
+//! with the state at the slot of the block. Blocks delivered while an indexer
+//! catches up, and live blocks whose state was already overwritten by a later
+//! slot, are delivered without accounts.
+
+use crate::solana_providers::SolanaNetworkProviders;
+use chain_solana::types::ConfirmedBlockWithSlot;
+use log::warn;
+use massbit::prelude::{Arc, Duration};
+use massbit_chain_solana::data_type::{AccountUpdate, Pubkey, SolanaFilter};
+use massbit_common::prelude::tokio::time::sleep;
+use solana_client::client_error::{ClientErrorKind, Result as ClientResult};
+use std::collections::{HashMap, HashSet};
+
+// Max number of accounts of a getMultipleAccounts request
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+// Requests for the accounts of a block before the block fails
+const FETCH_ATTEMPTS: u32 = 5;
+// Delay before asking again, e.g. for a provider which has not seen the block yet
+const RETRY_DELAY: Duration = Duration::from_millis(400);
+
+/// The accounts owned by the `account_owners` of `filters` which the
+/// transactions of `block` may have written, with the index of the last
+/// transaction which wrote them
+fn written_accounts(
+    filters: &[SolanaFilter],
+    block: &ConfirmedBlockWithSlot,
+) -> Vec<(Pubkey, usize)> {
+    let transactions = match &block.block {
+        Some(block) => &block.transactions,
+        None => return vec![],
+    };
+    let mut last_writes: HashMap<Pubkey, usize> = HashMap::new();
+    for (index, tran) in transactions.iter().enumerate() {
+        for filter in filters {
+            for key in filter.owned_account_candidates(tran) {
+                last_writes.insert(*key, index);
+            }
+        }
+    }
+    let mut accounts: Vec<(Pubkey, usize)> = last_writes.into_iter().collect();
+    accounts.sort_by_key(|(_, index)| *index);
+    accounts
+}
+
+/// Fetches the accounts owned by the `account_owners` of `filters` which the
+/// transactions of `block` may have written, at the slot of `block`. The
+/// write version of an update is the slot in the high bits and the index of
+/// the last transaction which wrote the account in the low bits.
+///
+/// A provider which answers with the state before `block` is asked again, as
+/// is a provider which fails, up to `FETCH_ATTEMPTS` times. The state after a
+/// later slot is not the state of `block`, which fails at once.
+pub async fn fetch_account_updates(
+    providers: &Arc<SolanaNetworkProviders>,
+    filters: &[SolanaFilter],
+    block: &ConfirmedBlockWithSlot,
+) -> ClientResult<Vec<AccountUpdate>> {
+    let filters: Vec<SolanaFilter> = filters
+        .iter()
+        .filter(|filter| !filter.account_owners.is_empty())
+        .cloned()
+        .collect();
+    let owners: HashSet<Pubkey> = filters
+        .iter()
+        .flat_map(|filter| filter.account_owners.iter().cloned())
+        .collect();
+    let accounts = written_accounts(&filters, block);
+    let mut updates = vec![];
+    for chunk in accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let keys: Vec<Pubkey> = chunk.iter().map(|(key, _)| *key).collect();
+        let mut attempt = 1;
+        let response = loop {
+            let keys = keys.clone();
+            let result = providers
+                .call_async("getMultipleAccounts", move |client| {
+                    client.get_multiple_accounts_with_commitment(&keys, client.commitment())
+                })
+                .await;
+            match result {
+                Ok(response) if response.context.slot == block.block_slot => break response,
+                Ok(response) if response.context.slot > block.block_slot => {
+                    return Err(ClientErrorKind::Custom(format!(
+                        "accounts at slot {} were written after the block",
+                        response.context.slot
+                    ))
+                    .into());
+                }
+                Ok(response) if attempt < FETCH_ATTEMPTS => {
+                    warn!(
+                        "Accounts at slot {} are older than block {}, attempt {}",
+                        response.context.slot, block.block_slot, attempt
+                    );
+                    attempt += 1;
+                    sleep(RETRY_DELAY).await;
+                }
+                Ok(response) => {
+                    return Err(ClientErrorKind::Custom(format!(
+                        "accounts at slot {} are older than the block",
+                        response.context.slot
+                    ))
+                    .into());
+                }
+                Err(err) if attempt < FETCH_ATTEMPTS => {
+                    warn!(
+                        "Cannot get {} accounts of block {}, attempt {}: {:?}",
+                        keys.len(),
+                        block.block_slot,
+                        attempt,
+                        err
+                    );
+                    attempt += 1;
+                    sleep(RETRY_DELAY).await;
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        let slot = response.context.slot;
+        // Closed accounts no longer exist and have no owner
+        for ((pubkey, index), account) in chunk.iter().zip(response.value) {
+            if let Some(account) = account {
+                if owners.contains(&account.owner) {
+                    updates.push(AccountUpdate {
+                        pubkey: *pubkey,
+                        owner: account.owner,
+                        lamports: account.lamports,
+                        data: account.data,
+                        slot,
+                        write_version: slot << 32 | *index as u64,
+                    });
+                }
+            }
+        }
+    }
+    Ok(updates)
+}
+
+/// The updates of `updates` for the indexer with `filter`: the accounts of
+/// its account owners which its transactions of `block` may have written
+pub fn select_account_updates(
+    updates: &[AccountUpdate],
+    filter: &SolanaFilter,
+    block: &ConfirmedBlockWithSlot,
+) -> Vec<AccountUpdate> {
+    if filter.account_owners.is_empty() || updates.is_empty() {
+        return vec![];
+    }
+    let written: HashSet<Pubkey> = written_accounts(std::slice::from_ref(filter), block)
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect();
+    updates
+        .iter()
+        .filter(|update| {
+            filter.account_owners.contains(&update.owner) && written.contains(&update.pubkey)
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::CompiledInstruction;
+    use solana_sdk::message::{Message, MessageHeader};
+    use solana_sdk::transaction::TransactionError;
+    use solana_transaction_status::{
+        ConfirmedBlock, TransactionStatusMeta, TransactionWithStatusMeta,
+    };
+
+    /// A transaction signed by `keys[0]` which calls the program `keys[3]`.
+    /// `keys[0]` and `keys[1]` are writable, `keys[2]` and `keys[3]` read only.
+    fn transaction(keys: &[Pubkey; 4], succeeded: bool) -> TransactionWithStatusMeta {
+        let mut tran = TransactionWithStatusMeta {
+            transaction: Default::default(),
+            meta: Some(TransactionStatusMeta {
+                status: match succeeded {
+                    true => Ok(()),
+                    false => Err(TransactionError::AccountInUse),
+                },
+                ..Default::default()
+            }),
+        };
+        tran.transaction.message = Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 2,
+            },
+            account_keys: keys.to_vec(),
+            instructions: vec![CompiledInstruction {
+                program_id_index: 3,
+                accounts: vec![0, 1, 2],
+                data: vec![],
+            }],
+            ..Default::default()
+        };
+        tran
+    }
+
+    fn keys() -> [Pubkey; 4] {
+        [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ]
+    }
+
+    fn block(transactions: Vec<TransactionWithStatusMeta>) -> ConfirmedBlockWithSlot {
+        ConfirmedBlockWithSlot {
+            block_slot: 10,
+            block: Some(ConfirmedBlock {
+                previous_blockhash: String::new(),
+                blockhash: String::new(),
+                parent_slot: 9,
+                transactions,
+                rewards: vec![],
+                block_time: None,
+                block_height: None,
+            }),
+        }
+    }
+
+    fn owned_by(owners: &[Pubkey]) -> SolanaFilter {
+        SolanaFilter {
+            account_owners: owners.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn update(pubkey: Pubkey, owner: Pubkey) -> AccountUpdate {
+        AccountUpdate {
+            pubkey,
+            owner,
+            lamports: 1,
+            data: vec![],
+            slot: 10,
+            write_version: 10 << 32,
+        }
+    }
+
+    #[test]
+    fn written_accounts_are_the_writable_accounts_of_owner_calls() {
+        let keys = keys();
+        let block = block(vec![transaction(&keys, true)]);
+        let accounts = written_accounts(&[owned_by(&[keys[3]])], &block);
+        let mut written: Vec<Pubkey> = accounts.iter().map(|(key, _)| *key).collect();
+        written.sort();
+        let mut expected = vec![keys[0], keys[1]];
+        expected.sort();
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn written_accounts_keep_the_last_write() {
+        let keys = keys();
+        let other = [keys[0], Pubkey::new_unique(), keys[2], keys[3]];
+        let block = block(vec![transaction(&keys, true), transaction(&other, true)]);
+        let accounts = written_accounts(&[owned_by(&[keys[3]])], &block);
+        // Sorted by the index of the transaction which wrote them last
+        assert_eq!(accounts.len(), 3);
+        assert_eq!(accounts[0], (keys[1], 0));
+        assert!(accounts.contains(&(keys[0], 1)));
+        assert!(accounts.contains(&(other[1], 1)));
+    }
+
+    #[test]
+    fn written_accounts_ignore_other_programs_and_failed_transactions() {
+        let keys = keys();
+        let block_of_failure = block(vec![transaction(&keys, false)]);
+        assert!(written_accounts(&[owned_by(&[keys[3]])], &block_of_failure).is_empty());
+
+        let block = block(vec![transaction(&keys, true)]);
+        assert!(written_accounts(&[owned_by(&[keys[2]])], &block).is_empty());
+        assert!(written_accounts(&[SolanaFilter::default()], &block).is_empty());
+        assert!(written_accounts(&[], &block).is_empty());
+    }
+
+    #[test]
+    fn select_account_updates_of_the_indexer() {
+        let keys = keys();
+        let block = block(vec![transaction(&keys, true)]);
+        let other_owner = Pubkey::new_unique();
+        let updates = vec![
+            update(keys[0], keys[3]),
+            update(keys[1], other_owner),
+            // Owned by the program, but not written by the transactions of the block
+            update(keys[2], keys[3]),
+        ];
+        assert_eq!(
+            select_account_updates(&updates, &owned_by(&[keys[3]]), &block),
+            vec![update(keys[0], keys[3])]
+        );
+        // The account of the other owner is written by a call of `keys[3]`
+        assert_eq!(
+            select_account_updates(&updates, &owned_by(&[keys[3], other_owner]), &block),
+            vec![update(keys[0], keys[3]), update(keys[1], other_owner)]
+        );
+    }
+
+    #[test]
+    fn no_account_updates_without_account_owners() {
+        let keys = keys();
+        let block = block(vec![transaction(&keys, true)]);
+        let updates = vec![update(keys[0], keys[3])];
+        assert!(select_account_updates(&updates, &SolanaFilter::default(), &block).is_empty());
+        // A filter whose owner is not called by the block
+        assert!(select_account_updates(&updates, &owned_by(&[keys[1]]), &block).is_empty());
+    }
+}
@@ -1,7 +1,9 @@
 use chain_reader::command;
+use chain_reader::config::Config;
 use chain_reader::stream_service::StreamService;
 use clap::{App, Arg};
 use logger::core::init_logger;
+use massbit::components::server::metrics::serve_metrics;
 use massbit_grpc::firehose::bstream::stream_server::StreamServer;
 use tonic::transport::Server;
 
@@ -21,20 +23,57 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>
                 .short("p")
                 .long("port")
                 .value_name("port")
-                .help("Sets port")
+                .help("Sets port, overrides the listen address of the config")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .value_name("config")
+                .help("Sets the configuration file of the networks")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("check-config")
+                .long("check-config")
+                .help("Validates the configuration file and exits"),
+        )
+        .arg(
+            Arg::with_name("metrics-port")
+                .long("metrics-port")
+                .value_name("metrics-port")
+                .help("Sets the port of the Prometheus metrics endpoint")
                 .takes_value(true),
         )
         .get_matches();
-    let port = matches.value_of("port").unwrap_or("50051").to_string();
-    //command::run().await
+    let config = Config::load(matches.value_of("config"))?;
+    if matches.is_present("check-config") {
+        println!("Successfully validated configuration");
+        return Ok(());
+    }
+    let addr = match matches.value_of("port") {
+        Some(port) => URL.to_owned() + port,
+        None => config.listen.clone(),
+    };
+    let metrics_port: u16 = matches.value_of("metrics-port").unwrap_or("8041").parse()?;
+
+    // Metrics server: serves the metrics of the chain reader at /metrics
+    tokio::spawn(async move {
+        log::info!("Serving metrics at http://0.0.0.0:{}/metrics", metrics_port);
+        if let Err(err) = serve_metrics(metrics_port).await {
+            log::error!("Metrics server stopped: {:?}", err);
+        }
+    });
+    //command::run(config).await
 
     // Rpc server: listens incoming request from indexer.
     // For each indexer create a channel
     // and then filtered data is sent via this channel
     // Init StreamService
     // Run StreamoutServer
-    let stream_service = StreamService::new();
-    let addr = (URL.to_owned() + &port).parse()?;
+    let stream_service = StreamService::new(&config);
+    let addr = addr.parse()?;
     Server::builder()
         .add_service(StreamServer::new(stream_service))
         .serve(addr)
@@ -0,0 +1,349 @@
+//! Configuration of the chain reader: the address it listens on and the
+//! networks it reads, with their providers.
+//!
+//! ```toml
+//! listen = "0.0.0.0:50051"
+//!
+//! [network.mainnet]
+//! chain_type = "solana"
+//! commitment = "finalized"
+//! block_batch_size = 10
+//! catch_up_batch_size = 100
+//! provider = [
+//!   { label = "massbit-3", url = "http://194.163.186.82:8899", weight = 2 },
+//!   { label = "solana", url = "https://api.mainnet-beta.solana.com", rate_limit = 10 },
+//! ]
+//!
+//! [network.localnet]
+//! chain_type = "solana"
+//! commitment = "confirmed"
+//! provider = [ { label = "local", url = "http://127.0.0.1:8899" } ]
+//! ```
+
+use crate::solana_providers::ProviderConfig;
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use massbit::firehose::bstream::ChainType;
+use massbit_common::NetworkType;
+use serde::{Deserialize, Deserializer};
+use solana_sdk::commitment_config::CommitmentLevel;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::read_to_string;
+
+const DEFAULT_LISTEN: &str = "0.0.0.0:50051";
+// The node used when there is no configuration file
+const DEFAULT_SOLANA_URL: &str = "http://194.163.186.82:8899"; // massbit 3
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Address of the gRPC server
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    #[serde(rename = "network")]
+    pub networks: BTreeMap<NetworkType, ChainConfig>,
+}
+
+impl Config {
+    /// Check that the config is valid.
+    fn validate(&mut self) -> Result<()> {
+        self.listen
+            .parse::<std::net::SocketAddr>()
+            .with_context(|| format!("invalid listen address `{}`", self.listen))?;
+        if self.networks.is_empty() {
+            return Err(anyhow!("no network is configured"));
+        }
+        for (name, chain) in self.networks.iter_mut() {
+            chain.validate(name)?;
+        }
+        Ok(())
+    }
+
+    /// Load the configuration file at `path` if it is set. If not, read
+    /// mainnet from the single node at `SOLANA_URL`
+    pub fn load(path: Option<&str>) -> Result<Config> {
+        let mut config = match path {
+            Some(path) => {
+                info!("Reading configuration file `{}`", path);
+                let config = read_to_string(path)
+                    .with_context(|| format!("cannot read configuration file `{}`", path))?;
+                toml::from_str(&config)?
+            }
+            None => {
+                info!("Generating configuration from environment variables");
+                Self::from_env()
+            }
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_env() -> Config {
+        let url = env::var("SOLANA_URL").unwrap_or(String::from(DEFAULT_SOLANA_URL));
+        let mut networks = BTreeMap::new();
+        networks.insert(
+            "mainnet".to_string(),
+            ChainConfig {
+                network: Default::default(),
+                chain_type: ChainType::Solana,
+                commitment: default_commitment(),
+                block_batch_size: default_block_batch_size(),
+                catch_up_batch_size: default_catch_up_batch_size(),
+                max_slot_lag: default_max_slot_lag(),
+                providers: vec![ProviderConfig::new("mainnet-0", &url)],
+            },
+        );
+        Config {
+            listen: default_listen(),
+            networks,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainConfig {
+    /// The name of the network, which is its key in the configuration file
+    #[serde(skip)]
+    pub network: NetworkType,
+    #[serde(deserialize_with = "deserialize_chain_type")]
+    pub chain_type: ChainType,
+    /// Commitment of the slots and blocks read from the network
+    #[serde(default = "default_commitment")]
+    pub commitment: CommitmentLevel,
+    /// Number of slots requested at once from the chain head
+    #[serde(default = "default_block_batch_size")]
+    pub block_batch_size: u64,
+    /// Number of historical slots requested at once while an indexer catches up
+    #[serde(default = "default_catch_up_batch_size")]
+    pub catch_up_batch_size: u64,
+    /// A provider lagging more slots than this behind the best one is evicted
+    #[serde(default = "default_max_slot_lag")]
+    pub max_slot_lag: u64,
+    #[serde(rename = "provider")]
+    pub providers: Vec<ProviderConfig>,
+}
+
+impl ChainConfig {
+    fn validate(&mut self, name: &str) -> Result<()> {
+        self.network = name.to_string();
+        if self.chain_type != ChainType::Solana {
+            return Err(anyhow!(
+                "network `{}` has chain type {:?}, but the chain reader only reads Solana",
+                name,
+                self.chain_type
+            ));
+        }
+        match self.commitment {
+            CommitmentLevel::Processed
+            | CommitmentLevel::Confirmed
+            | CommitmentLevel::Finalized => {}
+            commitment => {
+                return Err(anyhow!(
+                    "network `{}` has the deprecated commitment {:?}; \
+                     use processed, confirmed or finalized",
+                    name,
+                    commitment
+                ))
+            }
+        }
+        if self.block_batch_size == 0 || self.catch_up_batch_size == 0 {
+            return Err(anyhow!(
+                "batch sizes of network `{}` must be bigger than 0",
+                name
+            ));
+        }
+        ProviderConfig::validate(&self.providers, name)
+    }
+
+    /// The url of the first provider, for the clients which talk to a single node
+    pub fn url(&self) -> &str {
+        &self.providers[0].url
+    }
+}
+
+fn deserialize_chain_type<'de, D>(deserializer: D) -> Result<ChainType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let chain_type = String::deserialize(deserializer)?;
+    match chain_type.as_str() {
+        "solana" => Ok(ChainType::Solana),
+        "ethereum" => Ok(ChainType::Ethereum),
+        _ => Err(serde::de::Error::unknown_variant(
+            &chain_type,
+            &["solana", "ethereum"],
+        )),
+    }
+}
+
+fn default_listen() -> String {
+    DEFAULT_LISTEN.to_string()
+}
+
+fn default_commitment() -> CommitmentLevel {
+    CommitmentLevel::Finalized
+}
+
+fn default_block_batch_size() -> u64 {
+    10
+}
+
+fn default_catch_up_batch_size() -> u64 {
+    100
+}
+
+fn default_max_slot_lag() -> u64 {
+    50
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The example of the module documentation
+    const EXAMPLE: &str = r#"
+        listen = "0.0.0.0:50051"
+
+        [network.mainnet]
+        chain_type = "solana"
+        commitment = "finalized"
+        block_batch_size = 10
+        catch_up_batch_size = 100
+        provider = [
+          { label = "massbit-3", url = "http://194.163.186.82:8899", weight = 2 },
+          { label = "solana", url = "https://api.mainnet-beta.solana.com", rate_limit = 10 },
+        ]
+
+        [network.localnet]
+        chain_type = "solana"
+        commitment = "confirmed"
+        provider = [ { label = "local", url = "http://127.0.0.1:8899" } ]
+    "#;
+
+    fn parse(config: &str) -> Result<Config> {
+        let mut config: Config = toml::from_str(config)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn localnet(fields: &str) -> String {
+        format!(
+            "[network.localnet]\n{}\nprovider = [ {{ label = \"local\", url = \"http://127.0.0.1:8899\" }} ]\n",
+            fields
+        )
+    }
+
+    fn assert_error(config: &str, message: &str) {
+        let err = parse(config).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains(message),
+            "`{:#}` does not contain `{}`",
+            err,
+            message
+        );
+    }
+
+    #[test]
+    fn example_is_valid() {
+        let config = parse(EXAMPLE).unwrap();
+        assert_eq!(config.listen, "0.0.0.0:50051");
+        assert_eq!(
+            config.networks.keys().collect::<Vec<_>>(),
+            vec!["localnet", "mainnet"]
+        );
+
+        let mainnet = &config.networks["mainnet"];
+        assert_eq!(mainnet.network, "mainnet");
+        assert_eq!(mainnet.chain_type, ChainType::Solana);
+        assert_eq!(mainnet.commitment, CommitmentLevel::Finalized);
+        assert_eq!(mainnet.block_batch_size, 10);
+        assert_eq!(mainnet.catch_up_batch_size, 100);
+        assert_eq!(mainnet.url(), "http://194.163.186.82:8899");
+        assert_eq!(mainnet.providers.len(), 2);
+        assert_eq!(mainnet.providers[0].weight, 2);
+        assert_eq!(mainnet.providers[0].rate_limit, None);
+        assert_eq!(mainnet.providers[1].label, "solana");
+        assert_eq!(mainnet.providers[1].weight, 1);
+        assert_eq!(mainnet.providers[1].rate_limit, Some(10));
+    }
+
+    #[test]
+    fn defaults_are_applied() {
+        let config = parse(&localnet("chain_type = \"solana\"")).unwrap();
+        assert_eq!(config.listen, DEFAULT_LISTEN);
+        let localnet = &config.networks["localnet"];
+        assert_eq!(localnet.network, "localnet");
+        assert_eq!(localnet.commitment, CommitmentLevel::Finalized);
+        assert_eq!(localnet.block_batch_size, 10);
+        assert_eq!(localnet.catch_up_batch_size, 100);
+        assert_eq!(localnet.max_slot_lag, 50);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert_error(
+            &format!(
+                "listen_address = \"0.0.0.0:50051\"\n{}",
+                localnet("chain_type = \"solana\"")
+            ),
+            "unknown field `listen_address`",
+        );
+        assert_error(
+            &localnet("chain_type = \"solana\"\nbatch_size = 10"),
+            "unknown field `batch_size`",
+        );
+    }
+
+    #[test]
+    fn deprecated_commitment_is_rejected() {
+        assert_error(
+            &localnet("chain_type = \"solana\"\ncommitment = \"recent\""),
+            "deprecated commitment",
+        );
+    }
+
+    #[test]
+    fn zero_batch_size_is_rejected() {
+        assert_error(
+            &localnet("chain_type = \"solana\"\nblock_batch_size = 0"),
+            "must be bigger than 0",
+        );
+        assert_error(
+            &localnet("chain_type = \"solana\"\ncatch_up_batch_size = 0"),
+            "must be bigger than 0",
+        );
+    }
+
+    #[test]
+    fn only_solana_is_read() {
+        assert_error(
+            &localnet("chain_type = \"ethereum\""),
+            "the chain reader only reads Solana",
+        );
+        assert_error(
+            &localnet("chain_type = \"bitcoin\""),
+            "unknown variant `bitcoin`",
+        );
+    }
+
+    #[test]
+    fn invalid_listen_address_is_rejected() {
+        assert_error(
+            &format!(
+                "listen = \"localhost\"\n{}",
+                localnet("chain_type = \"solana\"")
+            ),
+            "invalid listen address `localhost`",
+        );
+    }
+
+    #[test]
+    fn no_network_is_rejected() {
+        assert_error(
+            "listen = \"0.0.0.0:50051\"\nnetwork = {}",
+            "no network is configured",
+        );
+    }
+}
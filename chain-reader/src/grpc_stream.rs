@@ -1,6 +1,9 @@
 use crate::solana_chain;
 use log::{error, info};
-use massbit::firehose::bstream::{stream_server::Stream, BlockRequest, BlockResponse, ChainType};
+use massbit::firehose::bstream::{
+    stream_server::Stream, BlockRequest, BlockResponse, ChainHeadRequest, ChainHeadResponse,
+    ChainType,
+};
 use massbit_chain_solana::data_type::SolanaFilter;
 use massbit_common::NetworkType;
 use solana_client::rpc_client::RpcClient;
@@ -63,4 +66,20 @@ impl Stream for StreamService {
 
         Ok(Response::new(ReceiverStream::new(rx)))
     }
+
+    async fn chain_head(
+        &self,
+        request: Request<ChainHeadRequest>,
+    ) -> Result<Response<ChainHeadResponse>, Status> {
+        let network: NetworkType = request.get_ref().network.clone();
+        let client = match self.solana_adaptors.get(&network) {
+            Some(client) => client.clone(),
+            None => return Err(Status::not_found(format!("unknown network {}", network))),
+        };
+        let slot = task::spawn_blocking(move || client.get_slot())
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?
+            .map_err(|err| Status::unavailable(err.to_string()))?;
+        Ok(Response::new(ChainHeadResponse { block_number: slot }))
+    }
 }
@@ -1,26 +1,68 @@
-use crate::stream_service::BlockInfo;
+use crate::account_updates::{fetch_account_updates, select_account_updates};
+use crate::solana_chain_adapter::{
+    ChainAdapter, BLOCK_AVAILABLE_MARGIN, GET_NEW_SLOT_DELAY_MS, RPC_BLOCK_ENCODING,
+};
+use crate::solana_providers::SolanaNetworkProviders;
+use crate::stream_service::{BlockInfo, QUEUE_BUFFER};
 use chain_solana::types::ConfirmedBlockWithSlot;
-use log::{debug, info};
-use massbit::prelude::Future;
-use massbit_chain_solana::data_type::{ExtBlock, SolanaBlock, SolanaFilter};
+use log::{debug, error, info, warn};
+use massbit::components::metrics::{self, IntCounterVec, IntGauge, IntGaugeVec};
+use massbit::prelude::{lazy_static, Future};
+use massbit_chain_solana::data_type::{AccountUpdate, ExtBlock, SolanaBlock, SolanaFilter};
+use massbit_common::prelude::tokio::time::{sleep, Duration};
 use massbit_grpc::firehose::bstream::BlockResponse;
+use massbit_grpc::firehose::payload::PayloadFormat;
 use solana_transaction_status::ConfirmedBlock;
-use std::collections::{HashMap, HashSet};
-use std::iter::FromIterator;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::task;
 use tonic::Status;
 
 const VERSION: &str = "1.7.0";
-#[derive(Default)]
+/// Most live blocks held back for an indexer which is catching up. Past it they are dropped
+/// and the catch up fetches them itself.
+const MAX_PENDING_BLOCKS: usize = 1000;
+
+lazy_static! {
+    static ref BROADCAST_QUEUE_DEPTH: IntGaugeVec = metrics::int_gauge_vec(
+        "chain_reader_broadcast_queue_depth",
+        "Number of blocks waiting in the broadcast buffer for a preceding slot",
+        &["network"]
+    );
+    static ref SUBSCRIBER_LAG: IntGaugeVec = metrics::int_gauge_vec(
+        "chain_reader_subscriber_lag",
+        "Number of block responses an indexer has not received yet",
+        &["network", "indexer"]
+    );
+    static ref SKIPPED_ACCOUNT_UPDATES: IntCounterVec = metrics::int_counter_vec(
+        "chain_reader_skipped_account_updates",
+        "Number of live blocks delivered without their accounts",
+        &["network"]
+    );
+}
+
 pub struct BlockBuffer {
     queue: HashMap<u64, ConfirmedBlockWithSlot>,
     //The first block is requested from network
     expected_slot: u64,
+    //Shared with the ChainAdapter so that it doesn't fetch too far ahead of the broadcast
+    delivered_slot: Arc<AtomicU64>,
+    queue_depth: IntGauge,
 }
 
 impl BlockBuffer {
+    pub fn new(network: &str, delivered_slot: Arc<AtomicU64>) -> Self {
+        BlockBuffer {
+            queue: HashMap::default(),
+            expected_slot: 0,
+            delivered_slot,
+            queue_depth: BROADCAST_QUEUE_DEPTH.with_label_values(&[network]),
+        }
+    }
+    ///Blocks are released in slot order, skipped slots arrive without a block
+    ///and only move the expected slot forward.
     fn handle_incoming_block(
         &mut self,
         block_info: BlockInfo,
@@ -30,23 +72,33 @@ impl BlockBuffer {
                 //Current block_slot
                 debug!("*** handle_incoming_block receive block: {}", &slot);
                 self.expected_slot = slot;
+                self.delivered_slot.store(slot, Ordering::SeqCst);
                 None
             }
             BlockInfo::ConfirmBlockWithSlot(confirm_block) => {
                 debug!("*** Receive block: {}", &confirm_block.block_slot);
-                if confirm_block.block_slot != self.expected_slot {
-                    self.queue.insert(confirm_block.block_slot, confirm_block);
+                if confirm_block.block_slot < self.expected_slot {
+                    //Already delivered
+                    return None;
+                }
+                self.queue.insert(confirm_block.block_slot, confirm_block);
+                let mut blocks = vec![];
+                let mut key = self.expected_slot;
+                while let Some(block) = self.queue.remove(&key) {
+                    blocks.push(block);
+                    key += 1;
+                }
+                self.expected_slot = key;
+                self.delivered_slot.store(key, Ordering::SeqCst);
+                self.queue_depth.set(self.queue.len() as i64);
+                if blocks.is_empty() {
+                    debug!(
+                        "Waiting for slot {}, {} blocks buffered",
+                        self.expected_slot,
+                        self.queue.len()
+                    );
                     None
                 } else {
-                    let mut blocks = vec![];
-                    blocks.push(confirm_block);
-                    //Todo: Handle case block slot is not continuous
-                    let mut key = self.expected_slot + 1;
-                    while let Some(block) = self.queue.remove(&key) {
-                        blocks.push(block);
-                        key += 1;
-                    }
-                    self.expected_slot = key;
                     Some(blocks)
                 }
             }
@@ -56,21 +108,67 @@ impl BlockBuffer {
 pub struct IndexerInfo {
     hash: String, //Indexer hash
     filter: SolanaFilter,
-    filter_hashes: HashSet<String>, //For quickly filter ConfirmedBlock
+    //Format of the payload the indexer asked for
+    format: PayloadFormat,
     sender: Sender<Result<BlockResponse, Status>>,
+    //Live blocks held back while the indexer catches up, with their accounts.
+    //None once it receives live blocks
+    pending: Option<Vec<(ConfirmedBlockWithSlot, Vec<AccountUpdate>)>>,
+    //First slot broadcast after the indexer was registered
+    live_from: Option<u64>,
 }
 pub struct IndexerBroadcast {
+    network: String,
+    //Fetch the accounts of the indexers with account owners
+    providers: Arc<SolanaNetworkProviders>,
     block_receiver: Receiver<BlockInfo>,
     block_buffer: BlockBuffer,
-    ind_senders: Mutex<Vec<IndexerInfo>>,
+    ind_senders: Arc<Mutex<Vec<IndexerInfo>>>,
+}
+
+/// Registers indexers with the broadcast of a network. It only locks the list of
+/// indexers, never while the broadcast waits for the network or for an indexer.
+#[derive(Clone)]
+pub struct IndexerRegistry {
+    ind_senders: Arc<Mutex<Vec<IndexerInfo>>>,
+}
+
+/// Keeps the transactions of `block` which match `filter`. Transactions are checked by
+/// reference so only the matching ones are cloned.
+/// Returns None if there are none.
+fn filter_block(
+    filter: &SolanaFilter,
+    block: &ConfirmedBlockWithSlot,
+) -> Option<ConfirmedBlockWithSlot> {
+    let transactions: Vec<_> = block
+        .block
+        .as_ref()?
+        .transactions
+        .iter()
+        .filter(|tran| filter.is_match(tran))
+        .cloned()
+        .collect();
+    if transactions.is_empty() {
+        return None;
+    }
+    let mut filtered_block = block.cheap_clone();
+    filtered_block.block.as_mut().unwrap().transactions = transactions;
+    Some(filtered_block)
 }
 
 impl IndexerBroadcast {
-    pub fn new(receiver: Receiver<BlockInfo>) -> Self {
+    pub fn new(
+        network: &str,
+        providers: Arc<SolanaNetworkProviders>,
+        receiver: Receiver<BlockInfo>,
+        delivered_slot: Arc<AtomicU64>,
+    ) -> Self {
         IndexerBroadcast {
+            network: network.to_string(),
+            providers,
             block_receiver: receiver,
-            block_buffer: BlockBuffer::default(),
-            ind_senders: Mutex::new(vec![]),
+            block_buffer: BlockBuffer::new(network, delivered_slot),
+            ind_senders: Arc::new(Mutex::new(vec![])),
         }
     }
     ///Init broadcast thread
@@ -88,95 +186,122 @@ impl IndexerBroadcast {
             }
         }
     }
-    ///Call from main thread to add new indexer
-    pub fn register_indexer(
-        &mut self,
-        hash: &String,
-        encoded_filter: &Vec<u8>,
-        indexer_sender: Sender<Result<BlockResponse, Status>>,
-    ) {
-        // Decode filter
-        let filter: SolanaFilter = serde_json::from_slice(&encoded_filter).unwrap_or_default();
-        let mut filter_hashes = HashSet::default();
-        filter.keys.iter().for_each(|key| {
-            filter_hashes.insert(key.to_string());
-        });
-        ///Create block buffer to store received block from ChainDispatcher
-        let mut senders = self.ind_senders.lock().unwrap();
-        senders.push(IndexerInfo {
-            hash: hash.clone(),
-            filter,
-            filter_hashes,
-            sender: indexer_sender,
-        });
+    pub fn registry(&self) -> IndexerRegistry {
+        IndexerRegistry {
+            ind_senders: self.ind_senders.clone(),
+        }
     }
     async fn broadcast_blocks(&mut self, block_with_slots: Vec<ConfirmedBlockWithSlot>) {
         debug!("*** broadcast_blocks");
-        let mut filtered_blocks: HashMap<String, Vec<ConfirmedBlockWithSlot>> = HashMap::default();
-        let mut indexers = self.ind_senders.lock().unwrap();
+        let first_slot = block_with_slots.first().map(|block| block.block_slot);
         //Remove stop indexers
-        indexers.retain(|indexer| !indexer.sender.is_closed());
-        block_with_slots
-            .iter()
-            .filter(|block| block.block.is_some())
-            .for_each(|block| {
-                let ref_block = block.block.as_ref().unwrap();
-                //Clone ConfirmedBlock for each indexer with empty transactions
-                let mut indexer_blocks: HashMap<_, _> = HashMap::from_iter(
-                    indexers
-                        .iter()
-                        .map(|indexer| (indexer.hash.clone(), block.cheap_clone())),
-                );
-                //Iterate throw transactions and clone it for interested indexer
-                ref_block.transactions.iter().for_each(|tran| {
-                    let keys = tran
-                        .transaction
-                        .message
-                        .account_keys
-                        .iter()
-                        .map(|key| key.to_string())
-                        .collect::<Vec<String>>();
-                    indexers.iter().for_each(|indexer| {
-                        if keys.iter().any(|key| indexer.filter_hashes.contains(key)) {
-                            indexer_blocks
-                                .get_mut(&indexer.hash)
-                                .unwrap()
-                                .block
-                                .as_mut()
-                                .unwrap()
-                                .transactions
-                                .push(tran.clone());
+        let network = self.network.as_str();
+        let (known, filters) = {
+            let mut indexers = self.ind_senders.lock().unwrap();
+            indexers.retain(|indexer| {
+                let closed = indexer.sender.is_closed();
+                if closed {
+                    let _ = SUBSCRIBER_LAG.remove_label_values(&[network, &indexer.hash]);
+                }
+                !closed
+            });
+            let filters: Vec<SolanaFilter> = indexers
+                .iter()
+                .map(|indexer| indexer.filter.clone())
+                .collect();
+            (indexers.len(), filters)
+        };
+        //The accounts of a block are fetched once for all the indexers, without holding
+        //the lock. A block whose accounts can't be fetched is delivered without them.
+        let mut block_accounts = Vec::with_capacity(block_with_slots.len());
+        for block in &block_with_slots {
+            match fetch_account_updates(&self.providers, &filters, block).await {
+                Ok(updates) => block_accounts.push(updates),
+                Err(err) => {
+                    error!(
+                        "Skipping the account triggers of block {}: {:?}",
+                        block.block_slot, err
+                    );
+                    SKIPPED_ACCOUNT_UPDATES.with_label_values(&[network]).inc();
+                    block_accounts.push(vec![]);
+                }
+            }
+        }
+        //Responses are sent after the lock is released, an indexer which doesn't keep up
+        //must not block the registration of other indexers
+        let mut responses = vec![];
+        {
+            let mut indexers = self.ind_senders.lock().unwrap();
+            //Indexers are only removed above, the ones registered since then are at the end.
+            //They did not ask for these blocks: they were on their way before.
+            for indexer in indexers.iter_mut().take(known) {
+                let blocks: Vec<(ConfirmedBlockWithSlot, Vec<AccountUpdate>)> = block_with_slots
+                    .iter()
+                    .zip(&block_accounts)
+                    .filter_map(|(block, updates)| {
+                        let block = filter_block(&indexer.filter, block)?;
+                        let updates = select_account_updates(updates, &indexer.filter, &block);
+                        Some((block, updates))
+                    })
+                    .collect();
+                match indexer.pending.as_mut() {
+                    //Indexer is still catching up, keep blocks until it is done
+                    Some(pending) => {
+                        if pending.len() + blocks.len() > MAX_PENDING_BLOCKS {
+                            // The catch up goes on up to the slot before the current blocks
+                            pending.clear();
+                            indexer.live_from = None;
                         }
-                    });
-                });
-                indexer_blocks.into_iter().for_each(|(hash, block)| {
-                    if block.block.as_ref().unwrap().transactions.len() > 0 {
-                        filtered_blocks
-                            .entry(hash)
-                            .or_insert_with(Vec::new)
-                            .push(block);
+                        indexer.live_from = indexer.live_from.or(first_slot);
+                        pending.extend(blocks);
                     }
-                });
+                    None => {
+                        if !blocks.is_empty() {
+                            let block_response =
+                                Self::create_block_response(blocks, indexer.format);
+                            responses.push((indexer.sender.clone(), block_response));
+                        }
+                    }
+                }
+                //Live blocks held back during a catch up, the queued responses are
+                //added once they are sent
+                let held_back = indexer.pending.as_ref().map_or(0, |pending| pending.len());
+                let queued = QUEUE_BUFFER - indexer.sender.capacity();
+                SUBSCRIBER_LAG
+                    .with_label_values(&[network, &indexer.hash])
+                    .set((queued + held_back) as i64);
+            }
+        }
+        let mut failed = vec![];
+        for (sender, block_response) in responses {
+            debug!("*** GRPC Send block_response");
+            if sender.send(Ok(block_response)).await.is_err() {
+                failed.push(sender);
+            }
+        }
+        //The stream of the indexer is closed
+        if !failed.is_empty() {
+            let mut indexers = self.ind_senders.lock().unwrap();
+            indexers.retain(|indexer| {
+                let closed = failed
+                    .iter()
+                    .any(|sender| sender.same_channel(&indexer.sender));
+                if closed {
+                    let _ = SUBSCRIBER_LAG.remove_label_values(&[network, &indexer.hash]);
+                }
+                !closed
             });
-        for indexer in indexers.iter() {
-            if let Some(blocks) = filtered_blocks.remove(&indexer.hash) {
-                let block_response = Self::create_block_response(blocks);
-                debug!("*** GRPC Send block_response");
-                indexer.sender.send(Ok(block_response)).await;
-            }
-        }
-        // indexers.iter().for_each(|indexer| {
-        //     if let Some(blocks) = filtered_blocks.remove(&indexer.hash) {
-        //         let block_response = Self::create_block_response(blocks);
-        //         debug!("*** GRPC Send block_response");
-        //         indexer.sender.send(Ok(block_response)).await;
-        //     }
-        // });
-    }
-    fn create_block_response(blocks: Vec<ConfirmedBlockWithSlot>) -> BlockResponse {
+        }
+    }
+    /// Encodes `blocks` with their accounts in `format`, or in JSON if they can not be
+    /// encoded in `format`.
+    fn create_block_response(
+        blocks: Vec<(ConfirmedBlockWithSlot, Vec<AccountUpdate>)>,
+        format: PayloadFormat,
+    ) -> BlockResponse {
         let ext_blocks = blocks
             .into_iter()
-            .map(|block_with_slot| {
+            .map(|(block_with_slot, account_updates)| {
                 let ConfirmedBlockWithSlot { block_slot, block } = block_with_slot;
                 debug!("Add block: {}", &block_slot);
                 let ref_block = block.as_ref().unwrap();
@@ -192,12 +317,387 @@ impl IndexerBroadcast {
                     block_number: block_slot,
                     block: block.unwrap(),
                     list_log_messages,
+                    account_updates,
                 }
             })
             .collect::<Vec<ExtBlock>>();
+        let (format, payload) = match format.encode(&ext_blocks) {
+            Ok(payload) => (format, payload),
+            Err(err) => {
+                warn!("Cannot encode blocks as {:?}: {:?}", &format, err);
+                (
+                    PayloadFormat::default(),
+                    serde_json::to_vec(&ext_blocks).unwrap(),
+                )
+            }
+        };
         BlockResponse {
             version: VERSION.to_string(),
-            payload: serde_json::to_vec(&ext_blocks).unwrap(),
+            payload,
+            encoding: format.encoding as i32,
+            compression: format.compression as i32,
         }
     }
 }
+
+impl IndexerRegistry {
+    ///Call from main thread to add new indexer.
+    ///If `start_slot` is given, the indexer only receives live blocks once the returned
+    ///`CatchUp` has delivered the blocks from `start_slot` on.
+    pub fn register_indexer(
+        &self,
+        hash: &String,
+        filter: SolanaFilter,
+        format: PayloadFormat,
+        start_slot: Option<u64>,
+        indexer_sender: Sender<Result<BlockResponse, Status>>,
+    ) -> Option<CatchUp> {
+        let catch_up = start_slot.map(|next_slot| CatchUp {
+            indexers: self.ind_senders.clone(),
+            sender: indexer_sender.clone(),
+            filter: filter.clone(),
+            format,
+            next_slot,
+        });
+        ///Create block buffer to store received block from ChainDispatcher
+        let mut senders = self.ind_senders.lock().unwrap();
+        senders.push(IndexerInfo {
+            hash: hash.clone(),
+            filter,
+            format,
+            sender: indexer_sender,
+            pending: start_slot.map(|_| vec![]),
+            live_from: None,
+        });
+        catch_up
+    }
+}
+
+/// Delivers the blocks an indexer missed, from its requested start slot up to the first
+/// slot of the live broadcast, then hands the indexer over to the live broadcast.
+pub struct CatchUp {
+    indexers: Arc<Mutex<Vec<IndexerInfo>>>,
+    sender: Sender<Result<BlockResponse, Status>>,
+    filter: SolanaFilter,
+    format: PayloadFormat,
+    //Next slot to deliver to the indexer
+    next_slot: u64,
+}
+
+impl CatchUp {
+    /// Runs `f` on the registered indexer. Returns None if the indexer is gone.
+    fn with_indexer<R>(&self, f: impl FnOnce(&mut IndexerInfo) -> R) -> Option<R> {
+        let mut indexers = self.indexers.lock().unwrap();
+        indexers
+            .iter_mut()
+            .find(|indexer| indexer.sender.same_channel(&self.sender))
+            .map(f)
+    }
+
+    /// Fetches `batch_size` slots at once
+    pub async fn run(mut self, providers: Arc<SolanaNetworkProviders>, batch_size: u64) {
+        info!("Catching up from slot {}", self.next_slot);
+        loop {
+            let live_from = match self.with_indexer(|indexer| indexer.live_from) {
+                Some(live_from) => live_from,
+                None => return,
+            };
+            if let Some(live_from) = live_from {
+                if self.next_slot >= live_from {
+                    break;
+                }
+            }
+            // Until the first live block arrives, fetch as far as the chain is finalized
+            let last_slot = match live_from {
+                Some(live_from) => live_from - 1,
+                None => match providers.get_slot_async().await {
+                    Ok(slot) => slot.saturating_sub(BLOCK_AVAILABLE_MARGIN),
+                    Err(err) => {
+                        warn!("Get slot error: {:?}", err);
+                        sleep(Duration::from_millis(GET_NEW_SLOT_DELAY_MS)).await;
+                        continue;
+                    }
+                },
+            }
+            .min(self.next_slot + batch_size - 1);
+            if last_slot < self.next_slot {
+                sleep(Duration::from_millis(GET_NEW_SLOT_DELAY_MS)).await;
+                continue;
+            }
+            if !self.deliver_range(&providers, last_slot).await {
+                sleep(Duration::from_millis(GET_NEW_SLOT_DELAY_MS)).await;
+            }
+            if self.sender.is_closed() {
+                return;
+            }
+        }
+        self.go_live().await;
+    }
+
+    /// Sends the blocks from `next_slot` to `last_slot`, without their accounts. Stops at
+    /// the first block which can not be fetched so that no block is skipped. Returns whether
+    /// all were sent.
+    async fn deliver_range(
+        &mut self,
+        providers: &Arc<SolanaNetworkProviders>,
+        last_slot: u64,
+    ) -> bool {
+        let next_slot = self.next_slot;
+        let slots = match providers
+            .call_async("getBlocks", move |client| {
+                client.get_blocks(next_slot, Some(last_slot))
+            })
+            .await
+        {
+            Ok(slots) => slots,
+            Err(err) => {
+                warn!(
+                    "Cannot get blocks from {} to {}: {:?}",
+                    self.next_slot, last_slot, err
+                );
+                return false;
+            }
+        };
+        let mut blocks = vec![];
+        let mut next_slot = last_slot + 1;
+        for slot in slots {
+            match providers
+                .call_async("getBlock", move |client| {
+                    client.get_block_with_encoding(slot, RPC_BLOCK_ENCODING)
+                })
+                .await
+            {
+                Ok(block) => {
+                    let block = ConfirmedBlockWithSlot {
+                        block_slot: slot,
+                        block: Some(ChainAdapter::decode_encoded_block(block)),
+                    };
+                    // The nodes no longer serve the accounts at the slot of the block,
+                    // account triggers are live only
+                    if let Some(block) = filter_block(&self.filter, &block) {
+                        blocks.push((block, vec![]));
+                    }
+                }
+                Err(err) => {
+                    warn!("Cannot get block {}: {:?}", slot, err);
+                    next_slot = slot;
+                    break;
+                }
+            }
+        }
+        if !blocks.is_empty() {
+            let block_response = IndexerBroadcast::create_block_response(blocks, self.format);
+            if self.sender.send(Ok(block_response)).await.is_err() {
+                return false;
+            }
+        }
+        let completed = next_slot == last_slot + 1;
+        self.next_slot = next_slot;
+        completed
+    }
+
+    /// Sends the live blocks held back during the catch up. Once there are none left the
+    /// indexer receives live blocks directly.
+    async fn go_live(self) {
+        let next_slot = self.next_slot;
+        loop {
+            let blocks = self.with_indexer(|indexer| {
+                // Blocks before `next_slot` were already delivered by the catch up
+                let blocks: Vec<(ConfirmedBlockWithSlot, Vec<AccountUpdate>)> = indexer
+                    .pending
+                    .take()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|(block, _)| block.block_slot >= next_slot)
+                    .collect();
+                if !blocks.is_empty() {
+                    indexer.pending = Some(vec![]);
+                }
+                blocks
+            });
+            match blocks {
+                Some(blocks) if !blocks.is_empty() => {
+                    let block_response =
+                        IndexerBroadcast::create_block_response(blocks, self.format);
+                    if self.sender.send(Ok(block_response)).await.is_err() {
+                        return;
+                    }
+                }
+                _ => break,
+            }
+        }
+        info!("Finished catching up at slot {}", next_slot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ChainConfig;
+    use crate::solana_providers::ProviderConfig;
+    use massbit::firehose::bstream::ChainType;
+    use solana_sdk::commitment_config::CommitmentLevel;
+    use solana_transaction_status::TransactionWithStatusMeta;
+    use tokio::sync::mpsc;
+
+    fn providers() -> Arc<SolanaNetworkProviders> {
+        let config = ChainConfig {
+            network: "test".to_string(),
+            chain_type: ChainType::Solana,
+            commitment: CommitmentLevel::Finalized,
+            block_batch_size: 10,
+            catch_up_batch_size: 100,
+            max_slot_lag: 50,
+            providers: vec![ProviderConfig::new("test", "http://127.0.0.1:8899")],
+        };
+        Arc::new(SolanaNetworkProviders::new(&config))
+    }
+
+    /// A broadcast without RPC requests: the indexers have no account owners
+    fn broadcast() -> IndexerBroadcast {
+        let (_, receiver) = mpsc::channel(1);
+        IndexerBroadcast::new("test", providers(), receiver, Arc::new(AtomicU64::new(0)))
+    }
+
+    /// A block with one transaction, which matches the empty filter
+    fn block(slot: u64) -> ConfirmedBlockWithSlot {
+        ConfirmedBlockWithSlot {
+            block_slot: slot,
+            block: Some(ConfirmedBlock {
+                previous_blockhash: String::new(),
+                blockhash: String::new(),
+                parent_slot: slot.saturating_sub(1),
+                transactions: vec![TransactionWithStatusMeta {
+                    transaction: Default::default(),
+                    meta: Some(Default::default()),
+                }],
+                rewards: vec![],
+                block_time: None,
+                block_height: None,
+            }),
+        }
+    }
+
+    fn blocks(slots: std::ops::Range<u64>) -> Vec<ConfirmedBlockWithSlot> {
+        slots.map(block).collect()
+    }
+
+    fn register(
+        broadcast: &mut IndexerBroadcast,
+        start_slot: u64,
+    ) -> (CatchUp, Receiver<Result<BlockResponse, Status>>) {
+        let (sender, receiver) = mpsc::channel(QUEUE_BUFFER);
+        let catch_up = broadcast
+            .registry()
+            .register_indexer(
+                &"indexer".to_string(),
+                SolanaFilter::default(),
+                PayloadFormat::default(),
+                Some(start_slot),
+                sender,
+            )
+            .unwrap();
+        (catch_up, receiver)
+    }
+
+    /// The slots of the blocks of the responses received so far
+    fn received(receiver: &mut Receiver<Result<BlockResponse, Status>>) -> Vec<Vec<u64>> {
+        let mut slots = vec![];
+        while let Ok(response) = receiver.try_recv() {
+            let response = response.unwrap();
+            let format = PayloadFormat::from_i32(response.encoding, response.compression).unwrap();
+            let blocks: Vec<ExtBlock> = format.decode(&response.payload).unwrap();
+            slots.push(blocks.iter().map(|block| block.block_number).collect());
+        }
+        slots
+    }
+
+    fn pending_slots(catch_up: &CatchUp) -> Option<Vec<u64>> {
+        catch_up
+            .with_indexer(|indexer| {
+                indexer
+                    .pending
+                    .as_ref()
+                    .map(|pending| pending.iter().map(|(block, _)| block.block_slot).collect())
+            })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn catch_up_finishing_before_live_blocks_arrive() {
+        let mut broadcast = broadcast();
+        let (catch_up, mut receiver) = register(&mut broadcast, 10);
+
+        catch_up.go_live().await;
+        assert!(received(&mut receiver).is_empty());
+
+        // The live blocks go to the indexer directly
+        broadcast.broadcast_blocks(blocks(10..12)).await;
+        broadcast.broadcast_blocks(blocks(12..13)).await;
+        assert_eq!(vec![vec![10, 11], vec![12]], received(&mut receiver));
+    }
+
+    #[tokio::test]
+    async fn catch_up_finishing_after_live_blocks_arrive() {
+        let mut broadcast = broadcast();
+        let (mut catch_up, mut receiver) = register(&mut broadcast, 5);
+
+        // Live blocks are held back while the indexer catches up
+        broadcast.broadcast_blocks(blocks(8..13)).await;
+        assert!(received(&mut receiver).is_empty());
+        assert_eq!(Some(vec![8, 9, 10, 11, 12]), pending_slots(&catch_up));
+        assert_eq!(
+            Some(Some(8)),
+            catch_up.with_indexer(|indexer| indexer.live_from)
+        );
+
+        // The catch up delivered up to slot 9, which is past the first live
+        // block: the held back blocks from slot 10 on are sent, and the
+        // indexer then receives live blocks directly
+        catch_up.next_slot = 10;
+        catch_up.run(providers(), 100).await;
+        assert_eq!(vec![vec![10, 11, 12]], received(&mut receiver));
+
+        broadcast.broadcast_blocks(blocks(13..14)).await;
+        assert_eq!(vec![vec![13]], received(&mut receiver));
+    }
+
+    #[tokio::test]
+    async fn closed_indexers_are_removed() {
+        let mut broadcast = broadcast();
+        let (catch_up, receiver) = register(&mut broadcast, 10);
+        catch_up.go_live().await;
+        drop(receiver);
+
+        broadcast.broadcast_blocks(blocks(10..12)).await;
+        assert!(broadcast.ind_senders.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn too_many_pending_blocks_reset_live_from() {
+        let mut broadcast = broadcast();
+        let (mut catch_up, mut receiver) = register(&mut broadcast, 0);
+
+        let first = 100;
+        let last = first + MAX_PENDING_BLOCKS as u64;
+        broadcast.broadcast_blocks(blocks(first..last)).await;
+        assert_eq!(MAX_PENDING_BLOCKS, pending_slots(&catch_up).unwrap().len());
+        assert_eq!(
+            Some(Some(first)),
+            catch_up.with_indexer(|indexer| indexer.live_from)
+        );
+
+        // The held back blocks are dropped, the catch up goes on up to the
+        // blocks which overflowed
+        broadcast.broadcast_blocks(blocks(last..last + 2)).await;
+        assert_eq!(Some(vec![last, last + 1]), pending_slots(&catch_up));
+        assert_eq!(
+            Some(Some(last)),
+            catch_up.with_indexer(|indexer| indexer.live_from)
+        );
+
+        catch_up.next_slot = last;
+        catch_up.go_live().await;
+        assert_eq!(vec![vec![last, last + 1]], received(&mut receiver));
+    }
+}
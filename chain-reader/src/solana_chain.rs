@@ -1,7 +1,5 @@
-use crate::CONFIG;
-
 use log::{debug, info, warn};
-use massbit::firehose::bstream::{BlockResponse, ChainType};
+use massbit::firehose::bstream::{BlockResponse, ChainType, PayloadCompression, PayloadEncoding};
 use massbit::prelude::serde_json::json;
 use massbit::prelude::tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use massbit::prelude::tokio::time::sleep;
@@ -244,7 +242,6 @@ pub async fn loop_get_block(
     );
     let filter = Arc::new(filter.clone());
 
-    let _config = CONFIG.get_chain_config(&CHAIN_TYPE, &network).unwrap();
     // let websocket_url = config.ws.clone();
     // let (mut _subscription_client, receiver) =
     //     PubsubClient::slot_subscribe(&websocket_url).unwrap();
@@ -455,6 +452,8 @@ fn _create_generic_block(blocks: &Vec<Block>) -> BlockResponse {
     let generic_data = BlockResponse {
         version: VERSION.to_string(),
         payload: serde_json::to_vec(blocks).unwrap(),
+        encoding: PayloadEncoding::Json as i32,
+        compression: PayloadCompression::Uncompressed as i32,
     };
     generic_data
 }
@@ -471,6 +470,7 @@ fn _to_generic_block(blocks_with_number: Vec<(ConfirmedBlock, u64)>) -> BlockRes
                 block_number,
                 timestamp,
                 list_log_messages,
+                account_updates: vec![],
             }
         })
         .collect();
@@ -513,14 +513,6 @@ async fn get_block(
     }
 }
 
-// Helper function for direct call
-fn get_rpc_client(network: NetworkType) -> Arc<RpcClient> {
-    let config = CONFIG.get_chain_config(&CHAIN_TYPE, &network).unwrap();
-    let json_rpc_url = config.url.clone();
-    info!("Init Solana client, url: {}", json_rpc_url);
-    Arc::new(RpcClient::new(json_rpc_url.clone()))
-}
-
 fn get_account_info(client: Arc<RpcClient>, pubkey: &Pubkey) -> ClientResult<Account> {
     client.get_account(pubkey)
 }
@@ -1,55 +1,132 @@
-use crate::command::ChainConfig;
+use crate::config::ChainConfig;
+use crate::solana_providers::{SolanaNetworkProviders, SKIPPED_SLOT_ERROR};
 use crate::stream_service::BlockInfo;
 use chain_solana::types::ConfirmedBlockWithSlot;
 use log::{debug, info, warn};
+use massbit::components::metrics::{
+    self, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+};
 use massbit::firehose::bstream::{BlockRequest, BlockResponse};
-use massbit::prelude::{Arc, Duration};
+use massbit::prelude::{lazy_static, Arc, Duration};
 use massbit_chain_solana::data_type::{ExtBlock, SolanaBlock};
 use massbit_common::prelude::tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use massbit_common::prelude::tokio::time::{sleep, timeout};
-use solana_client::rpc_client::RpcClient;
 use solana_transaction_status::UiInstruction::{Compiled, Parsed};
 use solana_transaction_status::{
     ConfirmedBlock, EncodedConfirmedBlock, InnerInstructions, TransactionStatusMeta,
     TransactionTokenBalance, UiInnerInstructions, UiTransactionEncoding, UiTransactionTokenBalance,
 };
+use std::collections::HashSet;
 use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
 use tokio::sync::mpsc::{Receiver, Sender};
 
 const VERSION: &str = "1.7.0";
-const BLOCK_AVAILABLE_MARGIN: u64 = 100;
-const RPC_BLOCK_ENCODING: UiTransactionEncoding = UiTransactionEncoding::Base64;
+pub(crate) const BLOCK_AVAILABLE_MARGIN: u64 = 100;
+pub(crate) const RPC_BLOCK_ENCODING: UiTransactionEncoding = UiTransactionEncoding::Base64;
 const GET_BLOCK_TIMEOUT_SEC: u64 = 60;
-const BLOCK_BATCH_SIZE: u64 = 10;
-const GET_NEW_SLOT_DELAY_MS: u64 = 500;
+pub(crate) const GET_NEW_SLOT_DELAY_MS: u64 = 500;
 const TRANSACTION_BATCH_SIZE: usize = 100;
 // The max value is 1000
 const LIMIT_FILTER_RESULT: usize = 1000;
+const RETRY_MIN_DELAY_MS: u64 = 500;
+const RETRY_MAX_DELAY_MS: u64 = 30_000;
+// Max number of slots requested ahead of the last slot delivered to indexers
+const MAX_BUFFERED_SLOTS: u64 = 1000;
+
+lazy_static! {
+    static ref BLOCK_RETRIES: IntCounterVec = metrics::int_counter_vec(
+        "chain_reader_block_retries_total",
+        "Number of times the block of a slot was requested again after a failure",
+        &["network"]
+    );
+    static ref SKIPPED_SLOTS: IntCounterVec = metrics::int_counter_vec(
+        "chain_reader_skipped_slots_total",
+        "Number of slots skipped by the cluster, which have no block",
+        &["network"]
+    );
+    static ref CHAIN_HEAD_SLOT: IntGaugeVec = metrics::int_gauge_vec(
+        "chain_reader_chain_head_slot",
+        "Latest slot of the network",
+        &["network"]
+    );
+    static ref FETCHED_SLOT: IntGaugeVec = metrics::int_gauge_vec(
+        "chain_reader_latest_fetched_slot",
+        "Latest slot whose block was fetched from the network",
+        &["network"]
+    );
+    static ref RPC_DURATION: HistogramVec = metrics::histogram_vec(
+        "chain_reader_rpc_duration_seconds",
+        "Duration of the RPC calls to the network",
+        &["network", "method"]
+    );
+    static ref RPC_ERRORS: IntCounterVec = metrics::int_counter_vec(
+        "chain_reader_rpc_errors_total",
+        "Number of RPC calls to the network which failed",
+        &["network", "method"]
+    );
+}
+
+/// The metrics of the `ChainAdapter` of a network
+#[derive(Clone)]
+struct AdapterMetrics {
+    chain_head_slot: IntGauge,
+    fetched_slot: IntGauge,
+    get_slot_errors: IntCounter,
+    get_block_duration: Histogram,
+    get_block_errors: IntCounter,
+    block_retries: IntCounter,
+    skipped_slots: IntCounter,
+}
+
+impl AdapterMetrics {
+    fn new(network: &str) -> Self {
+        AdapterMetrics {
+            chain_head_slot: CHAIN_HEAD_SLOT.with_label_values(&[network]),
+            fetched_slot: FETCHED_SLOT.with_label_values(&[network]),
+            get_slot_errors: RPC_ERRORS.with_label_values(&[network, "getSlot"]),
+            get_block_duration: RPC_DURATION.with_label_values(&[network, "getBlock"]),
+            get_block_errors: RPC_ERRORS.with_label_values(&[network, "getBlock"]),
+            block_retries: BLOCK_RETRIES.with_label_values(&[network]),
+            skipped_slots: SKIPPED_SLOTS.with_label_values(&[network]),
+        }
+    }
+}
 
 pub struct ChainAdapter {
-    client: Arc<RpcClient>,
+    providers: Arc<SolanaNetworkProviders>,
+    //Number of slots requested at once
+    block_batch_size: u64,
     sem: Arc<Semaphore>,
     sender: Sender<BlockInfo>,
     last_block: Option<u64>,
+    delivered_slot: Arc<AtomicU64>,
+    metrics: AdapterMetrics,
 }
 impl ChainAdapter {
-    pub fn new(config: &ChainConfig, sender: Sender<BlockInfo>) -> Self {
-        info!("Init Solana client with url: {:?}", &config.url);
-        let client = Arc::new(RpcClient::new(config.url.clone()));
-        info!("Finished init Solana client");
+    pub fn new(
+        config: &ChainConfig,
+        providers: Arc<SolanaNetworkProviders>,
+        sender: Sender<BlockInfo>,
+        delivered_slot: Arc<AtomicU64>,
+    ) -> Self {
         ChainAdapter {
-            client,
-            sem: Arc::new(Semaphore::new(2 * BLOCK_BATCH_SIZE as usize)),
+            providers,
+            block_batch_size: config.block_batch_size,
+            sem: Arc::new(Semaphore::new(2 * config.block_batch_size as usize)),
             sender,
             last_block: None,
+            delivered_slot,
+            metrics: AdapterMetrics::new(&config.network),
         }
     }
     pub async fn start(&mut self) {
         let mut first_notification_to_broadcast = true;
         loop {
-            match self.client.get_slot() {
+            match self.providers.get_slot_async().await {
                 Ok(new_slot) => {
+                    self.metrics.chain_head_slot.set(new_slot as i64);
                     // Root is finalized block in Solana
                     let current_root = new_slot - BLOCK_AVAILABLE_MARGIN;
                     //Send current slot to broadcaster only in first time
@@ -61,7 +138,19 @@ impl ChainAdapter {
                     //info!("Root: {:?}",new_info.root);
                     match self.last_block {
                         Some(value_last_indexed_slot) => {
-                            if current_root == value_last_indexed_slot {
+                            // A provider may be a few slots behind the one asked before
+                            if current_root <= value_last_indexed_slot {
+                                sleep(Duration::from_millis(GET_NEW_SLOT_DELAY_MS)).await;
+                                continue;
+                            }
+                            //Don't run too far ahead of the broadcaster while it waits for a missing slot
+                            let delivered_slot = self.delivered_slot.load(Ordering::SeqCst);
+                            if value_last_indexed_slot >= delivered_slot + MAX_BUFFERED_SLOTS {
+                                warn!(
+                                    "Waiting for slot {} to be delivered, {} slots in flight",
+                                    delivered_slot,
+                                    value_last_indexed_slot - delivered_slot
+                                );
                                 sleep(Duration::from_millis(GET_NEW_SLOT_DELAY_MS)).await;
                                 continue;
                             }
@@ -71,68 +160,120 @@ impl ChainAdapter {
                                 current_root - value_last_indexed_slot
                             );
                             let number_get_slot =
-                                (current_root - value_last_indexed_slot).min(BLOCK_BATCH_SIZE);
-                            let block_range = value_last_indexed_slot
-                                ..(value_last_indexed_slot + number_get_slot);
+                                (current_root - value_last_indexed_slot).min(self.block_batch_size);
+                            let last_slot = value_last_indexed_slot + number_get_slot - 1;
+                            //Slots which are not in the list were skipped by the cluster and have no block
+                            let confirmed_slots: HashSet<u64> = match self
+                                .providers
+                                .call_async("getBlocks", move |client| {
+                                    client.get_blocks(value_last_indexed_slot, Some(last_slot))
+                                })
+                                .await
+                            {
+                                Ok(slots) => slots.into_iter().collect(),
+                                Err(err) => {
+                                    warn!(
+                                        "get_blocks error from {} to {}: {:?}",
+                                        value_last_indexed_slot, last_slot, err
+                                    );
+                                    sleep(Duration::from_millis(GET_NEW_SLOT_DELAY_MS)).await;
+                                    continue;
+                                }
+                            };
 
-                            for block_slot in block_range {
-                                let permit = Arc::clone(&self.sem).acquire_owned().await.unwrap();
-                                let client = self.client.clone();
-                                let sender = self.sender.clone();
-                                tokio::spawn(async move {
-                                    match timeout(
-                                        Duration::from_secs(GET_BLOCK_TIMEOUT_SEC),
-                                        Self::get_block(client, permit, block_slot),
-                                    )
-                                    .await
-                                    {
-                                        Ok(res) => {
-                                            info!(
-                                                "Finish tokio::spawn for getting block number: {:?}",
-                                                &block_slot
-                                            );
-                                            if let Ok(block) = res {
-                                                debug!(
-                                                    "*** ChainAdapter sending block: {}",
-                                                    block.block_slot
-                                                );
-                                                sender
-                                                    .send(BlockInfo::ConfirmBlockWithSlot(block))
-                                                    .await;
-                                            }
-                                        }
-                                        Err(err) => {
-                                            warn!(
-                                                "get_block timed out at block number {}",
-                                                &block_slot
-                                            );
-                                        }
-                                    }
-                                });
+                            for block_slot in value_last_indexed_slot..=last_slot {
+                                if !confirmed_slots.contains(&block_slot) {
+                                    debug!("*** ChainAdapter skipped slot: {}", block_slot);
+                                    self.metrics.skipped_slots.inc();
+                                    self.sender
+                                        .send(BlockInfo::ConfirmBlockWithSlot(
+                                            ConfirmedBlockWithSlot {
+                                                block_slot,
+                                                block: None,
+                                            },
+                                        ))
+                                        .await;
+                                    continue;
+                                }
+                                tokio::spawn(Self::fetch_block(
+                                    self.providers.clone(),
+                                    self.sem.clone(),
+                                    self.sender.clone(),
+                                    self.metrics.clone(),
+                                    block_slot,
+                                ));
                             }
-                            self.last_block = Some(value_last_indexed_slot + number_get_slot);
+                            self.last_block = Some(last_slot + 1);
                         }
                         _ => self.last_block = Some(current_root),
                     };
                 }
                 Err(err) => {
-                    eprintln!("Get slot error: {:?}", err);
+                    self.metrics.get_slot_errors.inc();
+                    warn!("Get slot error: {:?}", err);
                     sleep(Duration::from_millis(GET_NEW_SLOT_DELAY_MS)).await;
                     continue;
                 }
             }
         }
     }
+    /// Gets the block at `block_slot` and sends it to the broadcaster, retrying with backoff
+    /// until it succeeds. The broadcaster can't deliver later blocks until this one arrives.
+    async fn fetch_block(
+        providers: Arc<SolanaNetworkProviders>,
+        sem: Arc<Semaphore>,
+        sender: Sender<BlockInfo>,
+        metrics: AdapterMetrics,
+        block_slot: u64,
+    ) {
+        let mut delay_ms = RETRY_MIN_DELAY_MS;
+        loop {
+            let permit = Arc::clone(&sem).acquire_owned().await.unwrap();
+            match timeout(
+                Duration::from_secs(GET_BLOCK_TIMEOUT_SEC),
+                Self::get_block(providers.clone(), permit, &metrics, block_slot),
+            )
+            .await
+            {
+                Ok(Ok(block)) => {
+                    debug!("*** ChainAdapter sending block: {}", block.block_slot);
+                    if block_slot as i64 > metrics.fetched_slot.get() {
+                        metrics.fetched_slot.set(block_slot as i64);
+                    }
+                    sender.send(BlockInfo::ConfirmBlockWithSlot(block)).await;
+                    return;
+                }
+                Ok(Err(err)) => {
+                    warn!("get_block error at block number {}: {:?}", &block_slot, err);
+                }
+                Err(_) => {
+                    warn!("get_block timed out at block number {}", &block_slot);
+                }
+            }
+            if sender.is_closed() {
+                return;
+            }
+            metrics.block_retries.inc();
+            sleep(Duration::from_millis(delay_ms)).await;
+            delay_ms = (delay_ms * 2).min(RETRY_MAX_DELAY_MS);
+        }
+    }
     async fn get_block(
-        client: Arc<RpcClient>,
+        providers: Arc<SolanaNetworkProviders>,
         permit: OwnedSemaphorePermit,
+        metrics: &AdapterMetrics,
         block_number: u64,
     ) -> Result<ConfirmedBlockWithSlot, Box<dyn Error + Send + Sync + 'static>> {
         let _permit = permit;
         info!("Starting RPC get Block {}", block_number);
         let now = Instant::now();
-        let block = client.get_block_with_encoding(block_number, RPC_BLOCK_ENCODING);
+        let block = providers
+            .call_async("getBlock", move |client| {
+                client.get_block_with_encoding(block_number, RPC_BLOCK_ENCODING)
+            })
+            .await;
         let elapsed = now.elapsed();
+        metrics.get_block_duration.observe(elapsed.as_secs_f64());
         match block {
             Ok(block) => {
                 info!(
@@ -144,19 +285,22 @@ impl ChainAdapter {
                     block: Some(Self::decode_encoded_block(block)),
                 })
             }
-            _ => {
-                info!(
-                    "Cannot get RPC get Block: {:?}, Error:{:?}, time: {:?}",
-                    block_number, block, elapsed
-                );
-                //Err(format!("Error cannot get block").into())
-                //Todo: implement retry get missing block
-                //Send None to broadcaster -
+            Err(err) if err.to_string().contains(SKIPPED_SLOT_ERROR) => {
+                info!("Block {} was skipped, time: {:?}", block_number, elapsed);
+                metrics.skipped_slots.inc();
                 Ok(ConfirmedBlockWithSlot {
                     block_slot: block_number,
                     block: None,
                 })
             }
+            Err(err) => {
+                metrics.get_block_errors.inc();
+                info!(
+                    "Cannot get RPC get Block: {:?}, Error:{:?}, time: {:?}",
+                    block_number, err, elapsed
+                );
+                Err(err.into())
+            }
         }
     }
     // fn to_generic_block(
@@ -186,7 +330,7 @@ impl ChainAdapter {
     //         payload: serde_json::to_vec(&ext_blocks).unwrap(),
     //     }
     // }
-    fn decode_encoded_block(encoded_block: EncodedConfirmedBlock) -> ConfirmedBlock {
+    pub(crate) fn decode_encoded_block(encoded_block: EncodedConfirmedBlock) -> ConfirmedBlock {
         ConfirmedBlock {
             rewards: encoded_block.rewards,
             transactions: encoded_block
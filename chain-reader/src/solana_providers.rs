@@ -0,0 +1,555 @@
+//! The RPC nodes of a Solana network. Requests are spread over the healthy
+//! providers by weight and retried on another provider when one fails.
+//! Providers which keep failing, or whose slot lags behind the others, are
+//! evicted until they recover.
+
+use crate::config::ChainConfig;
+use anyhow::{anyhow, Context};
+use log::{info, warn};
+use massbit::components::metrics::{self, IntGauge, IntGaugeVec};
+use massbit::prelude::{lazy_static, Arc, Duration};
+use rand::Rng;
+use serde::Deserialize;
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::task;
+
+// Part of the RPC error message for a slot without block. The node answered
+// the request, so the error does not count against the provider.
+pub(crate) const SKIPPED_SLOT_ERROR: &str = "was skipped";
+// Errors in a row after which a provider is evicted
+const MAX_CONSECUTIVE_ERRORS: u32 = 3;
+// How long an erroring provider stays evicted before it gets another chance
+const EVICTION_TIME: Duration = Duration::from_secs(30);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+// How long to wait when every provider used up its rate limit
+const RATE_LIMIT_DELAY: Duration = Duration::from_millis(50);
+
+lazy_static! {
+    static ref PROVIDER_HEALTHY: IntGaugeVec = metrics::int_gauge_vec(
+        "chain_reader_provider_healthy",
+        "Whether the provider receives requests, 1 if it does and 0 if it is evicted",
+        &["network", "provider"]
+    );
+    static ref PROVIDER_SLOT: IntGaugeVec = metrics::int_gauge_vec(
+        "chain_reader_provider_slot",
+        "Slot reported by the provider at the last health check",
+        &["network", "provider"]
+    );
+}
+
+/// A provider of a network in the configuration file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    pub label: String,
+    pub url: String,
+    /// Share of the requests the provider receives relative to the others
+    #[serde(default = "one")]
+    pub weight: usize,
+    /// Max number of requests per second sent to the provider
+    #[serde(default)]
+    pub rate_limit: Option<u32>,
+}
+
+fn one() -> usize {
+    1
+}
+
+impl ProviderConfig {
+    pub fn new(label: &str, url: &str) -> Self {
+        ProviderConfig {
+            label: label.to_string(),
+            url: url.to_string(),
+            weight: 1,
+            rate_limit: None,
+        }
+    }
+
+    pub fn validate(providers: &[ProviderConfig], network: &str) -> Result<(), anyhow::Error> {
+        if providers.is_empty() {
+            return Err(anyhow!("network `{}` has no providers", network));
+        }
+        let mut labels = HashSet::new();
+        for provider in providers {
+            if !labels.insert(&provider.label) {
+                return Err(anyhow!(
+                    "provider `{}` of network `{}` is declared twice",
+                    provider.label,
+                    network
+                ));
+            }
+            provider.url.parse::<http::Uri>().with_context(|| {
+                format!(
+                    "invalid url `{}` for provider `{}`",
+                    provider.url, provider.label
+                )
+            })?;
+            if provider.rate_limit == Some(0) {
+                return Err(anyhow!(
+                    "provider `{}` has a rate limit of 0; remove the rate limit \
+                     or set it to a value bigger than 0",
+                    provider.label
+                ));
+            }
+        }
+        if providers.iter().all(|provider| provider.weight == 0) {
+            return Err(anyhow!(
+                "all weights for network `{}` are 0; \
+                 remove explicit weights or set at least one of them to a value bigger than 0",
+                network
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Token bucket which refills `rate` requests per second, up to one second
+/// worth of requests
+struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: u32) -> Self {
+        RateLimiter {
+            rate: rate as f64,
+            tokens: rate as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Default)]
+struct ProviderState {
+    consecutive_errors: u32,
+    evicted_until: Option<Instant>,
+    // Whether the slot of the provider was too far behind at the last health check
+    lagging: bool,
+}
+
+impl ProviderState {
+    fn is_healthy(&self) -> bool {
+        !self.lagging
+            && self
+                .evicted_until
+                .map_or(true, |evicted_until| evicted_until <= Instant::now())
+    }
+}
+
+pub struct SolanaProvider {
+    pub label: String,
+    weight: usize,
+    client: RpcClient,
+    limiter: Option<Mutex<RateLimiter>>,
+    state: Mutex<ProviderState>,
+    healthy: IntGauge,
+    slot: IntGauge,
+}
+
+impl SolanaProvider {
+    fn new(network: &str, config: &ProviderConfig, commitment: CommitmentLevel) -> Self {
+        let healthy = PROVIDER_HEALTHY.with_label_values(&[network, &config.label]);
+        healthy.set(1);
+        SolanaProvider {
+            label: config.label.clone(),
+            weight: config.weight,
+            client: RpcClient::new_with_commitment(
+                config.url.clone(),
+                CommitmentConfig { commitment },
+            ),
+            limiter: config
+                .rate_limit
+                .map(|rate| Mutex::new(RateLimiter::new(rate))),
+            state: Default::default(),
+            healthy,
+            slot: PROVIDER_SLOT.with_label_values(&[network, &config.label]),
+        }
+    }
+
+    fn is_healthy(&self) -> bool {
+        self.state.lock().unwrap().is_healthy()
+    }
+
+    fn try_acquire(&self) -> bool {
+        self.limiter
+            .as_ref()
+            .map_or(true, |limiter| limiter.lock().unwrap().try_acquire())
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_errors = 0;
+        state.evicted_until = None;
+        self.healthy.set(state.is_healthy() as i64);
+    }
+
+    fn record_error(&self, network: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_errors += 1;
+        if state.consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+            if state.is_healthy() {
+                warn!(
+                    "Evicting provider {} of {} after {} errors in a row",
+                    self.label, network, state.consecutive_errors
+                );
+            }
+            state.evicted_until = Some(Instant::now() + EVICTION_TIME);
+        }
+        self.healthy.set(state.is_healthy() as i64);
+    }
+}
+
+/// The providers of a network
+pub struct SolanaNetworkProviders {
+    network: String,
+    providers: Vec<SolanaProvider>,
+    max_slot_lag: u64,
+}
+
+impl SolanaNetworkProviders {
+    pub fn new(config: &ChainConfig) -> Self {
+        let network = config.network.as_str();
+        let providers = config
+            .providers
+            .iter()
+            .map(|provider| {
+                info!(
+                    "Init Solana client {} of {} with url: {:?}",
+                    &provider.label, network, &provider.url
+                );
+                SolanaProvider::new(network, provider, config.commitment)
+            })
+            .collect();
+        SolanaNetworkProviders {
+            network: network.to_string(),
+            providers,
+            max_slot_lag: config.max_slot_lag,
+        }
+    }
+
+    /// The healthy providers in the order in which to try them: a random
+    /// order where providers with a bigger weight tend to come first. When
+    /// no provider is healthy all of them are tried.
+    fn candidates(&self) -> Vec<&SolanaProvider> {
+        let mut candidates: Vec<&SolanaProvider> = self
+            .providers
+            .iter()
+            .filter(|provider| provider.is_healthy())
+            .collect();
+        if candidates.is_empty() {
+            candidates = self.providers.iter().collect();
+        }
+        // Weighted random sampling: sort by u^(1/weight) for a uniform u
+        let mut rng = rand::thread_rng();
+        let mut keyed: Vec<(f64, &SolanaProvider)> = candidates
+            .into_iter()
+            .map(|provider| {
+                let key = match provider.weight {
+                    0 => 0.0,
+                    weight => rng.gen::<f64>().powf(1.0 / weight as f64),
+                };
+                (key, provider)
+            })
+            .collect();
+        keyed.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap());
+        keyed.into_iter().map(|(_, provider)| provider).collect()
+    }
+
+    /// Sends a request to one provider after the other until one answers.
+    /// Blocks while all providers are at their rate limit. Returns the error
+    /// of the last provider if none answered. The requests block the thread;
+    /// async code uses `call_async`.
+    pub fn call<T>(
+        &self,
+        method: &str,
+        request: impl Fn(&RpcClient) -> ClientResult<T>,
+    ) -> ClientResult<T> {
+        loop {
+            let mut last_error: Option<ClientError> = None;
+            let mut rate_limited = false;
+            for provider in self.candidates() {
+                if !provider.try_acquire() {
+                    rate_limited = true;
+                    continue;
+                }
+                match request(&provider.client) {
+                    Ok(result) => {
+                        provider.record_success();
+                        return Ok(result);
+                    }
+                    Err(err) if err.to_string().contains(SKIPPED_SLOT_ERROR) => {
+                        provider.record_success();
+                        return Err(err);
+                    }
+                    Err(err) => {
+                        warn!(
+                            "{} error from provider {} of {}: {:?}",
+                            method, provider.label, self.network, err
+                        );
+                        provider.record_error(&self.network);
+                        last_error = Some(err);
+                    }
+                }
+            }
+            match last_error {
+                Some(err) => return Err(err),
+                None if rate_limited => std::thread::sleep(RATE_LIMIT_DELAY),
+                None => unreachable!("a network has at least one provider"),
+            }
+        }
+    }
+
+    /// Like `call`, but the requests and the waits for the rate limits happen
+    /// on the blocking thread pool, so that they don't hold up the async workers
+    pub async fn call_async<T, F>(
+        self: &Arc<Self>,
+        method: &'static str,
+        request: F,
+    ) -> ClientResult<T>
+    where
+        T: Send + 'static,
+        F: Fn(&RpcClient) -> ClientResult<T> + Send + 'static,
+    {
+        let providers = self.clone();
+        task::spawn_blocking(move || providers.call(method, request))
+            .await
+            .unwrap_or_else(|err| {
+                Err(ClientErrorKind::Custom(format!("{} request failed: {}", method, err)).into())
+            })
+    }
+
+    pub fn get_slot(&self) -> ClientResult<u64> {
+        self.call("getSlot", |client| client.get_slot())
+    }
+
+    pub async fn get_slot_async(self: &Arc<Self>) -> ClientResult<u64> {
+        self.call_async("getSlot", |client| client.get_slot()).await
+    }
+
+    /// Asks every provider for its slot and evicts the ones that lag behind
+    /// the best of them by more than `max_slot_lag` slots
+    pub fn check_health(&self) {
+        let slots: Vec<Option<u64>> = self
+            .providers
+            .iter()
+            .map(|provider| match provider.client.get_slot() {
+                Ok(slot) => {
+                    provider.slot.set(slot as i64);
+                    Some(slot)
+                }
+                Err(err) => {
+                    warn!(
+                        "Health check of provider {} of {} failed: {:?}",
+                        provider.label, self.network, err
+                    );
+                    provider.record_error(&self.network);
+                    None
+                }
+            })
+            .collect();
+        let best_slot = match slots.iter().flatten().max() {
+            Some(best_slot) => *best_slot,
+            None => return,
+        };
+        for (provider, slot) in self.providers.iter().zip(slots) {
+            let mut state = provider.state.lock().unwrap();
+            let lagging = match slot {
+                Some(slot) => best_slot - slot > self.max_slot_lag,
+                // Keep the verdict of the last check for the providers which did not answer
+                None => state.lagging,
+            };
+            if lagging != state.lagging {
+                if lagging {
+                    warn!(
+                        "Evicting provider {} of {}: it is {} slots behind",
+                        provider.label,
+                        self.network,
+                        best_slot - slot.unwrap_or_default()
+                    );
+                } else {
+                    info!(
+                        "Provider {} of {} caught up at slot {}",
+                        provider.label, self.network, best_slot
+                    );
+                }
+            }
+            state.lagging = lagging;
+            provider.healthy.set(state.is_healthy() as i64);
+        }
+    }
+
+    /// Checks the health of the providers in the background as long as they
+    /// are in use
+    pub fn spawn_health_check(self: &Arc<Self>) {
+        if self.providers.len() < 2 {
+            return;
+        }
+        let providers = Arc::downgrade(self);
+        let name = format!("{:?}_health_check", &self.network);
+        massbit::spawn_thread(name, move || {
+            while let Some(providers) = providers.upgrade() {
+                providers.check_health();
+                drop(providers);
+                std::thread::sleep(HEALTH_CHECK_INTERVAL);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn providers(configs: &[ProviderConfig]) -> SolanaNetworkProviders {
+        SolanaNetworkProviders {
+            network: "test".to_string(),
+            providers: configs
+                .iter()
+                .map(|config| SolanaProvider::new("test", config, CommitmentLevel::Finalized))
+                .collect(),
+            max_slot_lag: 10,
+        }
+    }
+
+    fn labels(providers: &SolanaNetworkProviders) -> Vec<&str> {
+        let mut labels: Vec<&str> = providers
+            .candidates()
+            .into_iter()
+            .map(|provider| provider.label.as_str())
+            .collect();
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn rate_limiter_refuses_when_empty() {
+        let mut limiter = RateLimiter::new(2);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn rate_limiter_refills_over_time() {
+        let mut limiter = RateLimiter::new(2);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        limiter.refilled_at -= Duration::from_millis(500);
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+        // No more than one second worth of requests builds up
+        limiter.refilled_at -= Duration::from_secs(10);
+        assert!(limiter.try_acquire());
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn provider_without_rate_limit_always_acquires() {
+        let providers = providers(&[ProviderConfig::new("a", "http://a")]);
+        for _ in 0..100 {
+            assert!(providers.providers[0].try_acquire());
+        }
+    }
+
+    #[test]
+    fn candidates_are_the_healthy_providers() {
+        let providers = providers(&[
+            ProviderConfig::new("a", "http://a"),
+            ProviderConfig::new("b", "http://b"),
+            ProviderConfig::new("c", "http://c"),
+        ]);
+        assert_eq!(labels(&providers), vec!["a", "b", "c"]);
+
+        providers.providers[1].state.lock().unwrap().lagging = true;
+        assert_eq!(labels(&providers), vec!["a", "c"]);
+    }
+
+    #[test]
+    fn candidates_are_all_providers_when_none_is_healthy() {
+        let providers = providers(&[
+            ProviderConfig::new("a", "http://a"),
+            ProviderConfig::new("b", "http://b"),
+        ]);
+        providers.providers[0].state.lock().unwrap().lagging = true;
+        providers.providers[1].state.lock().unwrap().lagging = true;
+        assert_eq!(labels(&providers), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn provider_with_weight_zero_comes_last() {
+        let providers = providers(&[
+            ProviderConfig {
+                weight: 0,
+                ..ProviderConfig::new("a", "http://a")
+            },
+            ProviderConfig::new("b", "http://b"),
+            ProviderConfig::new("c", "http://c"),
+        ]);
+        for _ in 0..20 {
+            let candidates = providers.candidates();
+            assert_eq!(candidates.len(), 3);
+            assert_eq!(candidates[2].label, "a");
+        }
+    }
+
+    #[test]
+    fn provider_is_evicted_after_errors_in_a_row() {
+        let providers = providers(&[
+            ProviderConfig::new("a", "http://a"),
+            ProviderConfig::new("b", "http://b"),
+        ]);
+        let provider = &providers.providers[0];
+        for _ in 1..MAX_CONSECUTIVE_ERRORS {
+            provider.record_error("test");
+        }
+        assert!(provider.is_healthy());
+        // A success resets the count
+        provider.record_success();
+        for _ in 1..MAX_CONSECUTIVE_ERRORS {
+            provider.record_error("test");
+        }
+        assert!(provider.is_healthy());
+
+        provider.record_error("test");
+        assert!(!provider.is_healthy());
+        assert_eq!(provider.healthy.get(), 0);
+        assert_eq!(labels(&providers), vec!["b"]);
+    }
+
+    #[test]
+    fn evicted_provider_gets_another_chance() {
+        let providers = providers(&[
+            ProviderConfig::new("a", "http://a"),
+            ProviderConfig::new("b", "http://b"),
+        ]);
+        let provider = &providers.providers[0];
+        for _ in 0..MAX_CONSECUTIVE_ERRORS {
+            provider.record_error("test");
+        }
+        assert_eq!(labels(&providers), vec!["b"]);
+
+        provider.state.lock().unwrap().evicted_until = Some(Instant::now());
+        assert_eq!(labels(&providers), vec!["a", "b"]);
+    }
+}
@@ -1,8 +1,8 @@
-use crate::command::{ChainConfig, Config};
-use crate::indexer_broadcast::IndexerBroadcast;
+use crate::config::{ChainConfig, Config};
+use crate::indexer_broadcast::{IndexerBroadcast, IndexerRegistry};
 use crate::solana_chain;
 use crate::solana_chain_adapter::ChainAdapter;
-use crate::SOLANA_NETWORKS;
+use crate::solana_providers::SolanaNetworkProviders;
 use chain_ethereum::{Chain, TriggerFilter};
 use chain_solana::types::ConfirmedBlockWithSlot;
 use log::{error, info};
@@ -12,11 +12,13 @@ use massbit_common::prelude::tokio::sync::RwLock;
 use massbit_common::prelude::tokio::time::{sleep, Duration};
 use massbit_common::NetworkType;
 use massbit_grpc::firehose::bstream::{
-    stream_server::Stream, BlockRequest, BlockResponse, ChainType,
+    stream_server::Stream, BlockRequest, BlockResponse, ChainHeadRequest, ChainHeadResponse,
+    ChainType,
 };
-use solana_client::rpc_client::RpcClient;
+use massbit_grpc::firehose::payload::PayloadFormat;
 use solana_transaction_status::ConfirmedBlock;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::AtomicU64;
 use std::sync::{Arc, Mutex};
 use tokio::sync::{broadcast, mpsc};
 use tokio::task;
@@ -24,15 +26,17 @@ use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 use web3::api::Net;
 
-const QUEUE_BUFFER: usize = 1024;
+pub(crate) const QUEUE_BUFFER: usize = 1024;
 
 pub struct StreamService {
+    networks: BTreeMap<String, ChainConfig>,
     network_services: RwLock<HashMap<String, NetworkService>>,
 }
 
 impl StreamService {
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
         StreamService {
+            networks: config.networks.clone(),
             network_services: Default::default(),
         }
     }
@@ -45,41 +49,84 @@ impl Stream for StreamService {
         request: Request<BlockRequest>,
     ) -> Result<Response<Self::BlocksStream>, Status> {
         info!("Request = {:?}", &request);
+        let filter = SolanaFilter::decode(&request.get_ref().filter)
+            .map_err(|err| Status::invalid_argument(format!("invalid filter: {}", err)))?;
         let (tx, rx) = mpsc::channel(QUEUE_BUFFER);
         let network = &request.get_ref().network;
         let mut services = self.network_services.write().await;
         if !services.contains_key(network) {
-            if let Some(config) = SOLANA_NETWORKS.get(network) {
+            if let Some(config) = self.networks.get(network) {
                 let mut service = NetworkService::new(network, config);
                 &service.init();
                 services.insert(network.clone(), service);
             }
         }
         if let Some(service) = services.get_mut(network) {
-            service.register_indexer(request.get_ref(), tx);
+            service.register_indexer(request.get_ref(), filter, tx);
         };
         Ok(Response::new(ReceiverStream::new(rx)))
     }
+
+    async fn chain_head(
+        &self,
+        request: Request<ChainHeadRequest>,
+    ) -> Result<Response<ChainHeadResponse>, Status> {
+        let network = &request.get_ref().network;
+        let services = self.network_services.read().await;
+        // Ask the network itself; the slot delivered last lags behind the
+        // head while the reader catches up
+        let providers = match services.get(network) {
+            Some(service) => service.providers.clone(),
+            None => match self.networks.get(network) {
+                Some(config) => Arc::new(SolanaNetworkProviders::new(config)),
+                None => {
+                    return Err(Status::not_found(format!("unknown network {}", network)));
+                }
+            },
+        };
+        drop(services);
+        let slot = providers
+            .get_slot_async()
+            .await
+            .map_err(|err| Status::unavailable(err.to_string()))?;
+        Ok(Response::new(ChainHeadResponse { block_number: slot }))
+    }
 }
 
 struct NetworkService {
     network: String,
     chain_adapter: Arc<Mutex<ChainAdapter>>,
-    broadcaster: Arc<Mutex<IndexerBroadcast>>,
+    //Moved to the broadcaster thread by `init`, which owns it from then on
+    broadcaster: Option<IndexerBroadcast>,
+    registry: IndexerRegistry,
+    //Providers of the network, also fetch the history of indexers which are catching up
+    providers: Arc<SolanaNetworkProviders>,
+    catch_up_batch_size: u64,
 }
 
 impl NetworkService {
     fn new(network: &String, config: &ChainConfig) -> Self {
         let (tx, rx) = mpsc::channel(QUEUE_BUFFER);
-        let chain_adapter = Arc::new(Mutex::new(ChainAdapter::new(config, tx)));
-        let broadcaster = Arc::new(Mutex::new(IndexerBroadcast::new(rx)));
+        let delivered_slot = Arc::new(AtomicU64::new(0));
+        let providers = Arc::new(SolanaNetworkProviders::new(config));
+        let chain_adapter = Arc::new(Mutex::new(ChainAdapter::new(
+            config,
+            providers.clone(),
+            tx,
+            delivered_slot.clone(),
+        )));
+        let broadcaster = IndexerBroadcast::new(network, providers.clone(), rx, delivered_slot);
         NetworkService {
             network: network.to_string(),
             chain_adapter,
-            broadcaster,
+            registry: broadcaster.registry(),
+            broadcaster: Some(broadcaster),
+            providers,
+            catch_up_batch_size: config.catch_up_batch_size,
         }
     }
     fn init(&mut self) {
+        self.providers.spawn_health_check();
         /// chain reader thread
         let mut chain_adapter = self.chain_adapter.clone();
         let name = format!("{:?}_reader", &self.network);
@@ -88,17 +135,17 @@ impl NetworkService {
                 chain_adapter.lock().unwrap().start().await;
             }))
         });
-        let mut broadcaster = self.broadcaster.clone();
+        let mut broadcaster = match self.broadcaster.take() {
+            Some(broadcaster) => broadcaster,
+            None => return,
+        };
         let name = format!("{:?}_broadcaster", &self.network);
         massbit::spawn_thread(name, move || {
-            massbit::block_on(task::unconstrained(async {
+            massbit::block_on(task::unconstrained(async move {
                 loop {
-                    let success = broadcaster.lock().unwrap().try_recv().await;
+                    let success = broadcaster.try_recv().await;
                     if !success {
                         sleep(Duration::from_millis(100)).await;
-                        print!(".");
-                    } else {
-                        print!("*** Broadcaster receive block from chain_adapter");
                     }
                 }
             }))
@@ -107,13 +154,26 @@ impl NetworkService {
     fn register_indexer(
         &mut self,
         request: &BlockRequest,
+        filter: SolanaFilter,
         indexer_sender: Sender<Result<BlockResponse, Status>>,
     ) {
-        self.broadcaster.lock().unwrap().register_indexer(
+        let catch_up = self.registry.register_indexer(
             &request.indexer_hash,
-            &request.filter,
+            filter,
+            // Indexers which ask for an unknown format receive JSON
+            PayloadFormat::from_i32(request.encoding, request.compression).unwrap_or_default(),
+            request.start_block_number,
             indexer_sender,
         );
+        // catch up thread, hands the indexer over to the broadcaster when done
+        if let Some(catch_up) = catch_up {
+            let providers = self.providers.clone();
+            let batch_size = self.catch_up_batch_size;
+            let name = format!("{:?}_catch_up_{}", &self.network, &request.indexer_hash);
+            massbit::spawn_thread(name, move || {
+                massbit::block_on(task::unconstrained(catch_up.run(providers, batch_size)))
+            });
+        }
     }
 }
 
@@ -7,6 +7,7 @@ use massbit::blockchain::{
     Block, BlockStream, Blockchain, BlockchainKind, PollingBlockStream,
     TriggersAdapter as TriggersAdapterTrait,
 };
+use massbit::components::store::{DeploymentLocator, WritableStore};
 use massbit::firehose::endpoints::FirehoseNetworkEndpoints;
 use massbit::prelude::serde::Serialize;
 use massbit::prelude::*;
@@ -17,7 +18,7 @@ use crate::data_source::{
 use crate::ethereum_adapter::blocks_with_triggers;
 use crate::network::EthereumNetworkAdapters;
 use crate::TriggerFilter;
-use crate::{EthereumAdapter, RuntimeAdapter};
+use crate::{EthereumAdapter, EthereumAdapterTrait, RuntimeAdapter};
 use massbit::blockchain::block_stream::BlockStreamEvent;
 use massbit::firehose::bstream::BlockResponse;
 
@@ -65,6 +66,7 @@ impl Chain {
 
     async fn new_polling_block_stream(
         &self,
+        indexer_store: Arc<dyn WritableStore>,
         start_block: BlockNumber,
         triggers_adapter: Arc<TriggersAdapter>,
         filter: Arc<TriggerFilter>,
@@ -73,6 +75,7 @@ impl Chain {
         Ok(Box::new(PollingBlockStream::new(
             logger,
             triggers_adapter,
+            indexer_store,
             filter,
             start_block,
             *MAX_BLOCK_RANGE_SIZE,
@@ -82,6 +85,7 @@ impl Chain {
 
     async fn new_firehose_block_stream(
         &self,
+        deployment: DeploymentLocator,
         start_block: BlockNumber,
         filter: Arc<TriggerFilter>,
     ) -> Result<Box<dyn BlockStream<Self>>, Error> {
@@ -95,6 +99,7 @@ impl Chain {
         Ok(Box::new(FirehoseBlockStream::new(
             firehose_endpoint,
             firehose_mapper,
+            deployment,
             self.name.clone(),
             filter,
             start_block,
@@ -151,14 +156,17 @@ impl Blockchain for Chain {
 
     async fn new_block_stream(
         &self,
+        deployment: DeploymentLocator,
+        indexer_store: Arc<dyn WritableStore>,
         start_block: BlockNumber,
         filter: Arc<Self::TriggerFilter>,
     ) -> Result<Box<dyn BlockStream<Self>>, Error> {
         if self.firehose_endpoints.len() > 0 {
-            self.new_firehose_block_stream(start_block, filter).await
+            self.new_firehose_block_stream(deployment, start_block, filter)
+                .await
         } else {
             let adapter = self.triggers_adapter()?;
-            self.new_polling_block_stream(start_block, adapter, filter)
+            self.new_polling_block_stream(indexer_store, start_block, adapter, filter)
                 .await
         }
     }
@@ -264,17 +272,43 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
             }
         }
     }
+
+    async fn is_on_main_chain(&self, ptr: BlockPtr) -> Result<bool, Error> {
+        let canonical_ptr = self
+            .eth_adapter
+            .block_pointer_from_number(&self.logger, ptr.number)
+            .compat()
+            .await?;
+        Ok(canonical_ptr == ptr)
+    }
+
+    async fn parent_ptr(&self, block: &BlockPtr) -> Result<Option<BlockPtr>, Error> {
+        let blocks = self
+            .eth_adapter
+            .load_blocks(
+                self.logger.clone(),
+                std::iter::once(block.hash_as_h256()).collect(),
+            )
+            .collect()
+            .compat()
+            .await?;
+        let block = blocks
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Ethereum node did not find block {}", block))?;
+        Ok(block.parent_ptr())
+    }
 }
 
 pub struct FirehoseMapper {}
 
 impl FirehoseMapperTrait<Chain> for FirehoseMapper {
-    fn to_block_stream_event(
+    fn to_block_stream_events(
         &self,
         _logger: &Logger,
         response: &BlockResponse,
-    ) -> Result<BlockStreamEvent<Chain>, Error> {
+    ) -> Result<Vec<BlockStreamEvent<Chain>>, Error> {
         let block: BlockWithTriggers<Chain> = serde_json::from_slice(&response.payload)?;
-        Ok(BlockStreamEvent::ProcessBlock(block))
+        Ok(vec![BlockStreamEvent::ProcessBlock(block)])
     }
 }
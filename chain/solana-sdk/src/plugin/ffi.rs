@@ -0,0 +1,451 @@
+//! Boundary between the indexer manager and a plugin library.
+//!
+//! The manager and the plugin are built separately, so only C types cross the boundary.
+//! A handler call passes the name of the data source and the bincode encoded message,
+//! and the plugin writes the error of a failed handler in a buffer of the manager. The
+//! handlers themselves, which are trait objects, never leave the plugin.
+use crate::plugin::handler::SolanaHandler;
+use crate::plugin::proxy::SolanaHandlerProxy;
+use crate::plugin::{AdapterDeclaration, PluginRegistrar};
+use crate::types::{AccountUpdate, SolanaBlock, SolanaLogMessages, SolanaTransaction};
+use libloading::Library;
+use massbit_grpc::firehose::bstream::{PayloadCompression, PayloadEncoding};
+use massbit_grpc::firehose::payload::PayloadFormat;
+use serde::Serialize;
+use std::cell::UnsafeCell;
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+use std::sync::{Arc, Once};
+
+/// Encoding of the messages passed to the handlers
+pub const MESSAGE_FORMAT: PayloadFormat = PayloadFormat {
+    encoding: PayloadEncoding::Bincode,
+    compression: PayloadCompression::Uncompressed,
+};
+/// Longest error message returned by a handler, longer ones are truncated
+const ERROR_CAPACITY: usize = 4096;
+
+/// The handler was called
+pub const HANDLED: i32 = 0;
+/// The plugin registered no handler for the data source
+pub const NO_HANDLER: i32 = 1;
+/// The handler returned an error or panicked, the message is in the error buffer
+pub const FAILED: i32 = 2;
+
+/// Handler method of a call
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageKind {
+    Block = 0,
+    Transaction = 1,
+    LogMessages = 2,
+    Account = 3,
+}
+
+#[repr(C)]
+pub struct HandlerCall {
+    /// Name of the data source or template in the manifest
+    pub name: *const u8,
+    pub name_len: usize,
+    /// One of the `MessageKind`
+    pub kind: u32,
+    /// The bincode encoded message
+    pub message: *const u8,
+    pub message_len: usize,
+}
+
+/// Error message of a failed call: the plugin writes at most `capacity` bytes in `data`
+/// and their count in `len`.
+#[repr(C)]
+pub struct ErrorBuffer {
+    pub data: *mut u8,
+    pub capacity: usize,
+    pub len: usize,
+}
+
+/// Entry point of the handlers of a plugin, exported by `export_plugin!`
+pub type HandleFn = unsafe extern "C" fn(call: *const HandlerCall, error: *mut ErrorBuffer) -> i32;
+/// Whether the plugin registered a handler for the data source `name`, or the handler of
+/// the data sources without their own for an empty name. Exported by `export_plugin!`.
+pub type HasHandlerFn = unsafe extern "C" fn(name: *const u8, name_len: usize) -> bool;
+
+/// Handlers of a plugin, registered by the `register` function of the plugin on the first
+/// call. Only used by `export_plugin!` and the macros of other plugin crates.
+#[doc(hidden)]
+pub struct PluginHandlers<P = SolanaHandlerProxy> {
+    once: Once,
+    handlers: UnsafeCell<Option<P>>,
+}
+
+// The handlers are written once, before any read
+unsafe impl<P: Sync> Sync for PluginHandlers<P> {}
+
+impl<P> PluginHandlers<P> {
+    pub const fn new() -> Self {
+        PluginHandlers {
+            once: Once::new(),
+            handlers: UnsafeCell::new(None),
+        }
+    }
+    /// The handlers, created by `register` on the first call
+    pub fn get_or_register(&self, register: impl FnOnce() -> P) -> &P {
+        self.once.call_once(|| unsafe {
+            *self.handlers.get() = Some(register());
+        });
+        unsafe { (*self.handlers.get()).as_ref().unwrap() }
+    }
+}
+
+impl PluginHandlers<SolanaHandlerProxy> {
+    pub fn get(
+        &self,
+        register: unsafe extern "C" fn(&mut dyn PluginRegistrar),
+    ) -> &SolanaHandlerProxy {
+        self.get_or_register(|| {
+            let mut proxy = SolanaHandlerProxy::default();
+            unsafe { register(&mut proxy) };
+            proxy
+        })
+    }
+}
+
+impl PluginRegistrar for SolanaHandlerProxy {
+    fn register_solana_handler(&mut self, handler: Box<dyn SolanaHandler + Send + Sync>) {
+        self.handler = Some(handler);
+    }
+    fn register_solana_data_source_handler(
+        &mut self,
+        name: &str,
+        handler: Box<dyn SolanaHandler + Send + Sync>,
+    ) {
+        self.data_source_handlers.insert(name.to_string(), handler);
+    }
+}
+
+/// The handler the plugin registered for the data source `name`, without falling back to
+/// the shared handler: the manager already falls back to it with an empty name.
+fn registered<'a>(
+    handlers: &'a SolanaHandlerProxy,
+    name: &str,
+) -> Option<&'a (dyn SolanaHandler + Send + Sync)> {
+    match name {
+        "" => handlers.handler.as_deref(),
+        name => handlers
+            .data_source_handlers
+            .get(name)
+            .map(|handler| handler.as_ref()),
+    }
+}
+
+/// Plugin side of a call: registers the handlers on the first call, decodes the message
+/// and calls the handler of the data source. Panics of `register` and of the handler are
+/// caught, they must not unwind into the manager.
+#[doc(hidden)]
+pub unsafe fn handle(
+    handlers: &PluginHandlers,
+    register: unsafe extern "C" fn(&mut dyn PluginRegistrar),
+    call: *const HandlerCall,
+    error: *mut ErrorBuffer,
+) -> i32 {
+    catch_call(call, error, |name, kind, message| {
+        match registered(handlers.get(register), name) {
+            Some(handler) => dispatch(handler, kind, message).map(|_| HANDLED),
+            None => Ok(NO_HANDLER),
+        }
+    })
+}
+
+/// Plugin side of `HasHandlerFn`. A plugin whose `register` panics has no handler.
+#[doc(hidden)]
+pub unsafe fn has_handler(
+    handlers: &PluginHandlers,
+    register: unsafe extern "C" fn(&mut dyn PluginRegistrar),
+    name: *const u8,
+    name_len: usize,
+) -> bool {
+    catch_has_handler(name, name_len, |name| {
+        registered(handlers.get(register), name).is_some()
+    })
+}
+
+/// Plugin side of a call, for any kind of handlers: calls `f` with the data source name,
+/// the message kind and the encoded message of `call`. An error or a panic of `f` is
+/// written in `error`, a panic must not unwind into the manager.
+#[doc(hidden)]
+pub unsafe fn catch_call<F>(call: *const HandlerCall, error: *mut ErrorBuffer, f: F) -> i32
+where
+    F: FnOnce(&str, u32, &[u8]) -> Result<i32, Box<dyn Error>>,
+{
+    let call = &*call;
+    let name = slice::from_raw_parts(call.name, call.name_len);
+    let message = slice::from_raw_parts(call.message, call.message_len);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match std::str::from_utf8(name) {
+        Ok(name) => f(name, call.kind, message),
+        Err(_) => Ok(NO_HANDLER),
+    }));
+    let message = match result {
+        Ok(Ok(status)) => return status,
+        Ok(Err(err)) => err.to_string(),
+        Err(_) => String::from("handler panicked"),
+    };
+    let error = &mut *error;
+    let len = message.len().min(error.capacity);
+    std::ptr::copy_nonoverlapping(message.as_ptr(), error.data, len);
+    error.len = len;
+    FAILED
+}
+
+/// Plugin side of `HasHandlerFn`, for any kind of handlers. False if `f` panics.
+#[doc(hidden)]
+pub unsafe fn catch_has_handler<F>(name: *const u8, name_len: usize, f: F) -> bool
+where
+    F: FnOnce(&str) -> bool,
+{
+    let name = slice::from_raw_parts(name, name_len);
+    panic::catch_unwind(AssertUnwindSafe(|| match std::str::from_utf8(name) {
+        Ok(name) => f(name),
+        Err(_) => false,
+    }))
+    .unwrap_or(false)
+}
+
+fn dispatch(handler: &dyn SolanaHandler, kind: u32, message: &[u8]) -> Result<(), Box<dyn Error>> {
+    match kind {
+        kind if kind == MessageKind::Block as u32 => {
+            handler.handle_block(&MESSAGE_FORMAT.decode::<SolanaBlock>(message)?)
+        }
+        kind if kind == MessageKind::Transaction as u32 => {
+            handler.handle_transaction(&MESSAGE_FORMAT.decode::<SolanaTransaction>(message)?)
+        }
+        kind if kind == MessageKind::LogMessages as u32 => {
+            handler.handle_log_messages(&MESSAGE_FORMAT.decode::<SolanaLogMessages>(message)?)
+        }
+        kind if kind == MessageKind::Account as u32 => {
+            handler.handle_account(&MESSAGE_FORMAT.decode::<AccountUpdate>(message)?)
+        }
+        kind => Err(format!("unknown message kind {}", kind).into()),
+    }
+}
+
+/// Manager side of the handler of a data source in a plugin.
+/// It keeps the library loaded as long as it lives.
+pub struct PluginHandler {
+    name: String,
+    handle: HandleFn,
+    _lib: Arc<Library>,
+}
+
+impl PluginHandler {
+    pub fn new(name: &str, handle: HandleFn, lib: Arc<Library>) -> Self {
+        PluginHandler {
+            name: name.to_string(),
+            handle,
+            _lib: lib,
+        }
+    }
+    fn call<T: Serialize>(&self, kind: MessageKind, message: &T) -> Result<(), Box<dyn Error>> {
+        let message = MESSAGE_FORMAT.encode(message)?;
+        let mut data = vec![0u8; ERROR_CAPACITY];
+        let mut error = ErrorBuffer {
+            data: data.as_mut_ptr(),
+            capacity: data.len(),
+            len: 0,
+        };
+        let call = HandlerCall {
+            name: self.name.as_ptr(),
+            name_len: self.name.len(),
+            kind: kind as u32,
+            message: message.as_ptr(),
+            message_len: message.len(),
+        };
+        match unsafe { (self.handle)(&call, &mut error) } {
+            HANDLED | NO_HANDLER => Ok(()),
+            _ => {
+                data.truncate(error.len.min(error.capacity));
+                Err(String::from_utf8_lossy(&data).into_owned().into())
+            }
+        }
+    }
+}
+
+impl SolanaHandler for PluginHandler {
+    fn handle_block(&self, message: &SolanaBlock) -> Result<(), Box<dyn Error>> {
+        self.call(MessageKind::Block, message)
+    }
+    fn handle_transaction(&self, message: &SolanaTransaction) -> Result<(), Box<dyn Error>> {
+        self.call(MessageKind::Transaction, message)
+    }
+    fn handle_log_messages(&self, message: &SolanaLogMessages) -> Result<(), Box<dyn Error>> {
+        self.call(MessageKind::LogMessages, message)
+    }
+    fn handle_account(&self, message: &AccountUpdate) -> Result<(), Box<dyn Error>> {
+        self.call(MessageKind::Account, message)
+    }
+}
+
+impl SolanaHandlerProxy {
+    /// Proxy of the handlers of a plugin, with a handler for each data source and
+    /// template of `names` which the plugin registered a handler for. The others use the
+    /// handler the plugin registered for the data sources without their own, if any.
+    pub fn for_plugin<'a>(
+        declaration: &AdapterDeclaration,
+        lib: Arc<Library>,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        let has_handler =
+            |name: &str| unsafe { (declaration.has_handler)(name.as_ptr(), name.len()) };
+        let mut proxy = SolanaHandlerProxy::default();
+        if has_handler("") {
+            proxy.handler = Some(Box::new(PluginHandler::new(
+                "",
+                declaration.handle,
+                lib.clone(),
+            )));
+        }
+        for name in names.into_iter().filter(|name| has_handler(name)) {
+            proxy.data_source_handlers.insert(
+                name.to_string(),
+                Box::new(PluginHandler::new(name, declaration.handle, lib.clone())),
+            );
+        }
+        proxy
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::types::Pubkey;
+
+    struct AccountHandler;
+
+    impl SolanaHandler for AccountHandler {
+        fn handle_account(&self, message: &AccountUpdate) -> Result<(), Box<dyn Error>> {
+            match message.lamports {
+                0 => Ok(()),
+                1 => Err(format!(
+                    "account {} has {} lamport",
+                    message.pubkey, message.lamports
+                )
+                .into()),
+                2 => panic!("account without rent"),
+                _ => Err("x".repeat(2 * ERROR_CAPACITY).into()),
+            }
+        }
+    }
+
+    struct SharedHandler;
+
+    impl SolanaHandler for SharedHandler {
+        fn handle_account(&self, _message: &AccountUpdate) -> Result<(), Box<dyn Error>> {
+            Err("shared handler".into())
+        }
+    }
+
+    extern "C" fn register(registrar: &mut dyn PluginRegistrar) {
+        registrar.register_solana_handler(Box::new(SharedHandler));
+        registrar.register_solana_data_source_handler("accounts", Box::new(AccountHandler));
+    }
+
+    crate::export_plugin!(register);
+
+    fn library() -> Arc<Library> {
+        Arc::new(libloading::os::unix::Library::this().into())
+    }
+
+    fn account(lamports: u64) -> AccountUpdate {
+        AccountUpdate {
+            pubkey: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            lamports,
+            data: vec![1, 2, 3],
+            slot: 42,
+            write_version: 42 << 32,
+        }
+    }
+
+    #[test]
+    fn proxy_has_the_registered_handlers() {
+        let proxy = SolanaHandlerProxy::for_plugin(
+            &adapter_declaration,
+            library(),
+            vec!["accounts", "transfers"],
+        );
+        assert!(proxy.handler.is_some());
+        let names: Vec<&String> = proxy.data_source_handlers.keys().collect();
+        assert_eq!(names, vec!["accounts"]);
+    }
+
+    #[test]
+    fn call_handler_through_ffi() {
+        let proxy =
+            SolanaHandlerProxy::for_plugin(&adapter_declaration, library(), vec!["accounts"]);
+        let handler = proxy.handler_for("accounts").unwrap();
+        assert!(handler.handle_account(&account(0)).is_ok());
+        let update = account(1);
+        assert_eq!(
+            handler.handle_account(&update).unwrap_err().to_string(),
+            format!("account {} has 1 lamport", update.pubkey)
+        );
+        assert_eq!(
+            handler.handle_account(&account(2)).unwrap_err().to_string(),
+            "handler panicked"
+        );
+        assert_eq!(
+            handler.handle_account(&account(3)).unwrap_err().to_string(),
+            "x".repeat(ERROR_CAPACITY)
+        );
+        // Data sources without their own handler use the shared one
+        let shared = proxy.handler_for("transfers").unwrap();
+        assert_eq!(
+            shared.handle_account(&account(0)).unwrap_err().to_string(),
+            "shared handler"
+        );
+    }
+
+    #[test]
+    fn call_without_handler() {
+        // The plugin does not fall back to the shared handler by itself
+        let handler = PluginHandler::new("transfers", adapter_declaration.handle, library());
+        assert!(handler.handle_account(&account(1)).is_ok());
+        let name = "transfers";
+        let call = HandlerCall {
+            name: name.as_ptr(),
+            name_len: name.len(),
+            kind: MessageKind::Account as u32,
+            message: b"".as_ptr(),
+            message_len: 0,
+        };
+        let mut data = vec![0u8; ERROR_CAPACITY];
+        let mut error = ErrorBuffer {
+            data: data.as_mut_ptr(),
+            capacity: data.len(),
+            len: 0,
+        };
+        let status = unsafe { (adapter_declaration.handle)(&call, &mut error) };
+        assert_eq!(status, NO_HANDLER);
+        assert_eq!(error.len, 0);
+    }
+
+    #[test]
+    fn call_with_unknown_message_kind() {
+        let name = "accounts";
+        let call = HandlerCall {
+            name: name.as_ptr(),
+            name_len: name.len(),
+            kind: 7,
+            message: b"".as_ptr(),
+            message_len: 0,
+        };
+        let mut data = vec![0u8; ERROR_CAPACITY];
+        let mut error = ErrorBuffer {
+            data: data.as_mut_ptr(),
+            capacity: data.len(),
+            len: 0,
+        };
+        let status = unsafe { (adapter_declaration.handle)(&call, &mut error) };
+        assert_eq!(status, FAILED);
+        assert_eq!(&data[..error.len], b"unknown message kind 7");
+    }
+}
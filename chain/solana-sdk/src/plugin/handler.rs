@@ -1,4 +1,4 @@
-use crate::types::{SolanaBlock, SolanaLogMessages, SolanaTransaction};
+use crate::types::{AccountUpdate, SolanaBlock, SolanaLogMessages, SolanaTransaction};
 use std::error::Error;
 
 pub trait SolanaHandler {
@@ -11,4 +11,9 @@ pub trait SolanaHandler {
     fn handle_log_messages(&self, _message: &SolanaLogMessages) -> Result<(), Box<dyn Error>> {
         Ok(())
     }
+    /// Called with the state of each account of the indexed program written by a block,
+    /// after `handle_block`
+    fn handle_account(&self, _message: &AccountUpdate) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
 }
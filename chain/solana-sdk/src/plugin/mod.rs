@@ -1,20 +1,130 @@
+use crate::plugin::ffi::{HandleFn, HasHandlerFn};
 use crate::plugin::handler::SolanaHandler;
 use crate::store::IndexStore;
 use crate::types::SolanaBlock;
+use libloading::Library;
 pub use massbit_grpc::firehose::bstream::BlockResponse;
 use std::error::Error;
+use std::ffi::CStr;
+use std::os::raw::c_char;
 use std::sync::{Arc, Mutex};
+use thiserror::Error;
 
+pub mod ffi;
 pub mod handler;
 pub mod proxy;
 
+/// Version of the layout of `AdapterDeclaration` and of the `ffi` types, exported by a
+/// plugin as `plugin_abi_version`. Plugins built before it was introduced do not export it.
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+/// `rustc --version` of the compiler which built the SDK, nul terminated
+pub const RUSTC_VERSION: &str = concat!(env!("RUSTC_VERSION"), "\0");
+/// Version of the SDK, nul terminated
+pub const CORE_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
 pub trait PluginRegistrar {
+    /// Registers the handler of the data sources which have no handler of their own
     fn register_solana_handler(&mut self, handler: Box<dyn SolanaHandler + Send + Sync>);
+    /// Registers the handler of the data source or template `name` of the manifest
+    fn register_solana_data_source_handler(
+        &mut self,
+        name: &str,
+        handler: Box<dyn SolanaHandler + Send + Sync>,
+    );
 }
 
+/// Declaration exported by a plugin as `adapter_declaration`. Its layout is fixed, so the
+/// manager can check the versions before it uses anything else of the plugin: the store
+/// given to the plugin is a trait object, which only the same compiler and SDK agree on.
+#[repr(C)]
 #[derive(Copy, Clone)]
 pub struct AdapterDeclaration {
-    pub register: unsafe extern "C" fn(&mut dyn PluginRegistrar),
+    /// `RUSTC_VERSION` of the SDK the plugin was built with
+    pub rustc_version: *const c_char,
+    /// `CORE_VERSION` of the SDK the plugin was built with
+    pub core_version: *const c_char,
+    pub handle: HandleFn,
+    pub has_handler: HasHandlerFn,
+}
+
+// The versions point to static strings
+unsafe impl Sync for AdapterDeclaration {}
+
+/// Declaration exported by a plugin as `adapter_declaration`, whose layout starts with the
+/// versions of the compiler and SDK which built the plugin.
+///
+/// Safety: the layout must be `repr(C)` and start with the two nul terminated versions.
+pub unsafe trait PluginDeclaration: Copy {
+    /// Version of the layout of the declaration, exported by the plugin as `plugin_abi_version`
+    const ABI_VERSION: u32;
+    fn rustc_version(&self) -> *const c_char;
+    fn core_version(&self) -> *const c_char;
+}
+
+unsafe impl PluginDeclaration for AdapterDeclaration {
+    const ABI_VERSION: u32 = PLUGIN_ABI_VERSION;
+    fn rustc_version(&self) -> *const c_char {
+        self.rustc_version
+    }
+    fn core_version(&self) -> *const c_char {
+        self.core_version
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PluginError {
+    #[error("plugin was built with an older SDK without a versioned ABI, rebuild it with massbit-solana-sdk {}", version(CORE_VERSION))]
+    Unversioned,
+    #[error("plugin uses ABI version {found}, the indexer manager needs version {expected}, rebuild it with massbit-solana-sdk {}", version(CORE_VERSION))]
+    AbiVersion { found: u32, expected: u32 },
+    #[error("plugin was built with {rustc_version} and massbit-solana-sdk {core_version}, the indexer manager needs {} and massbit-solana-sdk {}, rebuild it", version(RUSTC_VERSION), version(CORE_VERSION))]
+    Incompatible {
+        rustc_version: String,
+        core_version: String,
+    },
+    #[error(transparent)]
+    Library(#[from] libloading::Error),
+}
+
+fn version(version: &str) -> &str {
+    version.trim_end_matches('\0')
+}
+
+/// Reads the declaration of a plugin, once it is checked that the plugin was built by the
+/// same compiler and SDK version as the manager.
+pub unsafe fn load_declaration<D: PluginDeclaration>(lib: &Library) -> Result<D, PluginError> {
+    let abi_version = lib
+        .get::<*const u32>(b"plugin_abi_version\0")
+        .map_err(|_| PluginError::Unversioned)?
+        .read();
+    check_abi_version::<D>(abi_version)?;
+    let declaration = lib.get::<*const D>(b"adapter_declaration\0")?.read();
+    check_versions(&declaration)?;
+    Ok(declaration)
+}
+
+/// Checks the `plugin_abi_version` exported by a plugin, before its declaration is read
+pub fn check_abi_version<D: PluginDeclaration>(abi_version: u32) -> Result<(), PluginError> {
+    match abi_version == D::ABI_VERSION {
+        true => Ok(()),
+        false => Err(PluginError::AbiVersion {
+            found: abi_version,
+            expected: D::ABI_VERSION,
+        }),
+    }
+}
+
+/// Checks that a plugin was built by the same compiler and SDK version as the manager
+pub unsafe fn check_versions<D: PluginDeclaration>(declaration: &D) -> Result<(), PluginError> {
+    let rustc_version = CStr::from_ptr(declaration.rustc_version()).to_string_lossy();
+    let core_version = CStr::from_ptr(declaration.core_version()).to_string_lossy();
+    if rustc_version != version(RUSTC_VERSION) || core_version != version(CORE_VERSION) {
+        return Err(PluginError::Incompatible {
+            rustc_version: rustc_version.into_owned(),
+            core_version: core_version.into_owned(),
+        });
+    }
+    Ok(())
 }
 
 // General trait for handling message,
@@ -36,14 +146,107 @@ pub trait MessageHandler {
     }
 }
 
+/// Exports the declaration of a plugin, whose handlers are registered by `$register`
+/// on the first handler call.
 #[macro_export]
 macro_rules! export_plugin {
     ($register:expr) => {
+        #[doc(hidden)]
+        static PLUGIN_HANDLERS: $crate::plugin::ffi::PluginHandlers =
+            $crate::plugin::ffi::PluginHandlers::new();
+
+        #[doc(hidden)]
+        unsafe extern "C" fn plugin_handle(
+            call: *const $crate::plugin::ffi::HandlerCall,
+            error: *mut $crate::plugin::ffi::ErrorBuffer,
+        ) -> i32 {
+            $crate::plugin::ffi::handle(&PLUGIN_HANDLERS, $register, call, error)
+        }
+
+        #[doc(hidden)]
+        unsafe extern "C" fn plugin_has_handler(name: *const u8, name_len: usize) -> bool {
+            $crate::plugin::ffi::has_handler(&PLUGIN_HANDLERS, $register, name, name_len)
+        }
+
+        #[doc(hidden)]
+        #[no_mangle]
+        pub static plugin_abi_version: u32 = $crate::plugin::PLUGIN_ABI_VERSION;
+
         #[doc(hidden)]
         #[no_mangle]
         pub static adapter_declaration: $crate::plugin::AdapterDeclaration =
             $crate::plugin::AdapterDeclaration {
-                register: $register,
+                rustc_version: $crate::plugin::RUSTC_VERSION.as_ptr() as *const _,
+                core_version: $crate::plugin::CORE_VERSION.as_ptr() as *const _,
+                handle: plugin_handle,
+                has_handler: plugin_has_handler,
             };
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::ffi::{ErrorBuffer, HandlerCall, NO_HANDLER};
+
+    unsafe extern "C" fn handle(_call: *const HandlerCall, _error: *mut ErrorBuffer) -> i32 {
+        NO_HANDLER
+    }
+
+    unsafe extern "C" fn has_handler(_name: *const u8, _name_len: usize) -> bool {
+        false
+    }
+
+    fn declaration(rustc_version: &'static str, core_version: &'static str) -> AdapterDeclaration {
+        AdapterDeclaration {
+            rustc_version: rustc_version.as_ptr() as *const _,
+            core_version: core_version.as_ptr() as *const _,
+            handle,
+            has_handler,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reject_unversioned_plugin() {
+        let lib = unsafe { Library::new("libc.so.6") }.unwrap();
+        let result = unsafe { load_declaration::<AdapterDeclaration>(&lib) };
+        assert!(matches!(result, Err(PluginError::Unversioned)));
+    }
+
+    #[test]
+    fn reject_other_abi_version() {
+        assert!(check_abi_version::<AdapterDeclaration>(PLUGIN_ABI_VERSION).is_ok());
+        for abi_version in [1, 99] {
+            let result = check_abi_version::<AdapterDeclaration>(abi_version);
+            assert!(matches!(
+                result,
+                Err(PluginError::AbiVersion { found, expected: PLUGIN_ABI_VERSION }) if found == abi_version
+            ));
+        }
+    }
+
+    #[test]
+    fn reject_other_compiler_or_sdk() {
+        let compatible = declaration(RUSTC_VERSION, CORE_VERSION);
+        assert!(unsafe { check_versions(&compatible) }.is_ok());
+        let other_rustc = declaration("rustc 1.0.0 (a59de37e9 2015-05-13)\0", CORE_VERSION);
+        let other_sdk = declaration(RUSTC_VERSION, "0.0.1\0");
+        for declaration in [other_rustc, other_sdk] {
+            match unsafe { check_versions(&declaration) } {
+                Err(PluginError::Incompatible {
+                    rustc_version,
+                    core_version,
+                }) => assert!(
+                    rustc_version != version(RUSTC_VERSION)
+                        || core_version != version(CORE_VERSION)
+                ),
+                _ => panic!("plugin of another compiler or SDK was accepted"),
+            }
+        }
+        match unsafe { check_versions(&declaration(RUSTC_VERSION, "0.0.1\0")) } {
+            Err(err) => assert!(err.to_string().contains("massbit-solana-sdk 0.0.1")),
+            Ok(_) => panic!("plugin of another SDK was accepted"),
+        }
+    }
+}
@@ -1,29 +1,59 @@
 use crate::plugin::{handler::SolanaHandler, MessageHandler};
 use crate::store::IndexStore;
-use crate::types::{SolanaBlock, SolanaLogMessages, SolanaTransaction};
+use crate::types::{AccountUpdate, SolanaBlock, SolanaLogMessages, SolanaTransaction};
 use crate::COMPONENT_NAME;
+use std::collections::HashMap;
 use std::error::Error;
 use std::sync::{Arc, Mutex};
 
 /// A proxy object which wraps a [`Handler`] and makes sure it can't outlive
 /// the library it came from.
+#[derive(Default)]
 pub struct SolanaHandlerProxy {
-    pub handler: Box<dyn SolanaHandler + Send + Sync>,
+    pub handler: Option<Box<dyn SolanaHandler + Send + Sync>>,
+    /// Handlers of data sources and templates by their name in the manifest
+    pub data_source_handlers: HashMap<String, Box<dyn SolanaHandler + Send + Sync>>,
 }
 impl SolanaHandlerProxy {
     pub fn new(handler: Box<dyn SolanaHandler + Send + Sync>) -> SolanaHandlerProxy {
-        SolanaHandlerProxy { handler }
+        SolanaHandlerProxy {
+            handler: Some(handler),
+            data_source_handlers: HashMap::default(),
+        }
+    }
+    /// Returns the handler registered for the data source `name`,
+    /// or the handler shared by all data sources.
+    pub fn handler_for(&self, name: &str) -> Option<&(dyn SolanaHandler + Send + Sync)> {
+        self.data_source_handlers
+            .get(name)
+            .or(self.handler.as_ref())
+            .map(|handler| handler.as_ref())
     }
 }
 impl SolanaHandler for SolanaHandlerProxy {
     fn handle_block(&self, message: &SolanaBlock) -> Result<(), Box<dyn Error>> {
-        self.handler.handle_block(message)
+        match &self.handler {
+            Some(handler) => handler.handle_block(message),
+            None => Ok(()),
+        }
     }
     fn handle_transaction(&self, message: &SolanaTransaction) -> Result<(), Box<dyn Error>> {
-        self.handler.handle_transaction(message)
+        match &self.handler {
+            Some(handler) => handler.handle_transaction(message),
+            None => Ok(()),
+        }
     }
     fn handle_log_messages(&self, message: &SolanaLogMessages) -> Result<(), Box<dyn Error>> {
-        self.handler.handle_log_messages(message)
+        match &self.handler {
+            Some(handler) => handler.handle_log_messages(message),
+            None => Ok(()),
+        }
+    }
+    fn handle_account(&self, message: &AccountUpdate) -> Result<(), Box<dyn Error>> {
+        match &self.handler {
+            Some(handler) => handler.handle_account(message),
+            None => Ok(()),
+        }
     }
 }
 
@@ -45,7 +75,7 @@ impl MessageHandler for SolanaHandlerProxy {
                 &block.block.blockhash,
                 &block.block.transactions.len()
             );
-            self.handler.handle_block(&block);
+            self.handle_block(&block);
             store
                 .lock()
                 .unwrap()
@@ -1,6 +1,16 @@
 use crate::entity::Entity;
 use std::error::Error;
 
+/// A data source created by a mapping at runtime from one of the manifest templates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DynamicDataSource {
+    /// Name of the template
+    pub name: String,
+    pub address: String,
+    /// The block in which the data source was created
+    pub creation_block: u64,
+}
+
 pub trait IndexStore: Sync + Send {
     fn save(&mut self, entity_name: String, data: Entity);
     fn get(&mut self, entity_name: String, entity_id: &String) -> Option<Entity>;
@@ -12,4 +22,9 @@ pub trait IndexStore: Sync + Send {
     //     range: EntityRange,
     // ) -> Vec<Entity>;
     fn flush(&mut self, block_hash: &String, block_slot: u64) -> Result<(), Box<dyn Error>>;
+    /// Creates a data source from the template `name` of the manifest, which watches `address`.
+    /// It is stored by the next `flush` and handles transactions from the following block on.
+    fn create_data_source(&mut self, name: &str, address: &str);
+    /// Returns the data sources stored by `flush` since the last call.
+    fn take_data_sources(&mut self) -> Vec<DynamicDataSource>;
 }
@@ -1,3 +1,5 @@
+use massbit_grpc::firehose::bstream::BlockResponse;
+use massbit_grpc::firehose::payload::PayloadFormat;
 use serde::{Deserialize, Serialize};
 
 pub type SolanaBlock = ExtBlock;
@@ -17,7 +19,16 @@ pub struct ExtBlock {
     pub block_number: u64,
     pub block: Block,
     pub list_log_messages: Vec<LogMessages>,
+    /// State of the accounts written by the block, if the indexer has account handlers
+    #[serde(default)]
+    pub account_updates: Vec<AccountUpdate>,
 }
+/// Decodes the blocks in the payload of a chain-reader response, with the encoding
+/// and compression of the response.
+pub fn decode_blocks(response: &BlockResponse) -> Result<Vec<SolanaBlock>, anyhow::Error> {
+    PayloadFormat::from_i32(response.encoding, response.compression)?.decode(&response.payload)
+}
+
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub struct ExtTransaction {
     //Todo: rename this field to block_slot
@@ -36,3 +47,93 @@ pub struct ExtLogMessages {
     pub transaction: Transaction,
     //pub block: Arc<ExtBlock>,
 }
+
+/// The data of an account owned by an indexed program after a block wrote it
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub slot: u64,
+    /// Orders the updates of an account, later writes have bigger versions
+    pub write_version: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use massbit_grpc::firehose::bstream::{PayloadCompression, PayloadEncoding};
+    use solana_program::message::{Message, MessageHeader};
+    use solana_transaction_status::TransactionStatusMeta;
+
+    fn block() -> ExtBlock {
+        let program_id = Pubkey::new_unique();
+        let mut transaction = Transaction {
+            transaction: Default::default(),
+            meta: Some(TransactionStatusMeta {
+                fee: 5000,
+                pre_balances: vec![10_000, 0],
+                post_balances: vec![5000, 0],
+                log_messages: Some(vec![String::from("Program log: Instruction: Swap")]),
+                ..Default::default()
+            }),
+        };
+        transaction.transaction.message = Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![Pubkey::new_unique(), program_id],
+            ..Default::default()
+        };
+        ExtBlock {
+            version: String::from("1.7.0"),
+            timestamp: 1_640_000_000,
+            block_number: 42,
+            block: Block {
+                previous_blockhash: String::from("4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZAMdL4VZHirAn"),
+                blockhash: String::from("8Mw7yZcTVQsxBDK4ZnqA7KzNHDUDBBzZ1L9hkJfUqKjY"),
+                parent_slot: 41,
+                transactions: vec![transaction],
+                rewards: vec![],
+                block_time: Some(1_640_000_000),
+                block_height: Some(40),
+            },
+            list_log_messages: vec![Some(vec![String::from("Program log: Instruction: Swap")])],
+            account_updates: vec![AccountUpdate {
+                pubkey: Pubkey::new_unique(),
+                owner: program_id,
+                lamports: 1_000_000,
+                data: vec![1, 2, 3],
+                slot: 42,
+                write_version: 7,
+            }],
+        }
+    }
+
+    #[test]
+    fn decode_bincode_zstd_blocks() {
+        let blocks = vec![block()];
+        let format = PayloadFormat::new(PayloadEncoding::Bincode, PayloadCompression::Zstd);
+        let response = BlockResponse {
+            version: String::from("1.7.0"),
+            payload: format.encode(&blocks).unwrap(),
+            encoding: PayloadEncoding::Bincode as i32,
+            compression: PayloadCompression::Zstd as i32,
+        };
+        assert_eq!(decode_blocks(&response).unwrap(), blocks);
+    }
+
+    #[test]
+    fn decode_blocks_with_unknown_format() {
+        let response = BlockResponse {
+            version: String::from("1.7.0"),
+            payload: vec![],
+            encoding: 2,
+            compression: 0,
+        };
+        assert!(decode_blocks(&response).is_err());
+    }
+}
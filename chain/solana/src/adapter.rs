@@ -1,8 +1,9 @@
 use super::types::ChainConfig;
 use crate::chain::Chain;
 use crate::data_source::DataSource;
-use crate::types::{ConfirmedBlockWithSlot, Pubkey};
-use crate::{LIMIT_FILTER_RESULT, TRANSACTION_BATCH_SIZE};
+use crate::runtime::host_fns;
+use crate::types::{ConfirmedBlockWithSlot, ExtBlock, Pubkey};
+use crate::{LIMIT_FILTER_RESULT, TRANSACTION_BATCH_SIZE, VERSION};
 use log::{debug, error, info, warn};
 use massbit::blockchain as bc;
 use massbit::blockchain::HostFn;
@@ -10,18 +11,23 @@ use massbit::prelude::*;
 use serde_json::json;
 use solana_client::client_error::Result as ClientResult;
 use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::RpcBlockConfig;
 use solana_client::rpc_request::RpcRequest;
 use solana_sdk::signature::Signature;
 use solana_transaction_status::UiInstruction::{Compiled, Parsed};
 use solana_transaction_status::{
-    ConfirmedBlock, ConfirmedTransaction, EncodedConfirmedTransaction, InnerInstructions,
-    TransactionStatusMeta, TransactionTokenBalance, TransactionWithStatusMeta, UiInnerInstructions,
+    ConfirmedBlock, ConfirmedTransaction, EncodedConfirmedTransaction,
+    EncodedTransactionWithStatusMeta, InnerInstructions, TransactionDetails, TransactionStatusMeta,
+    TransactionTokenBalance, TransactionWithStatusMeta, UiInnerInstructions, UiTransactionEncoding,
     UiTransactionTokenBalance,
 };
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 use std::time::Instant;
 
+// Blocks in which to look for the first signature after a slot
+const FIRST_SIGNATURE_BLOCKS: usize = 10;
+
 #[derive(Clone)]
 pub struct SolanaAdapter {
     pub rpc_client: Arc<RpcClient>,
@@ -34,97 +40,126 @@ impl SolanaAdapter {
         info!("Finished init Solana client");
         SolanaAdapter { rpc_client }
     }
+    /// Returns the slot of the latest finalized block.
+    pub fn get_slot(&self) -> Result<u64, Error> {
+        self.rpc_client
+            .get_slot()
+            .map_err(|e| anyhow!("failed to get slot: {}", e))
+    }
+    /// Returns the slots and signatures of the transactions of `address` in the slots
+    /// `[first_slot, last_slot]` which are older than `before` and newer than `until`, oldest
+    /// first. The signatures are paged backward from `before`, `LIMIT_FILTER_RESULT` at a
+    /// time, until `until` or a slot before `first_slot`.
     pub fn get_signatures_for_address(
         &self,
         address: &Pubkey,
-        first_slot: Option<u64>,
-        end_signature: Option<Signature>,
-    ) -> Vec<String> {
-        let mut before_signature = end_signature;
+        first_slot: u64,
+        last_slot: u64,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    ) -> Result<Vec<(u64, String)>, Error> {
+        let mut before_signature = before;
         let mut res_signatures = vec![];
         info!(
-            "Get history blocks for address {:?} from block {:?} to signature {:?}",
-            address, first_slot, &end_signature
+            "Get history signatures for address {:?} from block {} to block {}",
+            address, first_slot, last_slot
         );
-        let first_slot = first_slot.unwrap_or_default();
         loop {
             let now = Instant::now();
             let config = GetConfirmedSignaturesForAddress2Config {
                 before: before_signature,
-                until: None,
+                until,
                 limit: Some(LIMIT_FILTER_RESULT),
                 commitment: None,
             };
-            match self
+            let txs = self
                 .rpc_client
                 .get_signatures_for_address_with_config(address, config)
+                .map_err(|e| anyhow!("failed to get signatures for {}: {}", address, e))?;
+            let last_tran = match txs.last() {
+                Some(last_tran) => last_tran,
+                None => break,
+            };
+            before_signature = Some(Signature::from_str(&last_tran.signature)?);
+            // Finish when the page reaches `until` or a block before first_slot
+            let finished = txs.len() < LIMIT_FILTER_RESULT || last_tran.slot < first_slot;
+            info!(
+                "Got {:?} filtered addresses in {:?}, last address: {:?} in slot {:?}",
+                txs.len(),
+                now.elapsed(),
+                &before_signature,
+                last_tran.slot
+            );
+            res_signatures.extend(
+                txs.into_iter()
+                    .filter(|tran| first_slot <= tran.slot && tran.slot <= last_slot)
+                    .map(|tran| (tran.slot, tran.signature)),
+            );
+            if finished {
+                break;
+            }
+        }
+        res_signatures.reverse();
+        Ok(res_signatures)
+    }
+    /// Returns the signature of the first transaction of the first block in
+    /// `[first_slot, last_slot]`. Paging signatures before it returns the transactions of
+    /// the slots before `first_slot`.
+    pub fn first_signature(
+        &self,
+        first_slot: u64,
+        last_slot: u64,
+    ) -> Result<Option<Signature>, Error> {
+        let slots = self
+            .rpc_client
+            .get_blocks_with_limit(first_slot, FIRST_SIGNATURE_BLOCKS)
+            .map_err(|e| anyhow!("failed to get blocks from {}: {}", first_slot, e))?;
+        for slot in slots.into_iter().filter(|slot| *slot <= last_slot) {
+            let config = RpcBlockConfig {
+                encoding: None,
+                transaction_details: Some(TransactionDetails::Signatures),
+                rewards: Some(false),
+                commitment: None,
+            };
+            let block = self
+                .rpc_client
+                .get_block_with_config(slot, config)
+                .map_err(|e| anyhow!("failed to get signatures of block {}: {}", slot, e))?;
+            if let Some(signature) = block
+                .signatures
+                .and_then(|signatures| signatures.into_iter().next())
             {
-                Ok(txs) => {
-                    if txs.is_empty() {
-                        break;
-                    }
-                    let last_tran = txs.last().unwrap();
-                    before_signature = Some(Signature::from_str(&last_tran.signature).unwrap());
-                    // finish get history data when last call returns nothing
-                    // or first transaction block_slot less then first_slot
-                    let finished = last_tran.slot < first_slot;
-                    info!(
-                        "Got {:?} filtered addresses in {:?}, last address: {:?} in slot {:?}",
-                        txs.len(),
-                        now.elapsed(),
-                        &before_signature,
-                        last_tran.slot
-                    );
-                    for tran in txs {
-                        if first_slot <= tran.slot {
-                            //Prepend matched transaction hash
-                            res_signatures.insert(0, tran.signature);
-                        }
-                    }
-                    if finished {
-                        break;
-                    }
-                }
-                Err(err) => {
-                    error!("{:?}", &err);
-                    break;
-                }
+                return Ok(Some(Signature::from_str(&signature)?));
             }
         }
-        res_signatures
+        Ok(None)
     }
-    //Get transactions by hashes from chain and group by block_slot
-    pub fn get_confirmed_blocks(&self, signatures: &Vec<String>) -> Vec<ConfirmedBlockWithSlot> {
-        let mut start_tx = 0_usize;
-        //let mut res_vec = vec![];
-        //Group transactions by block
-        let mut group_transactions: HashMap<u64, Vec<ConfirmedTransaction>> = HashMap::new();
-        while start_tx < signatures.len() {
-            let last_index = signatures.len().min(start_tx + TRANSACTION_BATCH_SIZE);
-            let params = signatures[start_tx..last_index]
-                .iter()
-                .map(|tx| json!([tx, "base64"]))
-                .collect();
-            let res: ClientResult<Vec<ClientResult<EncodedConfirmedTransaction>>> = self
+    /// Gets transactions by signatures from chain and groups them by block slot. The blocks
+    /// are in slot order and their transactions in the order of the signatures. Fails if one
+    /// of the transactions can not be fetched, so that no transaction is skipped.
+    pub fn get_confirmed_blocks(
+        &self,
+        signatures: &[String],
+    ) -> Result<Vec<ConfirmedBlockWithSlot>, Error> {
+        let mut group_transactions: BTreeMap<u64, Vec<ConfirmedTransaction>> = BTreeMap::new();
+        for batch in signatures.chunks(TRANSACTION_BATCH_SIZE) {
+            let params = batch.iter().map(|tx| json!([tx, "base64"])).collect();
+            let trans: Vec<ClientResult<EncodedConfirmedTransaction>> = self
                 .rpc_client
-                .send_batch(RpcRequest::GetTransaction, params);
-            debug!("{:?}", res);
-            if let Ok(trans) = res {
-                trans
-                    .into_iter()
-                    .filter_map(|res| res.ok())
-                    .for_each(|tran| {
-                        if let Some(confirmed_transaction) = Self::decode_transaction(&tran) {
-                            group_transactions
-                                .entry(tran.slot)
-                                .or_insert(vec![])
-                                .push(confirmed_transaction);
-                        }
-                    });
+                .send_batch(RpcRequest::GetTransaction, params)
+                .map_err(|e| anyhow!("failed to get {} transactions: {}", batch.len(), e))?;
+            for (signature, tran) in batch.iter().zip(trans) {
+                let tran =
+                    tran.map_err(|e| anyhow!("failed to get transaction {}: {}", signature, e))?;
+                let confirmed_transaction = Self::decode_transaction(&tran)
+                    .ok_or_else(|| anyhow!("failed to decode transaction {}", signature))?;
+                group_transactions
+                    .entry(tran.slot)
+                    .or_insert(vec![])
+                    .push(confirmed_transaction);
             }
-            start_tx += TRANSACTION_BATCH_SIZE;
         }
-        group_transactions
+        Ok(group_transactions
             .into_iter()
             .map(|(block_slot, transactions)| {
                 let block = ConfirmedBlock {
@@ -144,12 +179,68 @@ impl SolanaAdapter {
                     block: Some(block),
                 }
             })
-            .collect::<Vec<ConfirmedBlockWithSlot>>()
+            .collect::<Vec<ConfirmedBlockWithSlot>>())
+    }
+    /// Returns the slots of the confirmed blocks in `[start_slot, end_slot]`. Slots without a
+    /// block are skipped.
+    pub fn get_blocks(&self, start_slot: u64, end_slot: u64) -> Result<Vec<u64>, Error> {
+        self.rpc_client
+            .get_blocks(start_slot, Some(end_slot))
+            .map_err(|e| anyhow!("failed to get blocks [{}, {}]: {}", start_slot, end_slot, e))
+    }
+
+    /// Fetches the confirmed block at `slot` with all its transactions.
+    pub fn get_block(&self, slot: u64) -> Result<ExtBlock, Error> {
+        let encoded_block = self
+            .rpc_client
+            .get_block_with_encoding(slot, UiTransactionEncoding::Base64)
+            .map_err(|e| anyhow!("failed to get block {}: {}", slot, e))?;
+        let transactions: Vec<TransactionWithStatusMeta> = encoded_block
+            .transactions
+            .iter()
+            .filter_map(Self::decode_transaction_with_meta)
+            .collect();
+        let list_log_messages = transactions
+            .iter()
+            .map(|transaction| {
+                transaction
+                    .meta
+                    .as_ref()
+                    .and_then(|meta| meta.log_messages.clone())
+            })
+            .collect();
+        Ok(ExtBlock {
+            version: VERSION.to_string(),
+            timestamp: encoded_block.block_time.unwrap_or_default(),
+            block_number: slot,
+            block: ConfirmedBlock {
+                previous_blockhash: encoded_block.previous_blockhash,
+                blockhash: encoded_block.blockhash,
+                parent_slot: encoded_block.parent_slot,
+                transactions,
+                rewards: encoded_block.rewards,
+                block_time: encoded_block.block_time,
+                block_height: encoded_block.block_height,
+            },
+            list_log_messages,
+            account_updates: Vec::new(),
+        })
     }
     fn decode_transaction(
         encode_txs: &EncodedConfirmedTransaction,
     ) -> Option<ConfirmedTransaction> {
-        let meta = encode_txs.transaction.meta.as_ref().and_then(|ui_meta| {
+        Self::decode_transaction_with_meta(&encode_txs.transaction).and_then(|transaction| {
+            Some(ConfirmedTransaction {
+                slot: encode_txs.slot,
+                transaction,
+                block_time: encode_txs.block_time.clone(),
+            })
+        })
+    }
+    fn decode_transaction_with_meta(
+        encode_txs: &EncodedTransactionWithStatusMeta,
+    ) -> Option<TransactionWithStatusMeta> {
+        let meta = encode_txs.meta.as_ref().and_then(|ui_meta| {
             Some(TransactionStatusMeta {
                 status: ui_meta.status.clone(),
                 rewards: ui_meta.rewards.clone(),
@@ -186,16 +277,9 @@ impl SolanaAdapter {
             })
         });
         encode_txs
-            .transaction
             .transaction
             .decode()
-            .and_then(|transaction| {
-                Some(ConfirmedTransaction {
-                    slot: encode_txs.slot,
-                    transaction: TransactionWithStatusMeta { transaction, meta },
-                    block_time: encode_txs.block_time.clone(),
-                })
-            })
+            .and_then(|transaction| Some(TransactionWithStatusMeta { transaction, meta }))
     }
     fn to_transaction_token_balance(ui_ttb: &UiTransactionTokenBalance) -> TransactionTokenBalance {
         TransactionTokenBalance {
@@ -275,7 +359,56 @@ pub struct RuntimeAdapter {
     pub sol_adapters: Arc<SolanaNetworkAdapters>,
 }
 impl bc::RuntimeAdapter<Chain> for RuntimeAdapter {
-    fn host_fns(&self, ds: &DataSource) -> Result<Vec<HostFn>, Error> {
-        todo!()
+    fn host_fns(&self, _ds: &DataSource) -> Result<Vec<HostFn>, Error> {
+        Ok(vec![
+            HostFn {
+                name: "base58.decode",
+                func: Arc::new(|ctx, wasm_ptr| {
+                    host_fns::base58_decode(ctx, wasm_ptr).map(|ptr| ptr.wasm_ptr())
+                }),
+            },
+            HostFn {
+                name: "crypto.sha256",
+                func: Arc::new(|ctx, wasm_ptr| {
+                    host_fns::crypto_sha256(ctx, wasm_ptr).map(|ptr| ptr.wasm_ptr())
+                }),
+            },
+            HostFn {
+                name: "solana.findProgramAddress",
+                func: Arc::new(|ctx, wasm_ptr| {
+                    host_fns::find_program_address(ctx, wasm_ptr).map(|ptr| ptr.wasm_ptr())
+                }),
+            },
+            HostFn {
+                name: "solana.createProgramAddress",
+                func: Arc::new(|ctx, wasm_ptr| {
+                    host_fns::create_program_address(ctx, wasm_ptr).map(|ptr| ptr.wasm_ptr())
+                }),
+            },
+            HostFn {
+                name: "solana.borshDecode",
+                func: Arc::new(|ctx, wasm_ptr| {
+                    host_fns::borsh_decode(ctx, wasm_ptr).map(|ptr| ptr.wasm_ptr())
+                }),
+            },
+            HostFn {
+                name: "solana.readIntLE",
+                func: Arc::new(|ctx, wasm_ptr| {
+                    host_fns::read_int_le(ctx, wasm_ptr, true).map(|ptr| ptr.wasm_ptr())
+                }),
+            },
+            HostFn {
+                name: "solana.readUintLE",
+                func: Arc::new(|ctx, wasm_ptr| {
+                    host_fns::read_int_le(ctx, wasm_ptr, false).map(|ptr| ptr.wasm_ptr())
+                }),
+            },
+            HostFn {
+                name: "solana.verifySignature",
+                func: Arc::new(|ctx, wasm_ptr| {
+                    host_fns::verify_signature(ctx, wasm_ptr).map(|valid| valid as u32)
+                }),
+            },
+        ])
     }
 }
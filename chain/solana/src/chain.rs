@@ -1,19 +1,45 @@
-use super::data_source::DataSource;
-use crate::adapter::SolanaAdapter;
-use crate::data_source::{DataSourceTemplate, UnresolvedDataSource, UnresolvedDataSourceTemplate};
-use crate::trigger::TriggerFilter;
-use massbit::blockchain::block_stream::BlockWithTriggers;
+use anyhow::Context;
+
+use massbit::blockchain::block_stream::{
+    BlockStreamEvent, BlockWithTriggers, FirehoseMapper as FirehoseMapperTrait,
+};
+use massbit::blockchain::firehose_block_stream::FirehoseBlockStream;
 use massbit::blockchain::{
-    Block, BlockPtr, BlockStream, Blockchain, BlockchainKind,
+    Block, BlockPtr, BlockStream, Blockchain, BlockchainKind, PollingBlockStream,
     TriggersAdapter as TriggersAdapterTrait,
 };
-use massbit::components::store::BlockNumber;
-use massbit::prelude::{async_trait, Arc, Deserialize, Error, Logger, LoggerFactory, Serialize};
-use solana_transaction_status::EncodedConfirmedBlock;
+use massbit::components::store::{BlockNumber, DeploymentLocator, WritableStore};
+use massbit::firehose::bstream::BlockResponse;
+use massbit::firehose::endpoints::FirehoseNetworkEndpoints;
+use massbit::prelude::*;
+use massbit_grpc::firehose::payload::PayloadFormat;
+
+use crate::adapter::{RuntimeAdapter, SolanaAdapter, SolanaNetworkAdapter, SolanaNetworkAdapters};
+use crate::data_source::{
+    DataSource, DataSourceTemplate, UnresolvedDataSource, UnresolvedDataSourceTemplate,
+};
+use crate::trigger::TriggerFilter;
+use crate::types::{block_ptr, parent_ptr, ExtBlock};
+
+lazy_static! {
+    /// Maximum number of slots to request in each chunk.
+    static ref MAX_BLOCK_RANGE_SIZE: BlockNumber = std::env::var("SOLANA_MAX_BLOCK_RANGE_SIZE")
+        .unwrap_or("100".into())
+        .parse::<BlockNumber>()
+        .expect("invalid SOLANA_MAX_BLOCK_RANGE_SIZE");
+
+    /// Ideal number of triggers in a range. The range size will adapt to try to meet this.
+    static ref TARGET_TRIGGERS_PER_BLOCK_RANGE: u64 = std::env::var("SOLANA_TARGET_TRIGGERS_PER_BLOCK_RANGE")
+        .unwrap_or("100".into())
+        .parse::<u64>()
+        .expect("invalid SOLANA_TARGET_TRIGGERS_PER_BLOCK_RANGE");
+}
 
 pub struct Chain {
     logger_factory: LoggerFactory,
     name: String,
+    solana_adapter: Arc<SolanaAdapter>,
+    firehose_endpoints: Arc<FirehoseNetworkEndpoints>,
 }
 
 impl std::fmt::Debug for Chain {
@@ -22,63 +48,187 @@ impl std::fmt::Debug for Chain {
     }
 }
 
-impl Chain {}
+impl Chain {
+    pub fn new(
+        logger_factory: LoggerFactory,
+        name: String,
+        solana_adapter: SolanaAdapter,
+        firehose_endpoints: FirehoseNetworkEndpoints,
+    ) -> Self {
+        Chain {
+            logger_factory,
+            name,
+            solana_adapter: Arc::new(solana_adapter),
+            firehose_endpoints: Arc::new(firehose_endpoints),
+        }
+    }
+
+    async fn new_polling_block_stream(
+        &self,
+        indexer_store: Arc<dyn WritableStore>,
+        start_block: BlockNumber,
+        triggers_adapter: Arc<TriggersAdapter>,
+        filter: Arc<TriggerFilter>,
+    ) -> Result<Box<dyn BlockStream<Self>>, Error> {
+        let logger = self.logger_factory.component_logger("BlockStream");
+        Ok(Box::new(PollingBlockStream::new(
+            logger,
+            triggers_adapter,
+            indexer_store,
+            filter,
+            start_block,
+            *MAX_BLOCK_RANGE_SIZE,
+            *TARGET_TRIGGERS_PER_BLOCK_RANGE,
+        )))
+    }
+
+    async fn new_firehose_block_stream(
+        &self,
+        deployment: DeploymentLocator,
+        start_block: BlockNumber,
+        filter: Arc<TriggerFilter>,
+    ) -> Result<Box<dyn BlockStream<Self>>, Error> {
+        let firehose_endpoint = match self.firehose_endpoints.random() {
+            Some(e) => e.clone(),
+            None => return Err(anyhow::format_err!("no firehose endpoint available",)),
+        };
+
+        let logger = self.logger_factory.component_logger("FirehoseBlockStream");
+        let firehose_mapper = Arc::new(FirehoseMapper {
+            filter: filter.cheap_clone(),
+        });
+        Ok(Box::new(FirehoseBlockStream::new(
+            firehose_endpoint,
+            firehose_mapper,
+            deployment,
+            self.name.clone(),
+            filter,
+            start_block,
+            logger,
+        )))
+    }
+}
+
 #[async_trait]
 impl Blockchain for Chain {
-    const KIND: BlockchainKind = BlockchainKind::Ethereum;
+    const KIND: BlockchainKind = BlockchainKind::Solana;
+
     type Block = SolanaBlock;
+
     type DataSource = DataSource;
+
     type UnresolvedDataSource = UnresolvedDataSource;
+
     type DataSourceTemplate = DataSourceTemplate;
+
     type UnresolvedDataSourceTemplate = UnresolvedDataSourceTemplate;
+
     type TriggersAdapter = TriggersAdapter;
+
     type TriggerData = crate::trigger::SolanaTriggerData;
+
     type MappingTrigger = crate::trigger::SolanaMappingTrigger;
+
     type TriggerFilter = crate::trigger::TriggerFilter;
-    type RuntimeAdapter = crate::adapter::RuntimeAdapter;
+
+    type RuntimeAdapter = RuntimeAdapter;
 
     fn triggers_adapter(&self) -> Result<Arc<Self::TriggersAdapter>, Error> {
-        todo!()
+        let logger = self.logger_factory.component_logger("TriggersAdapter");
+
+        let adapter = TriggersAdapter {
+            logger,
+            chain_adapter: self.solana_adapter.cheap_clone(),
+        };
+        Ok(Arc::new(adapter))
     }
 
     fn runtime_adapter(&self) -> Arc<Self::RuntimeAdapter> {
-        todo!()
+        Arc::new(RuntimeAdapter {
+            sol_adapters: Arc::new(SolanaNetworkAdapters {
+                adapters: vec![SolanaNetworkAdapter {
+                    network: self.name.clone(),
+                    adapter: self.solana_adapter.cheap_clone(),
+                }],
+            }),
+        })
     }
 
     async fn new_block_stream(
         &self,
+        deployment: DeploymentLocator,
+        indexer_store: Arc<dyn WritableStore>,
         start_block: BlockNumber,
         filter: Arc<Self::TriggerFilter>,
     ) -> Result<Box<dyn BlockStream<Self>>, Error> {
-        todo!()
+        if self.firehose_endpoints.len() > 0 {
+            self.new_firehose_block_stream(deployment, start_block, filter)
+                .await
+        } else {
+            let adapter = self.triggers_adapter()?;
+            self.new_polling_block_stream(indexer_store, start_block, adapter, filter)
+                .await
+        }
     }
 
     async fn block_pointer_from_number(
         &self,
-        logger: &Logger,
+        _logger: &Logger,
         number: BlockNumber,
     ) -> Result<BlockPtr, Error> {
-        todo!()
+        let block = get_block(self.solana_adapter.cheap_clone(), number as u64)
+            .await
+            .with_context(|| format!("no block {} on chain {}", number, self.name))?;
+        block_ptr(&block)
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum SolanaBlock {
-    Encoded(Arc<EncodedConfirmedBlock>),
+    /// Blocks are only processed once they are confirmed.
+    Final {
+        block: Arc<ExtBlock>,
+        ptr: BlockPtr,
+        parent_ptr: Option<BlockPtr>,
+    },
 }
 
-impl SolanaBlock {}
+impl SolanaBlock {
+    /// Fails if the hashes of `block` are not valid.
+    pub fn new(block: ExtBlock) -> Result<Self, Error> {
+        Ok(SolanaBlock::Final {
+            ptr: block_ptr(&block)?,
+            parent_ptr: parent_ptr(&block)?,
+            block: Arc::new(block),
+        })
+    }
+
+    pub(crate) fn ext_block(&self) -> Arc<ExtBlock> {
+        match self {
+            SolanaBlock::Final { block, .. } => block.cheap_clone(),
+        }
+    }
+}
 
 impl Block for SolanaBlock {
     fn ptr(&self) -> BlockPtr {
-        todo!()
+        match self {
+            SolanaBlock::Final { ptr, .. } => ptr.clone(),
+        }
     }
 
     fn parent_ptr(&self) -> Option<BlockPtr> {
-        todo!()
+        match self {
+            SolanaBlock::Final { parent_ptr, .. } => parent_ptr.clone(),
+        }
     }
 }
 
+/// Fetches a block without blocking the async runtime, since the RPC client is synchronous.
+async fn get_block(adapter: Arc<SolanaAdapter>, slot: u64) -> Result<ExtBlock, Error> {
+    massbit::spawn_blocking_allow_panic(move || adapter.get_block(slot)).await?
+}
+
 pub struct TriggersAdapter {
     logger: Logger,
     chain_adapter: Arc<SolanaAdapter>,
@@ -92,56 +242,69 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
         to: BlockNumber,
         filter: &TriggerFilter,
     ) -> Result<Vec<BlockWithTriggers<Chain>>, Error> {
-        todo!()
+        let adapter = self.chain_adapter.cheap_clone();
+        let slots =
+            massbit::spawn_blocking_allow_panic(move || adapter.get_blocks(from as u64, to as u64))
+                .await??;
+
+        debug!(self.logger, "Found {} blocks", slots.len(); "from" => from, "to" => to);
+
+        let mut blocks = Vec::with_capacity(slots.len());
+        for slot in slots {
+            let block = SolanaBlock::new(get_block(self.chain_adapter.cheap_clone(), slot).await?)?;
+            let trigger_data = filter.triggers_in_block(&block);
+            blocks.push(BlockWithTriggers::new(block, trigger_data));
+        }
+        Ok(blocks)
     }
 
     async fn triggers_in_block(
         &self,
-        logger: &Logger,
+        _logger: &Logger,
         block: SolanaBlock,
         filter: &TriggerFilter,
     ) -> Result<BlockWithTriggers<Chain>, Error> {
-        todo!()
+        let trigger_data = filter.triggers_in_block(&block);
+        Ok(BlockWithTriggers::new(block, trigger_data))
+    }
+
+    async fn is_on_main_chain(&self, ptr: BlockPtr) -> Result<bool, Error> {
+        let canonical = get_block(self.chain_adapter.cheap_clone(), ptr.number as u64).await?;
+        Ok(block_ptr(&canonical)? == ptr)
+    }
+
+    async fn parent_ptr(&self, block: &BlockPtr) -> Result<Option<BlockPtr>, Error> {
+        let block = get_block(self.chain_adapter.cheap_clone(), block.number as u64)
+            .await
+            .with_context(|| format!("Solana node did not find block {}", block))?;
+        parent_ptr(&block)
+    }
+}
+
+pub struct FirehoseMapper {
+    filter: Arc<TriggerFilter>,
+}
+
+impl FirehoseMapperTrait<Chain> for FirehoseMapper {
+    fn to_block_stream_events(
+        &self,
+        _logger: &Logger,
+        response: &BlockResponse,
+    ) -> Result<Vec<BlockStreamEvent<Chain>>, Error> {
+        // The chain-reader sends the blocks of a response in slot order, in the format
+        // given by the response.
+        let format = PayloadFormat::from_i32(response.encoding, response.compression)?;
+        let blocks: Vec<ExtBlock> = format.decode(&response.payload)?;
+        blocks
+            .into_iter()
+            .map(|block| {
+                let block = SolanaBlock::new(block)?;
+                let trigger_data = self.filter.triggers_in_block(&block);
+                Ok(BlockStreamEvent::ProcessBlock(BlockWithTriggers::new(
+                    block,
+                    trigger_data,
+                )))
+            })
+            .collect()
     }
 }
-// #[async_trait]
-// impl TriggersAdapterTrait<Chain> for TriggersAdapter {
-//     async fn scan_triggers(
-//         &self,
-//         from: BlockNumber,
-//         to: BlockNumber,
-//         filter: &TriggerFilter,
-//     ) -> Result<Vec<BlockWithTriggers<Chain>>, Error> {
-//         blocks_with_triggers(
-//             self.logger.clone(),
-//             self.eth_adapter.clone(),
-//             from,
-//             to,
-//             filter,
-//         )
-//         .await
-//     }
-//
-//     async fn triggers_in_block(
-//         &self,
-//         logger: &Logger,
-//         block: BlockFinality,
-//         filter: &TriggerFilter,
-//     ) -> Result<BlockWithTriggers<Chain>, Error> {
-//         match &block {
-//             BlockFinality::Final(_) => {
-//                 let block_number = block.number() as BlockNumber;
-//                 let blocks = blocks_with_triggers(
-//                     logger.clone(),
-//                     self.eth_adapter.clone(),
-//                     block_number,
-//                     block_number,
-//                     filter,
-//                 )
-//                 .await?;
-//                 assert!(blocks.len() == 1);
-//                 Ok(blocks.into_iter().next().unwrap())
-//             }
-//         }
-//     }
-// }
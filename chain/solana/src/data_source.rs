@@ -1,9 +1,10 @@
 use crate::chain::Chain;
 use crate::trigger::{SolanaBlockTriggerType, SolanaMappingTrigger, SolanaTriggerData};
+use crate::types::ExtBlock;
 use massbit::components::indexer::DataSourceTemplateInfo;
 use massbit::components::link_resolver::LinkResolver;
 use massbit::components::store::StoredDynamicDataSource;
-use massbit::data::indexer::{calls_host_fn, DataSourceContext, IndexerManifestValidationError};
+use massbit::data::indexer::{DataSourceContext, IndexerManifestValidationError, Link};
 use massbit::prelude::anyhow::Context;
 use std::collections::BTreeMap;
 use std::{convert::TryFrom, sync::Arc};
@@ -12,7 +13,6 @@ use massbit::{
     blockchain::{self, Blockchain},
     prelude::*,
 };
-use solana_transaction_status::EncodedConfirmedBlock;
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
 pub struct Source {
@@ -63,9 +63,8 @@ impl blockchain::DataSource<Chain> for DataSource {
         trigger: &<Chain as Blockchain>::TriggerData,
         block: Arc<<Chain as Blockchain>::Block>,
     ) -> Result<Option<<Chain as Blockchain>::MappingTrigger>, Error> {
-        todo!()
-        // let block = block.light_block();
-        // self.match_and_decode(trigger, block, logger)
+        let block = block.ext_block();
+        self.match_and_decode(trigger, block, logger)
     }
 
     fn name(&self) -> &str {
@@ -164,43 +163,37 @@ impl blockchain::DataSource<Chain> for DataSource {
             && network == &other.network
             && name == &other.name
             && source == &other.source
-            //&& mapping.abis == other.mapping.abis
-            && mapping.event_handlers == other.mapping.event_handlers
-            && mapping.call_handlers == other.mapping.call_handlers
             && mapping.block_handlers == other.mapping.block_handlers
+            && mapping.transaction_handlers == other.mapping.transaction_handlers
+            && mapping.instruction_handlers == other.mapping.instruction_handlers
+            && mapping.log_handlers == other.mapping.log_handlers
+            && mapping.account_handlers == other.mapping.account_handlers
             && context == &other.context
     }
 
     fn validate(&self) -> Vec<IndexerManifestValidationError> {
         let mut errors = vec![];
 
-        // Validate that there is a `source` address if there are call or block handlers
-        let no_source_address = self.address().is_none();
-        let has_call_handlers = !self.mapping.call_handlers.is_empty();
-        let has_block_handlers = !self.mapping.block_handlers.is_empty();
-        if no_source_address && (has_call_handlers || has_block_handlers) {
-            errors.push(IndexerManifestValidationError::SourceAddressRequired);
-        };
-
-        // Validate that there are no more than one of each type of block_handler
-        let has_too_many_block_handlers = {
-            let mut non_filtered_block_handler_count = 0;
-            let mut call_filtered_block_handler_count = 0;
-            self.mapping
-                .block_handlers
-                .iter()
-                .for_each(|block_handler| {
-                    if block_handler.filter.is_none() {
-                        non_filtered_block_handler_count += 1
-                    } else {
-                        call_filtered_block_handler_count += 1
-                    }
-                });
-            non_filtered_block_handler_count > 1 || call_filtered_block_handler_count > 1
-        };
-        if has_too_many_block_handlers {
+        // Only the first handler of each kind is ever called, so reject any others
+        if self.mapping.block_handlers.len() > 1 {
             errors.push(IndexerManifestValidationError::DataSourceBlockHandlerLimitExceeded);
         }
+        for (kind, count) in [
+            ("transaction", self.mapping.transaction_handlers.len()),
+            ("instruction", self.mapping.instruction_handlers.len()),
+            ("log", self.mapping.log_handlers.len()),
+            ("account", self.mapping.account_handlers.len()),
+        ]
+        .iter()
+        {
+            if *count > 1 {
+                errors.push(
+                    IndexerManifestValidationError::DataSourceHandlerLimitExceeded(
+                        kind.to_string(),
+                    ),
+                );
+            }
+        }
 
         errors
     }
@@ -274,21 +267,9 @@ impl DataSource {
         &self,
         trigger_type: &SolanaBlockTriggerType,
     ) -> Option<MappingBlockHandler> {
-        todo!()
-        // match trigger_type {
-        //     EthereumBlockTriggerType::Every => self
-        //         .mapping
-        //         .block_handlers
-        //         .iter()
-        //         .find(move |handler| handler.filter == None)
-        //         .cloned(),
-        //     EthereumBlockTriggerType::WithCallTo(_address) => self
-        //         .mapping
-        //         .block_handlers
-        //         .iter()
-        //         .find(move |handler| handler.filter == Some(BlockHandlerFilter::Call))
-        //         .cloned(),
-        // }
+        match trigger_type {
+            SolanaBlockTriggerType::Every => self.mapping.block_handlers.first().cloned(),
+        }
     }
 
     // /// Returns the contract event with the given signature, if it exists. A an event from the ABI
@@ -419,25 +400,32 @@ impl DataSource {
     //         })
     // }
 
-    fn matches_trigger_address(&self, trigger: &SolanaTriggerData) -> bool {
-        true
-        // let ds_address = match self.source.address {
-        //     Some(addr) => addr,
-        //
-        //     // 'wildcard' data sources match any trigger address.
-        //     None => return true,
-        // };
-        //
-        // let trigger_address = match trigger {
-        //     EthereumTrigger::Block(_, EthereumBlockTriggerType::WithCallTo(address)) => address,
-        //     EthereumTrigger::Call(call) => &call.to,
-        //     EthereumTrigger::Log(log) => &log.address,
-        //
-        //     // Unfiltered block triggers match any data source address.
-        //     EthereumTrigger::Block(_, EthereumBlockTriggerType::Every) => return true,
-        // };
-        //
-        // ds_address == *trigger_address
+    fn matches_trigger_address(&self, trigger: &SolanaTriggerData, block: &ExtBlock) -> bool {
+        let ds_address = match &self.source.address {
+            Some(addr) => addr,
+
+            // 'wildcard' data sources match any trigger address.
+            None => return true,
+        };
+
+        match trigger {
+            // Block triggers match any data source address.
+            SolanaTriggerData::Block(..) => true,
+            SolanaTriggerData::Instruction(_, instruction) => ds_address == &instruction.program_id,
+            SolanaTriggerData::Transaction(tx) | SolanaTriggerData::Log(tx) => block
+                .block
+                .transactions
+                .get(tx.index)
+                .map(|transaction| {
+                    transaction
+                        .transaction
+                        .message
+                        .account_keys
+                        .iter()
+                        .any(|key| &key.to_string() == ds_address)
+                })
+                .unwrap_or(false),
+        }
     }
 
     /// Checks if `trigger` matches this data source, and if so decodes it into a `MappingTrigger`.
@@ -445,219 +433,81 @@ impl DataSource {
     fn match_and_decode(
         &self,
         trigger: &SolanaTriggerData,
-        block: Arc<EncodedConfirmedBlock>,
-        logger: &Logger,
+        block: Arc<ExtBlock>,
+        _logger: &Logger,
     ) -> Result<Option<SolanaMappingTrigger>, Error> {
-        todo!()
-        // if !self.matches_trigger_address(&trigger) {
-        //     return Ok(None);
-        // }
-        //
-        // if self.source.start_block > block.number() {
-        //     return Ok(None);
-        // }
-        //
-        // match trigger {
-        //     EthereumTrigger::Block(_, trigger_type) => {
-        //         let handler = match self.handler_for_block(trigger_type) {
-        //             Some(handler) => handler,
-        //             None => return Ok(None),
-        //         };
-        //         Ok(Some(MappingTrigger::Block { block, handler }))
-        //     }
-        //     EthereumTrigger::Log(log) => {
-        //         let potential_handlers = self.handlers_for_log(log)?;
-        //
-        //         // Map event handlers to (event handler, event ABI) pairs; fail if there are
-        //         // handlers that don't exist in the contract ABI
-        //         let valid_handlers = potential_handlers
-        //             .into_iter()
-        //             .map(|event_handler| {
-        //                 // Identify the event ABI in the contract
-        //                 let event_abi = self
-        //                     .contract_event_with_signature(event_handler.event.as_str())
-        //                     .with_context(|| {
-        //                         anyhow!(
-        //                             "Event with the signature \"{}\" not found in \
-        //                                     contract \"{}\" of data source \"{}\"",
-        //                             event_handler.event,
-        //                             self.contract_abi.name,
-        //                             self.name,
-        //                         )
-        //                     })?;
-        //                 Ok((event_handler, event_abi))
-        //             })
-        //             .collect::<Result<Vec<_>, anyhow::Error>>()?;
-        //
-        //         // Filter out handlers whose corresponding event ABIs cannot decode the
-        //         // params (this is common for overloaded events that have the same topic0
-        //         // but have indexed vs. non-indexed params that are encoded differently).
-        //         //
-        //         // Map (handler, event ABI) pairs to (handler, decoded params) pairs.
-        //         let mut matching_handlers = valid_handlers
-        //             .into_iter()
-        //             .filter_map(|(event_handler, event_abi)| {
-        //                 event_abi
-        //                     .parse_log(RawLog {
-        //                         topics: log.topics.clone(),
-        //                         data: log.data.clone().0,
-        //                     })
-        //                     .map(|log| log.params)
-        //                     .map_err(|e| {
-        //                         trace!(
-        //                             logger,
-        //                             "Skipping handler because the event parameters do not \
-        //                             match the event signature. This is typically the case \
-        //                             when parameters are indexed in the event but not in the \
-        //                             signature or the other way around";
-        //                             "handler" => &event_handler.handler,
-        //                             "event" => &event_handler.event,
-        //                             "error" => format!("{}", e),
-        //                         );
-        //                     })
-        //                     .ok()
-        //                     .map(|params| (event_handler, params))
-        //             })
-        //             .collect::<Vec<_>>();
-        //
-        //         if matching_handlers.is_empty() {
-        //             return Ok(None);
-        //         }
-        //
-        //         // Process the event with the matching handler
-        //         let (event_handler, params) = matching_handlers.pop().unwrap();
-        //
-        //         ensure!(
-        //             matching_handlers.is_empty(),
-        //             format!(
-        //                 "Multiple handlers defined for event `{}`, only one is supported",
-        //                 &event_handler.event
-        //             )
-        //         );
-        //
-        //         // Special case: In Celo, there are Epoch Rewards events, which do not have an
-        //         // associated transaction and instead have `transaction_hash == block.hash`,
-        //         // in which case we pass a dummy transaction to the mappings.
-        //         // See also ca0edc58-0ec5-4c89-a7dd-2241797f5e50.
-        //         let transaction = if log.transaction_hash != block.hash {
-        //             block
-        //                 .transaction_for_log(&log)
-        //                 .context("Found no transaction for event")?
-        //         } else {
-        //             // Infer some fields from the log and fill the rest with zeros.
-        //             Transaction {
-        //                 hash: log.transaction_hash.unwrap(),
-        //                 block_hash: block.hash,
-        //                 block_number: block.number,
-        //                 transaction_index: log.transaction_index,
-        //                 ..Transaction::default()
-        //             }
-        //         };
-        //
-        //         Ok(Some(MappingTrigger::Log {
-        //             block,
-        //             transaction: Arc::new(transaction),
-        //             log: log.cheap_clone(),
-        //             params,
-        //             handler: event_handler,
-        //         }))
-        //     }
-        //     EthereumTrigger::Call(call) => {
-        //         // Identify the call handler for this call
-        //         let handler = match self.handler_for_call(&call)? {
-        //             Some(handler) => handler,
-        //             None => return Ok(None),
-        //         };
-        //
-        //         // Identify the function ABI in the contract
-        //         let function_abi = self
-        //             .contract_function_with_signature(handler.function.as_str())
-        //             .with_context(|| {
-        //                 anyhow!(
-        //                     "Function with the signature \"{}\" not found in \
-        //             contract \"{}\" of data source \"{}\"",
-        //                     handler.function,
-        //                     self.contract_abi.name,
-        //                     self.name
-        //                 )
-        //             })?;
-        //
-        //         // Parse the inputs
-        //         //
-        //         // Take the input for the call, chop off the first 4 bytes, then call
-        //         // `function.decode_input` to get a vector of `Token`s. Match the `Token`s
-        //         // with the `Param`s in `function.inputs` to create a `Vec<LogParam>`.
-        //         let tokens = function_abi
-        //             .decode_input(&call.input.0[4..])
-        //             .with_context(|| {
-        //                 format!(
-        //                     "Generating function inputs for the call {:?} failed, raw input: {}",
-        //                     &function_abi,
-        //                     hex::encode(&call.input.0)
-        //                 )
-        //             })?;
-        //
-        //         ensure!(
-        //             tokens.len() == function_abi.inputs.len(),
-        //             "Number of arguments in call does not match \
-        //             number of inputs in function signature."
-        //         );
-        //
-        //         let inputs = tokens
-        //             .into_iter()
-        //             .enumerate()
-        //             .map(|(i, token)| LogParam {
-        //                 name: function_abi.inputs[i].name.clone(),
-        //                 value: token,
-        //             })
-        //             .collect::<Vec<_>>();
-        //
-        //         // Parse the outputs
-        //         //
-        //         // Take the output for the call, then call `function.decode_output` to
-        //         // get a vector of `Token`s. Match the `Token`s with the `Param`s in
-        //         // `function.outputs` to create a `Vec<LogParam>`.
-        //         let tokens = function_abi
-        //             .decode_output(&call.output.0)
-        //             .with_context(|| {
-        //                 format!(
-        //                     "Decoding function outputs for the call {:?} failed, raw output: {}",
-        //                     &function_abi,
-        //                     hex::encode(&call.output.0)
-        //                 )
-        //             })?;
-        //
-        //         ensure!(
-        //             tokens.len() == function_abi.outputs.len(),
-        //             "Number of parameters in the call output does not match \
-        //                 number of outputs in the function signature."
-        //         );
-        //
-        //         let outputs = tokens
-        //             .into_iter()
-        //             .enumerate()
-        //             .map(|(i, token)| LogParam {
-        //                 name: function_abi.outputs[i].name.clone(),
-        //                 value: token,
-        //             })
-        //             .collect::<Vec<_>>();
-        //
-        //         let transaction = Arc::new(
-        //             block
-        //                 .transaction_for_call(&call)
-        //                 .context("Found no transaction for call")?,
-        //         );
-        //
-        //         Ok(Some(MappingTrigger::Call {
-        //             block,
-        //             transaction,
-        //             call: call.cheap_clone(),
-        //             inputs,
-        //             outputs,
-        //             handler,
-        //         }))
-        //     }
-        //}
+        if !self.matches_trigger_address(trigger, &block) {
+            return Ok(None);
+        }
+
+        if self.source.start_block > trigger.block_number() {
+            return Ok(None);
+        }
+
+        let transaction = |index: usize| {
+            block
+                .block
+                .transactions
+                .get(index)
+                .cloned()
+                .map(Arc::new)
+                .with_context(|| {
+                    format!(
+                        "Found no transaction {} in block #{}",
+                        index, block.block_number
+                    )
+                })
+        };
+
+        match trigger {
+            SolanaTriggerData::Block(_, trigger_type) => {
+                let handler = match self.handler_for_block(trigger_type) {
+                    Some(handler) => handler,
+                    None => return Ok(None),
+                };
+                Ok(Some(SolanaMappingTrigger::Block {
+                    block: block.cheap_clone(),
+                    handler,
+                }))
+            }
+            SolanaTriggerData::Transaction(tx) => {
+                let handler = match self.mapping.transaction_handlers.first() {
+                    Some(handler) => handler.clone(),
+                    None => return Ok(None),
+                };
+                Ok(Some(SolanaMappingTrigger::Transaction {
+                    transaction: transaction(tx.index)?,
+                    block: block.cheap_clone(),
+                    index: tx.index,
+                    handler,
+                }))
+            }
+            SolanaTriggerData::Instruction(tx, instruction) => {
+                let handler = match self.mapping.instruction_handlers.first() {
+                    Some(handler) => handler.clone(),
+                    None => return Ok(None),
+                };
+                Ok(Some(SolanaMappingTrigger::Instruction {
+                    transaction: transaction(tx.index)?,
+                    block: block.cheap_clone(),
+                    tx_index: tx.index,
+                    instruction: instruction.clone(),
+                    handler,
+                }))
+            }
+            SolanaTriggerData::Log(tx) => {
+                let handler = match self.mapping.log_handlers.first() {
+                    Some(handler) => handler.clone(),
+                    None => return Ok(None),
+                };
+                Ok(Some(SolanaMappingTrigger::Log {
+                    transaction: transaction(tx.index)?,
+                    block: block.cheap_clone(),
+                    index: tx.index,
+                    handler,
+                }))
+            }
+        }
     }
 }
 
@@ -805,14 +655,22 @@ pub struct UnresolvedMapping {
     pub api_version: String,
     pub language: String,
     pub entities: Vec<String>,
-    //pub abis: Vec<UnresolvedMappingABI>,
     #[serde(default)]
     pub block_handlers: Vec<MappingBlockHandler>,
     #[serde(default)]
-    pub call_handlers: Vec<MappingCallHandler>,
+    pub transaction_handlers: Vec<MappingTransactionHandler>,
+    #[serde(default)]
+    pub instruction_handlers: Vec<MappingInstructionHandler>,
     #[serde(default)]
-    pub event_handlers: Vec<MappingEventHandler>,
-    //pub file: Link,
+    pub log_handlers: Vec<MappingLogHandler>,
+    /// Handlers of the state of the accounts owned by the program at the address of the
+    /// data source. Only native plugins receive account updates.
+    #[serde(default)]
+    pub account_handlers: Vec<MappingAccountHandler>,
+    /// The WASM module of the mapping. Native plugins are loaded by the plugin adapter
+    /// and have none.
+    #[serde(default)]
+    pub file: Option<Link>,
 }
 
 #[derive(Clone, Debug)]
@@ -821,37 +679,13 @@ pub struct Mapping {
     pub api_version: semver::Version,
     pub language: String,
     pub entities: Vec<String>,
-    //pub abis: Vec<Arc<MappingABI>>,
     pub block_handlers: Vec<MappingBlockHandler>,
-    pub call_handlers: Vec<MappingCallHandler>,
-    pub event_handlers: Vec<MappingEventHandler>,
+    pub transaction_handlers: Vec<MappingTransactionHandler>,
+    pub instruction_handlers: Vec<MappingInstructionHandler>,
+    pub log_handlers: Vec<MappingLogHandler>,
+    pub account_handlers: Vec<MappingAccountHandler>,
     pub runtime: Arc<Vec<u8>>,
-    //pub link: Link,
-}
-
-impl Mapping {
-    pub fn requires_archive(&self) -> anyhow::Result<bool> {
-        calls_host_fn(&self.runtime, "ethereum.call")
-    }
-
-    pub fn has_call_handler(&self) -> bool {
-        !self.call_handlers.is_empty()
-    }
-
-    pub fn has_block_handler_with_call_filter(&self) -> bool {
-        self.block_handlers
-            .iter()
-            .any(|handler| matches!(handler.filter, Some(BlockHandlerFilter::Call)))
-    }
-
-    // pub fn find_abi(&self, abi_name: &str) -> Result<Arc<MappingABI>, Error> {
-    //     Ok(self
-    //         .abis
-    //         .iter()
-    //         .find(|abi| abi.name == abi_name)
-    //         .ok_or_else(|| anyhow!("No ABI entry with name `{}` found", abi_name))?
-    //         .cheap_clone())
-    // }
+    pub link: Option<Link>,
 }
 
 impl UnresolvedMapping {
@@ -865,35 +699,23 @@ impl UnresolvedMapping {
             api_version,
             language,
             entities,
-            //abis,
             block_handlers,
-            call_handlers,
-            event_handlers,
-            //file: link,
+            transaction_handlers,
+            instruction_handlers,
+            log_handlers,
+            account_handlers,
+            file: link,
         } = self;
 
-        //info!(logger, "Resolve mapping"; "link" => &link.link);
-
         let api_version = semver::Version::parse(&api_version)?;
 
-        // let (abis, runtime) = try_join(
-        //     // resolve each abi
-        //     abis.into_iter()
-        //         .map(|unresolved_abi| async {
-        //             Result::<_, Error>::Ok(Arc::new(
-        //                 unresolved_abi.resolve(resolver, logger).await?,
-        //             ))
-        //         })
-        //         .collect::<FuturesOrdered<_>>()
-        //         .try_collect::<Vec<_>>(),
-        //     async {
-        //         let module_bytes = resolver.cat(&logger, &link).await?;
-        //         Ok(Arc::new(module_bytes))
-        //     },
-        // )
-        //     .await?;
-        //let module_bytes = resolver.cat(&logger, &link).await?;
-        let module_bytes = vec![];
+        let module_bytes = match &link {
+            Some(link) => {
+                info!(logger, "Resolve mapping"; "link" => &link.link);
+                resolver.cat(&logger, link).await?
+            }
+            None => vec![],
+        };
         let runtime = Arc::new(module_bytes);
 
         Ok(Mapping {
@@ -901,12 +723,13 @@ impl UnresolvedMapping {
             api_version,
             language,
             entities,
-            //abis,
-            block_handlers: block_handlers.clone(),
-            call_handlers: call_handlers.clone(),
-            event_handlers: event_handlers.clone(),
+            block_handlers,
+            transaction_handlers,
+            instruction_handlers,
+            log_handlers,
+            account_handlers,
             runtime,
-            //link,
+            link,
         })
     }
 }
@@ -948,49 +771,27 @@ impl UnresolvedMappingABI {
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
 pub struct MappingBlockHandler {
     pub handler: String,
-    pub filter: Option<BlockHandlerFilter>,
 }
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
-#[serde(tag = "kind", rename_all = "lowercase")]
-pub enum BlockHandlerFilter {
-    // Call filter will trigger on all blocks where the data source contract
-    // address has been called
-    Call,
+pub struct MappingTransactionHandler {
+    pub handler: String,
 }
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
-pub struct MappingCallHandler {
-    pub function: String,
+pub struct MappingInstructionHandler {
     pub handler: String,
 }
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
-pub struct MappingEventHandler {
-    pub event: String,
+pub struct MappingLogHandler {
     pub handler: String,
 }
 
-// impl MappingEventHandler {
-//     pub fn topic0(&self) -> H256 {
-//         self.topic0
-//             .unwrap_or_else(|| string_to_h256(&self.event.replace("indexed ", "")))
-//     }
-// }
-
-// /// Hashes a string to a H256 hash.
-// fn string_to_h256(s: &str) -> H256 {
-//     let mut result = [0u8; 32];
-//     let data = s.replace(" ", "").into_bytes();
-//     let mut sponge = Keccak::new_keccak256();
-//     sponge.update(&data);
-//     sponge.finalize(&mut result);
-//
-//     // This was deprecated but the replacement seems to not be available in the
-//     // version web3 uses.
-//     #[allow(deprecated)]
-//     H256::from_slice(&result)
-// }
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
+pub struct MappingAccountHandler {
+    pub handler: String,
+}
 
 #[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Deserialize)]
 pub struct TemplateSource {
@@ -2,6 +2,7 @@ pub mod adapter;
 pub mod chain;
 pub mod data_source;
 pub mod manifest;
+pub mod runtime;
 pub mod trigger;
 pub mod types;
 
@@ -14,9 +15,11 @@ use std::collections::HashMap;
 use std::iter::FromIterator;
 use types::ChainConfig;
 
+/// Version of the block format, matching the blocks sent by the chain-reader.
+const VERSION: &str = "1.7.0";
 const TRANSACTION_BATCH_SIZE: usize = 100;
 // The max value is 1000
-const LIMIT_FILTER_RESULT: usize = 1000;
+pub const LIMIT_FILTER_RESULT: usize = 1000;
 
 lazy_static! {
     // Load default config
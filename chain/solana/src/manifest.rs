@@ -98,6 +98,8 @@ impl ManifestUnresolve for UnresolvedSolanaIndexerManifest {
             schema,
             data_sources,
             templates,
+            features,
+            graft,
             chain,
         } = self;
 
@@ -134,6 +136,8 @@ impl ManifestUnresolve for UnresolvedSolanaIndexerManifest {
             schema,
             data_sources,
             templates,
+            features,
+            graft,
             chain,
         })
     }
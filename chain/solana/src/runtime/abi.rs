@@ -0,0 +1,201 @@
+use massbit::prelude::BigInt;
+use massbit::runtime::{asc_new, AscPtr, DeterministicHostError, ToAscObj};
+use massbit::runtime::{AscHeap, AscIndexId, AscType, IndexForAscTypeId};
+use runtime_derive::AscType;
+use runtime_wasm::asc_abi::class::{Array, AscBigInt, AscString, Uint8Array};
+
+use crate::trigger::{
+    SolanaBlockData, SolanaInstructionData, SolanaLogData, SolanaTransactionData,
+};
+
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscSolanaBlock {
+    pub hash: AscPtr<AscString>,
+    pub previous_hash: AscPtr<AscString>,
+    pub slot: AscPtr<AscBigInt>,
+    pub parent_slot: AscPtr<AscBigInt>,
+    pub timestamp: AscPtr<AscBigInt>,
+    pub block_height: AscPtr<AscBigInt>,
+}
+
+impl AscIndexId for AscSolanaBlock {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SolanaBlock;
+}
+
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscSolanaTransaction {
+    pub block: AscPtr<AscSolanaBlock>,
+    pub index: AscPtr<AscBigInt>,
+    pub signatures: AscPtr<Array<AscPtr<AscString>>>,
+    pub account_keys: AscPtr<Array<AscPtr<AscString>>>,
+    pub recent_blockhash: AscPtr<AscString>,
+    pub fee: AscPtr<AscBigInt>,
+    pub error: AscPtr<AscString>,
+    pub log_messages: AscPtr<Array<AscPtr<AscString>>>,
+}
+
+impl AscIndexId for AscSolanaTransaction {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SolanaTransaction;
+}
+
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscSolanaInstruction {
+    pub transaction: AscPtr<AscSolanaTransaction>,
+    pub program_id: AscPtr<AscString>,
+    pub accounts: AscPtr<Array<AscPtr<AscString>>>,
+    pub data: AscPtr<Uint8Array>,
+    pub index: AscPtr<AscBigInt>,
+    pub inner_index: AscPtr<AscBigInt>,
+}
+
+impl AscIndexId for AscSolanaInstruction {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SolanaInstruction;
+}
+
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscSolanaLog {
+    pub transaction: AscPtr<AscSolanaTransaction>,
+    pub messages: AscPtr<Array<AscPtr<AscString>>>,
+}
+
+impl AscIndexId for AscSolanaLog {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SolanaLog;
+}
+
+/// Arguments of `solana.findProgramAddress` and `solana.createProgramAddress`
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscProgramAddressParams {
+    pub seeds: AscPtr<Array<AscPtr<Uint8Array>>>,
+    pub program_id: AscPtr<AscString>,
+}
+
+impl AscIndexId for AscProgramAddressParams {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SolanaProgramAddressParams;
+}
+
+/// Result of `solana.findProgramAddress`
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscProgramAddress {
+    pub address: AscPtr<AscString>,
+    pub bump: i32,
+}
+
+impl AscIndexId for AscProgramAddress {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SolanaProgramAddress;
+}
+
+/// Arguments of `solana.borshDecode`
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscBorshDecodeParams {
+    pub descriptor: AscPtr<AscString>,
+    pub data: AscPtr<Uint8Array>,
+}
+
+impl AscIndexId for AscBorshDecodeParams {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SolanaBorshDecodeParams;
+}
+
+/// Arguments of `solana.readIntLE` and `solana.readUintLE`
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscReadIntParams {
+    pub data: AscPtr<Uint8Array>,
+    pub offset: i32,
+    pub size: i32,
+}
+
+impl AscIndexId for AscReadIntParams {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SolanaReadIntParams;
+}
+
+/// Arguments of `solana.verifySignature`
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscSignatureParams {
+    pub public_key: AscPtr<AscString>,
+    pub message: AscPtr<Uint8Array>,
+    pub signature: AscPtr<Uint8Array>,
+}
+
+impl AscIndexId for AscSignatureParams {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::SolanaSignatureParams;
+}
+
+impl ToAscObj<AscSolanaBlock> for SolanaBlockData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscSolanaBlock, DeterministicHostError> {
+        Ok(AscSolanaBlock {
+            hash: asc_new(heap, &self.hash)?,
+            previous_hash: asc_new(heap, &self.previous_hash)?,
+            slot: asc_new(heap, &BigInt::from(self.slot))?,
+            parent_slot: asc_new(heap, &BigInt::from(self.parent_slot))?,
+            timestamp: asc_new(heap, &BigInt::from(self.timestamp))?,
+            block_height: self
+                .block_height
+                .map(|height| asc_new(heap, &BigInt::from(height)))
+                .unwrap_or(Ok(AscPtr::null()))?,
+        })
+    }
+}
+
+impl ToAscObj<AscSolanaTransaction> for SolanaTransactionData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscSolanaTransaction, DeterministicHostError> {
+        Ok(AscSolanaTransaction {
+            block: asc_new(heap, &self.block)?,
+            index: asc_new(heap, &BigInt::from(self.index))?,
+            signatures: asc_new(heap, &*self.signatures)?,
+            account_keys: asc_new(heap, &*self.account_keys)?,
+            recent_blockhash: asc_new(heap, &self.recent_blockhash)?,
+            fee: asc_new(heap, &BigInt::from(self.fee))?,
+            error: self
+                .error
+                .as_ref()
+                .map(|error| asc_new(heap, error))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            log_messages: asc_new(heap, &*self.log_messages)?,
+        })
+    }
+}
+
+impl ToAscObj<AscSolanaInstruction> for SolanaInstructionData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscSolanaInstruction, DeterministicHostError> {
+        Ok(AscSolanaInstruction {
+            transaction: asc_new(heap, &self.transaction)?,
+            program_id: asc_new(heap, &self.program_id)?,
+            accounts: asc_new(heap, &*self.accounts)?,
+            data: asc_new(heap, &*self.data)?,
+            index: asc_new(heap, &BigInt::from(self.index))?,
+            inner_index: self
+                .inner_index
+                .map(|index| asc_new(heap, &BigInt::from(index)))
+                .unwrap_or(Ok(AscPtr::null()))?,
+        })
+    }
+}
+
+impl ToAscObj<AscSolanaLog> for SolanaLogData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscSolanaLog, DeterministicHostError> {
+        Ok(AscSolanaLog {
+            transaction: asc_new(heap, &self.transaction)?,
+            messages: asc_new(heap, &*self.messages)?,
+        })
+    }
+}
@@ -0,0 +1,132 @@
+//! Host fns of Solana mappings, see `RuntimeAdapter::host_fns`
+
+use massbit::blockchain::HostFnCtx;
+use massbit::prelude::*;
+use massbit::runtime::{
+    asc_get, asc_new, AscHeap, AscPtr, DeterministicHostError, HostExportError,
+};
+use runtime_wasm::asc_abi::class::{AscBigInt, AscEnum, AscString, JsonValueKind, Uint8Array};
+use runtime_wasm::host_exports;
+use solana_program::pubkey::{Pubkey, MAX_SEEDS, MAX_SEED_LEN};
+use std::str::FromStr;
+
+use super::abi::{
+    AscBorshDecodeParams, AscProgramAddress, AscProgramAddressParams, AscReadIntParams,
+    AscSignatureParams,
+};
+
+/// function base58.decode(s: string): Bytes
+pub(crate) fn base58_decode(
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<Uint8Array>, HostExportError> {
+    let s: String = asc_get::<_, AscString, _>(ctx.heap, wasm_ptr.into())?;
+    let bytes = host_exports::base58_decode(&s)?;
+    Ok(asc_new(ctx.heap, bytes.as_slice())?)
+}
+
+/// function crypto.sha256(input: Bytes): Bytes
+pub(crate) fn crypto_sha256(
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<Uint8Array>, HostExportError> {
+    let input: Vec<u8> = asc_get::<_, Uint8Array, _>(ctx.heap, wasm_ptr.into())?;
+    let hash = host_exports::crypto_sha256(&input);
+    Ok(asc_new(ctx.heap, hash.as_ref())?)
+}
+
+/// function solana.findProgramAddress(params: ProgramAddressParams): ProgramAddress
+pub(crate) fn find_program_address(
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<AscProgramAddress>, HostExportError> {
+    let (seeds, program_id) = program_address_params(ctx.heap, wasm_ptr)?;
+    let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+    // There is no bump seed left for the last seed
+    if seeds.len() >= MAX_SEEDS {
+        return Err(DeterministicHostError(anyhow!(
+            "Failed to find program address: at most {} seeds are allowed",
+            MAX_SEEDS - 1
+        ))
+        .into());
+    }
+    let (address, bump) = Pubkey::find_program_address(&seeds, &program_id);
+    let address = asc_new(ctx.heap, &address.to_string())?;
+    let program_address = AscProgramAddress {
+        address,
+        bump: bump as i32,
+    };
+    Ok(AscPtr::alloc_obj(program_address, ctx.heap)?)
+}
+
+/// function solana.createProgramAddress(params: ProgramAddressParams): string | null
+///
+/// Returns null if the seeds lead to an address which is not a valid
+/// program address.
+pub(crate) fn create_program_address(
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<AscString>, HostExportError> {
+    let (seeds, program_id) = program_address_params(ctx.heap, wasm_ptr)?;
+    let seeds: Vec<&[u8]> = seeds.iter().map(Vec::as_slice).collect();
+    match Pubkey::create_program_address(&seeds, &program_id) {
+        Ok(address) => Ok(asc_new(ctx.heap, &address.to_string())?),
+        Err(_) => Ok(AscPtr::null()),
+    }
+}
+
+fn program_address_params(
+    heap: &dyn AscHeap,
+    wasm_ptr: u32,
+) -> Result<(Vec<Vec<u8>>, Pubkey), DeterministicHostError> {
+    let params = AscPtr::<AscProgramAddressParams>::new(wasm_ptr).read_ptr(heap)?;
+    let seeds: Vec<Vec<u8>> = asc_get(heap, params.seeds)?;
+    let program_id: String = asc_get(heap, params.program_id)?;
+    if let Some(seed) = seeds.iter().find(|seed| seed.len() > MAX_SEED_LEN) {
+        return Err(DeterministicHostError(anyhow!(
+            "Invalid seed of {} bytes, seeds are at most {} bytes",
+            seed.len(),
+            MAX_SEED_LEN
+        )));
+    }
+    let program_id = Pubkey::from_str(&program_id).map_err(|e| {
+        DeterministicHostError(anyhow!("Invalid program id `{}`: {}", program_id, e))
+    })?;
+    Ok((seeds, program_id))
+}
+
+/// function solana.borshDecode(params: BorshDecodeParams): JSONValue
+pub(crate) fn borsh_decode(
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+) -> Result<AscPtr<AscEnum<JsonValueKind>>, HostExportError> {
+    let params = AscPtr::<AscBorshDecodeParams>::new(wasm_ptr).read_ptr(ctx.heap)?;
+    let descriptor: String = asc_get(ctx.heap, params.descriptor)?;
+    let data: Vec<u8> = asc_get(ctx.heap, params.data)?;
+    let value = host_exports::borsh_decode(&descriptor, &data)?;
+    Ok(asc_new(ctx.heap, &value)?)
+}
+
+/// function solana.readIntLE(params: ReadIntParams): BigInt
+/// function solana.readUintLE(params: ReadIntParams): BigInt
+pub(crate) fn read_int_le(
+    ctx: HostFnCtx<'_>,
+    wasm_ptr: u32,
+    signed: bool,
+) -> Result<AscPtr<AscBigInt>, HostExportError> {
+    let params = AscPtr::<AscReadIntParams>::new(wasm_ptr).read_ptr(ctx.heap)?;
+    let data: Vec<u8> = asc_get(ctx.heap, params.data)?;
+    let value = host_exports::read_int_le(&data, params.offset, params.size, signed)?;
+    Ok(asc_new(ctx.heap, &value)?)
+}
+
+/// function solana.verifySignature(params: SignatureParams): bool
+pub(crate) fn verify_signature(ctx: HostFnCtx<'_>, wasm_ptr: u32) -> Result<bool, HostExportError> {
+    let params = AscPtr::<AscSignatureParams>::new(wasm_ptr).read_ptr(ctx.heap)?;
+    let public_key: String = asc_get(ctx.heap, params.public_key)?;
+    let message: Vec<u8> = asc_get(ctx.heap, params.message)?;
+    let signature: Vec<u8> = asc_get(ctx.heap, params.signature)?;
+    let public_key = host_exports::base58_decode(&public_key)?;
+    let valid = host_exports::ed25519_verify(&public_key, &message, &signature)?;
+    Ok(valid)
+}
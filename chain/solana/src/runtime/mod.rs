@@ -0,0 +1,2 @@
+pub mod abi;
+pub(crate) mod host_fns;
@@ -1,15 +1,28 @@
-use crate::chain::Chain;
-use crate::data_source::DataSource;
+use crate::chain::{Chain, SolanaBlock};
+use crate::data_source::{
+    DataSource, MappingBlockHandler, MappingInstructionHandler, MappingLogHandler,
+    MappingTransactionHandler,
+};
+use crate::runtime::abi::{AscSolanaInstruction, AscSolanaLog, AscSolanaTransaction};
+use crate::types::{ExtBlock, Pubkey};
 use massbit::blockchain as bc;
-use massbit::blockchain::TriggerData;
-use massbit::prelude::prost::alloc::fmt::Formatter;
+use massbit::blockchain::{Block, TriggerData};
 use massbit::prelude::*;
-use massbit::runtime::{AscHeap, AscPtr, DeterministicHostError};
+use massbit::runtime::{asc_new, AscHeap, AscPtr, DeterministicHostError};
+use massbit::slog::{o, SendSyncRefUnwindSafeKV};
+use solana_program::instruction::CompiledInstruction;
+use solana_transaction_status::TransactionWithStatusMeta;
 use std::cmp::Ordering;
+use std::str::FromStr;
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct TriggerFilter {
-    addresses: Vec<String>,
+    /// Accounts whose transactions are of interest. Serialized as `keys` so that
+    /// the chain-reader can decode the filter as its own `SolanaFilter`.
+    #[serde(rename = "keys")]
+    addresses: Vec<Pubkey>,
+    #[serde(default)]
+    trigger_every_block: bool,
 }
 
 impl bc::TriggerFilter<Chain> for TriggerFilter {
@@ -22,26 +35,190 @@ impl bc::TriggerFilter<Chain> for TriggerFilter {
     fn extend<'a>(&mut self, data_sources: impl Iterator<Item = &'a DataSource> + Clone) {
         data_sources.for_each(|ds| {
             if let Some(addr) = &ds.source.address {
-                self.addresses.push(addr.clone());
+                if let Ok(key) = Pubkey::from_str(addr) {
+                    self.addresses.push(key);
+                }
             }
+            self.trigger_every_block |= !ds.mapping.block_handlers.is_empty();
         });
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct SolanaTriggerData {}
+impl TriggerFilter {
+    /// An empty filter matches every account.
+    fn matches(&self, key: &Pubkey) -> bool {
+        self.addresses.is_empty() || self.addresses.contains(key)
+    }
 
-impl Ord for SolanaTriggerData {
-    fn cmp(&self, other: &Self) -> Ordering {
-        todo!()
+    /// Extracts all triggers from `block` that match this filter.
+    pub(crate) fn triggers_in_block(&self, solana_block: &SolanaBlock) -> Vec<SolanaTriggerData> {
+        let block_ptr = solana_block.ptr();
+        let block_number = block_ptr.number;
+        let block = solana_block.ext_block();
+        let mut triggers = Vec::new();
+
+        for (tx_index, transaction) in block.block.transactions.iter().enumerate() {
+            let message = &transaction.transaction.message;
+            if !message.account_keys.iter().any(|key| self.matches(key)) {
+                continue;
+            }
+
+            let tx = TransactionRef {
+                block_number,
+                index: tx_index,
+                signature: transaction
+                    .transaction
+                    .signatures
+                    .first()
+                    .map(|signature| signature.to_string())
+                    .unwrap_or_default(),
+            };
+
+            let meta = transaction.meta.as_ref();
+            let succeeded = meta.map(|meta| meta.status.is_ok()).unwrap_or(false);
+
+            // Instructions of failed transactions had no effect on the chain
+            if succeeded {
+                for (index, instruction) in message.instructions.iter().enumerate() {
+                    if let Some(program_id) = program_id(transaction, instruction) {
+                        if self.matches(&program_id) {
+                            triggers.push(SolanaTriggerData::Instruction(
+                                tx.clone(),
+                                InstructionRef {
+                                    program_id: program_id.to_string(),
+                                    index,
+                                    inner_index: None,
+                                },
+                            ));
+                        }
+                    }
+                }
+                let inner_instructions = meta.and_then(|meta| meta.inner_instructions.as_ref());
+                for inner in inner_instructions.into_iter().flatten() {
+                    for (inner_index, instruction) in inner.instructions.iter().enumerate() {
+                        if let Some(program_id) = program_id(transaction, instruction) {
+                            if self.matches(&program_id) {
+                                triggers.push(SolanaTriggerData::Instruction(
+                                    tx.clone(),
+                                    InstructionRef {
+                                        program_id: program_id.to_string(),
+                                        index: inner.index as usize,
+                                        inner_index: Some(inner_index),
+                                    },
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+
+            let has_logs = meta
+                .and_then(|meta| meta.log_messages.as_ref())
+                .map(|messages| !messages.is_empty())
+                .unwrap_or(false);
+            if has_logs {
+                triggers.push(SolanaTriggerData::Log(tx.clone()));
+            }
+
+            triggers.push(SolanaTriggerData::Transaction(tx));
+        }
+
+        if self.trigger_every_block {
+            triggers.push(SolanaTriggerData::Block(
+                block_ptr,
+                SolanaBlockTriggerType::Every,
+            ));
+        }
+
+        triggers
+    }
+}
+
+fn program_id(
+    transaction: &TransactionWithStatusMeta,
+    instruction: &CompiledInstruction,
+) -> Option<Pubkey> {
+    transaction
+        .transaction
+        .message
+        .account_keys
+        .get(instruction.program_id_index as usize)
+        .cloned()
+}
+
+/// Identifies a transaction within a block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionRef {
+    pub block_number: BlockNumber,
+    /// Position of the transaction in the block.
+    pub index: usize,
+    /// The first signature, which is the id of the transaction.
+    pub signature: String,
+}
+
+/// Identifies an instruction within a transaction. Inner instructions carry the
+/// index of the top-level instruction that invoked them.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstructionRef {
+    pub program_id: String,
+    pub index: usize,
+    pub inner_index: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SolanaTriggerData {
+    Block(BlockPtr, SolanaBlockTriggerType),
+    Transaction(TransactionRef),
+    Instruction(TransactionRef, InstructionRef),
+    Log(TransactionRef),
+}
+
+impl SolanaTriggerData {
+    pub fn block_number(&self) -> BlockNumber {
+        match self {
+            SolanaTriggerData::Block(block_ptr, _) => block_ptr.number,
+            SolanaTriggerData::Transaction(tx)
+            | SolanaTriggerData::Instruction(tx, _)
+            | SolanaTriggerData::Log(tx) => tx.block_number,
+        }
+    }
+
+    fn transaction(&self) -> Option<&TransactionRef> {
+        match self {
+            SolanaTriggerData::Block(..) => None,
+            SolanaTriggerData::Transaction(tx)
+            | SolanaTriggerData::Instruction(tx, _)
+            | SolanaTriggerData::Log(tx) => Some(tx),
+        }
+    }
+
+    /// Triggers of a transaction are processed in execution order: its instructions
+    /// (each followed by the instructions it invoked), then its logs and finally the
+    /// transaction itself.
+    fn sort_key(&self) -> (usize, u8, usize, Option<usize>) {
+        match self {
+            SolanaTriggerData::Block(..) => (usize::MAX, 0, 0, None),
+            SolanaTriggerData::Instruction(tx, instruction) => {
+                (tx.index, 0, instruction.index, instruction.inner_index)
+            }
+            SolanaTriggerData::Log(tx) => (tx.index, 1, 0, None),
+            SolanaTriggerData::Transaction(tx) => (tx.index, 2, 0, None),
+        }
     }
 }
 
-impl Eq for SolanaTriggerData {}
+impl Ord for SolanaTriggerData {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            // Keep the order when comparing two block triggers
+            (Self::Block(..), Self::Block(..)) => Ordering::Equal,
+
+            // Block triggers always come last
+            (Self::Block(..), _) => Ordering::Greater,
+            (_, Self::Block(..)) => Ordering::Less,
 
-impl PartialEq<Self> for SolanaTriggerData {
-    fn eq(&self, other: &Self) -> bool {
-        todo!()
+            _ => self.sort_key().cmp(&other.sort_key()),
+        }
     }
 }
 
@@ -53,23 +230,168 @@ impl PartialOrd for SolanaTriggerData {
 
 impl TriggerData for SolanaTriggerData {
     fn error_context(&self) -> String {
-        todo!()
+        match self.transaction() {
+            Some(tx) => format!("block #{}, transaction {}", tx.block_number, tx.signature),
+            None => String::new(),
+        }
     }
 }
-pub struct SolanaMappingTrigger {}
+
+pub enum SolanaMappingTrigger {
+    Block {
+        block: Arc<ExtBlock>,
+        handler: MappingBlockHandler,
+    },
+    Transaction {
+        block: Arc<ExtBlock>,
+        transaction: Arc<TransactionWithStatusMeta>,
+        index: usize,
+        handler: MappingTransactionHandler,
+    },
+    Instruction {
+        block: Arc<ExtBlock>,
+        transaction: Arc<TransactionWithStatusMeta>,
+        tx_index: usize,
+        instruction: InstructionRef,
+        handler: MappingInstructionHandler,
+    },
+    Log {
+        block: Arc<ExtBlock>,
+        transaction: Arc<TransactionWithStatusMeta>,
+        index: usize,
+        handler: MappingLogHandler,
+    },
+}
+
+// Logging the block is too verbose, so this only shows the handler and what it is called for.
 impl std::fmt::Debug for SolanaMappingTrigger {
-    fn fmt(&self, f: &mut Formatter<'_>) -> prost::alloc::fmt::Result {
-        todo!()
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SolanaMappingTrigger::Block { block, handler } => f
+                .debug_struct("Block")
+                .field("block_number", &block.block_number)
+                .field("handler", handler)
+                .finish(),
+            SolanaMappingTrigger::Transaction {
+                transaction,
+                handler,
+                ..
+            } => f
+                .debug_struct("Transaction")
+                .field("signature", &signature(transaction))
+                .field("handler", handler)
+                .finish(),
+            SolanaMappingTrigger::Instruction {
+                transaction,
+                instruction,
+                handler,
+                ..
+            } => f
+                .debug_struct("Instruction")
+                .field("signature", &signature(transaction))
+                .field("instruction", instruction)
+                .field("handler", handler)
+                .finish(),
+            SolanaMappingTrigger::Log {
+                transaction,
+                handler,
+                ..
+            } => f
+                .debug_struct("Log")
+                .field("signature", &signature(transaction))
+                .field("handler", handler)
+                .finish(),
+        }
     }
 }
 
+fn signature(transaction: &TransactionWithStatusMeta) -> String {
+    transaction
+        .transaction
+        .signatures
+        .first()
+        .map(|signature| signature.to_string())
+        .unwrap_or_default()
+}
+
 impl bc::MappingTrigger for SolanaMappingTrigger {
     fn handler_name(&self) -> &str {
-        todo!()
+        match self {
+            SolanaMappingTrigger::Block { handler, .. } => &handler.handler,
+            SolanaMappingTrigger::Transaction { handler, .. } => &handler.handler,
+            SolanaMappingTrigger::Instruction { handler, .. } => &handler.handler,
+            SolanaMappingTrigger::Log { handler, .. } => &handler.handler,
+        }
     }
 
     fn to_asc_ptr<H: AscHeap>(self, heap: &mut H) -> Result<AscPtr<()>, DeterministicHostError> {
-        todo!()
+        Ok(match self {
+            SolanaMappingTrigger::Block { block, handler: _ } => {
+                let block = SolanaBlockData::from(block.as_ref());
+                asc_new(heap, &block)?.erase()
+            }
+            SolanaMappingTrigger::Transaction {
+                block,
+                transaction,
+                index,
+                handler: _,
+            } => {
+                let transaction = SolanaTransactionData::new(&block, &transaction, index);
+                asc_new::<AscSolanaTransaction, _, _>(heap, &transaction)?.erase()
+            }
+            SolanaMappingTrigger::Instruction {
+                block,
+                transaction,
+                tx_index,
+                instruction,
+                handler: _,
+            } => {
+                let instruction =
+                    SolanaInstructionData::new(&block, &transaction, tx_index, &instruction)
+                        .ok_or_else(|| {
+                            DeterministicHostError(anyhow!(
+                                "instruction {:?} not found in transaction {}",
+                                instruction,
+                                signature(&transaction)
+                            ))
+                        })?;
+                asc_new::<AscSolanaInstruction, _, _>(heap, &instruction)?.erase()
+            }
+            SolanaMappingTrigger::Log {
+                block,
+                transaction,
+                index,
+                handler: _,
+            } => {
+                let log = SolanaLogData {
+                    messages: transaction
+                        .meta
+                        .as_ref()
+                        .and_then(|meta| meta.log_messages.clone())
+                        .unwrap_or_default(),
+                    transaction: SolanaTransactionData::new(&block, &transaction, index),
+                };
+                asc_new::<AscSolanaLog, _, _>(heap, &log)?.erase()
+            }
+        })
+    }
+
+    fn logging_extras(&self) -> Box<dyn SendSyncRefUnwindSafeKV> {
+        match self {
+            SolanaMappingTrigger::Block { .. } => Box::new(o! {}),
+            SolanaMappingTrigger::Transaction { transaction, .. }
+            | SolanaMappingTrigger::Log { transaction, .. } => Box::new(o! {
+                "signature" => signature(transaction),
+            }),
+            SolanaMappingTrigger::Instruction {
+                transaction,
+                instruction,
+                ..
+            } => Box::new(o! {
+                "signature" => signature(transaction),
+                "program_id" => instruction.program_id.clone(),
+            }),
+        }
     }
 }
 
@@ -77,3 +399,128 @@ impl bc::MappingTrigger for SolanaMappingTrigger {
 pub enum SolanaBlockTriggerType {
     Every,
 }
+
+/// Solana block data.
+#[derive(Clone, Debug, Default)]
+pub struct SolanaBlockData {
+    pub hash: String,
+    pub previous_hash: String,
+    pub slot: u64,
+    pub parent_slot: u64,
+    pub timestamp: i64,
+    pub block_height: Option<u64>,
+}
+
+impl From<&'_ ExtBlock> for SolanaBlockData {
+    fn from(block: &ExtBlock) -> SolanaBlockData {
+        SolanaBlockData {
+            hash: block.block.blockhash.clone(),
+            previous_hash: block.block.previous_blockhash.clone(),
+            slot: block.block_number,
+            parent_slot: block.block.parent_slot,
+            timestamp: block.timestamp,
+            block_height: block.block.block_height,
+        }
+    }
+}
+
+/// Solana transaction data.
+#[derive(Clone, Debug)]
+pub struct SolanaTransactionData {
+    pub block: SolanaBlockData,
+    pub index: u64,
+    pub signatures: Vec<String>,
+    pub account_keys: Vec<String>,
+    pub recent_blockhash: String,
+    pub fee: u64,
+    /// The reason the transaction failed, if it did.
+    pub error: Option<String>,
+    pub log_messages: Vec<String>,
+}
+
+impl SolanaTransactionData {
+    fn new(block: &ExtBlock, transaction: &TransactionWithStatusMeta, index: usize) -> Self {
+        let meta = transaction.meta.as_ref();
+        SolanaTransactionData {
+            block: SolanaBlockData::from(block),
+            index: index as u64,
+            signatures: transaction
+                .transaction
+                .signatures
+                .iter()
+                .map(|signature| signature.to_string())
+                .collect(),
+            account_keys: transaction
+                .transaction
+                .message
+                .account_keys
+                .iter()
+                .map(|key| key.to_string())
+                .collect(),
+            recent_blockhash: transaction.transaction.message.recent_blockhash.to_string(),
+            fee: meta.map(|meta| meta.fee).unwrap_or_default(),
+            error: meta.and_then(|meta| meta.status.as_ref().err().map(|e| e.to_string())),
+            log_messages: meta
+                .and_then(|meta| meta.log_messages.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// A single, possibly inner, instruction of a Solana transaction.
+#[derive(Clone, Debug)]
+pub struct SolanaInstructionData {
+    pub transaction: SolanaTransactionData,
+    pub program_id: String,
+    pub accounts: Vec<String>,
+    pub data: Vec<u8>,
+    pub index: u64,
+    pub inner_index: Option<u64>,
+}
+
+impl SolanaInstructionData {
+    fn new(
+        block: &ExtBlock,
+        transaction: &TransactionWithStatusMeta,
+        tx_index: usize,
+        instruction: &InstructionRef,
+    ) -> Option<Self> {
+        let compiled = match instruction.inner_index {
+            None => transaction
+                .transaction
+                .message
+                .instructions
+                .get(instruction.index)?,
+            Some(inner_index) => transaction
+                .meta
+                .as_ref()?
+                .inner_instructions
+                .as_ref()?
+                .iter()
+                .find(|inner| inner.index as usize == instruction.index)?
+                .instructions
+                .get(inner_index)?,
+        };
+        let account_keys = &transaction.transaction.message.account_keys;
+        Some(SolanaInstructionData {
+            transaction: SolanaTransactionData::new(block, transaction, tx_index),
+            program_id: instruction.program_id.clone(),
+            accounts: compiled
+                .accounts
+                .iter()
+                .filter_map(|account| account_keys.get(*account as usize))
+                .map(|key| key.to_string())
+                .collect(),
+            data: compiled.data.clone(),
+            index: instruction.index as u64,
+            inner_index: instruction.inner_index.map(|index| index as u64),
+        })
+    }
+}
+
+/// The log messages emitted by a Solana transaction.
+#[derive(Clone, Debug)]
+pub struct SolanaLogData {
+    pub transaction: SolanaTransactionData,
+    pub messages: Vec<String>,
+}
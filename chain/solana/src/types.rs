@@ -1,3 +1,5 @@
+use massbit::blockchain as bc;
+use massbit::prelude::{anyhow, BlockNumber, Error};
 use serde::{Deserialize, Serialize};
 use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
 use solana_sdk::signature::Signature;
@@ -23,6 +25,37 @@ pub struct BlockPtr {
     pub hash: String,
     pub number: BlockSlot,
 }
+/// A confirmed block together with its slot, as delivered in the payload of the
+/// chain-reader's `Stream` service.
+pub use massbit_solana_sdk::types::ExtBlock;
+
+/// Pointer to `block`, fails if its hash is not valid base58.
+pub fn block_ptr(block: &ExtBlock) -> Result<bc::BlockPtr, Error> {
+    Ok(bc::BlockPtr::from((
+        decode_blockhash(&block.block.blockhash, block.block_number)?,
+        block.block_number as BlockNumber,
+    )))
+}
+
+/// Pointer to the parent of `block`, fails if its hash is not valid base58.
+pub fn parent_ptr(block: &ExtBlock) -> Result<Option<bc::BlockPtr>, Error> {
+    // The genesis block is its own parent
+    if block.block.parent_slot >= block.block_number {
+        return Ok(None);
+    }
+    Ok(Some(bc::BlockPtr::from((
+        decode_blockhash(&block.block.previous_blockhash, block.block.parent_slot)?,
+        block.block.parent_slot as BlockNumber,
+    ))))
+}
+
+/// Solana block hashes are base58 encoded.
+fn decode_blockhash(hash: &str, slot: u64) -> Result<Vec<u8>, Error> {
+    bs58::decode(hash)
+        .into_vec()
+        .map_err(|e| anyhow!("invalid hash `{}` of block {}: {}", hash, slot, e))
+}
+
 #[derive(PartialEq, Debug, Serialize, Deserialize)]
 pub struct ConfirmedBlockWithSlot {
     pub block_slot: u64,
@@ -52,6 +85,9 @@ impl ConfirmedBlockWithSlot {
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct SolanaFilter {
     pub keys: Vec<Pubkey>,
+    /// Programs whose accounts the chain reader delivers with the blocks
+    #[serde(default)]
+    pub account_owners: Vec<Pubkey>,
 }
 impl SolanaFilter {
     pub fn new(keys: Vec<&str>) -> Self {
@@ -60,6 +96,7 @@ impl SolanaFilter {
                 .iter()
                 .map(|key| Pubkey::from_str(key).unwrap_or_default())
                 .collect(),
+            account_owners: vec![],
         }
     }
     fn is_match(&self, tran: &TransactionWithStatusMeta) -> bool {
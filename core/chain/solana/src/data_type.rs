@@ -1,7 +1,9 @@
 use bs58;
 use log::warn;
+use massbit_grpc::firehose::payload::PayloadFormat;
 use serde::{Deserialize, Serialize};
 use serde_json;
+use solana_program::instruction::CompiledInstruction;
 use solana_transaction_status;
 use solana_transaction_status::UiInstruction::{Compiled, Parsed};
 use solana_transaction_status::{
@@ -36,9 +38,36 @@ type Transaction = solana_transaction_status::TransactionWithStatusMeta;
 type EncodedBlock = solana_transaction_status::EncodedConfirmedBlock;
 type Block = solana_transaction_status::ConfirmedBlock;
 
+/// Selects the transactions an indexer receives.
+///
+/// A transaction matches if it references one of `keys` or has an instruction selected by
+/// `program_ids` and `instruction_prefixes`, and passes the signer, status and log checks.
+/// A filter without keys, program ids and instruction prefixes accepts every transaction
+/// which passes the other checks.
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct SolanaFilter {
+    /// Accounts of which at least one must be referenced by the transaction
     pub keys: Vec<Pubkey>,
+    /// Programs of which at least one must be called by a top-level or inner instruction
+    #[serde(default)]
+    pub program_ids: Vec<Pubkey>,
+    /// Prefixes, e.g. Anchor discriminators, of which one must start the data of an
+    /// instruction of `program_ids`
+    #[serde(default)]
+    pub instruction_prefixes: Vec<Vec<u8>>,
+    /// Accounts which must all sign the transaction
+    #[serde(default)]
+    pub signers: Vec<Pubkey>,
+    /// Whether failed transactions are excluded
+    #[serde(default)]
+    pub exclude_failed: bool,
+    /// Substrings of which one must appear in the log messages of the transaction
+    #[serde(default)]
+    pub log_contains: Vec<String>,
+    /// Programs whose accounts are delivered with the blocks: after each matching
+    /// transaction which calls one of them, the writable accounts they own
+    #[serde(default)]
+    pub account_owners: Vec<Pubkey>,
 }
 impl SolanaFilter {
     pub fn new(keys: Vec<&str>) -> Self {
@@ -47,41 +76,163 @@ impl SolanaFilter {
                 .iter()
                 .map(|key| Pubkey::from_str(key).unwrap_or_default())
                 .collect(),
+            ..Default::default()
         }
     }
-    fn is_match(&self, tran: &TransactionWithStatusMeta) -> bool {
-        self.keys.iter().any(|key| {
-            tran.transaction
-                .message
-                .account_keys
-                .iter()
-                .any(|account_key| key == account_key)
+    /// Decodes the JSON filter of a block request. An empty filter accepts every transaction.
+    pub fn decode(encoded: &[u8]) -> Result<Self, serde_json::Error> {
+        if encoded.is_empty() {
+            return Ok(SolanaFilter::default());
+        }
+        serde_json::from_slice(encoded)
+    }
+    /// Returns true if the filter accepts every transaction.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+            && !self.has_instruction_filter()
+            && self.signers.is_empty()
+            && !self.exclude_failed
+            && self.log_contains.is_empty()
+    }
+    fn has_instruction_filter(&self) -> bool {
+        !self.program_ids.is_empty() || !self.instruction_prefixes.is_empty()
+    }
+    /// Checks `tran` without copying it.
+    pub fn is_match(&self, tran: &TransactionWithStatusMeta) -> bool {
+        self.matches_accounts(tran)
+            && self.matches_signers(tran)
+            && self.matches_status(tran)
+            && self.matches_logs(tran)
+    }
+    fn matches_accounts(&self, tran: &TransactionWithStatusMeta) -> bool {
+        if self.keys.is_empty() && !self.has_instruction_filter() {
+            return true;
+        }
+        let account_keys = &tran.transaction.message.account_keys;
+        self.keys.iter().any(|key| account_keys.contains(key))
+            || (self.has_instruction_filter() && self.matches_instructions(tran))
+    }
+    fn matches_instructions(&self, tran: &TransactionWithStatusMeta) -> bool {
+        instructions(tran).any(|(program_id, instruction)| {
+            (self.program_ids.is_empty() || self.program_ids.contains(program_id))
+                && (self.instruction_prefixes.is_empty()
+                    || self
+                        .instruction_prefixes
+                        .iter()
+                        .any(|prefix| instruction.data.starts_with(prefix)))
         })
     }
+    /// The accounts of `tran` which may hold state of `account_owners`: the writable
+    /// accounts of a successful transaction which calls one of them.
+    pub fn owned_account_candidates<'a>(
+        &self,
+        tran: &'a TransactionWithStatusMeta,
+    ) -> Vec<&'a Pubkey> {
+        let succeeded = tran
+            .meta
+            .as_ref()
+            .map(|meta| meta.status.is_ok())
+            .unwrap_or(true);
+        if !succeeded
+            || !instructions(tran).any(|(program_id, _)| self.account_owners.contains(program_id))
+        {
+            return vec![];
+        }
+        let message = &tran.transaction.message;
+        let header = &message.header;
+        let signed = header.num_required_signatures as usize;
+        let writable_signed = signed.saturating_sub(header.num_readonly_signed_accounts as usize);
+        let writable_unsigned = message
+            .account_keys
+            .len()
+            .saturating_sub(header.num_readonly_unsigned_accounts as usize);
+        message
+            .account_keys
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| {
+                *index < writable_signed || (*index >= signed && *index < writable_unsigned)
+            })
+            .map(|(_, key)| key)
+            .collect()
+    }
+    fn matches_signers(&self, tran: &TransactionWithStatusMeta) -> bool {
+        let message = &tran.transaction.message;
+        let signers = &message.account_keys
+            [..(message.header.num_required_signatures as usize).min(message.account_keys.len())];
+        self.signers.iter().all(|signer| signers.contains(signer))
+    }
+    fn matches_status(&self, tran: &TransactionWithStatusMeta) -> bool {
+        !self.exclude_failed
+            || tran
+                .meta
+                .as_ref()
+                .map(|meta| meta.status.is_ok())
+                .unwrap_or(true)
+    }
+    fn matches_logs(&self, tran: &TransactionWithStatusMeta) -> bool {
+        if self.log_contains.is_empty() {
+            return true;
+        }
+        tran.meta
+            .as_ref()
+            .and_then(|meta| meta.log_messages.as_ref())
+            .map(|messages| {
+                messages.iter().any(|message| {
+                    self.log_contains
+                        .iter()
+                        .any(|pattern| message.contains(pattern.as_str()))
+                })
+            })
+            .unwrap_or(false)
+    }
 
     pub fn filter_block(&self, block: Block) -> Block {
-        // If there are no key, then accept all transactions
-        if self.keys.is_empty() {
+        if self.is_empty() {
             return block;
         }
-        let mut filtered_block = block.clone();
-        filtered_block.transactions = block
-            .transactions
+        let mut filtered_block = block;
+        let transactions = std::mem::take(&mut filtered_block.transactions);
+        filtered_block.transactions = transactions
             .into_iter()
-            .filter_map(|tran| {
-                if self.is_match(&tran) {
-                    Some(tran)
-                } else {
-                    None
-                }
-            })
+            .filter(|tran| self.is_match(tran))
             .collect();
         filtered_block
     }
 }
 
-pub fn decode(payload: &mut Vec<u8>) -> Result<Vec<SolanaBlock>, Box<dyn Error>> {
-    let decode_block: Vec<SolanaBlock> = serde_json::from_slice(&payload).unwrap();
+/// The top-level and inner instructions of `tran` with the programs they call
+fn instructions(
+    tran: &TransactionWithStatusMeta,
+) -> impl Iterator<Item = (&Pubkey, &CompiledInstruction)> {
+    let message = &tran.transaction.message;
+    let inner_instructions = tran
+        .meta
+        .as_ref()
+        .and_then(|meta| meta.inner_instructions.as_ref())
+        .into_iter()
+        .flatten()
+        .flat_map(|inner| inner.instructions.iter());
+    message
+        .instructions
+        .iter()
+        .chain(inner_instructions)
+        .filter_map(move |instruction| {
+            message
+                .account_keys
+                .get(instruction.program_id_index as usize)
+                .map(|program_id| (program_id, instruction))
+        })
+}
+
+/// Decodes the payload of a `BlockResponse` with the `encoding` and `compression` of the response.
+pub fn decode(
+    payload: &[u8],
+    encoding: i32,
+    compression: i32,
+) -> Result<Vec<SolanaBlock>, Box<dyn Error>> {
+    let format = PayloadFormat::from_i32(encoding, compression)?;
+    let decode_block: Vec<SolanaBlock> = format.decode(payload)?;
     Ok(decode_block)
 }
 
@@ -262,6 +413,7 @@ pub fn convert_solana_encoded_block_to_solana_block(
         block_number: encoded_block.block_number,
         block: decode_encoded_block(encoded_block.block),
         list_log_messages: encoded_block.list_log_messages,
+        account_updates: vec![],
     }
 }
 
@@ -283,6 +435,21 @@ pub struct ExtBlock {
     pub block_number: u64,
     pub block: Block,
     pub list_log_messages: Vec<LogMessages>,
+    /// State of the accounts of the `account_owners` of the filter written by the block
+    #[serde(default)]
+    pub account_updates: Vec<AccountUpdate>,
+}
+
+/// The data of an account after a block wrote it
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub slot: u64,
+    /// Orders the updates of an account, later writes have bigger versions
+    pub write_version: u64,
 }
 
 #[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
@@ -301,3 +468,152 @@ pub struct ExtLogMessages {
     pub transaction: Transaction,
     //pub block: Arc<ExtBlock>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::message::{Message, MessageHeader};
+
+    /// A transaction signed by `keys[0]` which calls the program `keys[2]` with `data`
+    fn transaction(keys: &[Pubkey; 3], data: Vec<u8>) -> TransactionWithStatusMeta {
+        let mut tran = TransactionWithStatusMeta {
+            transaction: Default::default(),
+            meta: Some(TransactionStatusMeta {
+                log_messages: Some(vec![String::from("Program log: Instruction: Swap")]),
+                ..Default::default()
+            }),
+        };
+        tran.transaction.message = Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: keys.to_vec(),
+            instructions: vec![CompiledInstruction {
+                program_id_index: 2,
+                accounts: vec![0, 1],
+                data,
+            }],
+            ..Default::default()
+        };
+        tran
+    }
+
+    fn keys() -> [Pubkey; 3] {
+        [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ]
+    }
+
+    #[test]
+    fn empty_filter_matches_all() {
+        let keys = keys();
+        assert!(SolanaFilter::default().is_match(&transaction(&keys, vec![1])));
+        assert!(SolanaFilter::decode(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_filter_is_rejected() {
+        assert!(SolanaFilter::decode(b"{\"keys\": 1}").is_err());
+        assert!(SolanaFilter::decode(b"not a filter").is_err());
+    }
+
+    #[test]
+    fn matches_keys() {
+        let keys = keys();
+        let tran = transaction(&keys, vec![1]);
+        let filter = SolanaFilter {
+            keys: vec![keys[1]],
+            ..Default::default()
+        };
+        assert!(filter.is_match(&tran));
+        let filter = SolanaFilter {
+            keys: vec![Pubkey::new_unique()],
+            ..Default::default()
+        };
+        assert!(!filter.is_match(&tran));
+    }
+
+    #[test]
+    fn matches_program_ids_and_instruction_prefixes() {
+        let keys = keys();
+        let tran = transaction(&keys, vec![7, 8, 9]);
+        let filter = SolanaFilter {
+            program_ids: vec![keys[2]],
+            instruction_prefixes: vec![vec![7, 8]],
+            ..Default::default()
+        };
+        assert!(filter.is_match(&tran));
+        let filter = SolanaFilter {
+            program_ids: vec![keys[2]],
+            instruction_prefixes: vec![vec![8]],
+            ..Default::default()
+        };
+        assert!(!filter.is_match(&tran));
+        // The program is only an account of the instruction
+        let filter = SolanaFilter {
+            program_ids: vec![keys[1]],
+            ..Default::default()
+        };
+        assert!(!filter.is_match(&tran));
+        // A key of the transaction is enough
+        let filter = SolanaFilter {
+            keys: vec![keys[1]],
+            program_ids: vec![Pubkey::new_unique()],
+            ..Default::default()
+        };
+        assert!(filter.is_match(&tran));
+    }
+
+    #[test]
+    fn matches_signers() {
+        let keys = keys();
+        let tran = transaction(&keys, vec![1]);
+        let filter = SolanaFilter {
+            signers: vec![keys[0]],
+            ..Default::default()
+        };
+        assert!(filter.is_match(&tran));
+        let filter = SolanaFilter {
+            signers: vec![keys[0], keys[1]],
+            ..Default::default()
+        };
+        assert!(!filter.is_match(&tran));
+    }
+
+    #[test]
+    fn matches_status() {
+        let keys = keys();
+        let mut tran = transaction(&keys, vec![1]);
+        let filter = SolanaFilter {
+            exclude_failed: true,
+            ..Default::default()
+        };
+        assert!(filter.is_match(&tran));
+        tran.meta.as_mut().unwrap().status =
+            serde_json::from_str(r#"{"Err":"AccountInUse"}"#).unwrap();
+        assert!(!filter.is_match(&tran));
+        assert!(SolanaFilter::default().is_match(&tran));
+    }
+
+    #[test]
+    fn matches_logs() {
+        let keys = keys();
+        let mut tran = transaction(&keys, vec![1]);
+        let filter = SolanaFilter {
+            log_contains: vec![String::from("Instruction: Swap")],
+            ..Default::default()
+        };
+        assert!(filter.is_match(&tran));
+        let filter = SolanaFilter {
+            log_contains: vec![String::from("Instruction: Deposit")],
+            ..Default::default()
+        };
+        assert!(!filter.is_match(&tran));
+        tran.meta = None;
+        assert!(!filter.is_match(&tran));
+    }
+}
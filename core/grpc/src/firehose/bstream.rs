@@ -10,6 +10,12 @@ pub struct BlockRequest {
     pub network: ::prost::alloc::string::String,
     #[prost(bytes = "vec", tag = "5")]
     pub filter: ::prost::alloc::vec::Vec<u8>,
+    /// Encoding the client wants the payload of the responses in
+    #[prost(enumeration = "PayloadEncoding", tag = "6")]
+    pub encoding: i32,
+    /// Compression the client wants the payload of the responses with
+    #[prost(enumeration = "PayloadCompression", tag = "7")]
+    pub compression: i32,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct BlockResponse {
@@ -20,6 +26,24 @@ pub struct BlockResponse {
     ///  uint64 block_slot = 4;
     #[prost(bytes = "vec", tag = "5")]
     pub payload: ::prost::alloc::vec::Vec<u8>,
+    /// Encoding of the payload, the client must decode it with this one
+    #[prost(enumeration = "PayloadEncoding", tag = "6")]
+    pub encoding: i32,
+    /// Compression of the payload
+    #[prost(enumeration = "PayloadCompression", tag = "7")]
+    pub compression: i32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChainHeadRequest {
+    #[prost(enumeration = "ChainType", tag = "1")]
+    pub chain_type: i32,
+    #[prost(string, tag = "2")]
+    pub network: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChainHeadResponse {
+    #[prost(uint64, tag = "1")]
+    pub block_number: u64,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
@@ -27,6 +51,18 @@ pub enum ChainType {
     Solana = 0,
     Ethereum = 1,
 }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum PayloadEncoding {
+    Json = 0,
+    Bincode = 1,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum PayloadCompression {
+    Uncompressed = 0,
+    Zstd = 1,
+}
 #[doc = r" Generated client implementations."]
 pub mod stream_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -104,6 +140,21 @@ pub mod stream_client {
                 .server_streaming(request.into_request(), path, codec)
                 .await
         }
+        #[doc = " Latest block the chain reader has seen on a network"]
+        pub async fn chain_head(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ChainHeadRequest>,
+        ) -> Result<tonic::Response<super::ChainHeadResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/bstream.Stream/ChainHead");
+            self.inner.unary(request.into_request(), path, codec).await
+        }
     }
 }
 #[doc = r" Generated server implementations."]
@@ -122,6 +173,11 @@ pub mod stream_server {
             &self,
             request: tonic::Request<super::BlockRequest>,
         ) -> Result<tonic::Response<Self::BlocksStream>, tonic::Status>;
+        #[doc = " Latest block the chain reader has seen on a network"]
+        async fn chain_head(
+            &self,
+            request: tonic::Request<super::ChainHeadRequest>,
+        ) -> Result<tonic::Response<super::ChainHeadResponse>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct StreamServer<T: Stream> {
@@ -195,6 +251,37 @@ pub mod stream_server {
                     };
                     Box::pin(fut)
                 }
+                "/bstream.Stream/ChainHead" => {
+                    #[allow(non_camel_case_types)]
+                    struct ChainHeadSvc<T: Stream>(pub Arc<T>);
+                    impl<T: Stream> tonic::server::UnaryService<super::ChainHeadRequest> for ChainHeadSvc<T> {
+                        type Response = super::ChainHeadResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ChainHeadRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).chain_head(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ChainHeadSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec).apply_compression_config(
+                            accept_compression_encodings,
+                            send_compression_encodings,
+                        );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
@@ -2,6 +2,7 @@
 mod pbbstream;
 
 pub mod endpoints;
+pub mod payload;
 
 pub mod bstream {
     pub use super::pbbstream::*;
@@ -0,0 +1,172 @@
+//! Encoding of the payload of a `BlockResponse`.
+//!
+//! A client asks for an encoding and a compression in its `BlockRequest` and the server
+//! tells in each `BlockResponse` which ones it used. A server which does not know the
+//! fields leaves them unset, which means uncompressed JSON, so clients always decode
+//! the payload with the format of the response.
+
+use super::bstream::{PayloadCompression, PayloadEncoding};
+use anyhow::anyhow;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+// 0 selects the default level of zstd
+const ZSTD_LEVEL: i32 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayloadFormat {
+    pub encoding: PayloadEncoding,
+    pub compression: PayloadCompression,
+}
+
+impl Default for PayloadFormat {
+    fn default() -> Self {
+        PayloadFormat {
+            encoding: PayloadEncoding::Json,
+            compression: PayloadCompression::Uncompressed,
+        }
+    }
+}
+
+impl PayloadFormat {
+    pub fn new(encoding: PayloadEncoding, compression: PayloadCompression) -> Self {
+        PayloadFormat {
+            encoding,
+            compression,
+        }
+    }
+
+    /// Reads the format from the raw `encoding` and `compression` fields of a message.
+    pub fn from_i32(encoding: i32, compression: i32) -> Result<Self, anyhow::Error> {
+        Ok(PayloadFormat {
+            encoding: PayloadEncoding::from_i32(encoding)
+                .ok_or_else(|| anyhow!("unknown payload encoding {}", encoding))?,
+            compression: PayloadCompression::from_i32(compression)
+                .ok_or_else(|| anyhow!("unknown payload compression {}", compression))?,
+        })
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, anyhow::Error> {
+        let encoded = match self.encoding {
+            PayloadEncoding::Json => serde_json::to_vec(value)?,
+            PayloadEncoding::Bincode => bincode::serialize(value)?,
+        };
+        match self.compression {
+            PayloadCompression::Uncompressed => Ok(encoded),
+            PayloadCompression::Zstd => Ok(zstd::encode_all(encoded.as_slice(), ZSTD_LEVEL)?),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, anyhow::Error> {
+        let decompressed;
+        let encoded = match self.compression {
+            PayloadCompression::Uncompressed => payload,
+            PayloadCompression::Zstd => {
+                decompressed = zstd::decode_all(payload)?;
+                decompressed.as_slice()
+            }
+        };
+        match self.encoding {
+            PayloadEncoding::Json => Ok(serde_json::from_slice(encoded)?),
+            PayloadEncoding::Bincode => Ok(bincode::deserialize(encoded)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_account_decoder::parse_token::UiTokenAmount;
+    use solana_sdk::instruction::InstructionError;
+    use solana_sdk::transaction::TransactionError;
+    use solana_transaction_status::{
+        ConfirmedBlock, Reward, RewardType, TransactionStatusMeta, TransactionTokenBalance,
+        TransactionWithStatusMeta,
+    };
+
+    /// A block with a failed transaction, which has everything a block response can carry
+    fn block() -> ConfirmedBlock {
+        let token_balance = |amount: &str, ui_amount: f64| TransactionTokenBalance {
+            account_index: 1,
+            mint: String::from("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"),
+            ui_token_amount: UiTokenAmount {
+                ui_amount: Some(ui_amount),
+                decimals: 6,
+                amount: amount.to_string(),
+                ui_amount_string: ui_amount.to_string(),
+            },
+            owner: String::from("9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM"),
+        };
+        let reward = Reward {
+            pubkey: String::from("9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM"),
+            lamports: 2500,
+            post_balance: 1_002_500,
+            reward_type: Some(RewardType::Fee),
+            commission: None,
+        };
+        let transaction = TransactionWithStatusMeta {
+            transaction: Default::default(),
+            meta: Some(TransactionStatusMeta {
+                status: Err(TransactionError::InstructionError(
+                    0,
+                    InstructionError::Custom(6001),
+                )),
+                fee: 5000,
+                pre_balances: vec![10_000, 2_039_280],
+                post_balances: vec![5000, 2_039_280],
+                inner_instructions: Some(vec![]),
+                log_messages: Some(vec![
+                    String::from("Program 9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin invoke [1]"),
+                    String::from("Program log: Error: slippage exceeded"),
+                    String::from(
+                        "Program 9xQeWvG816bUx9EPjHmaT23yvVM2ZWbrrpZb9PusVFin failed: custom program error: 0x1771",
+                    ),
+                ]),
+                pre_token_balances: Some(vec![token_balance("1500000", 1.5)]),
+                post_token_balances: Some(vec![token_balance("1250000", 1.25)]),
+                rewards: Some(vec![reward.clone()]),
+            }),
+        };
+        ConfirmedBlock {
+            previous_blockhash: String::from("4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZAMdL4VZHirAn"),
+            blockhash: String::from("8Mw7yZcTVQsxBDK4ZnqA7KzNHDUDBBzZ1L9hkJfUqKjY"),
+            parent_slot: 41,
+            transactions: vec![transaction],
+            rewards: vec![reward],
+            block_time: Some(1_640_000_000),
+            block_height: Some(40),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = vec![(1u64, Some("block".to_string())), (2, None)];
+        for encoding in [PayloadEncoding::Json, PayloadEncoding::Bincode].iter() {
+            for compression in [PayloadCompression::Uncompressed, PayloadCompression::Zstd].iter() {
+                let format = PayloadFormat::new(*encoding, *compression);
+                let payload = format.encode(&value).unwrap();
+                let decoded: Vec<(u64, Option<String>)> = format.decode(&payload).unwrap();
+                assert_eq!(value, decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn block_round_trip() {
+        let blocks = vec![block()];
+        let format = PayloadFormat::new(PayloadEncoding::Bincode, PayloadCompression::Zstd);
+        let payload = format.encode(&blocks).unwrap();
+        let decoded: Vec<ConfirmedBlock> = format.decode(&payload).unwrap();
+        assert_eq!(blocks, decoded);
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert_eq!(
+            PayloadFormat::from_i32(0, 0).unwrap(),
+            PayloadFormat::default()
+        );
+        assert!(PayloadFormat::from_i32(2, 0).is_err());
+        assert!(PayloadFormat::from_i32(0, 2).is_err());
+    }
+}
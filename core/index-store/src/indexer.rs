@@ -39,7 +39,8 @@ impl IndexerStore {
             indexers::description.eq(""),
             indexers::repo.eq(""),
             indexers::index_status.eq(IndexerStatus::Synced.as_static().to_lowercase()),
-            indexers::got_block.eq(0),
+            //No block is indexed yet
+            indexers::got_block.eq(-1),
             indexers::hash.eq(&hash),
             indexers::manifest.eq(&manifest_file),
         );
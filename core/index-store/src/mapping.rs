@@ -101,39 +101,40 @@ impl Store for IndexerState {
             &mut self.entity_cache,
             Self::create_entity_cache(&self.store),
         );
-        if let Ok(ModificationsAndCache {
+        let ModificationsAndCache {
             modifications: mods,
             data_sources: _,
             entity_lfu_cache: _cache,
-        }) = entity_cache.as_modifications().map_err(|e| {
+        } = entity_cache.as_modifications().map_err(|e| {
             log::error!("Error {:?}", e);
             StoreError::Unknown(e.into())
-        }) {
-            // Transact entity modifications into the store
-            let length = mods.len();
-            if length > 0 {
-                let start = Instant::now();
-                let block_ptr = BlockPtr {
-                    hash: BlockHash::from(block_hash.as_bytes().to_vec()),
-                    number: block_number as i32,
-                };
-                match self.store.transact_block_operations(
-                    block_ptr,
-                    mods,
-                    self.stopwatch.cheap_clone(),
-                    Vec::default(),
-                    vec![],
-                ) {
-                    Ok(_) => {
-                        log::info!("Transact block operation with {} records successfully in {:?}", length, start.elapsed());
-                    }
-                    Err(err) => {
-                        log::error!("Transact block operation with error {:?}", &err);
-                    }
+        })?;
+        // Transact entity modifications into the store.
+        // Blocks without modifications are transacted too, to record the indexer progress.
+        let length = mods.len();
+        let start = Instant::now();
+        let block_ptr = BlockPtr {
+            hash: BlockHash::from(block_hash.as_bytes().to_vec()),
+            number: block_number as i32,
+        };
+        match self.store.transact_block_operations(
+            block_ptr,
+            mods,
+            self.stopwatch.cheap_clone(),
+            Vec::default(),
+            vec![],
+        ) {
+            Ok(_) => {
+                if length > 0 {
+                    log::info!("Transact block operation with {} records successfully in {:?}", length, start.elapsed());
                 }
+                Ok(())
+            }
+            Err(err) => {
+                log::error!("Transact block operation with error {:?}", &err);
+                Err(err.into())
             }
         }
-        Ok(())
     }
 }
 
@@ -0,0 +1,60 @@
+use graph::prelude::{Collector, Counter, Gauge, MetricsRegistry, Opts, PrometheusError};
+use massbit::components::metrics::METRICS_REGISTRY;
+use massbit_common::prelude::log;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Registers the metrics of the graph store, e.g. the ones of its connection
+/// pool, with the `METRICS_REGISTRY` of the process so that its `/metrics`
+/// endpoint serves them
+#[derive(Default)]
+pub struct StoreMetricsRegistry {
+    global_counters: Mutex<HashMap<String, Counter>>,
+    global_gauges: Mutex<HashMap<String, Gauge>>,
+}
+
+impl MetricsRegistry for StoreMetricsRegistry {
+    fn register(&self, name: &str, c: Box<dyn Collector>) {
+        if let Err(err) = METRICS_REGISTRY.register(c) {
+            log::warn!("Cannot register metric {}: {:?}", name, err);
+        }
+    }
+
+    fn unregister(&self, metric: Box<dyn Collector>) {
+        if let Err(err) = METRICS_REGISTRY.unregister(metric) {
+            log::warn!("Cannot unregister metric: {:?}", err);
+        }
+    }
+
+    fn global_counter(
+        &self,
+        name: &str,
+        help: &str,
+        const_labels: HashMap<String, String>,
+    ) -> Result<Counter, PrometheusError> {
+        let mut counters = self.global_counters.lock().unwrap();
+        if let Some(counter) = counters.get(name) {
+            return Ok(counter.clone());
+        }
+        let counter = Counter::with_opts(Opts::new(name, help).const_labels(const_labels))?;
+        METRICS_REGISTRY.register(Box::new(counter.clone()))?;
+        counters.insert(name.to_string(), counter.clone());
+        Ok(counter)
+    }
+
+    fn global_gauge(
+        &self,
+        name: &str,
+        help: &str,
+        const_labels: HashMap<String, String>,
+    ) -> Result<Gauge, PrometheusError> {
+        let mut gauges = self.global_gauges.lock().unwrap();
+        if let Some(gauge) = gauges.get(name) {
+            return Ok(gauge.clone());
+        }
+        let gauge = Gauge::with_opts(Opts::new(name, help).const_labels(const_labels))?;
+        METRICS_REGISTRY.register(Box::new(gauge.clone()))?;
+        gauges.insert(name.to_string(), gauge.clone());
+        Ok(gauge)
+    }
+}
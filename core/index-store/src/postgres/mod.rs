@@ -1,3 +1,4 @@
+pub mod metrics;
 pub mod relational;
 pub mod store_builder;
 use graph::components::metrics::stopwatch::StopwatchMetrics;
@@ -36,6 +37,8 @@ pub const BLOCK_NUMBER_MAX: BlockNumber = <i32>::MAX;
 
 #[derive(Clone)]
 pub struct PostgresIndexStore {
+    /// Hash of the indexer whose progress (`indexers.got_block`) is committed with each block
+    pub indexer_hash: String,
     pub logger: Logger,
     pub connection: ConnectionPool,
     pub layout: Layout,
@@ -46,7 +49,18 @@ pub struct PostgresIndexStore {
 impl PostgresIndexStore {
     pub fn new(indexer: &str) -> Result<PostgresIndexStore, anyhow::Error> {
         let path = PathBuf::new();
-        StoreBuilder::create_store(indexer, &path)
+        StoreBuilder::create_store(indexer, indexer, &path)
+    }
+    /// Returns the last block whose entities were committed to the store,
+    /// or None if the indexer has not processed any block yet.
+    pub fn get_got_block(&self) -> Result<Option<i64>, StoreError> {
+        let conn = self.get_conn()?;
+        let got_block = indexers::table
+            .filter(indexers::hash.eq(&self.indexer_hash))
+            .select(indexers::got_block)
+            .first::<i64>(&conn)
+            .optional()?;
+        Ok(got_block.filter(|block| *block >= 0))
     }
     pub fn save_got_block(&self, indexer: &String, block_number: i64) {
         let logger = Logger::root(slog::Discard, slog::o!());
@@ -154,15 +168,20 @@ impl WritableStore for PostgresIndexStore {
          */
         let conn = self.get_conn()?;
         let event = conn.transaction(|| -> Result<_, StoreError> {
-            // Emit a store event for the changes we are about to make. We
-            // wait with sending it until we have done all our other work
-            // so that we do not hold a lock on the notification queue
-            // for longer than we have to
+            // Emit a store event for the changes we are about to make. Postgres
+            // delivers the notification to subscribers when the transaction commits
             let event: StoreEvent = mods.iter().collect();
+            send_store_event(&conn, &self.indexer_hash, block_ptr_to.number, &mods)?;
 
             let section = stopwatch.start_section("apply_entity_modifications");
             let _count = self.apply_entity_modifications(&conn, mods, &block_ptr_to, stopwatch)?;
             section.end();
+
+            // Progress is written in the same transaction as the entities, so that after a
+            // restart the indexer resumes right after the last block it has stored
+            diesel::update(indexers::table.filter(indexers::hash.eq(&self.indexer_hash)))
+                .set(indexers::got_block.eq(block_ptr_to.number as i64))
+                .execute(&conn)?;
             /*
             deployment::update_entity_count(
                 &conn,
@@ -223,6 +242,30 @@ impl WritableStore for PostgresIndexStore {
     }
 }
 
+/// Notify the subscribers of the indexer api of the entities changed by `mods`. The
+/// notification is delivered when the transaction it is sent in commits.
+fn send_store_event(
+    conn: &PgConnection,
+    indexer_hash: &str,
+    block: BlockNumber,
+    mods: &[EntityModification],
+) -> Result<(), StoreError> {
+    let event = massbit::prelude::StoreEvent::from_changes(
+        indexer_hash,
+        block,
+        mods.iter().map(|modification| {
+            let key = modification.entity_key();
+            (
+                key.entity_type.as_str(),
+                key.entity_id.as_str(),
+                modification.is_remove(),
+            )
+        }),
+    );
+    massbit_store_postgres::send_store_event(conn, &event)
+        .map_err(|err| StoreError::Unknown(err.into()))
+}
+
 impl PostgresIndexStore {
     fn get_conn(&self) -> Result<PooledConnection<ConnectionManager<PgConnection>>, StoreError> {
         self.connection.get_with_timeout_warning(&self.logger)
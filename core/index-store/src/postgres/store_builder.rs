@@ -1,3 +1,4 @@
+use super::metrics::StoreMetricsRegistry;
 use super::relational::LayoutExt;
 use super::PostgresIndexStore;
 use crate::models::Indexer;
@@ -6,11 +7,11 @@ use diesel::prelude::*;
 use diesel::sql_types::BigInt;
 use diesel::QueryableByName;
 
+use crate::FORCE_SCHEMA_MIGRATION;
 use graph::cheap_clone::CheapClone;
 use graph::data::schema::Schema;
 use graph::log::logger;
 use graph::prelude::{DeploymentHash, NodeId, StoreError};
-use graph_mock::MockMetricsRegistry;
 use graph_node::{
     config::{Config, Opt},
     store_builder::StoreBuilder as GraphStoreBuilder,
@@ -36,6 +37,9 @@ use massbit_common::prelude::{
     slog::{self, Logger},
 };
 
+use massbit_store_postgres::command_support::catalog::Site as MassbitSite;
+use massbit_store_postgres::migration;
+use massbit_store_postgres::relational::{Catalog as MassbitCatalog, Layout as MassbitLayout};
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
@@ -83,6 +87,7 @@ impl StoreBuilder {
         Ok(())
     }
     pub fn create_store<P: AsRef<Path>>(
+        indexer_hash: &str,
         db_schema: &str,
         schema_path: P,
     ) -> Result<PostgresIndexStore, anyhow::Error> {
@@ -92,7 +97,7 @@ impl StoreBuilder {
         opt.store_connection_pool_size = CONN_POOL_SIZE;
 
         let config = Config::load(&logger, &opt).expect("config is not valid");
-        let registry = Arc::new(MockMetricsRegistry::new());
+        let registry = Arc::new(StoreMetricsRegistry::default());
         let shard_config = config.stores.get(PRIMARY_SHARD.as_str()).unwrap();
         let shard_name = String::from(PRIMARY_SHARD.as_str());
         let connection = GraphStoreBuilder::main_pool(
@@ -121,6 +126,7 @@ impl StoreBuilder {
                     Ok(layout) => {
                         //let entity_dependencies = layout.create_dependencies();
                         Ok(PostgresIndexStore {
+                            indexer_hash: indexer_hash.to_string(),
                             connection,
                             layout,
                             logger,
@@ -144,7 +150,7 @@ impl StoreBuilder {
         //let deployment_hash = DeploymentHash::new(indexer_hash.to_string()).unwrap();
         let deployment_hash = DeploymentHash::new("_indexer").unwrap();
         let schema = Schema::parse(schema_buffer.as_str(), deployment_hash.cheap_clone()).unwrap();
-        let namespace = Namespace::new(schema_name).unwrap();
+        let namespace = Namespace::new(schema_name.clone()).unwrap();
         let logger = Logger::root(slog::Discard, slog::o!());
         let conn = connection.get_with_timeout_warning(&logger).unwrap();
         //Create simple site
@@ -162,16 +168,9 @@ impl StoreBuilder {
         let catalog = Catalog::make_empty(arc_site.clone()).unwrap();
         match Layout::new(arc_site, &schema, catalog, false) {
             Ok(layout) => {
-                let sql = layout.as_ddl().map_err(|_| {
-                    StoreError::Unknown(anyhow!("failed to generate DDL for layout"))
-                })?;
                 //let sql_relationships = layout.gen_relationship();
-                match conn.batch_execute(&sql) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        log::error!("{:?}", e);
-                    }
-                }
+                Self::migrate_schema(&conn, &schema_buffer, &schema_name)
+                    .map_err(|e| StoreError::Unknown(anyhow!("{}", e)))?;
                 /*
                 if sql_relationships.len() > 0 {
                     let query = sql_relationships.join(";");
@@ -212,6 +211,30 @@ impl StoreBuilder {
         }
     }
 
+    /// Creates the tables of the GraphQL schema `raw_schema` in the database
+    /// schema `schema_name`, or migrates them if they were created with another
+    /// version of the schema.
+    fn migrate_schema(
+        conn: &PgConnection,
+        raw_schema: &str,
+        schema_name: &str,
+    ) -> Result<(), massbit::prelude::StoreError> {
+        let deployment_hash = massbit::prelude::DeploymentHash::new("_indexer").unwrap();
+        let schema = massbit::prelude::Schema::parse(raw_schema, deployment_hash.clone())?;
+        let site = Arc::new(MassbitSite::new(
+            &deployment_hash,
+            schema_name,
+            NETWORK.as_str(),
+        ));
+        let catalog = MassbitCatalog::new(conn, site.clone())?;
+        let layout = MassbitLayout::new(site, &schema, catalog)?;
+        let changes = migration::migrate(conn, &layout, raw_schema, *FORCE_SCHEMA_MIGRATION)?;
+        for change in changes {
+            log::info!("Schema {}: {}", schema_name, change);
+        }
+        Ok(())
+    }
+
     pub fn create_relationships(layout: &Layout, connection: &PgConnection) {
         let relationships = layout.gen_relationship();
         if relationships.len() > 0 {
@@ -0,0 +1,800 @@
+use diesel::PgConnection;
+use graphql_parser::Pos;
+use massbit::components::store::EntityType;
+use massbit::data::graphql::ext::{DirectiveExt, DirectiveFinder, ObjectTypeExt, ValueExt};
+use massbit::data::query::{QueryError, QueryExecutionError};
+use massbit::prelude::{
+    q, s, BigDecimal, BigInt, BlockNumber, EntityFilter, EntityOrder, EntityQuery, EntityRange,
+    Value, BLOCK_NUMBER_MAX,
+};
+use massbit_store_postgres::relational::{Column, ColumnType, Table};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::request::{QueryRequest, QueryResult};
+use crate::schema::{
+    base_type, is_list, QuerySchema, DEFAULT_FIRST, FILTER_OPS, MAX_FIRST, MAX_SKIP,
+};
+
+/// An entity as it is loaded from the store, keyed by field name
+type EntityData = BTreeMap<String, q::Value>;
+
+/// Executes the query of `request` against the tables of an indexer. Each field
+/// of the query reads the entities at the block given by its `block` argument,
+/// and the fields nested in it read them at the same block.
+pub fn execute_query(
+    conn: &PgConnection,
+    schema: &QuerySchema,
+    request: &QueryRequest,
+) -> QueryResult {
+    execute_operation(conn, schema, request, false)
+}
+
+/// Executes the subscription of `request` once, against the entities as they
+/// are at the latest block.
+pub fn execute_subscription(
+    conn: &PgConnection,
+    schema: &QuerySchema,
+    request: &QueryRequest,
+) -> QueryResult {
+    execute_operation(conn, schema, request, true)
+}
+
+fn execute_operation(
+    conn: &PgConnection,
+    schema: &QuerySchema,
+    request: &QueryRequest,
+    subscription: bool,
+) -> QueryResult {
+    let document = match parse_query(request) {
+        Ok(document) => document,
+        Err(err) => return QueryResult::from_error(err),
+    };
+    let result = operation(&document, request.operation_name.as_deref()).and_then(|operation| {
+        let executor = Executor::new(conn, schema, &document, operation, request.variables());
+        executor.execute(selection_set(operation, subscription)?)
+    });
+    match result {
+        Ok(data) => QueryResult {
+            data: Some(data),
+            errors: vec![],
+        },
+        Err(err) => QueryResult::from_error(err),
+    }
+}
+
+/// Returns the entities the subscription of `request` reads, as their type
+/// and, for entities looked up by id, their id.
+pub(crate) fn subscribed_entities(
+    conn: &PgConnection,
+    schema: &QuerySchema,
+    request: &QueryRequest,
+) -> Result<BTreeSet<(EntityType, Option<String>)>, QueryError> {
+    let document = parse_query(request)?;
+    let operation = operation(&document, request.operation_name.as_deref())?;
+    let executor = Executor::new(conn, schema, &document, operation, request.variables());
+    let mut entities = BTreeSet::new();
+    executor.subscribed_entities(selection_set(operation, true)?, &mut entities)?;
+    Ok(entities)
+}
+
+fn parse_query(request: &QueryRequest) -> Result<q::Document, QueryError> {
+    graphql_parser::parse_query::<String>(&request.query)
+        .map(|document| document.into_static())
+        .map_err(|err| QueryError::ParseError(Arc::new(err.into())))
+}
+
+/// Returns the selection set of `operation`; queries are answered over HTTP
+/// and subscriptions over a websocket.
+fn selection_set(
+    operation: &q::OperationDefinition,
+    subscription: bool,
+) -> Result<&q::SelectionSet, QueryExecutionError> {
+    match operation {
+        q::OperationDefinition::Mutation(_) => {
+            Err(QueryExecutionError::NotSupported("mutations".to_string()))
+        }
+        q::OperationDefinition::Subscription(operation) if subscription => {
+            Ok(&operation.selection_set)
+        }
+        q::OperationDefinition::Subscription(_) => Err(QueryExecutionError::NotSupported(
+            "subscriptions over HTTP".to_string(),
+        )),
+        _ if subscription => Err(QueryExecutionError::NotSupported(
+            "queries over a subscription connection".to_string(),
+        )),
+        q::OperationDefinition::SelectionSet(selection_set) => Ok(selection_set),
+        q::OperationDefinition::Query(query) => Ok(&query.selection_set),
+    }
+}
+
+/// Returns the operation named `name`, or the only operation of the document.
+fn operation<'a>(
+    document: &'a q::Document,
+    name: Option<&str>,
+) -> Result<&'a q::OperationDefinition, QueryExecutionError> {
+    let operations: Vec<&q::OperationDefinition> = document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            q::Definition::Operation(operation) => Some(operation),
+            q::Definition::Fragment(_) => None,
+        })
+        .collect();
+    match name {
+        Some(name) => operations
+            .into_iter()
+            .find(|operation| operation_name(operation) == Some(name))
+            .ok_or_else(|| QueryExecutionError::OperationNotFound(name.to_string())),
+        None if operations.len() == 1 => Ok(operations[0]),
+        None if operations.is_empty() => Err(QueryExecutionError::EmptyQuery),
+        None => Err(QueryExecutionError::OperationNameRequired),
+    }
+}
+
+fn operation_name(operation: &q::OperationDefinition) -> Option<&str> {
+    match operation {
+        q::OperationDefinition::SelectionSet(_) => None,
+        q::OperationDefinition::Query(query) => query.name.as_deref(),
+        q::OperationDefinition::Mutation(mutation) => mutation.name.as_deref(),
+        q::OperationDefinition::Subscription(subscription) => subscription.name.as_deref(),
+    }
+}
+
+fn response_key(field: &q::Field) -> String {
+    field.alias.clone().unwrap_or_else(|| field.name.clone())
+}
+
+struct Executor<'a> {
+    conn: &'a PgConnection,
+    schema: &'a QuerySchema,
+    /// The `Query` or `Subscription` type, whose fields the operation selects
+    root: &'a s::ObjectType,
+    fragments: HashMap<&'a str, &'a q::FragmentDefinition>,
+    variables: HashMap<String, q::Value>,
+}
+
+impl<'a> Executor<'a> {
+    fn new(
+        conn: &'a PgConnection,
+        schema: &'a QuerySchema,
+        document: &'a q::Document,
+        operation: &'a q::OperationDefinition,
+        mut variables: HashMap<String, q::Value>,
+    ) -> Self {
+        let fragments = document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                q::Definition::Fragment(fragment) => Some((fragment.name.as_str(), fragment)),
+                q::Definition::Operation(_) => None,
+            })
+            .collect();
+        let (root, variable_definitions) = match operation {
+            q::OperationDefinition::Query(query) => (
+                &*schema.api.query_type,
+                query.variable_definitions.as_slice(),
+            ),
+            q::OperationDefinition::Subscription(subscription) => (
+                schema
+                    .api
+                    .subscription_type
+                    .as_deref()
+                    .unwrap_or(&*schema.api.query_type),
+                subscription.variable_definitions.as_slice(),
+            ),
+            _ => (&*schema.api.query_type, &[][..]),
+        };
+        for definition in variable_definitions.iter() {
+            if let Some(default) = &definition.default_value {
+                variables
+                    .entry(definition.name.clone())
+                    .or_insert_with(|| default.clone());
+            }
+        }
+        Executor {
+            conn,
+            schema,
+            root,
+            fragments,
+            variables,
+        }
+    }
+
+    fn execute(&self, selection_set: &'a q::SelectionSet) -> Result<q::Value, QueryExecutionError> {
+        let mut fields = vec![];
+        self.collect_fields(selection_set, &self.root.name, &mut fields, &mut vec![])?;
+        let mut data = BTreeMap::new();
+        for field in fields {
+            let value = if field.name == "__typename" {
+                q::Value::String(self.root.name.clone())
+            } else {
+                self.resolve_root_field(field)?
+            };
+            data.insert(response_key(field), value);
+        }
+        Ok(q::Value::Object(data))
+    }
+
+    /// Collects the fields selected for an object of `type_name`, expanding
+    /// fragments and leaving out the fields excluded with `@skip` or `@include`.
+    fn collect_fields(
+        &self,
+        selection_set: &'a q::SelectionSet,
+        type_name: &str,
+        fields: &mut Vec<&'a q::Field>,
+        visited: &mut Vec<&'a str>,
+    ) -> Result<(), QueryExecutionError> {
+        for selection in selection_set.items.iter() {
+            match selection {
+                q::Selection::Field(field) => {
+                    if self.is_included(&field.directives)? {
+                        fields.push(field);
+                    }
+                }
+                q::Selection::FragmentSpread(spread) => {
+                    if !self.is_included(&spread.directives)? {
+                        continue;
+                    }
+                    let name = spread.fragment_name.as_str();
+                    if visited.contains(&name) {
+                        return Err(QueryExecutionError::CyclicalFragment(name.to_string()));
+                    }
+                    let fragment: &'a q::FragmentDefinition = *self
+                        .fragments
+                        .get(name)
+                        .ok_or_else(|| QueryExecutionError::UndefinedFragment(name.to_string()))?;
+                    if applies_to(&fragment.type_condition, type_name) {
+                        visited.push(name);
+                        self.collect_fields(&fragment.selection_set, type_name, fields, visited)?;
+                        visited.pop();
+                    }
+                }
+                q::Selection::InlineFragment(fragment) => {
+                    let applies = fragment
+                        .type_condition
+                        .as_ref()
+                        .map_or(true, |condition| applies_to(condition, type_name));
+                    if applies && self.is_included(&fragment.directives)? {
+                        self.collect_fields(&fragment.selection_set, type_name, fields, visited)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_included(&self, directives: &[q::Directive]) -> Result<bool, QueryExecutionError> {
+        for directive in directives {
+            let condition = || match directive.argument("if") {
+                Some(value) => match self.resolve_variables(value) {
+                    q::Value::Boolean(condition) => Ok(condition),
+                    value => Err(QueryExecutionError::InvalidArgumentError(
+                        directive.position,
+                        "if".to_string(),
+                        value,
+                    )),
+                },
+                None => Err(QueryExecutionError::MissingArgumentError(
+                    directive.position,
+                    "if".to_string(),
+                )),
+            };
+            match directive.name.as_str() {
+                "skip" if condition()? => return Ok(false),
+                "include" if !condition()? => return Ok(false),
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+
+    /// Replaces the variables in `value` with their values; variables the
+    /// request does not set are null.
+    fn resolve_variables(&self, value: &q::Value) -> q::Value {
+        match value {
+            q::Value::Variable(name) => self.variables.get(name).cloned().unwrap_or(q::Value::Null),
+            q::Value::List(values) => q::Value::List(
+                values
+                    .iter()
+                    .map(|value| self.resolve_variables(value))
+                    .collect(),
+            ),
+            q::Value::Object(map) => q::Value::Object(
+                map.iter()
+                    .map(|(key, value)| (key.clone(), self.resolve_variables(value)))
+                    .collect(),
+            ),
+            value => value.clone(),
+        }
+    }
+
+    /// Returns the value of the argument `name` of `field`, where null is
+    /// the same as a missing argument.
+    fn argument(&self, field: &q::Field, name: &str) -> Option<q::Value> {
+        field
+            .arguments
+            .iter()
+            .find(|(argument, _)| argument == name)
+            .map(|(_, value)| self.resolve_variables(value))
+            .filter(|value| value != &q::Value::Null)
+    }
+
+    fn object_type(&self, name: &str) -> Result<&'a s::ObjectType, QueryExecutionError> {
+        self.schema
+            .entity_type(name)
+            .ok_or_else(|| QueryExecutionError::NamedTypeError(name.to_string()))
+    }
+
+    /// Collects the entities read by the fields of `selection_set`: every
+    /// entity of the types that collections and nested fields read, and the
+    /// entity with the given id for root fields that look up a single entity.
+    fn subscribed_entities(
+        &self,
+        selection_set: &'a q::SelectionSet,
+        entities: &mut BTreeSet<(EntityType, Option<String>)>,
+    ) -> Result<(), QueryExecutionError> {
+        let mut fields = vec![];
+        self.collect_fields(selection_set, &self.root.name, &mut fields, &mut vec![])?;
+        for field in fields
+            .into_iter()
+            .filter(|field| field.name != "__typename")
+        {
+            let root_field = self.root_field(field)?;
+            let object_type = self.object_type(base_type(&root_field.field_type))?;
+            let id = if is_list(&root_field.field_type) {
+                None
+            } else {
+                Some(self.id_argument(field)?)
+            };
+            entities.insert((EntityType::new(object_type.name.clone()), id));
+            self.nested_entities(field, object_type, entities)?;
+        }
+        Ok(())
+    }
+
+    fn nested_entities(
+        &self,
+        field: &'a q::Field,
+        object_type: &'a s::ObjectType,
+        entities: &mut BTreeSet<(EntityType, Option<String>)>,
+    ) -> Result<(), QueryExecutionError> {
+        let mut fields = vec![];
+        self.collect_fields(
+            &field.selection_set,
+            &object_type.name,
+            &mut fields,
+            &mut vec![],
+        )?;
+        for child in fields {
+            let nested_type = object_type.field(&child.name).and_then(|schema_field| {
+                self.schema.entity_type(base_type(&schema_field.field_type))
+            });
+            if let Some(nested_type) = nested_type {
+                entities.insert((EntityType::new(nested_type.name.clone()), None));
+                self.nested_entities(child, nested_type, entities)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn root_field(&self, field: &q::Field) -> Result<&'a s::Field, QueryExecutionError> {
+        self.root.field(&field.name).ok_or_else(|| {
+            QueryExecutionError::UnknownField(
+                field.position,
+                self.root.name.clone(),
+                field.name.clone(),
+            )
+        })
+    }
+
+    fn id_argument(&self, field: &q::Field) -> Result<String, QueryExecutionError> {
+        match self.argument(field, "id") {
+            Some(q::Value::String(id)) => Ok(id),
+            Some(value) => Err(QueryExecutionError::InvalidArgumentError(
+                field.position,
+                "id".to_string(),
+                value,
+            )),
+            None => Err(QueryExecutionError::MissingArgumentError(
+                field.position,
+                "id".to_string(),
+            )),
+        }
+    }
+
+    fn resolve_root_field(&self, field: &'a q::Field) -> Result<q::Value, QueryExecutionError> {
+        let root_field = self.root_field(field)?;
+        let object_type = self.object_type(base_type(&root_field.field_type))?;
+        let block = self.block(field)?;
+
+        if is_list(&root_field.field_type) {
+            let query = self.collection_query(field, object_type, block)?;
+            self.resolve_entities(field, object_type, &query, block)
+        } else {
+            let id = self.id_argument(field)?;
+            let query = EntityQuery::new(EntityType::new(object_type.name.clone()), block)
+                .filter(EntityFilter::new_equal("id", id))
+                .range(EntityRange::first(1));
+            self.resolve_entity(field, object_type, &query, block)
+        }
+    }
+
+    /// Resolves a field of an entity. References and `@derivedFrom` fields
+    /// are resolved by querying the entities they point to.
+    fn resolve_field(
+        &self,
+        field: &'a q::Field,
+        schema_field: &s::Field,
+        entity: &EntityData,
+        block: BlockNumber,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let object_type = match self.schema.entity_type(base_type(&schema_field.field_type)) {
+            Some(object_type) => object_type,
+            None => {
+                return Ok(entity
+                    .get(&schema_field.name)
+                    .cloned()
+                    .unwrap_or(q::Value::Null))
+            }
+        };
+        let entity_type = EntityType::new(object_type.name.clone());
+        let list = is_list(&schema_field.field_type);
+
+        let filter = match schema_field.find_directive("derivedFrom") {
+            Some(derived_from) => {
+                let parent_field = derived_from
+                    .argument("field")
+                    .and_then(|value| value.as_str())
+                    .ok_or_else(|| {
+                        QueryExecutionError::EntityFieldError(
+                            object_type.name.clone(),
+                            schema_field.name.clone(),
+                        )
+                    })?;
+                let parent_id = entity
+                    .get("id")
+                    .and_then(|id| id.as_str())
+                    .unwrap_or_default();
+                let table = self.schema.layout.table_for_entity(&entity_type)?;
+                if table.column_for_field(parent_field)?.is_list() {
+                    EntityFilter::Contains(
+                        parent_field.to_string(),
+                        Value::List(vec![Value::from(parent_id)]),
+                    )
+                } else {
+                    EntityFilter::new_equal(parent_field, parent_id)
+                }
+            }
+            // A reference stores the ids of the entities it points to
+            None => match entity.get(&schema_field.name) {
+                Some(q::Value::String(id)) => EntityFilter::new_equal("id", id.as_str()),
+                Some(q::Value::List(ids)) => EntityFilter::In(
+                    "id".to_string(),
+                    ids.iter()
+                        .filter_map(|id| id.as_str())
+                        .map(Value::from)
+                        .collect(),
+                ),
+                _ if list => return Ok(q::Value::List(vec![])),
+                _ => return Ok(q::Value::Null),
+            },
+        };
+
+        if list {
+            let query = self
+                .collection_query(field, object_type, block)?
+                .filter(filter);
+            self.resolve_entities(field, object_type, &query, block)
+        } else {
+            let query = EntityQuery::new(entity_type, block)
+                .filter(filter)
+                .range(EntityRange::first(1));
+            self.resolve_entity(field, object_type, &query, block)
+        }
+    }
+
+    fn resolve_entities(
+        &self,
+        field: &'a q::Field,
+        object_type: &'a s::ObjectType,
+        query: &EntityQuery,
+        block: BlockNumber,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let entities = self
+            .schema
+            .layout
+            .query::<EntityData>(self.conn, query)?
+            .into_iter()
+            .map(|entity| self.resolve_object(field, object_type, entity, block))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(q::Value::List(entities))
+    }
+
+    fn resolve_entity(
+        &self,
+        field: &'a q::Field,
+        object_type: &'a s::ObjectType,
+        query: &EntityQuery,
+        block: BlockNumber,
+    ) -> Result<q::Value, QueryExecutionError> {
+        match self
+            .schema
+            .layout
+            .query::<EntityData>(self.conn, query)?
+            .into_iter()
+            .next()
+        {
+            Some(entity) => self.resolve_object(field, object_type, entity, block),
+            None => Ok(q::Value::Null),
+        }
+    }
+
+    fn resolve_object(
+        &self,
+        field: &'a q::Field,
+        object_type: &'a s::ObjectType,
+        entity: EntityData,
+        block: BlockNumber,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let mut fields = vec![];
+        self.collect_fields(
+            &field.selection_set,
+            &object_type.name,
+            &mut fields,
+            &mut vec![],
+        )?;
+        if fields.is_empty() {
+            return Err(QueryExecutionError::EmptySelectionSet(
+                object_type.name.clone(),
+            ));
+        }
+
+        let mut object = BTreeMap::new();
+        for child in fields {
+            let value = if child.name == "__typename" {
+                q::Value::String(object_type.name.clone())
+            } else {
+                let schema_field = object_type.field(&child.name).ok_or_else(|| {
+                    QueryExecutionError::UnknownField(
+                        child.position,
+                        object_type.name.clone(),
+                        child.name.clone(),
+                    )
+                })?;
+                self.resolve_field(child, schema_field, &entity, block)?
+            };
+            object.insert(response_key(child), value);
+        }
+        Ok(q::Value::Object(object))
+    }
+
+    /// The block from the `block: { number }` argument, or the latest block.
+    fn block(&self, field: &q::Field) -> Result<BlockNumber, QueryExecutionError> {
+        let value = match self.argument(field, "block") {
+            Some(value) => value,
+            None => return Ok(BLOCK_NUMBER_MAX),
+        };
+        let number = match value.as_object().map(|block| block.get("number")) {
+            Some(None) | Some(Some(q::Value::Null)) => return Ok(BLOCK_NUMBER_MAX),
+            Some(Some(q::Value::Int(number))) => number.as_i64(),
+            _ => None,
+        };
+        number
+            .filter(|number| *number >= 0 && *number < BLOCK_NUMBER_MAX as i64)
+            .map(|number| number as BlockNumber)
+            .ok_or_else(|| {
+                QueryExecutionError::InvalidArgumentError(
+                    field.position,
+                    "block".to_string(),
+                    value.clone(),
+                )
+            })
+    }
+
+    /// The query for a collection field from its `where`, `orderBy`,
+    /// `orderDirection`, `first` and `skip` arguments.
+    fn collection_query(
+        &self,
+        field: &q::Field,
+        object_type: &s::ObjectType,
+        block: BlockNumber,
+    ) -> Result<EntityQuery, QueryExecutionError> {
+        let entity_type = EntityType::new(object_type.name.clone());
+        let table = self.schema.layout.table_for_entity(&entity_type)?;
+        let range = EntityRange {
+            first: Some(self.range_argument(field, "first", DEFAULT_FIRST, MAX_FIRST)?),
+            skip: self.range_argument(field, "skip", 0, MAX_SKIP)?,
+        };
+        let query = EntityQuery::new(entity_type, block)
+            .order(self.order(field)?)
+            .range(range);
+        match self.filter(field, table)? {
+            Some(filter) => Ok(query.filter(filter)),
+            None => Ok(query),
+        }
+    }
+
+    fn range_argument(
+        &self,
+        field: &q::Field,
+        name: &'static str,
+        default: u32,
+        max: u32,
+    ) -> Result<u32, QueryExecutionError> {
+        match self.argument(field, name) {
+            None => Ok(default),
+            Some(q::Value::Int(number)) => match number.as_i64() {
+                Some(number) if number >= 0 && number <= max as i64 => Ok(number as u32),
+                number => Err(QueryExecutionError::RangeArgumentsError(
+                    name,
+                    max,
+                    number.unwrap_or_default(),
+                )),
+            },
+            Some(value) => Err(QueryExecutionError::InvalidArgumentError(
+                field.position,
+                name.to_string(),
+                value,
+            )),
+        }
+    }
+
+    fn order(&self, field: &q::Field) -> Result<EntityOrder, QueryExecutionError> {
+        let attribute = match self.argument(field, "orderBy") {
+            None => return Ok(EntityOrder::Default),
+            Some(q::Value::Enum(attribute)) | Some(q::Value::String(attribute)) => attribute,
+            Some(value) => {
+                return Err(QueryExecutionError::InvalidArgumentError(
+                    field.position,
+                    "orderBy".to_string(),
+                    value,
+                ))
+            }
+        };
+        match self.argument(field, "orderDirection") {
+            None => Ok(EntityOrder::Ascending(attribute)),
+            Some(q::Value::Enum(direction)) | Some(q::Value::String(direction))
+                if direction == "asc" =>
+            {
+                Ok(EntityOrder::Ascending(attribute))
+            }
+            Some(q::Value::Enum(direction)) | Some(q::Value::String(direction))
+                if direction == "desc" =>
+            {
+                Ok(EntityOrder::Descending(attribute))
+            }
+            Some(value) => Err(QueryExecutionError::InvalidArgumentError(
+                field.position,
+                "orderDirection".to_string(),
+                value,
+            )),
+        }
+    }
+
+    fn filter(
+        &self,
+        field: &q::Field,
+        table: &Table,
+    ) -> Result<Option<EntityFilter>, QueryExecutionError> {
+        let filter = match self.argument(field, "where") {
+            None => return Ok(None),
+            Some(q::Value::Object(filter)) => filter,
+            Some(_) => return Err(QueryExecutionError::InvalidFilterError),
+        };
+        let filters = filter
+            .into_iter()
+            .map(|(key, value)| entity_filter(field.position, table, &key, value))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some(EntityFilter::And(filters)))
+    }
+}
+
+fn applies_to(condition: &q::TypeCondition, type_name: &str) -> bool {
+    match condition {
+        q::TypeCondition::On(name) => name == type_name,
+    }
+}
+
+/// Turns one entry of a `where` argument, like `name_starts_with: "a"`, into a filter.
+fn entity_filter(
+    pos: Pos,
+    table: &Table,
+    key: &str,
+    value: q::Value,
+) -> Result<EntityFilter, QueryExecutionError> {
+    let (attribute, op) = match table.column_for_field(key) {
+        Ok(_) => (key, ""),
+        Err(_) => FILTER_OPS
+            .iter()
+            .find_map(|op| key.strip_suffix(op).map(|attribute| (attribute, *op)))
+            .ok_or_else(|| {
+                QueryExecutionError::EntityFieldError(table.object.to_string(), key.to_string())
+            })?,
+    };
+    let column = table.column_for_field(attribute).map_err(|_| {
+        QueryExecutionError::EntityFieldError(table.object.to_string(), attribute.to_string())
+    })?;
+    let attribute = attribute.to_string();
+
+    if op == "_in" || op == "_not_in" {
+        let values = match &value {
+            q::Value::List(values) => values
+                .iter()
+                .map(|value| store_value(pos, value, column))
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => return Err(QueryExecutionError::ListFilterError(key.to_string())),
+        };
+        return Ok(if op == "_in" {
+            EntityFilter::In(attribute, values)
+        } else {
+            EntityFilter::NotIn(attribute, values)
+        });
+    }
+
+    let value = store_value(pos, &value, column)?;
+    Ok(match op {
+        "" => EntityFilter::Equal(attribute, value),
+        "_not" => EntityFilter::Not(attribute, value),
+        "_gt" => EntityFilter::GreaterThan(attribute, value),
+        "_lt" => EntityFilter::LessThan(attribute, value),
+        "_gte" => EntityFilter::GreaterOrEqual(attribute, value),
+        "_lte" => EntityFilter::LessOrEqual(attribute, value),
+        "_contains" => EntityFilter::Contains(attribute, value),
+        "_not_contains" => EntityFilter::NotContains(attribute, value),
+        "_starts_with" => EntityFilter::StartsWith(attribute, value),
+        "_not_starts_with" => EntityFilter::NotStartsWith(attribute, value),
+        "_ends_with" => EntityFilter::EndsWith(attribute, value),
+        "_not_ends_with" => EntityFilter::NotEndsWith(attribute, value),
+        _ => unreachable!("all operators in FILTER_OPS are handled"),
+    })
+}
+
+/// Converts a value from a query to the store value for `column`.
+fn store_value(pos: Pos, value: &q::Value, column: &Column) -> Result<Value, QueryExecutionError> {
+    let coercion_error = || {
+        QueryExecutionError::ScalarCoercionError(
+            pos,
+            column.field.clone(),
+            value.clone(),
+            column.column_type.sql_type().to_string(),
+        )
+    };
+    match (value, &column.column_type) {
+        (q::Value::Null, _) => Ok(Value::Null),
+        (q::Value::List(values), _) => values
+            .iter()
+            .map(|value| store_value(pos, value, column))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::List),
+        (q::Value::Boolean(b), ColumnType::Boolean) => Ok(Value::Bool(*b)),
+        (q::Value::Int(number), ColumnType::Int) => number
+            .as_i64()
+            .and_then(|number| i32::try_from(number).ok())
+            .map(Value::Int)
+            .ok_or_else(coercion_error),
+        (q::Value::Int(number), ColumnType::BigInt) => number
+            .as_i64()
+            .map(|number| Value::BigInt(BigInt::from(number)))
+            .ok_or_else(coercion_error),
+        (q::Value::String(s), ColumnType::BigInt) => Ok(Value::BigInt(BigInt::from_str(s)?)),
+        (q::Value::Int(number), ColumnType::BigDecimal) => number
+            .as_i64()
+            .map(|number| Value::BigDecimal(BigDecimal::from(number)))
+            .ok_or_else(coercion_error),
+        (q::Value::Float(number), ColumnType::BigDecimal) => {
+            Ok(Value::BigDecimal(BigDecimal::from(*number)))
+        }
+        (q::Value::String(s), ColumnType::BigDecimal) => {
+            Ok(Value::BigDecimal(BigDecimal::from_str(s)?))
+        }
+        (q::Value::String(s), ColumnType::String)
+        | (q::Value::String(s), ColumnType::Bytes)
+        | (q::Value::String(s), ColumnType::BytesId)
+        | (q::Value::String(s), ColumnType::Enum(_))
+        | (q::Value::Enum(s), ColumnType::Enum(_)) => Ok(Value::String(s.clone())),
+        _ => Err(coercion_error()),
+    }
+}
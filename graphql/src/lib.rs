@@ -0,0 +1,22 @@
+//! A GraphQL query server for the entities of an indexer. The API schema
+//! is generated from the indexer's GraphQL schema and queries are answered
+//! straight from the relational `Layout` of its tables, at the latest block
+//! or at the block given with `block: { number }`. Subscriptions run their
+//! query again for every block that changes an entity they read.
+
+/// Generate the API schema of an indexer.
+pub mod schema;
+
+/// Execute queries against the tables of an indexer.
+pub mod execution;
+
+/// Requests and responses.
+pub mod request;
+
+/// Subscriptions to the changes of entities.
+pub mod subscription;
+
+pub use execution::{execute_query, execute_subscription};
+pub use request::{QueryRequest, QueryResult};
+pub use schema::{api_schema, QuerySchema};
+pub use subscription::Subscription;
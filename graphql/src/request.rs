@@ -0,0 +1,86 @@
+use massbit::data::graphql::SerializableValue;
+use massbit::data::query::QueryError;
+use massbit::prelude::q;
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// The body of a GraphQL request sent over HTTP.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryRequest {
+    pub query: String,
+    #[serde(default)]
+    pub operation_name: Option<String>,
+    #[serde(default)]
+    pub variables: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+impl QueryRequest {
+    /// The variables of the request as GraphQL values.
+    pub fn variables(&self) -> HashMap<String, q::Value> {
+        self.variables
+            .iter()
+            .flatten()
+            .map(|(name, value)| (name.clone(), json_to_value(value)))
+            .collect()
+    }
+}
+
+fn json_to_value(value: &serde_json::Value) -> q::Value {
+    use serde_json::Value as j;
+    match value {
+        j::Null => q::Value::Null,
+        j::Bool(b) => q::Value::Boolean(*b),
+        j::Number(number) => match number.as_i64() {
+            Some(i) if i >= i32::MIN as i64 && i <= i32::MAX as i64 => {
+                q::Value::Int(q::Number::from(i as i32))
+            }
+            _ => q::Value::Float(number.as_f64().unwrap_or_default()),
+        },
+        j::String(s) => q::Value::String(s.clone()),
+        j::Array(values) => q::Value::List(values.iter().map(json_to_value).collect()),
+        j::Object(map) => q::Value::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), json_to_value(value)))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+    }
+}
+
+/// The outcome of a query; it is serialized as the `data` and `errors` of the
+/// GraphQL response.
+#[derive(Debug, Default)]
+pub struct QueryResult {
+    pub data: Option<q::Value>,
+    pub errors: Vec<QueryError>,
+}
+
+impl QueryResult {
+    pub fn from_error(error: impl Into<QueryError>) -> Self {
+        QueryResult {
+            data: None,
+            errors: vec![error.into()],
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+}
+
+impl Serialize for QueryResult {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(data) = &self.data {
+            map.serialize_entry("data", &SerializableValue(data))?;
+        }
+        if self.has_errors() {
+            map.serialize_entry("errors", &self.errors)?;
+        }
+        map.end()
+    }
+}
@@ -0,0 +1,325 @@
+use inflector::Inflector;
+use massbit::components::store::EntityType;
+use massbit::data::graphql::ext::{DirectiveFinder, DocumentExt};
+use massbit::data::schema::{ApiSchema, Schema, SCHEMA_TYPE_NAME};
+use massbit::prelude::{anyhow, s, Arc};
+use massbit_store_postgres::relational::Layout;
+use std::fmt::{self, Write};
+
+/// The number of entities a collection returns when the query does not set `first`
+pub const DEFAULT_FIRST: u32 = 100;
+/// The largest value allowed for `first`
+pub const MAX_FIRST: u32 = 1000;
+/// The largest value allowed for `skip`
+pub const MAX_SKIP: u32 = 5000;
+
+/// The suffixes of the fields of a `<Type>_filter`, ordered so that a suffix
+/// comes before the shorter suffixes it ends with
+pub const FILTER_OPS: &[&str] = &[
+    "_not_contains",
+    "_not_starts_with",
+    "_not_ends_with",
+    "_contains",
+    "_starts_with",
+    "_ends_with",
+    "_not_in",
+    "_not",
+    "_gte",
+    "_lte",
+    "_gt",
+    "_lt",
+    "_in",
+];
+
+/// Everything needed to answer the queries for the entities of an indexer
+pub struct QuerySchema {
+    /// The schema the indexer was deployed with
+    pub schema: Schema,
+    /// The API schema generated from `schema`
+    pub api: ApiSchema,
+    /// The tables of the indexer
+    pub layout: Arc<Layout>,
+}
+
+impl QuerySchema {
+    pub fn new(schema: Schema, layout: Arc<Layout>) -> Result<Self, anyhow::Error> {
+        let document = api_schema(&schema)?;
+        let api = ApiSchema::from_api_schema(Schema::new(schema.id.clone(), document))?;
+        Ok(QuerySchema {
+            schema,
+            api,
+            layout,
+        })
+    }
+
+    /// Returns the definition of the entity type `name`.
+    pub fn entity_type(&self, name: &str) -> Option<&s::ObjectType> {
+        if !self
+            .layout
+            .tables
+            .contains_key(&EntityType::new(name.to_string()))
+        {
+            return None;
+        }
+        self.schema.document.get_object_type_definition(name)
+    }
+}
+
+/// The name of the `Query` field which looks up an entity by its id.
+pub fn single_field_name(entity_type: &str) -> String {
+    entity_type.to_camel_case()
+}
+
+/// The name of the `Query` field which returns a collection of entities.
+pub fn collection_field_name(entity_type: &str) -> String {
+    let name = entity_type.to_plural().to_camel_case();
+    if name == single_field_name(entity_type) {
+        format!("{}_collection", name)
+    } else {
+        name
+    }
+}
+
+pub fn is_list(field_type: &s::Type) -> bool {
+    match field_type {
+        s::Type::NamedType(_) => false,
+        s::Type::ListType(_) => true,
+        s::Type::NonNullType(inner) => is_list(inner),
+    }
+}
+
+/// Generates the API schema of an indexer from its schema. For every entity
+/// type it has a `Query` field that looks up one entity by id and a field
+/// for collections which takes `where`, `orderBy`, `orderDirection`, `first`
+/// and `skip`. Both take `block: { number }` to query the entities as they
+/// were at that block. `Subscription` has the same fields as `Query`.
+pub fn api_schema(input: &Schema) -> Result<s::Document, anyhow::Error> {
+    let mut sdl = String::new();
+    write_api_schema(input, &mut sdl)
+        .map_err(|_| anyhow::anyhow!("failed to generate the API schema"))?;
+    Ok(graphql_parser::parse_schema::<String>(&sdl)?.into_static())
+}
+
+fn write_api_schema(input: &Schema, out: &mut String) -> fmt::Result {
+    writeln!(out, "scalar BigDecimal\nscalar BigInt\nscalar Bytes\n")?;
+    writeln!(out, "enum OrderDirection {{\n  asc\n  desc\n}}\n")?;
+    writeln!(out, "input Block_height {{\n  number: Int\n}}\n")?;
+
+    for enum_type in input.document.get_enum_definitions() {
+        writeln!(out, "enum {} {{", enum_type.name)?;
+        for value in enum_type.values.iter() {
+            writeln!(out, "  {}", value.name)?;
+        }
+        writeln!(out, "}}\n")?;
+    }
+
+    let object_types: Vec<&s::ObjectType> = input
+        .document
+        .get_object_type_definitions()
+        .into_iter()
+        .filter(|object_type| object_type.name != SCHEMA_TYPE_NAME)
+        .collect();
+    let is_entity = |name: &str| object_types.iter().any(|object| object.name == name);
+
+    for object_type in object_types.iter() {
+        write_object_type(object_type, &is_entity, out)?;
+        write_filter(object_type, &is_entity, out)?;
+        write_order_by(object_type, out)?;
+    }
+
+    for root in &["Query", "Subscription"] {
+        writeln!(out, "type {} {{", root)?;
+        for object_type in object_types.iter() {
+            let name = &object_type.name;
+            writeln!(
+                out,
+                "  {}(id: ID!, block: Block_height): {}",
+                single_field_name(name),
+                name
+            )?;
+            writeln!(
+                out,
+                "  {}({}, block: Block_height): [{}!]!",
+                collection_field_name(name),
+                collection_arguments(name),
+                name
+            )?;
+        }
+        writeln!(out, "}}\n")?;
+    }
+    Ok(())
+}
+
+fn collection_arguments(entity_type: &str) -> String {
+    format!(
+        "where: {0}_filter, orderBy: {0}_orderBy, orderDirection: OrderDirection, \
+         first: Int = {1}, skip: Int = 0",
+        entity_type, DEFAULT_FIRST
+    )
+}
+
+fn type_name(field_type: &s::Type) -> String {
+    match field_type {
+        s::Type::NamedType(name) => name.clone(),
+        s::Type::ListType(inner) => format!("[{}]", type_name(inner)),
+        s::Type::NonNullType(inner) => format!("{}!", type_name(inner)),
+    }
+}
+
+pub(crate) fn base_type(field_type: &s::Type) -> &str {
+    match field_type {
+        s::Type::NamedType(name) => name,
+        s::Type::ListType(inner) | s::Type::NonNullType(inner) => base_type(inner),
+    }
+}
+
+/// Write the entity type with arguments for the fields that are collections
+/// of entities.
+fn write_object_type(
+    object_type: &s::ObjectType,
+    is_entity: &dyn Fn(&str) -> bool,
+    out: &mut String,
+) -> fmt::Result {
+    writeln!(out, "type {} {{", object_type.name)?;
+    for field in object_type.fields.iter() {
+        let base = base_type(&field.field_type);
+        if is_entity(base) && is_list(&field.field_type) {
+            writeln!(
+                out,
+                "  {}({}): {}",
+                field.name,
+                collection_arguments(base),
+                type_name(&field.field_type)
+            )?;
+        } else {
+            writeln!(out, "  {}: {}", field.name, type_name(&field.field_type))?;
+        }
+    }
+    writeln!(out, "}}\n")
+}
+
+/// Write the input type for the `where` argument. References are filtered
+/// by the ids of the entities they point to.
+fn write_filter(
+    object_type: &s::ObjectType,
+    is_entity: &dyn Fn(&str) -> bool,
+    out: &mut String,
+) -> fmt::Result {
+    writeln!(out, "input {}_filter {{", object_type.name)?;
+    for field in object_type
+        .fields
+        .iter()
+        .filter(|field| !field.is_derived())
+    {
+        let base = base_type(&field.field_type);
+        let value_type = if is_entity(base) { "String" } else { base };
+        let name = &field.name;
+        if is_list(&field.field_type) {
+            for op in &["", "_not", "_contains", "_not_contains"] {
+                writeln!(out, "  {}{}: [{}!]", name, op, value_type)?;
+            }
+            continue;
+        }
+        for op in &["", "_not"] {
+            writeln!(out, "  {}{}: {}", name, op, value_type)?;
+        }
+        if matches!(
+            value_type,
+            "ID" | "String" | "Int" | "BigInt" | "BigDecimal" | "Bytes"
+        ) {
+            for op in &["_gt", "_lt", "_gte", "_lte"] {
+                writeln!(out, "  {}{}: {}", name, op, value_type)?;
+            }
+        }
+        for op in &["_in", "_not_in"] {
+            writeln!(out, "  {}{}: [{}!]", name, op, value_type)?;
+        }
+        if value_type == "String" && !is_entity(base) {
+            for op in &[
+                "_contains",
+                "_not_contains",
+                "_starts_with",
+                "_not_starts_with",
+                "_ends_with",
+                "_not_ends_with",
+            ] {
+                writeln!(out, "  {}{}: {}", name, op, value_type)?;
+            }
+        }
+    }
+    writeln!(out, "}}\n")
+}
+
+/// Write the enum for the `orderBy` argument; only fields with a single
+/// value stored in the entity's table can be used for ordering.
+fn write_order_by(object_type: &s::ObjectType, out: &mut String) -> fmt::Result {
+    writeln!(out, "enum {}_orderBy {{", object_type.name)?;
+    for field in object_type
+        .fields
+        .iter()
+        .filter(|field| !field.is_derived() && !is_list(&field.field_type))
+    {
+        writeln!(out, "  {}", field.name)?;
+    }
+    writeln!(out, "}}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use massbit::data::graphql::ext::ObjectTypeExt;
+    use massbit::prelude::DeploymentHash;
+
+    const SCHEMA: &str = "
+        type Account @entity {
+            id: ID!
+            name: String!
+            tokens: [Token!]! @derivedFrom(field: \"owner\")
+        }
+
+        type Token @entity {
+            id: ID!
+            owner: Account!
+            amount: BigInt!
+            tags: [String!]
+        }";
+
+    #[test]
+    fn query_fields() {
+        let schema = Schema::parse(SCHEMA, DeploymentHash::new("test").unwrap()).unwrap();
+        let document = api_schema(&schema).expect("the API schema is valid GraphQL");
+        let query = document
+            .get_root_query_type()
+            .expect("there is a Query type");
+
+        let subscription = document
+            .get_root_subscription_type()
+            .expect("there is a Subscription type");
+        for name in &["account", "accounts", "token", "tokens"] {
+            assert!(query.field(name).is_some(), "Query has no field {}", name);
+            assert!(
+                subscription.field(name).is_some(),
+                "Subscription has no field {}",
+                name
+            );
+        }
+        let filter = document
+            .get_named_type("Token_filter")
+            .expect("there is a filter for Token");
+        let filter = match filter {
+            s::TypeDefinition::InputObject(filter) => filter,
+            _ => panic!("Token_filter is not an input type"),
+        };
+        let fields: Vec<&str> = filter.fields.iter().map(|f| f.name.as_str()).collect();
+        assert!(fields.contains(&"owner_in"));
+        assert!(fields.contains(&"amount_gte"));
+        assert!(fields.contains(&"tags_contains"));
+        assert!(!fields.contains(&"owner_starts_with"));
+    }
+
+    #[test]
+    fn collection_names() {
+        assert_eq!("tokens", collection_field_name("Token"));
+        assert_eq!("sheep_collection", collection_field_name("Sheep"));
+    }
+}
@@ -0,0 +1,86 @@
+use diesel::PgConnection;
+use massbit::components::store::{EntityType, StoreEvent};
+use massbit::data::query::QueryError;
+use std::collections::BTreeSet;
+
+use crate::execution::{execute_subscription, subscribed_entities};
+use crate::request::{QueryRequest, QueryResult};
+use crate::schema::QuerySchema;
+
+/// A subscription of a client. Its query is run again whenever a block
+/// changes one of the entities it reads.
+pub struct Subscription {
+    pub request: QueryRequest,
+    /// The types of the entities the query reads, with the id of the entity
+    /// when it only reads the entity with that id
+    entities: BTreeSet<(EntityType, Option<String>)>,
+}
+
+impl Subscription {
+    /// Validates the subscription of `request` and finds the entities it reads.
+    pub fn new(
+        conn: &PgConnection,
+        schema: &QuerySchema,
+        request: QueryRequest,
+    ) -> Result<Self, QueryError> {
+        let entities = subscribed_entities(conn, schema, &request)?;
+        Ok(Subscription { request, entities })
+    }
+
+    /// Returns true if `event` changes an entity the subscription reads.
+    pub fn matches(&self, event: &StoreEvent) -> bool {
+        self.entities
+            .iter()
+            .any(|(entity_type, id)| event.touches(entity_type, id.as_deref()))
+    }
+
+    /// Runs the query of the subscription against the latest entities.
+    pub fn execute(&self, conn: &PgConnection, schema: &QuerySchema) -> QueryResult {
+        execute_subscription(conn, schema, &self.request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(entities: &[(&str, Option<&str>)]) -> Subscription {
+        Subscription {
+            request: QueryRequest {
+                query: String::from("subscription { tokens { id } }"),
+                operation_name: None,
+                variables: None,
+            },
+            entities: entities
+                .iter()
+                .map(|(entity_type, id)| {
+                    (
+                        EntityType::new(entity_type.to_string()),
+                        id.map(str::to_string),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn event(changes: &[(&str, &str, bool)]) -> StoreEvent {
+        StoreEvent::from_changes("indexer", 10, changes.iter().cloned())
+    }
+
+    #[test]
+    fn matches_changed_entity_types() {
+        let subscription = subscription(&[("Token", None)]);
+        assert!(subscription.matches(&event(&[("Token", "1", false)])));
+        assert!(subscription.matches(&event(&[("Account", "1", false), ("Token", "2", true)])));
+        assert!(!subscription.matches(&event(&[("Account", "1", false)])));
+        assert!(!subscription.matches(&event(&[])));
+    }
+
+    #[test]
+    fn matches_changed_entity_ids() {
+        let subscription = subscription(&[("Token", Some("1")), ("Account", None)]);
+        assert!(subscription.matches(&event(&[("Token", "1", true)])));
+        assert!(!subscription.matches(&event(&[("Token", "2", false)])));
+        assert!(subscription.matches(&event(&[("Account", "7", false)])));
+    }
+}
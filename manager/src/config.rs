@@ -1,3 +1,4 @@
+use massbit::blockchain::BlockchainKind;
 use massbit::prelude::{
     anyhow::{anyhow, bail, Context, Result},
     info, Logger,
@@ -320,9 +321,10 @@ impl ChainSection {
                         headers: Default::default(),
                     }),
                 };
-                let entry = chains
-                    .entry(name.to_string())
-                    .or_insert_with(|| Chain { providers: vec![] });
+                let entry = chains.entry(name.to_string()).or_insert_with(|| Chain {
+                    protocol: BlockchainKind::Ethereum,
+                    providers: vec![],
+                });
                 entry.providers.push(provider);
             }
         }
@@ -332,6 +334,8 @@ impl ChainSection {
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Chain {
+    #[serde(default = "default_blockchain_kind")]
+    pub protocol: BlockchainKind,
     #[serde(rename = "provider")]
     pub providers: Vec<Provider>,
 }
@@ -347,6 +351,10 @@ impl Chain {
     }
 }
 
+fn default_blockchain_kind() -> BlockchainKind {
+    BlockchainKind::Ethereum
+}
+
 fn deserialize_http_headers<'de, D>(deserializer: D) -> Result<HeaderMap, D::Error>
 where
     D: serde::Deserializer<'de>,
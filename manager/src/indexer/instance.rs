@@ -139,4 +139,19 @@ where
             Some(host)
         })
     }
+
+    /// Remove the hosts of dynamic data sources that were created at or
+    /// after `reverted_block`
+    pub(crate) fn revert_data_sources(&mut self, reverted_block: BlockNumber) {
+        // `hosts` is ordered by the creation block.
+        // See also 8f1bca33-d3b7-4035-affc-fd6161a12448.
+        while self
+            .hosts
+            .last()
+            .filter(|h| h.creation_block_number() >= Some(reverted_block))
+            .is_some()
+        {
+            self.hosts.pop();
+        }
+    }
 }
@@ -1,7 +1,8 @@
 use fail::fail_point;
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::sync::{Arc, RwLock};
+use std::time::Instant;
 use tokio::task;
 
 use massbit::blockchain::{
@@ -13,12 +14,14 @@ use massbit::components::indexer::{DataSourceTemplateInfo, MappingError};
 use massbit::components::store::{
     DeploymentId, DeploymentLocator, IndexerStore, ModificationsAndCache, WritableStore,
 };
-use massbit::data::indexer::MAX_SPEC_VERSION;
+use massbit::data::indexer::{IndexerFeature, MAX_SPEC_VERSION};
 use massbit::ext::futures::CancelHandle;
 use massbit::prelude::{IndexerInstanceManager as IndexerInstanceManagerTrait, TryStreamExt, *};
+use massbit::util::backoff::ExponentialBackoff;
 use massbit::util::lfu_cache::LfuCache;
 
 use super::loader::load_dynamic_data_sources;
+use super::metrics::IndexerInstanceMetrics;
 use super::IndexerInstance;
 use massbit::data::indexer::schema::IndexerError;
 
@@ -32,6 +35,15 @@ lazy_static! {
             .expect("invalid ENTITY_CACHE_SIZE");
 }
 
+/// Delay before restarting an indexer after its first failure. The delay
+/// doubles with every failure in a row until it reaches `MAX_FAILURE_DELAY`
+const MIN_FAILURE_DELAY: Duration = Duration::from_secs(30);
+const MAX_FAILURE_DELAY: Duration = Duration::from_secs(30 * 60);
+
+/// How often an indexer that failed deterministically checks whether a
+/// reorg replaced the block that failed
+const REORG_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
 type SharedInstanceKeepAliveMap = Arc<RwLock<HashMap<DeploymentId, CancelGuard>>>;
 
 struct IndexingInputs<C: Blockchain> {
@@ -40,6 +52,8 @@ struct IndexingInputs<C: Blockchain> {
     triggers_adapter: Arc<C::TriggersAdapter>,
     chain: Arc<C>,
     templates: Arc<Vec<C::DataSourceTemplate>>,
+    features: BTreeSet<IndexerFeature>,
+    metrics: IndexerInstanceMetrics,
 }
 
 struct IndexingState<T: RuntimeHostBuilder<C>, C: Blockchain> {
@@ -84,6 +98,11 @@ where
                         .start_indexer_inner::<chain_ethereum::Chain>(logger, loc, manifest)
                         .await
                 }
+                BlockchainKind::Solana => {
+                    instance_manager
+                        .start_indexer_inner::<chain_solana::Chain>(logger, loc, manifest)
+                        .await
+                }
             }
         };
 
@@ -178,6 +197,7 @@ where
         let filter = C::TriggerFilter::from_data_sources(manifest.data_sources.iter());
 
         let templates = Arc::new(manifest.templates.clone());
+        let features = manifest.features.clone();
 
         let triggers_adapter = chain
             .triggers_adapter()
@@ -206,6 +226,8 @@ where
                 triggers_adapter,
                 chain,
                 templates,
+                features,
+                metrics: IndexerInstanceMetrics::new(deployment.hash.as_str()),
             },
             state: IndexingState {
                 logger: logger.cheap_clone(),
@@ -250,9 +272,20 @@ where
 {
     // Clone a few things for different parts of the async processing
     let logger = ctx.state.logger.cheap_clone();
-    let mut first_run = true;
+
+    // Whether the deployment may still be marked as failed from an earlier
+    // run. Processing a block at or past the failed block clears the failure
+    let mut should_try_unfail = true;
+    let mut backoff = ExponentialBackoff::new(MIN_FAILURE_DELAY, MAX_FAILURE_DELAY);
 
     loop {
+        // A deterministic error happens again whenever the failed block is
+        // processed, so only resume once that block is no longer canonical
+        if let Some(failed_ptr) = ctx.inputs.store.deterministic_error_block()? {
+            wait_for_reorg(&logger, &*ctx.inputs.triggers_adapter, failed_ptr).await;
+            should_try_unfail = true;
+        }
+
         debug!(logger, "Starting or restarting indexer");
 
         let block_stream_canceler = CancelGuard::new();
@@ -261,7 +294,12 @@ where
         let mut block_stream = ctx
             .inputs
             .chain
-            .new_block_stream(indexer_ptr, Arc::new(ctx.state.filter.clone()))
+            .new_block_stream(
+                ctx.inputs.deployment.clone(),
+                ctx.inputs.store.cheap_clone(),
+                indexer_ptr,
+                Arc::new(ctx.state.filter.clone()),
+            )
             .await?
             .map_err(CancelableError::Error)
             .cancelable(&block_stream_canceler, || Err(CancelableError::Cancel));
@@ -278,6 +316,39 @@ where
         loop {
             let block = match block_stream.next().await {
                 Some(Ok(BlockStreamEvent::ProcessBlock(block))) => block,
+                Some(Ok(BlockStreamEvent::Revert(block_ptr_to))) => {
+                    info!(
+                        logger,
+                        "Reverting indexer to get back to main chain";
+                        "block_number" => format!("{}", block_ptr_to.number),
+                        "block_hash" => format!("{}", block_ptr_to.hash),
+                    );
+
+                    // Revert the store to the block we are reverting to
+                    if let Err(e) = ctx
+                        .inputs
+                        .store
+                        .revert_block_operations(block_ptr_to.cheap_clone())
+                    {
+                        error!(
+                            &logger,
+                            "Could not revert block. Retrying";
+                            "error" => format!("{:#}", e),
+                        );
+
+                        // Exit inner block stream consumption loop and go up to loop that restarts indexer
+                        break;
+                    }
+
+                    // Revert the in-memory state:
+                    // - Remove hosts for reverted dynamic data sources.
+                    // - Clear the entity cache.
+                    ctx.state
+                        .instance
+                        .revert_data_sources(block_ptr_to.number + 1);
+                    ctx.state.entity_lfu_cache = LfuCache::new();
+                    continue;
+                }
                 // Log and drop the errors from the block_stream
                 // The block stream will continue attempting to produce blocks
                 Some(Err(e)) => {
@@ -291,24 +362,23 @@ where
                 None => unreachable!("The block stream stopped producing blocks"),
             };
 
+            let block_ptr = block.ptr();
             let res = process_block(
                 &logger,
                 ctx.inputs.triggers_adapter.cheap_clone(),
-                ctx,
+                &mut ctx,
                 block_stream_cancel_handle.clone(),
                 block,
             )
             .await;
 
             match res {
-                Ok((c, needs_restart)) => {
-                    ctx = c;
-
+                Ok(needs_restart) => {
                     // Unfail the indexer if it was previously failed.
-                    // As an optimization we check this only on the first run.
-                    if first_run {
-                        first_run = false;
+                    if should_try_unfail {
+                        should_try_unfail = !ctx.inputs.store.unfail(&block_ptr)?;
                     }
+                    backoff.reset();
 
                     if needs_restart {
                         // Cancel the stream for real
@@ -326,17 +396,96 @@ where
                     return Ok(());
                 }
 
-                // Handle unexpected stream errors by marking the indexer as failed.
+                // A deterministic error would happen again when the block is
+                // processed again, so mark the indexer as failed and restart
+                // it once a reorg replaced the block
+                Err(BlockProcessingError::Deterministic(error)) => {
+                    let message = error.to_string().replace("\n", "\t");
+                    ctx.inputs.store.fail_indexer(error)?;
+
+                    // Forget what the failed block did to the in-memory state
+                    ctx.state.instance.revert_data_sources(block_ptr.number);
+                    ctx.state.entity_lfu_cache = LfuCache::new();
+
+                    error!(
+                        &logger,
+                        "Indexer failed with deterministic error";
+                        "error" => &message,
+                        "block_number" => format!("{}", block_ptr.number),
+                        "block_hash" => format!("{}", block_ptr.hash),
+                    );
+                    break;
+                }
+
+                // Handle unexpected errors by marking the indexer as failed and
+                // restarting it after a delay that grows with every failure
                 Err(e) => {
                     let message = format!("{:#}", e).replace("\n", "\t");
-                    let err = anyhow!("{}", message);
-                    return Err(err);
+                    ctx.inputs.store.fail_indexer(IndexerError {
+                        indexer_id: ctx.inputs.deployment.hash.clone(),
+                        message: message.clone(),
+                        block_ptr: Some(block_ptr.cheap_clone()),
+                        handler: None,
+                        deterministic: false,
+                    })?;
+                    should_try_unfail = true;
+
+                    // Forget what the failed block did to the in-memory state
+                    ctx.state.instance.revert_data_sources(block_ptr.number);
+                    ctx.state.entity_lfu_cache = LfuCache::new();
+
+                    error!(
+                        &logger,
+                        "Indexer failed with non-deterministic error, retrying";
+                        "error" => &message,
+                        "attempt" => backoff.attempt,
+                        "retry_delay_s" => backoff.delay().as_secs(),
+                    );
+                    backoff.sleep_async().await;
+                    break;
                 }
             }
         }
     }
 }
 
+/// Wait until `failed_ptr` is no longer part of the canonical chain, which
+/// happens when the chain head moves past it after a reorg replaced it
+async fn wait_for_reorg<C: Blockchain>(
+    logger: &Logger,
+    triggers_adapter: &C::TriggersAdapter,
+    failed_ptr: BlockPtr,
+) {
+    info!(
+        logger,
+        "Indexer failed deterministically, waiting for a reorg of the failed block";
+        "block_number" => format!("{}", failed_ptr.number),
+        "block_hash" => format!("{}", failed_ptr.hash),
+    );
+    loop {
+        match triggers_adapter
+            .is_on_main_chain(failed_ptr.cheap_clone())
+            .await
+        {
+            Ok(false) => {
+                info!(
+                    logger,
+                    "Failed block is no longer canonical, resuming indexer";
+                    "block_number" => format!("{}", failed_ptr.number),
+                );
+                return;
+            }
+            Ok(true) => {}
+            Err(e) => debug!(
+                logger,
+                "Could not check whether the failed block is still canonical";
+                "error" => format!("{:#}", e),
+            ),
+        }
+        tokio::time::sleep(REORG_CHECK_INTERVAL).await;
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 enum BlockProcessingError {
     #[error("{0:#}")]
@@ -357,15 +506,15 @@ impl From<Error> for BlockProcessingError {
     }
 }
 
-/// Processes a block and returns the updated context and a boolean flag indicating
-/// whether new dynamic data sources have been added to the indexer.
+/// Processes a block and returns a boolean flag indicating whether new dynamic
+/// data sources have been added to the indexer.
 async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
     logger: &Logger,
     triggers_adapter: Arc<C::TriggersAdapter>,
-    mut ctx: IndexingContext<T, C>,
+    ctx: &mut IndexingContext<T, C>,
     block_stream_cancel_handle: CancelHandle,
     block: BlockWithTriggers<C>,
-) -> Result<(IndexingContext<T, C>, bool), BlockProcessingError> {
+) -> Result<bool, BlockProcessingError> {
     let triggers = block.trigger_data;
     let block = Arc::new(block.block);
     let block_ptr = block.ptr();
@@ -392,6 +541,7 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
             std::mem::take(&mut ctx.state.entity_lfu_cache),
         ),
         &ctx.state.instance,
+        &ctx.inputs.metrics,
         &block,
         triggers,
     )
@@ -416,7 +566,7 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
             // Losing the cache is a bit annoying but not an issue for correctness.
             //
             // See also b21fa73b-6453-4340-99fb-1a78ec62efb1.
-            return Ok((ctx, true));
+            return Ok(true);
         }
     };
 
@@ -436,7 +586,7 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
         // Instantiate dynamic data sources, removing them from the block state.
         let (data_sources, runtime_hosts) = create_dynamic_data_sources(
             logger.clone(),
-            &mut ctx,
+            ctx,
             block_state.drain_created_data_sources(),
         )?;
 
@@ -461,7 +611,7 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
         // and add runtimes for the data sources to the indexer instance.
         persist_dynamic_data_sources(
             logger.clone(),
-            &mut ctx,
+            ctx,
             &mut block_state.entity_cache,
             data_sources,
         );
@@ -469,6 +619,7 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
         // Process the triggers in each host in the same order the
         // corresponding data sources have been created.
         for trigger in triggers {
+            let start = Instant::now();
             block_state = IndexerInstance::<C, T>::process_trigger_in_runtime_hosts(
                 &logger,
                 &runtime_hosts,
@@ -488,19 +639,36 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
                     }
                 }
             })?;
+            let metrics = &ctx.inputs.metrics;
+            metrics
+                .trigger_processing_duration
+                .observe(start.elapsed().as_secs_f64());
+            metrics.triggers_processed.inc();
         }
     }
 
     // The triggers were processed but some were skipped due to deterministic errors, if the
     // `nonFatalErrors` feature is not present, return early with an error.
     let has_errors = block_state.has_errors();
-    if has_errors {
+    let is_non_fatal_errors_active = ctx
+        .inputs
+        .features
+        .contains(&IndexerFeature::NonFatalErrors);
+    if has_errors && !is_non_fatal_errors_active {
         // Take just the first error to report.
         return Err(BlockProcessingError::Deterministic(
             block_state.deterministic_errors.into_iter().next().unwrap(),
         ));
     }
 
+    // The changes of the failed handlers were discarded; the errors are
+    // stored with the block so that queries can tell that data is missing
+    let deterministic_errors = block_state.deterministic_errors;
+    for error in &deterministic_errors {
+        warn!(&logger, "Skipped handler after a deterministic error";
+              "error" => error.to_string());
+    }
+
     // Apply entity operations and advance the stream
 
     // Avoid writing to store if block stream has been canceled
@@ -530,8 +698,11 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
     // Transact entity operations into the store and update the
     // indexer's block stream pointer
     let store = &ctx.inputs.store;
-    match store.transact_block_operations(block_ptr, mods, data_sources) {
-        Ok(_) => Ok((ctx, needs_restart)),
+    match store.transact_block_operations(block_ptr, mods, data_sources, deterministic_errors) {
+        Ok(_) => {
+            ctx.inputs.metrics.blocks_processed.inc();
+            Ok(needs_restart)
+        }
         Err(e) => Err(anyhow!("Error while processing block stream for a indexer: {}", e).into()),
     }
 }
@@ -540,10 +711,12 @@ async fn process_triggers<C: Blockchain>(
     logger: &Logger,
     mut block_state: BlockState<C>,
     instance: &IndexerInstance<C, impl RuntimeHostBuilder<C>>,
+    metrics: &IndexerInstanceMetrics,
     block: &Arc<C::Block>,
     triggers: Vec<C::TriggerData>,
 ) -> Result<BlockState<C>, MappingError> {
     for trigger in triggers.into_iter() {
+        let start = Instant::now();
         block_state = instance
             .process_trigger(&logger, block, &trigger, block_state)
             .await
@@ -554,6 +727,10 @@ async fn process_triggers<C: Blockchain>(
                 }
                 e.context("failed to process trigger".to_string())
             })?;
+        metrics
+            .trigger_processing_duration
+            .observe(start.elapsed().as_secs_f64());
+        metrics.triggers_processed.inc();
     }
 
     Ok(block_state)
@@ -0,0 +1,39 @@
+use lazy_static::lazy_static;
+
+use massbit::components::metrics::{self, Histogram, HistogramVec, IntCounter, IntCounterVec};
+
+lazy_static! {
+    static ref BLOCKS_PROCESSED: IntCounterVec = metrics::int_counter_vec(
+        "indexer_blocks_processed_total",
+        "Number of blocks an indexer processed",
+        &["deployment"]
+    );
+    static ref TRIGGERS_PROCESSED: IntCounterVec = metrics::int_counter_vec(
+        "indexer_triggers_processed_total",
+        "Number of triggers an indexer ran handlers for",
+        &["deployment"]
+    );
+    static ref TRIGGER_PROCESSING_DURATION: HistogramVec = metrics::histogram_vec(
+        "indexer_trigger_processing_duration_seconds",
+        "Time the handlers of an indexer took to process a trigger",
+        &["deployment"]
+    );
+}
+
+/// The metrics of a running indexer
+pub(crate) struct IndexerInstanceMetrics {
+    pub blocks_processed: IntCounter,
+    pub triggers_processed: IntCounter,
+    pub trigger_processing_duration: Histogram,
+}
+
+impl IndexerInstanceMetrics {
+    pub fn new(deployment: &str) -> Self {
+        IndexerInstanceMetrics {
+            blocks_processed: BLOCKS_PROCESSED.with_label_values(&[deployment]),
+            triggers_processed: TRIGGERS_PROCESSED.with_label_values(&[deployment]),
+            trigger_processing_duration: TRIGGER_PROCESSING_DURATION
+                .with_label_values(&[deployment]),
+        }
+    }
+}
@@ -2,6 +2,7 @@ mod instance;
 mod instance_manager;
 mod link_resolver;
 mod loader;
+mod metrics;
 mod provider;
 mod registrar;
 
@@ -99,6 +99,18 @@ where
                 )
                 .await?
             }
+            BlockchainKind::Solana => {
+                create_indexer_deployment::<chain_solana::Chain, _, _>(
+                    &logger,
+                    self.store.clone(),
+                    self.chains.cheap_clone(),
+                    name.clone(),
+                    hash.cheap_clone(),
+                    raw.clone(),
+                    self.resolver.cheap_clone(),
+                )
+                .await?
+            }
         };
 
         let locations = self.store.locators(&hash)?;
@@ -160,23 +172,23 @@ async fn create_indexer_deployment<C: Blockchain, S: IndexerStore, L: LinkResolv
         return Err(IndexerRegistrarError::NameNotFound(name.to_string()));
     }
 
-    let start_block = resolve_indexer_chain_blocks(logger, &manifest, chain).await?;
+    let (start_block, base_block) = resolve_indexer_chain_blocks(logger, &manifest, chain).await?;
 
     // Apply the indexer versioning and deployment operations,
     // creating a new indexer deployment if one doesn't exist.
-    let deployment = IndexerDeploymentEntity::new(&manifest, start_block);
+    let deployment = IndexerDeploymentEntity::new(&manifest, start_block).graft(base_block);
     deployment_store
         .create_indexer_deployment(name, &manifest.schema, deployment, network_name)
         .map_err(|e| IndexerRegistrarError::IndexerDeploymentError(e))
         .map(|_| ())
 }
 
-/// Resolves the indexer's earliest block
+/// Resolves the indexer's earliest block and the graft base block
 async fn resolve_indexer_chain_blocks(
     logger: &Logger,
     manifest: &IndexerManifest<impl Blockchain>,
     chain: Arc<impl Blockchain>,
-) -> Result<Option<BlockPtr>, IndexerRegistrarError> {
+) -> Result<(Option<BlockPtr>, Option<(DeploymentHash, BlockPtr)>), IndexerRegistrarError> {
     // If the minimum start block is 0 (i.e. the genesis block),
     // return `None` to start indexing from the genesis block. Otherwise
     // return a block pointer for the block with number `min_start_block - 1`.
@@ -198,5 +210,18 @@ async fn resolve_indexer_chain_blocks(
             })?,
     };
 
-    Ok(start_block_ptr)
+    let base_ptr = match &manifest.graft {
+        None => None,
+        Some(graft) => chain
+            .block_pointer_from_number(logger, graft.block)
+            .await
+            .map(|ptr| Some((graft.base.clone(), ptr)))
+            .map_err(move |_| {
+                IndexerRegistrarError::ManifestValidationError(vec![
+                    IndexerManifestValidationError::BlockNotFound(graft.block.to_string()),
+                ])
+            })?,
+    };
+
+    Ok((start_block_ptr, base_ptr))
 }
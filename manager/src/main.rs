@@ -5,7 +5,10 @@ use structopt::StructOpt;
 use chain_ethereum::adapter::EthereumAdapter;
 use chain_ethereum::network::EthereumNetworks;
 use chain_ethereum::Transport;
-use massbit::blockchain::BlockchainMap;
+use chain_solana::adapter::{SolanaAdapter, SolanaNetworkAdapter};
+use chain_solana::types::ChainConfig;
+use massbit::blockchain::{BlockchainKind, BlockchainMap};
+use massbit::components::server::metrics::serve_metrics;
 use massbit::firehose::endpoints::{FirehoseEndpoint, FirehoseNetworkEndpoints, FirehoseNetworks};
 use massbit::ipfs_client::IpfsClient;
 use massbit::log::logger;
@@ -67,8 +70,11 @@ async fn main() {
         .await
         .expect("Failed to parse Firehose networks");
 
-    // Obtain JSON-RPC server port
+    let solana_networks = create_solana_networks(logger.clone(), &config);
+
+    // Obtain ports to use for the JSON-RPC and metrics servers
     let json_rpc_port = opt.json_rpc_port;
+    let metrics_port = opt.metrics_port;
 
     let launch_services = || async move {
         let (eth_networks, _) = connect_networks(&logger, eth_networks).await;
@@ -80,6 +86,12 @@ async fn main() {
             &firehose_networks,
             &logger_factory,
         );
+        solana_networks_as_chains(
+            &mut blockchain_map,
+            solana_networks,
+            &firehose_networks,
+            &logger_factory,
+        );
         let blockchain_map = Arc::new(blockchain_map);
 
         let indexer_instance_manager = IndexerInstanceManager::new(
@@ -106,6 +118,15 @@ async fn main() {
 
         // Let the server run forever.
         std::mem::forget(json_rpc_server);
+
+        // Serve the metrics of the indexers and the store for Prometheus
+        info!(
+            logger,
+            "Starting metrics server at: http://localhost:{}/metrics", metrics_port
+        );
+        if let Err(e) = serve_metrics(metrics_port).await {
+            error!(logger, "Metrics server stopped"; "error" => e.to_string());
+        }
     };
 
     massbit::spawn(launch_services());
@@ -220,6 +241,9 @@ async fn create_ethereum_networks(
 ) -> Result<EthereumNetworks, anyhow::Error> {
     let mut parsed_networks = EthereumNetworks::new();
     for (name, chain) in config.chains.chains {
+        if chain.protocol != BlockchainKind::Ethereum {
+            continue;
+        }
         for provider in chain.providers {
             if let ProviderDetails::Web3(web3) = provider.details {
                 let logger = logger.new(o!("provider" => provider.label.clone()));
@@ -295,6 +319,68 @@ async fn create_firehose_networks(
     Ok(parsed_networks)
 }
 
+/// Creates a Solana adapter for each chain with the `solana` protocol. The RPC url is taken
+/// from the first Web3 provider of the chain, falling back to the built-in network defaults.
+fn create_solana_networks(logger: Logger, config: &Config) -> HashMap<String, SolanaAdapter> {
+    let mut parsed_networks = HashMap::new();
+    for (name, chain) in &config.chains.chains {
+        if chain.protocol != BlockchainKind::Solana {
+            continue;
+        }
+        let web3 = chain
+            .providers
+            .iter()
+            .find_map(|provider| match provider.details {
+                ProviderDetails::Web3(ref web3) => Some(web3),
+                _ => None,
+            });
+        let adapter = match web3 {
+            Some(web3) => {
+                info!(
+                    logger,
+                    "Creating Solana adapter";
+                    "network" => name,
+                    "url" => &web3.url,
+                );
+                SolanaAdapter::new(&ChainConfig {
+                    url: web3.url.clone(),
+                    ws: Default::default(),
+                    network: name.clone(),
+                    supports_eip_1898: false,
+                })
+            }
+            None => {
+                info!(logger, "Creating Solana adapter with default url"; "network" => name);
+                SolanaNetworkAdapter::from(name.clone())
+                    .get_adapter()
+                    .as_ref()
+                    .clone()
+            }
+        };
+        parsed_networks.insert(name.clone(), adapter);
+    }
+    parsed_networks
+}
+
+/// Add the Solana chains to `blockchain_map`.
+fn solana_networks_as_chains(
+    blockchain_map: &mut BlockchainMap,
+    solana_networks: HashMap<String, SolanaAdapter>,
+    firehose_networks: &FirehoseNetworks,
+    logger_factory: &LoggerFactory,
+) {
+    for (network_name, solana_adapter) in solana_networks {
+        let firehose_endpoints = firehose_networks.networks.get(&network_name);
+        let chain = chain_solana::Chain::new(
+            logger_factory.clone(),
+            network_name.clone(),
+            solana_adapter,
+            firehose_endpoints.map_or_else(|| FirehoseNetworkEndpoints::new(), |v| v.clone()),
+        );
+        blockchain_map.insert::<chain_solana::Chain>(network_name, Arc::new(chain))
+    }
+}
+
 /// Return the hashmap of ethereum chains and also add them to `blockchain_map`.
 fn ethereum_networks_as_chains(
     blockchain_map: &mut BlockchainMap,
@@ -99,6 +99,13 @@ pub struct Opt {
         help = "Port for the JSON-RPC indexer manager server"
     )]
     pub json_rpc_port: u16,
+    #[structopt(
+        long,
+        default_value = "8040",
+        value_name = "PORT",
+        help = "Port for the Prometheus metrics server"
+    )]
+    pub metrics_port: u16,
     #[structopt(long, help = "Enable debug logging")]
     pub debug: bool,
     #[structopt(
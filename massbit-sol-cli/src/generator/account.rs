@@ -0,0 +1,73 @@
+use crate::generator::Generator;
+use crate::schema::{Encoding, Schema, VariantArray};
+use std::fmt::Write;
+
+/// Name of the generated enum of the account layouts
+pub const ACCOUNT_LAYOUT: &str = "AccountLayout";
+
+impl<'a> Generator<'a> {
+    /// Enum of the account layouts of the program, appended to the instruction
+    /// module. Its inner values are the definitions of the account data.
+    pub fn generate_account_layouts(&self, schema: &Schema) -> String {
+        let mut out = String::new();
+        if let Some(layouts) = &schema.account_layouts {
+            let unpack = match schema.encoding {
+                Encoding::Borsh => {
+                    self.expand_discriminator_unpack(&ACCOUNT_LAYOUT.to_string(), layouts)
+                }
+                Encoding::Packed => self.expand_packed_layout_unpack(layouts),
+            };
+            let _ = write! {
+                out,
+                "#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]\npub enum {name} {{\n{variants}\n}}\nimpl {name} {{\n{unpack}\n}}",
+                name = ACCOUNT_LAYOUT,
+                variants = self.expand_variants(layouts),
+                unpack = unpack
+            };
+        }
+        out
+    }
+    /// Packed accounts have no discriminator: a layout is identified by the
+    /// size of its definition. Layouts of unknown size are tried in order.
+    pub fn expand_packed_layout_unpack(&self, layouts: &VariantArray) -> String {
+        let branches = layouts
+            .iter()
+            .filter_map(|layout| {
+                let inner_type = layout.inner_type.as_ref()?;
+                let size = self
+                    .definitions
+                    .get(inner_type)
+                    .and_then(|schema| schema.get_size(&self.definitions));
+                let branch = match size {
+                    Some(size) => format!(
+                        r#"if input.len() == {size} {{
+                            if let Some(inner) = {inner_type}::unpack(array_ref![input, 0, {size}]) {{
+                                return Some({name}::{var_name}(inner));
+                            }}
+                        }}"#,
+                        size = size,
+                        inner_type = inner_type,
+                        name = ACCOUNT_LAYOUT,
+                        var_name = &layout.name
+                    ),
+                    None => format!(
+                        r#"if let Some(inner) = {inner_type}::unpack(input) {{
+                            return Some({name}::{var_name}(inner));
+                        }}"#,
+                        inner_type = inner_type,
+                        name = ACCOUNT_LAYOUT,
+                        var_name = &layout.name
+                    ),
+                };
+                Some(branch)
+            })
+            .collect::<Vec<String>>();
+        format!(
+            r#"pub fn unpack(input: &[u8]) -> Option<Self> {{
+                {branches}
+                None
+            }}"#,
+            branches = branches.join("\n")
+        )
+    }
+}
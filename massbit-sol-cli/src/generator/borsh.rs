@@ -0,0 +1,192 @@
+use crate::generator::Generator;
+use crate::schema::{PropertyArray, Schema, VariantArray};
+use std::fmt::Write;
+
+const MODULES: &str = r#"
+use borsh::BorshDeserialize;
+use serde::{Deserialize, Serialize};
+use solana_program::pubkey::Pubkey;
+"#;
+impl<'a> Generator<'a> {
+    /// Instruction module for Borsh encoded schemas: the definitions derive
+    /// BorshDeserialize, the instruction enum is unpacked by its discriminators
+    pub fn generate_borsh_instruction(&self, schema: &Schema) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", MODULES);
+        self.definitions.iter().for_each(|(name, def)| {
+            self.expand_borsh_definition(&mut out, name, def);
+        });
+        if let (Some(name), Some(variants)) = (&schema.name, &schema.variants) {
+            let name = schema.get_pascal_name(name);
+            let unpack = self.expand_discriminator_unpack(&name, variants);
+            let _ = write! {
+                out,
+                "#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]\npub enum {name} {{\n{variants}\n}}\nimpl {name} {{\n{unpack}\n}}",
+                name = &name,
+                variants = self.expand_variants(variants),
+                unpack = unpack
+            };
+        }
+        let _ = writeln!(out, "{}", self.generate_account_layouts(schema));
+        out
+    }
+    pub fn expand_borsh_definition(&self, out: &mut String, name: &String, schema: &Schema) {
+        let name = schema.get_pascal_name(name);
+        let body = if let Some(properties) = &schema.properties {
+            format!(
+                "pub struct {} {{{}}}",
+                &name,
+                self.expand_borsh_fields(properties)
+            )
+        } else if let Some(variants) = &schema.variants {
+            format!(
+                "pub enum {} {{\n{}\n}}",
+                &name,
+                self.expand_borsh_variants(variants)
+            )
+        } else {
+            return;
+        };
+        let _ = writeln!(
+            out,
+            "#[derive(Clone, PartialEq, Debug, Serialize, Deserialize, BorshDeserialize)]\n{}",
+            body
+        );
+    }
+    pub fn expand_borsh_fields(&self, properties: &PropertyArray) -> String {
+        properties
+            .iter()
+            .map(|property| {
+                let data_type = match property.array_length {
+                    Some(len) if len > 0 => format!("Vec<{}>", &property.data_type),
+                    _ => property.data_type.clone(),
+                };
+                format!(
+                    "{}pub {}: {}",
+                    serde_attribute(&data_type),
+                    &property.name,
+                    data_type
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(",\n")
+    }
+    pub fn expand_borsh_variants(&self, variants: &VariantArray) -> String {
+        variants
+            .iter()
+            .map(|variant| match &variant.inner_type {
+                None => variant.name.clone(),
+                Some(inner) => format!("{}({}{})", &variant.name, serde_attribute(inner), inner),
+            })
+            .collect::<Vec<String>>()
+            .join(",\n")
+    }
+    /// Match the data against the discriminator of each variant, then
+    /// deserialize the inner value from the remaining bytes. A variant without
+    /// discriminator is identified by a single byte tag, as Borsh does for enums.
+    pub fn expand_discriminator_unpack(&self, name: &String, variants: &VariantArray) -> String {
+        let branches = variants
+            .iter()
+            .map(|variant| {
+                let discriminator = variant
+                    .discriminator
+                    .clone()
+                    .unwrap_or_else(|| vec![variant.variant_tag as u8]);
+                let discriminator = discriminator
+                    .iter()
+                    .map(|byte| byte.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                match &variant.inner_type {
+                    None => format!(
+                        r#"if input.starts_with(&[{discriminator}]) {{
+                            return Some({name}::{var_name});
+                        }}"#,
+                        discriminator = discriminator,
+                        name = name,
+                        var_name = &variant.name
+                    ),
+                    Some(inner_type) => format!(
+                        r#"if let Some(mut data) = input.strip_prefix(&[{discriminator}]) {{
+                            return <{inner_type} as BorshDeserialize>::deserialize(&mut data)
+                                .ok()
+                                .map({name}::{var_name});
+                        }}"#,
+                        discriminator = discriminator,
+                        inner_type = inner_type,
+                        name = name,
+                        var_name = &variant.name
+                    ),
+                }
+            })
+            .collect::<Vec<String>>();
+        format!(
+            r#"pub fn unpack(input: &[u8]) -> Option<Self> {{
+                {branches}
+                None
+            }}"#,
+            branches = branches.join("\n")
+        )
+    }
+}
+
+/// Serde implements its traits for arrays of up to 32 items, bigger arrays
+/// are (de)serialized with `serde_big_array`. Borsh has no such limit.
+fn serde_attribute(data_type: &str) -> &'static str {
+    let len = data_type
+        .trim()
+        .strip_prefix('[')
+        .and_then(|data_type| data_type.strip_suffix(']'))
+        .and_then(|data_type| data_type.rsplit_once(';'))
+        .and_then(|(_, len)| len.trim().parse::<usize>().ok());
+    match len {
+        Some(len) if len > 32 => "#[serde(with = \"serde_big_array::BigArray\")] ",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Property;
+
+    fn property(name: &str, data_type: &str) -> Property {
+        Property {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            length: None,
+            array_length: None,
+            required: true,
+            description: None,
+        }
+    }
+
+    #[test]
+    fn big_arrays_use_serde_big_array() {
+        assert_eq!(serde_attribute("u64"), "");
+        assert_eq!(serde_attribute("[u8; 32]"), "");
+        assert_eq!(
+            serde_attribute("[u8; 64]"),
+            "#[serde(with = \"serde_big_array::BigArray\")] "
+        );
+        assert_eq!(
+            serde_attribute("[[u8; 2]; 33]"),
+            "#[serde(with = \"serde_big_array::BigArray\")] "
+        );
+        assert_eq!(serde_attribute("Vec<[u8; 2]>"), "");
+    }
+
+    #[test]
+    fn fields_with_big_arrays() {
+        let generator = Generator::builder().build();
+        let fields = generator.expand_borsh_fields(&vec![
+            property("amount", "u64"),
+            property("signature", "[u8; 64]"),
+        ]);
+        assert_eq!(
+            fields,
+            "pub amount: u64,\n\
+             #[serde(with = \"serde_big_array::BigArray\")] pub signature: [u8; 64]"
+        );
+    }
+}
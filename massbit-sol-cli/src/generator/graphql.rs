@@ -92,8 +92,44 @@ impl<'a> Generator<'a> {
                 // }
             }
         }
+        if let Some(layouts) = &schema.account_layouts {
+            for layout in layouts {
+                let _ = writeln!(out, "{}", &self.generate_account_entity(layout));
+            }
+        }
         out
     }
+    /// Entity of an account layout, identified by the account pubkey
+    fn generate_account_entity(&self, layout: &Variant) -> String {
+        let mut entity_properties: Vec<String> = vec![
+            String::from("id: ID!"),
+            String::from("\towner: String"),
+            String::from("\tlamports: BigInt"),
+            String::from("\tslot: BigInt"),
+        ];
+        let properties = layout
+            .inner_type
+            .as_ref()
+            .and_then(|inner_type| self.definitions.get(inner_type.as_str()))
+            .and_then(|def| def.properties.as_ref());
+        for property in properties.into_iter().flatten() {
+            let db_type = MAPPING_RUST_TYPES_TO_DB
+                .get(property.data_type.as_str())
+                .unwrap_or(&*DEFAULT_TYPE_DB);
+            if property.array_length.is_some() {
+                entity_properties.push(format!("\t{}: [{}]", &property.name, db_type));
+            } else {
+                entity_properties.push(format!("\t{}: {}", &property.name, db_type));
+            }
+        }
+        format!(
+            r#"type {} @entity {{
+    {entity_properties}
+}}"#,
+            &layout.name,
+            entity_properties = entity_properties.join(",\n")
+        )
+    }
     fn generate_variant_entity(
         &self,
         variant: &Variant,
@@ -1,3 +1,4 @@
+use crate::generator::account::ACCOUNT_LAYOUT;
 use crate::generator::graphql::MAPPING_RUST_TYPES_TO_DB;
 use crate::generator::Generator;
 use crate::schema::{Schema, Variant, VariantArray};
@@ -8,7 +9,7 @@ const MODULES: &str = r#"
 use crate::generated::instruction::*;
 use crate::STORE;
 use massbit_solana_sdk::entity::{Attribute, Entity, Value};
-use massbit_solana_sdk::types::SolanaBlock;
+use massbit_solana_sdk::types::{AccountUpdate, SolanaBlock};
 use serde_json;
 use solana_program::pubkey::Pubkey;
 use solana_transaction_status::TransactionWithStatusMeta;
@@ -41,6 +42,7 @@ impl<'a> Generator<'a> {
             let patterns = self.expand_handler_patterns(&name, schema.variants.as_ref().unwrap());
             let handler_functions =
                 self.expand_handler_functions(schema.variants.as_ref().unwrap());
+            let account_functions = self.expand_account_handler(schema);
             let _ = write!(
                 &mut out,
                 r#"pub struct Handler {{}}
@@ -61,10 +63,12 @@ impl<'a> Generator<'a> {
                             }}
                         }}
                         {handler_functions}
+                        {account_functions}
                     }}"#,
                 name = name,
                 patterns = patterns.join(",\n"),
-                handler_functions = handler_functions.join("\n")
+                handler_functions = handler_functions.join("\n"),
+                account_functions = account_functions
             );
         }
         out
@@ -167,6 +171,73 @@ impl<'a> Generator<'a> {
             entity_name = &variant.name
         )
     }
+    /// Handler of the account updates: the account data is unpacked with its
+    /// layout, then saved as an entity of the layout, with the account pubkey as id
+    pub fn expand_account_handler(&self, schema: &Schema) -> String {
+        let layouts = match &schema.account_layouts {
+            Some(layouts) => layouts,
+            None => return String::default(),
+        };
+        let mut patterns: Vec<String> = Vec::default();
+        let mut functions: Vec<String> = Vec::default();
+        for layout in layouts {
+            let inner_type = match &layout.inner_type {
+                Some(inner_type) => inner_type,
+                None => continue,
+            };
+            let function_name = format!("process_{}_account", &layout.name.to_snake_case());
+            patterns.push(format!(
+                r#"{enum_name}::{var_name}(arg) => {{
+                    self.{function_name}(account, arg);
+                }}"#,
+                enum_name = ACCOUNT_LAYOUT,
+                var_name = &layout.name,
+                function_name = &function_name
+            ));
+            let mut assignments: Vec<String> = Vec::default();
+            if let Some(inner_schema) = self.definitions.get(inner_type.as_str()) {
+                self.expand_entity_assignment(&mut assignments, inner_schema);
+            }
+            functions.push(format!(
+                r#"pub fn {function_name}(
+                        &self,
+                        account: &AccountUpdate,
+                        arg: {inner_type},
+                    ) -> Result<(), anyhow::Error> {{
+                        println!("call function {function_name} for account {{}} at slot {{}}", account.pubkey, account.slot);
+                        let mut map : HashMap<Attribute, Value> = HashMap::default();
+                        map.insert("id".to_string(), Value::from(account.pubkey.to_string()));
+                        map.insert("owner".to_string(), Value::from(account.owner.to_string()));
+                        map.insert("lamports".to_string(), Value::from(account.lamports));
+                        map.insert("slot".to_string(), Value::from(account.slot));
+                        {assignments}
+                        Entity::from(map).save("{entity_name}");
+                        Ok(())
+                    }}"#,
+                function_name = &function_name,
+                inner_type = inner_type,
+                assignments = assignments.join("\n"),
+                entity_name = &layout.name
+            ));
+        }
+        //Layouts without data have no handler
+        if patterns.len() < layouts.len() {
+            patterns.push(String::from("_ => {}"));
+        }
+        format!(
+            r#"pub fn process_account(&self, account: &AccountUpdate) {{
+                if let Some(layout) = {enum_name}::unpack(account.data.as_slice()) {{
+                    match layout {{
+                        {patterns}
+                    }}
+                }}
+            }}
+            {functions}"#,
+            enum_name = ACCOUNT_LAYOUT,
+            patterns = patterns.join(",\n"),
+            functions = functions.join("\n")
+        )
+    }
     fn expand_single_assignment(
         &self,
         assignments: &mut Vec<String>,
@@ -182,6 +253,14 @@ impl<'a> Generator<'a> {
         //If inner schema is a struct
         if let Some(properties) = &inner_schema.properties {
             for property in properties {
+                //Store public keys in their base58 form
+                if property.data_type == "Pubkey" {
+                    assignments.push(format!(
+                        r#"map.insert("{name}".to_string(), Value::from(arg.{name}.to_string()));"#,
+                        name = &property.name
+                    ));
+                    continue;
+                }
                 let db_type = MAPPING_RUST_TYPES_TO_DB.get(property.data_type.as_str());
                 //.unwrap_or(&*DEFAULT_TYPE_DB);
                 // If data_type is not primitive (e.g. Enum, Struct)
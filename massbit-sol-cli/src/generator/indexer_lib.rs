@@ -6,7 +6,7 @@ use massbit_solana_sdk::{
     export_plugin,
     plugin::{handler::SolanaHandler, PluginRegistrar},
     store::IndexStore,
-    types::SolanaBlock,
+    types::{AccountUpdate, SolanaBlock},
 };
 use lazy_static::lazy_static;
 use solana_client::rpc_client::RpcClient;
@@ -39,5 +39,10 @@ impl SolanaHandler for SolanaHandlerAdapter {
     fn handle_block(&self, block: &SolanaBlock) -> Result<(), Box<dyn Error>> {
         mapping::handle_block(block)
     }
+{{#if accounts}}
+    fn handle_account(&self, account: &AccountUpdate) -> Result<(), Box<dyn Error>> {
+        mapping::handle_account(account)
+    }
+{{/if}}
 }
 "#;
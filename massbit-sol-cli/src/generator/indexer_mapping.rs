@@ -57,3 +57,13 @@ fn parse_instructions(block: &SolanaBlock, tran: &TransactionWithStatusMeta, tx_
 }
 
 "#;
+/// Appended to the mapping when the program has account layouts
+pub const INDEXER_MAPPING_ACCOUNT: &str = r#"
+use massbit_solana_sdk::types::AccountUpdate;
+
+pub fn handle_account(account: &AccountUpdate) -> Result<(), Box<dyn std::error::Error>> {
+    let handler = Handler {};
+    handler.process_account(account);
+    Ok(())
+}
+"#;
@@ -14,6 +14,7 @@ anyhow = "1.0.44"
 uuid = { version = "0.8", features = ["serde", "v4"] }
 num-traits = "0.2.12"
 arrayref = "0.3.6"
+borsh = "0.9.1"
 arbitrary = { version = "0.4.6", features = ["derive"], optional = true }
 bincode = "1.3.1"
 enumflags2 = "0.6.4"
@@ -24,6 +25,7 @@ safe-transmute = "0.11.0"
 lazy_static     = "1.4.0"
 serde = "1.0.114"
 serde_json = "1.0.69"
+serde-big-array = "0.4"
 static_assertions = "1.1.0"
 spl-token = { version = "3.0.0-pre1", features = ["no-entrypoint"] }
 
@@ -89,6 +91,10 @@ dataSources:
       handlers:
         - handler: handleBlock
           kind: solana/BlockHandler
+{{#if accounts}}
+      accountHandlers:
+        - handler: handleAccount
+{{/if}}
       file: ./src/mapping.rs
       abis:
         - name: Serum
@@ -1,6 +1,6 @@
 use crate::generator::helper::is_integer_type;
 use crate::generator::Generator;
-use crate::schema::{Property, PropertyArray, Schema, Variant, VariantArray};
+use crate::schema::{Encoding, Property, PropertyArray, Schema, Variant, VariantArray};
 use std::fmt::Write;
 
 const MODULES: &str = r#"
@@ -10,6 +10,9 @@ use std::num::*;
 "#;
 impl<'a> Generator<'a> {
     pub fn generate_instruction(&self, schema: &Schema) -> String {
+        if schema.encoding == Encoding::Borsh {
+            return self.generate_borsh_instruction(schema);
+        }
         let mut out = String::new();
         //Import modules for instruction
         let _ = writeln!(out, "{}", MODULES);
@@ -20,6 +23,7 @@ impl<'a> Generator<'a> {
         if let Some(name) = &schema.name {
             self.expand_schema(&mut out, name, schema);
         }
+        let _ = writeln!(out, "{}", self.generate_account_layouts(schema));
         out
     }
     pub fn expand_schema(&self, out: &mut String, name: &String, schema: &Schema) {
@@ -1,3 +1,5 @@
+pub mod account;
+pub mod borsh;
 pub mod graphql;
 pub mod handler;
 pub mod helper;
@@ -9,10 +11,11 @@ pub mod instruction;
 //pub mod model;
 
 use crate::generator::indexer_mod::INDEXER_MOD;
+use crate::parser::Idl;
 use crate::schema::Schema;
 use handlebars::Handlebars;
 use indexer_lib::INDEXER_LIB;
-use indexer_mapping::INDEXER_MAPPING;
+use indexer_mapping::{INDEXER_MAPPING, INDEXER_MAPPING_ACCOUNT};
 use indexer_setting::*;
 use minifier::json::minify;
 
@@ -26,6 +29,8 @@ use std::io;
 #[must_use]
 pub struct Generator<'a> {
     pub structure_path: &'a str,
+    /// Anchor IDL, used instead of the instruction structure when it is set
+    pub idl_path: &'a str,
     pub config_path: &'a str,
     /// The output dir
     pub output_dir: &'a str,
@@ -46,6 +51,7 @@ impl<'a> Generator<'a> {
             let name = &config["name"].as_str().unwrap_or_default();
             let contract_address = &config["contract_address"].as_str().unwrap_or_default();
             let start_block = &config["start_block"].as_i64().unwrap_or_default();
+            let accounts = schema.account_layouts.is_some();
 
             //Instruction
             let data = self.generate_instruction(schema);
@@ -75,6 +81,7 @@ impl<'a> Generator<'a> {
                     INDEXER_LIB,
                     &json!({
                         "address": contract_address,
+                        "accounts": accounts,
                     }),
                 )
                 .unwrap();
@@ -84,9 +91,13 @@ impl<'a> Generator<'a> {
                 true,
             )?;
             //Mapping
+            let mut mapping = format!("{}", INDEXER_MAPPING);
+            if accounts {
+                mapping.push_str(INDEXER_MAPPING_ACCOUNT);
+            }
             self.write_to_file(
                 &format!("{}/{}", self.output_dir, "src/mapping.rs"),
-                &format!("{}", INDEXER_MAPPING),
+                &mapping,
                 true,
             )?;
             //mod.rs
@@ -105,7 +116,8 @@ impl<'a> Generator<'a> {
                         &json!({
                             "name": name,
                             "address": contract_address,
-                            "start_block": start_block
+                            "start_block": start_block,
+                            "accounts": accounts
                         }),
                     )
                     .unwrap(),
@@ -173,6 +185,7 @@ impl<'a> Default for GeneratorBuilder<'a> {
         Self {
             inner: Generator {
                 structure_path: "",
+                idl_path: "",
                 config_path: "",
                 output_dir: "",
                 schema: None,
@@ -195,6 +208,17 @@ impl<'a> GeneratorBuilder<'a> {
         self.inner.schema = Some(schema);
         self
     }
+    pub fn with_idl_path(mut self, path: &'a str) -> Self {
+        self.inner.idl_path = path;
+        let json = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("Unable to read `{}`: {}", path, err));
+        let idl: Idl = serde_json::from_str(&json)
+            .unwrap_or_else(|err| panic!("Cannot parse `{}` as Anchor IDL: {}", path, err));
+        let schema = idl.to_schema();
+        self.collect_definitions(&schema);
+        self.inner.schema = Some(schema);
+        self
+    }
     pub fn with_config_path(mut self, path: &'a str) -> Self {
         self.inner.config_path = path;
         let json = std::fs::read_to_string(path)
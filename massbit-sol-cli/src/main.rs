@@ -21,8 +21,11 @@ fn main() {
         let structure_path = matches.value_of("structure").unwrap_or("instruction.rs");
         let config_path = matches.value_of("config").unwrap_or("config.json");
         let output = matches.value_of("output").unwrap_or("src");
-        let generator = Generator::builder()
-            .with_structure_path(structure_path)
+        let builder = match matches.value_of("idl") {
+            Some(idl_path) => Generator::builder().with_idl_path(idl_path),
+            None => Generator::builder().with_structure_path(structure_path),
+        };
+        let generator = builder
             .with_config_path(config_path)
             .with_output_dir(output)
             .build();
@@ -71,6 +74,15 @@ fn create_gencode_cmd() -> App<'static, 'static> {
                 .help("Input instruction structure file")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("idl")
+                .short("i")
+                .long("idl")
+                .value_name("idl")
+                .help("Input Anchor IDL file, used instead of the instruction structure")
+                .takes_value(true)
+                .conflicts_with("structure"),
+        )
         .arg(
             Arg::with_name("config")
                 .short("c")
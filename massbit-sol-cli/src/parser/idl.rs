@@ -0,0 +1,483 @@
+//! Conversion of an Anchor IDL into the instruction `Schema`.
+//!
+//! Anchor programs serialize instructions with Borsh, after an 8 bytes
+//! discriminator: the first bytes of `sha256("global:<instruction name>")`.
+//! Each instruction becomes a variant of the `<Program>Instruction` enum, with
+//! its arguments in a `<Instruction>Args` struct. The accounts and types of the
+//! IDL become definitions, and the accounts are the account layouts, identified
+//! by the first bytes of `sha256("account:<Account>")`.
+use crate::generator::helper::replace_invalid_identifier_chars;
+use crate::schema::{AccountInfo, Encoding, Property, Schema, Variant};
+use inflector::Inflector;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Idl {
+    pub version: String,
+    pub name: String,
+    pub instructions: Vec<IdlInstruction>,
+    #[serde(default)]
+    pub accounts: Vec<IdlTypeDefinition>,
+    #[serde(default)]
+    pub types: Vec<IdlTypeDefinition>,
+    #[serde(default)]
+    pub metadata: Option<IdlMetadata>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdlMetadata {
+    pub address: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdlInstruction {
+    pub name: String,
+    pub accounts: Vec<IdlAccountItem>,
+    pub args: Vec<IdlField>,
+}
+
+/// An account of an instruction, or a group of accounts declared by a nested
+/// `Accounts` struct
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum IdlAccountItem {
+    Accounts {
+        name: String,
+        accounts: Vec<IdlAccountItem>,
+    },
+    Account {
+        name: String,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdlField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlType,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdlTypeDefinition {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: IdlTypeDefinitionTy,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase", tag = "kind")]
+pub enum IdlTypeDefinitionTy {
+    Struct { fields: Vec<IdlField> },
+    Enum { variants: Vec<IdlEnumVariant> },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IdlEnumVariant {
+    pub name: String,
+    #[serde(default)]
+    pub fields: Option<IdlEnumFields>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum IdlEnumFields {
+    Named(Vec<IdlField>),
+    Tuple(Vec<IdlType>),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum IdlType {
+    /// `bool`, integers, floats, `string`, `publicKey` and `bytes`
+    Primitive(String),
+    Vec {
+        vec: Box<IdlType>,
+    },
+    Option {
+        option: Box<IdlType>,
+    },
+    Defined {
+        defined: String,
+    },
+    Array {
+        array: (Box<IdlType>, usize),
+    },
+}
+
+impl IdlType {
+    /// The Rust type which Borsh deserializes from this type
+    pub fn rust_type(&self) -> String {
+        match self {
+            IdlType::Primitive(name) => match name.as_str() {
+                "string" => String::from("String"),
+                "publicKey" => String::from("Pubkey"),
+                "bytes" => String::from("Vec<u8>"),
+                primitive => primitive.to_string(),
+            },
+            IdlType::Vec { vec } => format!("Vec<{}>", vec.rust_type()),
+            IdlType::Option { option } => format!("Option<{}>", option.rust_type()),
+            IdlType::Defined { defined } => type_name(defined),
+            IdlType::Array { array: (item, len) } => format!("[{}; {}]", item.rust_type(), len),
+        }
+    }
+}
+
+/// The discriminator of an Anchor instruction
+pub fn sighash(instruction_name: &str) -> Vec<u8> {
+    let preimage = format!("global:{}", instruction_name.to_snake_case());
+    Sha256::digest(preimage.as_bytes())[..8].to_vec()
+}
+
+/// The discriminator of an Anchor account, written before its Borsh data
+pub fn account_discriminator(account_name: &str) -> Vec<u8> {
+    let preimage = format!("account:{}", account_name);
+    Sha256::digest(preimage.as_bytes())[..8].to_vec()
+}
+
+fn type_name(name: &str) -> String {
+    replace_invalid_identifier_chars(&name.to_pascal_case())
+}
+
+fn field_name(name: &str) -> String {
+    replace_invalid_identifier_chars(&name.to_snake_case())
+}
+
+fn properties(fields: &[IdlField]) -> Vec<Property> {
+    fields
+        .iter()
+        .map(|field| Property {
+            name: field_name(&field.name),
+            data_type: field.ty.rust_type(),
+            length: None,
+            array_length: None,
+            required: !matches!(field.ty, IdlType::Option { .. }),
+            description: None,
+        })
+        .collect()
+}
+
+fn struct_schema(name: &str, fields: &[IdlField]) -> Schema {
+    Schema {
+        name: Some(name.to_string()),
+        properties: Some(properties(fields)),
+        encoding: Encoding::Borsh,
+        ..Default::default()
+    }
+}
+
+fn variant(name: String, inner_type: Option<String>, variant_tag: u32) -> Variant {
+    Variant {
+        name,
+        value: None,
+        inner_name: None,
+        inner_type,
+        inner_scope: None,
+        description: None,
+        offset: None,
+        variant_tag,
+        accounts: None,
+        discriminator: None,
+    }
+}
+
+/// Flattens nested account groups, whose accounts follow each other in the
+/// instruction. Accounts of a group are prefixed with the name of the group.
+fn collect_accounts(items: &[IdlAccountItem], prefix: &str, accounts: &mut Vec<AccountInfo>) {
+    for item in items {
+        match item {
+            IdlAccountItem::Accounts {
+                name,
+                accounts: group,
+            } => {
+                let prefix = format!("{}{}_", prefix, field_name(name));
+                collect_accounts(group, &prefix, accounts);
+            }
+            IdlAccountItem::Account { name } => accounts.push(AccountInfo {
+                index: accounts.len(),
+                name: format!("{}{}", prefix, field_name(name)),
+            }),
+        }
+    }
+}
+
+impl Idl {
+    pub fn to_schema(&self) -> Schema {
+        let mut definitions = BTreeMap::new();
+        for def in self.accounts.iter().chain(self.types.iter()) {
+            add_definition(&mut definitions, def);
+        }
+        let variants = self
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| {
+                let name = type_name(&instruction.name);
+                let inner_type = if instruction.args.is_empty() {
+                    None
+                } else {
+                    let args_name = format!("{}Args", &name);
+                    definitions.insert(
+                        args_name.clone(),
+                        struct_schema(&args_name, &instruction.args),
+                    );
+                    Some(args_name)
+                };
+                let mut accounts = Vec::default();
+                collect_accounts(&instruction.accounts, "", &mut accounts);
+                Variant {
+                    accounts: Some(accounts),
+                    discriminator: Some(sighash(&instruction.name)),
+                    ..variant(name, inner_type, index as u32)
+                }
+            })
+            .collect();
+        let account_layouts = self
+            .accounts
+            .iter()
+            .enumerate()
+            .map(|(index, account)| {
+                let name = type_name(&account.name);
+                Variant {
+                    discriminator: Some(account_discriminator(&account.name)),
+                    ..variant(name.clone(), Some(name), index as u32)
+                }
+            })
+            .collect::<Vec<Variant>>();
+        Schema {
+            name: Some(format!("{}Instruction", type_name(&self.name))),
+            variants: Some(variants),
+            definitions,
+            encoding: Encoding::Borsh,
+            account_layouts: Some(account_layouts).filter(|layouts| !layouts.is_empty()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Adds an account or a type of the IDL. Variants of enums with named
+/// fields hold a `<Enum><Variant>` struct, which is added as well.
+fn add_definition(definitions: &mut BTreeMap<String, Schema>, def: &IdlTypeDefinition) {
+    let name = type_name(&def.name);
+    let schema = match &def.ty {
+        IdlTypeDefinitionTy::Struct { fields } => struct_schema(&name, fields),
+        IdlTypeDefinitionTy::Enum { variants } => {
+            let variants = variants
+                .iter()
+                .enumerate()
+                .map(|(index, enum_variant)| {
+                    let variant_name = type_name(&enum_variant.name);
+                    let inner_type = match &enum_variant.fields {
+                        None => None,
+                        Some(IdlEnumFields::Named(fields)) => {
+                            let struct_name = format!("{}{}", &name, &variant_name);
+                            definitions
+                                .insert(struct_name.clone(), struct_schema(&struct_name, fields));
+                            Some(struct_name)
+                        }
+                        Some(IdlEnumFields::Tuple(types)) => Some(
+                            types
+                                .iter()
+                                .map(IdlType::rust_type)
+                                .collect::<Vec<String>>()
+                                .join(", "),
+                        ),
+                    };
+                    variant(variant_name, inner_type, index as u32)
+                })
+                .collect();
+            Schema {
+                name: Some(name.clone()),
+                variants: Some(variants),
+                encoding: Encoding::Borsh,
+                ..Default::default()
+            }
+        }
+    };
+    definitions.insert(name, schema);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDL: &str = r#"{
+        "version": "0.1.0",
+        "name": "dex_market",
+        "instructions": [
+            {
+                "name": "initializeMarket",
+                "accounts": [
+                    { "name": "market", "isMut": true, "isSigner": false },
+                    {
+                        "name": "vaults",
+                        "accounts": [
+                            { "name": "baseVault", "isMut": true, "isSigner": false },
+                            { "name": "quoteVault", "isMut": true, "isSigner": false }
+                        ]
+                    }
+                ],
+                "args": [
+                    { "name": "feeRate", "type": "u64" },
+                    { "name": "authority", "type": { "option": "publicKey" } },
+                    { "name": "seed", "type": { "array": ["u8", 64] } }
+                ]
+            },
+            { "name": "closeMarket", "accounts": [], "args": [] }
+        ],
+        "accounts": [
+            {
+                "name": "Market",
+                "type": {
+                    "kind": "struct",
+                    "fields": [{ "name": "orders", "type": { "vec": { "defined": "Side" } } }]
+                }
+            }
+        ],
+        "types": [
+            {
+                "name": "Side",
+                "type": {
+                    "kind": "enum",
+                    "variants": [
+                        { "name": "Bid" },
+                        { "name": "Ask", "fields": [{ "name": "price", "type": "u64" }] },
+                        { "name": "Cancel", "fields": ["u8", "bool"] }
+                    ]
+                }
+            }
+        ]
+    }"#;
+
+    fn schema() -> Schema {
+        serde_json::from_str::<Idl>(IDL).unwrap().to_schema()
+    }
+
+    #[test]
+    fn sighash_of_instruction() {
+        // The first bytes of sha256("global:initialize")
+        assert_eq!(
+            sighash("initialize"),
+            vec![175, 175, 109, 31, 13, 152, 155, 237]
+        );
+        // Anchor snake cases the name of the instruction
+        assert_eq!(
+            sighash("initializeMarket"),
+            vec![35, 35, 189, 193, 155, 48, 170, 203]
+        );
+        assert_eq!(sighash("initializeMarket"), sighash("initialize_market"));
+    }
+
+    #[test]
+    fn discriminator_of_account() {
+        // The first bytes of sha256("account:Market")
+        assert_eq!(
+            account_discriminator("Market"),
+            vec![219, 190, 213, 55, 0, 227, 198, 154]
+        );
+    }
+
+    #[test]
+    fn instructions_are_variants() {
+        let schema = schema();
+        assert_eq!(schema.name.as_deref(), Some("DexMarketInstruction"));
+        assert!(matches!(schema.encoding, Encoding::Borsh));
+        let variants = schema.variants.unwrap();
+        assert_eq!(variants.len(), 2);
+
+        let initialize = &variants[0];
+        assert_eq!(initialize.name, "InitializeMarket");
+        assert_eq!(initialize.variant_tag, 0);
+        assert_eq!(
+            initialize.inner_type.as_deref(),
+            Some("InitializeMarketArgs")
+        );
+        assert_eq!(initialize.discriminator, Some(sighash("initialize_market")));
+        let accounts: Vec<(usize, &str)> = initialize
+            .accounts
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|account| (account.index, account.name.as_str()))
+            .collect();
+        assert_eq!(
+            accounts,
+            vec![
+                (0, "market"),
+                (1, "vaults_base_vault"),
+                (2, "vaults_quote_vault")
+            ]
+        );
+
+        let close = &variants[1];
+        assert_eq!(close.name, "CloseMarket");
+        assert_eq!(close.variant_tag, 1);
+        assert_eq!(close.inner_type, None);
+        assert_eq!(close.accounts.as_ref().map(Vec::len), Some(0));
+    }
+
+    #[test]
+    fn arguments_are_a_struct() {
+        let schema = schema();
+        let args = &schema.definitions["InitializeMarketArgs"];
+        let fields: Vec<(&str, &str, bool)> = args
+            .properties
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|property| {
+                (
+                    property.name.as_str(),
+                    property.data_type.as_str(),
+                    property.required,
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("fee_rate", "u64", true),
+                ("authority", "Option<Pubkey>", false),
+                ("seed", "[u8; 64]", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn accounts_and_types_are_definitions() {
+        let schema = schema();
+        let market = &schema.definitions["Market"];
+        assert_eq!(
+            market.properties.as_ref().unwrap()[0].data_type,
+            "Vec<Side>"
+        );
+
+        let side: Vec<(&str, Option<&str>)> = schema.definitions["Side"]
+            .variants
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|variant| (variant.name.as_str(), variant.inner_type.as_deref()))
+            .collect();
+        assert_eq!(
+            side,
+            vec![
+                ("Bid", None),
+                ("Ask", Some("SideAsk")),
+                ("Cancel", Some("u8, bool"))
+            ]
+        );
+        assert_eq!(
+            schema.definitions["SideAsk"].properties.as_ref().unwrap()[0].name,
+            "price"
+        );
+
+        let layouts = schema.account_layouts.unwrap();
+        assert_eq!(layouts.len(), 1);
+        assert_eq!(layouts[0].inner_type.as_deref(), Some("Market"));
+        assert_eq!(
+            layouts[0].discriminator,
+            Some(account_discriminator("Market"))
+        );
+    }
+}
@@ -1,2 +1,4 @@
+pub mod idl;
 pub mod schema_builder;
+pub use idl::Idl;
 pub use schema_builder::SchemaBuilder;
@@ -26,6 +26,10 @@ pub struct Variant {
     pub variant_tag: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub accounts: Option<Vec<AccountInfo>>,
+    //Bytes before the Borsh encoded inner value, which identify the variant
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discriminator: Option<Vec<u8>>,
 }
 
 impl Variant {
@@ -108,6 +112,23 @@ pub enum SimpleTypes {
     #[serde(rename = "string")]
     String,
 }
+/// Layout of the instruction data
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize, Serialize)]
+pub enum Encoding {
+    /// Fixed size fields at fixed offsets, unpacked with arrayref
+    #[serde(rename = "packed")]
+    Packed,
+    /// Borsh serialized values, variants of the instruction are identified
+    /// by their discriminator
+    #[serde(rename = "borsh")]
+    Borsh,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Packed
+    }
+}
 pub type StringArray = Vec<String>;
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct Schema {
@@ -126,6 +147,13 @@ pub struct Schema {
     pub offset: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "variantTagLength")]
     pub variant_tag_length: Option<usize>,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Layouts of the accounts owned by the program. The inner type of a
+    /// variant is the definition of the account data
+    #[serde(skip_serializing_if = "Option::is_none", rename = "accountLayouts")]
+    #[serde(default)]
+    pub account_layouts: Option<VariantArray>,
 }
 
 impl Schema {
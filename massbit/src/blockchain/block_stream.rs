@@ -58,16 +58,29 @@ pub trait TriggersAdapter<C: Blockchain>: Send + Sync {
         block: C::Block,
         filter: &C::TriggerFilter,
     ) -> Result<BlockWithTriggers<C>, Error>;
+
+    /// Return `true` if the block is part of the chain that the adapter
+    /// currently considers canonical.
+    async fn is_on_main_chain(&self, ptr: BlockPtr) -> Result<bool, Error>;
+
+    /// Get pointer to parent of `block`. This is called when reverting `block`.
+    async fn parent_ptr(&self, block: &BlockPtr) -> Result<Option<BlockPtr>, Error>;
 }
 
 pub trait FirehoseMapper<C: Blockchain>: Send + Sync {
-    fn to_block_stream_event(
+    /// Decodes a response from the stream service. A single response may carry several
+    /// blocks, which must be returned in the order in which they should be processed.
+    fn to_block_stream_events(
         &self,
         logger: &Logger,
         response: &bstream::BlockResponse,
-    ) -> Result<BlockStreamEvent<C>, Error>;
+    ) -> Result<Vec<BlockStreamEvent<C>>, Error>;
 }
 
 pub enum BlockStreamEvent<C: Blockchain> {
+    /// Revert the indexer to the given block, undoing all changes made by
+    /// blocks after it that are no longer part of the main chain.
+    Revert(BlockPtr),
+
     ProcessBlock(BlockWithTriggers<C>),
 }
@@ -1,4 +1,5 @@
 use futures03::{FutureExt, Stream, StreamExt};
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -7,7 +8,8 @@ use crate::firehose::endpoints::FirehoseEndpoint;
 use crate::prelude::*;
 
 use super::block_stream::{BlockStream, BlockStreamEvent, FirehoseMapper};
-use super::Blockchain;
+use super::{Blockchain, BlockchainKind};
+use crate::components::store::DeploymentLocator;
 use crate::firehose::bstream;
 
 pub struct FirehoseBlockStreamContext<C, F>
@@ -16,6 +18,7 @@ where
     F: FirehoseMapper<C>,
 {
     mapper: Arc<F>,
+    deployment: DeploymentLocator,
     network: String,
     filter: Arc<C::TriggerFilter>,
     start_block: BlockNumber,
@@ -26,6 +29,7 @@ impl<C: Blockchain, F: FirehoseMapper<C>> Clone for FirehoseBlockStreamContext<C
     fn clone(&self) -> Self {
         Self {
             mapper: self.mapper.clone(),
+            deployment: self.deployment.clone(),
             network: self.network.clone(),
             filter: self.filter.clone(),
             start_block: self.start_block.clone(),
@@ -53,6 +57,8 @@ pub struct FirehoseBlockStream<C: Blockchain, F: FirehoseMapper<C>> {
     state: BlockStreamState,
     ctx: FirehoseBlockStreamContext<C, F>,
     connection_attempts: u64,
+    /// Events decoded from the last response that have not been yielded yet.
+    pending_events: Box<VecDeque<BlockStreamEvent<C>>>,
 }
 
 impl<C, F> FirehoseBlockStream<C, F>
@@ -63,6 +69,7 @@ where
     pub fn new(
         endpoint: Arc<FirehoseEndpoint>,
         mapper: Arc<F>,
+        deployment: DeploymentLocator,
         network: String,
         filter: Arc<C::TriggerFilter>,
         start_block: BlockNumber,
@@ -73,12 +80,14 @@ where
             state: BlockStreamState::Disconnected,
             ctx: FirehoseBlockStreamContext {
                 mapper,
+                deployment,
                 network,
                 logger,
                 filter,
                 start_block,
             },
             connection_attempts: 0,
+            pending_events: Box::new(VecDeque::new()),
         }
     }
 }
@@ -89,6 +98,10 @@ impl<C: Blockchain, F: FirehoseMapper<C>> Stream for FirehoseBlockStream<C, F> {
     type Item = Result<BlockStreamEvent<C>, Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(event) = self.pending_events.pop_front() {
+            return Poll::Ready(Some(Ok(event)));
+        }
+
         loop {
             match &mut self.state {
                 BlockStreamState::Disconnected => {
@@ -98,11 +111,18 @@ impl<C: Blockchain, F: FirehoseMapper<C>> Stream for FirehoseBlockStream<C, F> {
                     );
 
                     let filter_bytes = serde_json::to_vec(&self.ctx.filter)?;
+                    let chain_type = match C::KIND {
+                        BlockchainKind::Ethereum => bstream::ChainType::Ethereum,
+                        BlockchainKind::Solana => bstream::ChainType::Solana,
+                    };
                     let future = self.endpoint.clone().stream_blocks(bstream::BlockRequest {
+                        indexer_hash: self.ctx.deployment.hash.to_string(),
                         start_block_number: Some(self.ctx.start_block as u64),
-                        chain_type: bstream::ChainType::Ethereum as i32,
+                        chain_type: chain_type as i32,
                         network: self.ctx.network.clone(),
                         filter: filter_bytes,
+                        encoding: bstream::PayloadEncoding::Json as i32,
+                        compression: bstream::PayloadCompression::Uncompressed as i32,
                     });
                     let mut stream_connection = Box::pin(future);
 
@@ -163,10 +183,15 @@ impl<C: Blockchain, F: FirehoseMapper<C>> Stream for FirehoseBlockStream<C, F> {
                         match self
                             .ctx
                             .mapper
-                            .to_block_stream_event(&self.ctx.logger, &response)
+                            .to_block_stream_events(&self.ctx.logger, &response)
                         {
-                            Ok(event) => {
-                                return Poll::Ready(Some(Ok(event)));
+                            Ok(events) => {
+                                self.pending_events.extend(events);
+                                match self.pending_events.pop_front() {
+                                    Some(event) => return Poll::Ready(Some(Ok(event))),
+                                    // The response carried no blocks, wait for the next one
+                                    None => continue,
+                                }
                             }
                             Err(e) => {
                                 error!(
@@ -11,6 +11,7 @@ mod types;
 use anyhow::{anyhow, Context, Error};
 use async_trait::async_trait;
 use serde::de::DeserializeOwned;
+use serde_derive::Serialize;
 use slog::{self, SendSyncRefUnwindSafeKV};
 use std::any::Any;
 use std::collections::{BTreeMap, HashMap};
@@ -23,10 +24,12 @@ use std::{
 
 use crate::components::indexer::DataSourceTemplateInfo;
 use crate::components::link_resolver::LinkResolver;
-use crate::components::store::{BlockNumber, StoredDynamicDataSource};
+use crate::components::store::{
+    BlockNumber, DeploymentLocator, StoredDynamicDataSource, WritableStore,
+};
 use crate::data::indexer::{DataSourceContext, IndexerManifestValidationError};
 use crate::prelude::serde::Serialize;
-use crate::prelude::{CheapClone, Logger};
+use crate::prelude::{CheapClone, Deserialize, Logger};
 use crate::runtime::{AscHeap, AscPtr, DeterministicHostError, HostExportError};
 
 pub use block_stream::{BlockStream, TriggersAdapter};
@@ -82,6 +85,8 @@ pub trait Blockchain: Debug + Sized + Send + Sync + Unpin + 'static {
 
     async fn new_block_stream(
         &self,
+        deployment: DeploymentLocator,
+        indexer_store: Arc<dyn WritableStore>,
         start_block: BlockNumber,
         filter: Arc<Self::TriggerFilter>,
     ) -> Result<Box<dyn BlockStream<Self>>, Error>;
@@ -214,16 +219,21 @@ pub trait RuntimeAdapter<C: Blockchain>: Send + Sync {
     fn host_fns(&self, ds: &C::DataSource) -> Result<Vec<HostFn>, Error>;
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BlockchainKind {
     /// Ethereum itself or chains that are compatible.
     Ethereum,
+
+    /// Solana, fed by the chain-reader.
+    Solana,
 }
 
 impl fmt::Display for BlockchainKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let value = match self {
             BlockchainKind::Ethereum => "ethereum",
+            BlockchainKind::Solana => "solana",
         };
         write!(f, "{}", value)
     }
@@ -235,6 +245,7 @@ impl FromStr for BlockchainKind {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "ethereum" => Ok(BlockchainKind::Ethereum),
+            "solana" => Ok(BlockchainKind::Solana),
             _ => Err(anyhow!("unknown blockchain kind {}", s)),
         }
     }
@@ -3,7 +3,8 @@ use std::collections::VecDeque;
 use std::task::{Context, Poll};
 
 use crate::blockchain::block_stream::{BlockStreamEvent, BlockWithTriggers, TriggersAdapter};
-use crate::blockchain::{BlockStream, Blockchain};
+use crate::blockchain::{Block, BlockStream, Blockchain};
+use crate::components::store::WritableStore;
 use crate::prelude::*;
 
 lazy_static! {
@@ -44,6 +45,10 @@ enum ReconciliationStep<C>
 where
     C: Blockchain,
 {
+    /// Revert the current block pointed at by the indexer pointer. The pointer is to the
+    /// parent block, i.e. the block the indexer store is reverted to.
+    RevertBlock(BlockPtr),
+
     /// Move forwards, processing one or more blocks. Second element is the block range size.
     ProcessDescendantBlocks(Vec<BlockWithTriggers<C>>, BlockNumber),
 }
@@ -54,6 +59,7 @@ where
 {
     logger: Logger,
     adapter: Arc<C::TriggersAdapter>,
+    indexer_store: Arc<dyn WritableStore>,
     filter: Arc<C::TriggerFilter>,
     stream_ptr: BlockNumber,
     previous_triggers_per_block: f64,
@@ -69,6 +75,7 @@ impl<C: Blockchain> Clone for BlockStreamContext<C> {
         Self {
             logger: self.logger.clone(),
             adapter: self.adapter.clone(),
+            indexer_store: self.indexer_store.clone(),
             filter: self.filter.clone(),
             stream_ptr: self.stream_ptr.clone(),
             previous_triggers_per_block: self.previous_triggers_per_block,
@@ -89,6 +96,9 @@ enum NextBlocks<C>
 where
     C: Blockchain,
 {
+    /// Revert the indexer store to the given block
+    Revert(BlockPtr),
+
     /// Blocks and range size
     Blocks(VecDeque<BlockWithTriggers<C>>, BlockNumber),
 }
@@ -100,6 +110,7 @@ where
     pub fn new(
         logger: Logger,
         adapter: Arc<C::TriggersAdapter>,
+        indexer_store: Arc<dyn WritableStore>,
         filter: Arc<C::TriggerFilter>,
         stream_ptr: BlockNumber,
         max_block_range_size: BlockNumber,
@@ -110,6 +121,7 @@ where
             ctx: BlockStreamContext {
                 logger,
                 adapter,
+                indexer_store,
                 filter,
                 stream_ptr,
                 // A high number here forces a slow start, with a range of 1.
@@ -131,6 +143,9 @@ where
 
         loop {
             match ctx.get_next_step().await? {
+                ReconciliationStep::RevertBlock(parent_ptr) => {
+                    return Ok(NextBlocks::Revert(parent_ptr));
+                }
                 ReconciliationStep::ProcessDescendantBlocks(next_blocks, range_size) => {
                     return Ok(NextBlocks::Blocks(
                         next_blocks.into_iter().collect(),
@@ -146,6 +161,15 @@ where
         let ctx = self.clone();
         let max_block_range_size = self.max_block_range_size;
 
+        // If the indexer head is no longer on the main chain, the chain was
+        // reorganized and the head must be reverted before moving forward.
+        let indexer_ptr = self.indexer_store.block_ptr()?;
+        if let Some(head) = &indexer_ptr {
+            if !self.adapter.is_on_main_chain(head.cheap_clone()).await? {
+                return self.revert_step(head).await;
+            }
+        }
+
         let from = self.stream_ptr + 1;
 
         // Calculate the range size according to the target number of triggers,
@@ -180,10 +204,35 @@ where
 
         let blocks = self.adapter.scan_triggers(from, to, &self.filter).await?;
 
+        // A block that directly follows the indexer head must have the head as its
+        // parent; otherwise the head was reorged out while we were scanning.
+        if let (Some(head), Some(first)) = (&indexer_ptr, blocks.first()) {
+            if first.block.number() == head.number + 1
+                && first.block.parent_hash().as_ref() != Some(&head.hash)
+            {
+                return self.revert_step(head).await;
+            }
+        }
+
         Ok(ReconciliationStep::ProcessDescendantBlocks(
             blocks, range_size,
         ))
     }
+
+    async fn revert_step(&self, head: &BlockPtr) -> Result<ReconciliationStep<C>, Error> {
+        info!(
+            self.logger,
+            "Indexer head is not on the main chain, reverting";
+            "block_number" => head.number,
+            "block_hash" => head.hash.to_string(),
+        );
+        let parent_ptr = self
+            .adapter
+            .parent_ptr(head)
+            .await?
+            .ok_or_else(|| anyhow!("can not revert block {} since it has no parent", head))?;
+        Ok(ReconciliationStep::RevertBlock(parent_ptr))
+    }
 }
 
 impl<C: Blockchain> BlockStream<C> for PollingBlockStream<C> {}
@@ -204,6 +253,13 @@ impl<C: Blockchain> Stream for PollingBlockStream<C> {
                 // Waiting for the reconciliation to complete or yield blocks
                 BlockStreamState::Reconciliation(next_blocks_future) => {
                     match next_blocks_future.poll_unpin(cx) {
+                        Poll::Ready(Ok(NextBlocks::Revert(parent_ptr))) => {
+                            // Continue scanning right after the block we revert to
+                            self.ctx.stream_ptr = parent_ptr.number;
+                            self.state = BlockStreamState::BeginReconciliation;
+
+                            break Poll::Ready(Some(Ok(BlockStreamEvent::Revert(parent_ptr))));
+                        }
                         Poll::Ready(Ok(NextBlocks::Blocks(next_blocks, block_range_size))) => {
                             let total_triggers =
                                 next_blocks.iter().map(|b| b.trigger_count()).sum::<usize>();
@@ -252,3 +308,539 @@ impl<C: Blockchain> Stream for PollingBlockStream<C> {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{
+        BlockchainKind, DataSource, DataSourceTemplate, HostFn, MappingTrigger, RuntimeAdapter,
+        TriggerData, TriggerFilter, UnresolvedDataSource, UnresolvedDataSourceTemplate,
+    };
+    use crate::components::indexer::DataSourceTemplateInfo;
+    use crate::components::link_resolver::LinkResolver;
+    use crate::components::store::{
+        DeploymentLocator, EntityModification, EntityType, StoredDynamicDataSource,
+    };
+    use crate::data::indexer::schema::IndexerError;
+    use crate::data::indexer::{DataSourceContext, IndexerManifestValidationError, Source};
+    use crate::data::query::QueryExecutionError;
+    use crate::runtime::{AscHeap, AscPtr, DeterministicHostError};
+    use std::collections::BTreeMap;
+    use std::convert::TryFrom;
+    use std::sync::Mutex;
+
+    #[derive(Debug)]
+    struct MockChain;
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    struct MockBlock {
+        number: BlockNumber,
+        hash: u8,
+        parent: u8,
+    }
+
+    impl MockBlock {
+        fn new(number: BlockNumber, hash: u8, parent: u8) -> Self {
+            MockBlock {
+                number,
+                hash,
+                parent,
+            }
+        }
+    }
+
+    impl Block for MockBlock {
+        fn ptr(&self) -> BlockPtr {
+            BlockPtr::from((vec![self.hash], self.number))
+        }
+
+        fn parent_ptr(&self) -> Option<BlockPtr> {
+            if self.number == 0 {
+                None
+            } else {
+                Some(BlockPtr::from((vec![self.parent], self.number - 1)))
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    struct MockTriggerData;
+
+    impl TriggerData for MockTriggerData {
+        fn error_context(&self) -> String {
+            String::new()
+        }
+    }
+
+    #[derive(Debug)]
+    struct MockMappingTrigger;
+
+    impl MappingTrigger for MockMappingTrigger {
+        fn handler_name(&self) -> &str {
+            unimplemented!()
+        }
+
+        fn to_asc_ptr<H: AscHeap>(self, _: &mut H) -> Result<AscPtr<()>, DeterministicHostError> {
+            unimplemented!()
+        }
+    }
+
+    #[derive(Clone, Default, Serialize, Deserialize)]
+    struct MockTriggerFilter;
+
+    impl TriggerFilter<MockChain> for MockTriggerFilter {
+        fn extend<'a>(&mut self, _: impl Iterator<Item = &'a MockDataSource> + Clone) {}
+    }
+
+    #[derive(Clone, Debug)]
+    struct MockDataSource;
+
+    impl TryFrom<DataSourceTemplateInfo<MockChain>> for MockDataSource {
+        type Error = Error;
+
+        fn try_from(_: DataSourceTemplateInfo<MockChain>) -> Result<Self, Error> {
+            unimplemented!()
+        }
+    }
+
+    impl DataSource<MockChain> for MockDataSource {
+        fn address(&self) -> Option<&[u8]> {
+            unimplemented!()
+        }
+        fn start_block(&self) -> BlockNumber {
+            unimplemented!()
+        }
+        fn name(&self) -> &str {
+            unimplemented!()
+        }
+        fn kind(&self) -> &str {
+            unimplemented!()
+        }
+        fn network(&self) -> Option<&str> {
+            unimplemented!()
+        }
+        fn context(&self) -> Arc<Option<DataSourceContext>> {
+            unimplemented!()
+        }
+        fn creation_block(&self) -> Option<BlockNumber> {
+            unimplemented!()
+        }
+        fn api_version(&self) -> semver::Version {
+            unimplemented!()
+        }
+        fn runtime(&self) -> &[u8] {
+            unimplemented!()
+        }
+        fn match_and_decode(
+            &self,
+            _: &Logger,
+            _: &MockTriggerData,
+            _: Arc<MockBlock>,
+        ) -> Result<Option<MockMappingTrigger>, Error> {
+            unimplemented!()
+        }
+        fn is_duplicate_of(&self, _: &Self) -> bool {
+            unimplemented!()
+        }
+        fn as_stored_dynamic_data_source(&self) -> StoredDynamicDataSource {
+            unimplemented!()
+        }
+        fn from_stored_dynamic_data_source(
+            _: &BTreeMap<&str, &MockDataSourceTemplate>,
+            _: StoredDynamicDataSource,
+        ) -> Result<Self, Error> {
+            unimplemented!()
+        }
+        fn validate(&self) -> Vec<IndexerManifestValidationError> {
+            unimplemented!()
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct MockUnresolvedDataSource;
+
+    #[async_trait]
+    impl UnresolvedDataSource<MockChain> for MockUnresolvedDataSource {
+        async fn resolve(self, _: &impl LinkResolver, _: &Logger) -> Result<MockDataSource, Error> {
+            unimplemented!()
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    struct MockDataSourceTemplate;
+
+    impl DataSourceTemplate<MockChain> for MockDataSourceTemplate {
+        fn runtime(&self) -> &[u8] {
+            unimplemented!()
+        }
+        fn api_version(&self) -> semver::Version {
+            unimplemented!()
+        }
+        fn name(&self) -> &str {
+            unimplemented!()
+        }
+    }
+
+    #[derive(Default, Deserialize)]
+    struct MockUnresolvedDataSourceTemplate;
+
+    #[async_trait]
+    impl UnresolvedDataSourceTemplate<MockChain> for MockUnresolvedDataSourceTemplate {
+        async fn resolve(
+            self,
+            _: &impl LinkResolver,
+            _: &Logger,
+        ) -> Result<MockDataSourceTemplate, Error> {
+            unimplemented!()
+        }
+    }
+
+    struct MockRuntimeAdapter;
+
+    impl RuntimeAdapter<MockChain> for MockRuntimeAdapter {
+        fn host_fns(&self, _: &MockDataSource) -> Result<Vec<HostFn>, Error> {
+            unimplemented!()
+        }
+    }
+
+    #[async_trait]
+    impl Blockchain for MockChain {
+        const KIND: BlockchainKind = BlockchainKind::Solana;
+
+        type Block = MockBlock;
+        type DataSource = MockDataSource;
+        type UnresolvedDataSource = MockUnresolvedDataSource;
+        type DataSourceTemplate = MockDataSourceTemplate;
+        type UnresolvedDataSourceTemplate = MockUnresolvedDataSourceTemplate;
+        type TriggersAdapter = MockTriggersAdapter;
+        type TriggerData = MockTriggerData;
+        type MappingTrigger = MockMappingTrigger;
+        type TriggerFilter = MockTriggerFilter;
+        type RuntimeAdapter = MockRuntimeAdapter;
+
+        fn triggers_adapter(&self) -> Result<Arc<MockTriggersAdapter>, Error> {
+            unimplemented!()
+        }
+
+        fn runtime_adapter(&self) -> Arc<MockRuntimeAdapter> {
+            unimplemented!()
+        }
+
+        async fn new_block_stream(
+            &self,
+            _: DeploymentLocator,
+            _: Arc<dyn WritableStore>,
+            _: BlockNumber,
+            _: Arc<MockTriggerFilter>,
+        ) -> Result<Box<dyn BlockStream<Self>>, Error> {
+            unimplemented!()
+        }
+
+        async fn block_pointer_from_number(
+            &self,
+            _: &Logger,
+            _: BlockNumber,
+        ) -> Result<BlockPtr, Error> {
+            unimplemented!()
+        }
+    }
+
+    /// A chain store that knows the blocks of the main chain and of the
+    /// forks that were abandoned
+    struct MockTriggersAdapter {
+        main_chain: Vec<MockBlock>,
+        forks: Vec<MockBlock>,
+        /// Report every block as being on the main chain, like a chain store
+        /// that has not yet seen the reorg when the head is checked
+        stale_head: bool,
+    }
+
+    impl MockTriggersAdapter {
+        fn new(main_chain: Vec<MockBlock>, forks: Vec<MockBlock>) -> Self {
+            MockTriggersAdapter {
+                main_chain,
+                forks,
+                stale_head: false,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TriggersAdapter<MockChain> for MockTriggersAdapter {
+        async fn scan_triggers(
+            &self,
+            from: BlockNumber,
+            to: BlockNumber,
+            _: &MockTriggerFilter,
+        ) -> Result<Vec<BlockWithTriggers<MockChain>>, Error> {
+            Ok(self
+                .main_chain
+                .iter()
+                .filter(|block| block.number >= from && block.number <= to)
+                .map(|block| BlockWithTriggers::new(block.clone(), vec![]))
+                .collect())
+        }
+
+        async fn triggers_in_block(
+            &self,
+            _: &Logger,
+            _: MockBlock,
+            _: &MockTriggerFilter,
+        ) -> Result<BlockWithTriggers<MockChain>, Error> {
+            unimplemented!()
+        }
+
+        async fn is_on_main_chain(&self, ptr: BlockPtr) -> Result<bool, Error> {
+            Ok(self.stale_head || self.main_chain.iter().any(|block| block.ptr() == ptr))
+        }
+
+        async fn parent_ptr(&self, ptr: &BlockPtr) -> Result<Option<BlockPtr>, Error> {
+            Ok(self
+                .main_chain
+                .iter()
+                .chain(self.forks.iter())
+                .find(|block| &block.ptr() == ptr)
+                .and_then(|block| block.parent_ptr()))
+        }
+    }
+
+    /// The state of an indexer in the mock store: the block pointer, and
+    /// the entities and dynamic data sources with the block that wrote them
+    #[derive(Default)]
+    struct MockState {
+        block_ptr: Option<BlockPtr>,
+        entities: Vec<(BlockNumber, String)>,
+        data_sources: Vec<StoredDynamicDataSource>,
+    }
+
+    #[derive(Default)]
+    struct MockStore {
+        state: Mutex<MockState>,
+    }
+
+    #[async_trait]
+    impl WritableStore for MockStore {
+        fn block_ptr(&self) -> Result<Option<BlockPtr>, Error> {
+            Ok(self.state.lock().unwrap().block_ptr.clone())
+        }
+
+        fn get(&self, _: &EntityKey) -> Result<Option<Entity>, QueryExecutionError> {
+            unimplemented!()
+        }
+
+        fn get_many(
+            &self,
+            _: BTreeMap<&EntityType, Vec<&str>>,
+        ) -> Result<BTreeMap<EntityType, Vec<Entity>>, StoreError> {
+            unimplemented!()
+        }
+
+        fn transact_block_operations(
+            &self,
+            block_ptr_to: BlockPtr,
+            mods: Vec<EntityModification>,
+            data_sources: Vec<StoredDynamicDataSource>,
+            _: Vec<IndexerError>,
+        ) -> Result<(), StoreError> {
+            let mut state = self.state.lock().unwrap();
+            for modification in mods {
+                let id = modification.entity_key().entity_id.clone();
+                state.entities.push((block_ptr_to.number, id));
+            }
+            state.data_sources.extend(data_sources);
+            state.block_ptr = Some(block_ptr_to);
+            Ok(())
+        }
+
+        fn revert_block_operations(&self, block_ptr_to: BlockPtr) -> Result<(), StoreError> {
+            let mut state = self.state.lock().unwrap();
+            let block = block_ptr_to.number;
+            state.entities.retain(|(number, _)| *number <= block);
+            state
+                .data_sources
+                .retain(|ds| ds.creation_block.map_or(true, |number| number <= block));
+            state.block_ptr = Some(block_ptr_to);
+            Ok(())
+        }
+
+        fn fail_indexer(&self, _: IndexerError) -> Result<(), StoreError> {
+            unimplemented!()
+        }
+
+        fn unfail(&self, _: &BlockPtr) -> Result<bool, StoreError> {
+            unimplemented!()
+        }
+
+        fn deterministic_error_block(&self) -> Result<Option<BlockPtr>, StoreError> {
+            unimplemented!()
+        }
+
+        async fn load_dynamic_data_sources(
+            &self,
+        ) -> Result<Vec<StoredDynamicDataSource>, StoreError> {
+            unimplemented!()
+        }
+    }
+
+    fn ptr(hash: u8, number: BlockNumber) -> BlockPtr {
+        BlockPtr::from((vec![hash], number))
+    }
+
+    /// Processes `block` the way the instance manager does: the block writes
+    /// an entity and creates a data source named after its hash
+    fn process(store: &MockStore, block: &BlockPtr) {
+        let id = block.hash.to_string();
+        let key = EntityKey {
+            indexer_id: DeploymentHash::new("reorg").unwrap(),
+            entity_type: EntityType::from("Block"),
+            entity_id: id.clone(),
+        };
+        let data_source = StoredDynamicDataSource {
+            name: id.clone(),
+            source: Source {
+                address: None,
+                abi: String::new(),
+                start_block: block.number,
+            },
+            context: None,
+            creation_block: Some(block.number),
+        };
+        store
+            .transact_block_operations(
+                block.clone(),
+                vec![EntityModification::Insert {
+                    key,
+                    data: Entity::new(),
+                }],
+                vec![data_source],
+                vec![],
+            )
+            .unwrap();
+    }
+
+    /// Drives the stream like the instance manager does, and returns the
+    /// block pointers of the events it saw. Reverts are marked with `true`.
+    async fn run_stream(
+        stream: &mut PollingBlockStream<MockChain>,
+        store: &MockStore,
+        events: usize,
+    ) -> Vec<(bool, BlockPtr)> {
+        let mut seen = vec![];
+        for _ in 0..events {
+            match stream.next().await {
+                Some(Ok(BlockStreamEvent::ProcessBlock(block))) => {
+                    process(store, &block.ptr());
+                    seen.push((false, block.ptr()));
+                }
+                Some(Ok(BlockStreamEvent::Revert(block_ptr_to))) => {
+                    store.revert_block_operations(block_ptr_to.clone()).unwrap();
+                    seen.push((true, block_ptr_to));
+                }
+                Some(Err(e)) => panic!("unexpected error: {}", e),
+                None => panic!("the stream ended"),
+            }
+        }
+        seen
+    }
+
+    /// The main chain `0 - 1 - 2 - 3' - 4'`, and the block `3` on a fork off `2`
+    fn forked_chain() -> (Vec<MockBlock>, Vec<MockBlock>) {
+        let main_chain = vec![
+            MockBlock::new(0, 0, 0),
+            MockBlock::new(1, 1, 0),
+            MockBlock::new(2, 2, 1),
+            MockBlock::new(3, 13, 2),
+            MockBlock::new(4, 14, 13),
+        ];
+        let forks = vec![MockBlock::new(3, 3, 2)];
+        (main_chain, forks)
+    }
+
+    /// A store that processed the blocks `0 - 1 - 2 - 3` before the reorg
+    fn store_on_fork() -> Arc<MockStore> {
+        let store = Arc::new(MockStore::default());
+        for block in &[ptr(0, 0), ptr(1, 1), ptr(2, 2), ptr(3, 3)] {
+            process(&store, block);
+        }
+        store
+    }
+
+    fn stream(
+        adapter: MockTriggersAdapter,
+        store: Arc<MockStore>,
+    ) -> PollingBlockStream<MockChain> {
+        let stream_ptr = store.block_ptr().unwrap().unwrap().number;
+        PollingBlockStream::new(
+            Logger::root(slog::Discard, o!()),
+            Arc::new(adapter),
+            store,
+            Arc::new(MockTriggerFilter),
+            stream_ptr,
+            100,
+            1000,
+        )
+    }
+
+    fn assert_state(store: &MockStore, head: BlockPtr, hashes: &[u8]) {
+        let state = store.state.lock().unwrap();
+        let names: Vec<String> = hashes
+            .iter()
+            .map(|hash| ptr(*hash, 0).hash.to_string())
+            .collect();
+        let entities: Vec<String> = state.entities.iter().map(|(_, id)| id.clone()).collect();
+        let data_sources: Vec<String> = state
+            .data_sources
+            .iter()
+            .map(|ds| ds.name.clone())
+            .collect();
+        assert_eq!(Some(head), state.block_ptr);
+        assert_eq!(names, entities);
+        assert_eq!(names, data_sources);
+    }
+
+    #[tokio::test]
+    async fn reorg_reverts_head_off_the_main_chain() {
+        let (main_chain, forks) = forked_chain();
+        let store = store_on_fork();
+        let mut stream = stream(MockTriggersAdapter::new(main_chain, forks), store.clone());
+
+        let seen = run_stream(&mut stream, &store, 1).await;
+        assert_eq!(vec![(true, ptr(2, 2))], seen);
+        // Entities, dynamic data sources and the block pointer of the
+        // reverted block are gone together
+        assert_state(&store, ptr(2, 2), &[0, 1, 2]);
+
+        let seen = run_stream(&mut stream, &store, 2).await;
+        assert_eq!(vec![(false, ptr(13, 3)), (false, ptr(14, 4))], seen);
+        assert_state(&store, ptr(14, 4), &[0, 1, 2, 13, 14]);
+    }
+
+    #[tokio::test]
+    async fn reorg_reverts_head_when_next_block_has_other_parent() {
+        let (main_chain, forks) = forked_chain();
+        let store = store_on_fork();
+        let mut adapter = MockTriggersAdapter::new(main_chain, forks);
+        adapter.stale_head = true;
+        let mut stream = stream(adapter, store.clone());
+
+        // Block 4' does not follow the head 3, which is reverted even
+        // though the adapter still reports it as on the main chain
+        let seen = run_stream(&mut stream, &store, 1).await;
+        assert_eq!(vec![(true, ptr(2, 2))], seen);
+        assert_state(&store, ptr(2, 2), &[0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn no_revert_on_main_chain() {
+        let (main_chain, forks) = forked_chain();
+        let store = Arc::new(MockStore::default());
+        for block in &[ptr(0, 0), ptr(1, 1), ptr(2, 2)] {
+            process(&store, block);
+        }
+        let mut stream = stream(MockTriggersAdapter::new(main_chain, forks), store.clone());
+
+        let seen = run_stream(&mut stream, &store, 2).await;
+        assert_eq!(vec![(false, ptr(13, 3)), (false, ptr(14, 4))], seen);
+        assert_state(&store, ptr(14, 4), &[0, 1, 2, 13, 14]);
+    }
+}
@@ -0,0 +1,85 @@
+//! Prometheus metrics of a process. Metrics are registered with the process
+//! wide `METRICS_REGISTRY` when they are created, and the `/metrics` endpoint
+//! of the process serves all of them.
+
+use lazy_static::lazy_static;
+use prometheus::{Encoder, TextEncoder};
+
+pub use prometheus::{
+    Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    PrometheusError, Registry,
+};
+
+lazy_static! {
+    pub static ref METRICS_REGISTRY: Registry = Registry::new();
+}
+
+fn register<M>(metric: M) -> M
+where
+    M: prometheus::core::Collector + Clone + 'static,
+{
+    METRICS_REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("a metric with this name is already registered");
+    metric
+}
+
+/// Create and register a gauge with the given variable `labels`
+pub fn int_gauge_vec(name: &str, help: &str, labels: &[&str]) -> IntGaugeVec {
+    register(IntGaugeVec::new(Opts::new(name, help), labels).expect("invalid gauge"))
+}
+
+/// Create and register a counter with the given variable `labels`
+pub fn int_counter_vec(name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    register(IntCounterVec::new(Opts::new(name, help), labels).expect("invalid counter"))
+}
+
+/// Create and register a histogram with the default buckets, which suit
+/// durations in seconds, and the given variable `labels`
+pub fn histogram_vec(name: &str, help: &str, labels: &[&str]) -> HistogramVec {
+    register(HistogramVec::new(HistogramOpts::new(name, help), labels).expect("invalid histogram"))
+}
+
+/// Create and register a histogram with the given `buckets`
+pub fn histogram_vec_with_buckets(
+    name: &str,
+    help: &str,
+    labels: &[&str],
+    buckets: Vec<f64>,
+) -> HistogramVec {
+    register(
+        HistogramVec::new(HistogramOpts::new(name, help).buckets(buckets), labels)
+            .expect("invalid histogram"),
+    )
+}
+
+/// The current value of all registered metrics in the Prometheus text format
+pub fn encode_metrics() -> Result<Vec<u8>, PrometheusError> {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&METRICS_REGISTRY.gather(), &mut buffer)?;
+    Ok(buffer)
+}
+
+/// The content type of `encode_metrics`
+pub const METRICS_CONTENT_TYPE: &str = prometheus::TEXT_FORMAT;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_metrics_are_encoded() {
+        let counter = int_counter_vec("test_encoded_total", "Encoded by the test", &["indexer"]);
+        counter.with_label_values(&["serum"]).inc_by(3);
+        let metrics = String::from_utf8(encode_metrics().unwrap()).unwrap();
+        assert!(metrics.contains("# HELP test_encoded_total Encoded by the test"));
+        assert!(metrics.contains("test_encoded_total{indexer=\"serum\"} 3"));
+    }
+
+    #[test]
+    #[should_panic(expected = "already registered")]
+    fn metrics_are_registered_once() {
+        int_gauge_vec("test_registered_once", "Registered twice", &[]);
+        int_gauge_vec("test_registered_once", "Registered twice", &[]);
+    }
+}
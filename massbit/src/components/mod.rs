@@ -1,5 +1,6 @@
 pub mod ethereum;
 pub mod indexer;
 pub mod link_resolver;
+pub mod metrics;
 pub mod server;
 pub mod store;
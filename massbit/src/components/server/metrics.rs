@@ -0,0 +1,71 @@
+use std::convert::Infallible;
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Request, Response, Server, StatusCode};
+
+use crate::components::metrics::{encode_metrics, METRICS_CONTENT_TYPE};
+
+/// Serve the metrics of this process at `http://0.0.0.0:<port>/metrics` for
+/// Prometheus to scrape. Runs until the server fails.
+pub async fn serve_metrics(port: u16) -> Result<(), hyper::Error> {
+    let addr = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), port);
+    let make_service =
+        make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle_request)) });
+    Server::bind(&addr.into()).serve(make_service).await
+}
+
+async fn handle_request(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.uri().path() != "/metrics" {
+        return Ok(status_response(StatusCode::NOT_FOUND));
+    }
+    let response = match encode_metrics() {
+        Ok(metrics) => Response::builder()
+            .header(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)
+            .body(Body::from(metrics))
+            .unwrap(),
+        Err(_) => status_response(StatusCode::INTERNAL_SERVER_ERROR),
+    };
+    Ok(response)
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::metrics::int_counter_vec;
+
+    async fn get(path: &str) -> Response<Body> {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        handle_request(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn metrics_are_served() {
+        let counter = int_counter_vec("test_served_total", "Served by the test", &[]);
+        counter.with_label_values(&[]).inc();
+
+        let response = get("/metrics").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            METRICS_CONTENT_TYPE
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let metrics = String::from_utf8(body.to_vec()).unwrap();
+        assert!(metrics.contains("test_served_total 1"));
+    }
+
+    #[tokio::test]
+    async fn other_paths_are_not_found() {
+        for path in &["/", "/metrics/", "/metric", "/indexers"] {
+            assert_eq!(get(path).await.status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
@@ -1,14 +1,14 @@
 use graphql_parser::schema as s;
 use serde::{Deserialize, Serialize};
 use stable_hash::prelude::*;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt;
 use std::fmt::Display;
 use std::sync::Arc;
 use thiserror::Error;
 
 use crate::blockchain::{Blockchain, DataSource};
-use crate::data::indexer::schema::IndexerDeploymentEntity;
+use crate::data::indexer::schema::{IndexerDeploymentEntity, IndexerError};
 use crate::data::indexer::Source;
 use crate::data::query::QueryExecutionError;
 use crate::data::store::Entity;
@@ -502,6 +502,213 @@ impl EntityKey {
     }
 }
 
+/// Supported types of store filters.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntityFilter {
+    And(Vec<EntityFilter>),
+    Or(Vec<EntityFilter>),
+    Equal(Attribute, Value),
+    Not(Attribute, Value),
+    GreaterThan(Attribute, Value),
+    LessThan(Attribute, Value),
+    GreaterOrEqual(Attribute, Value),
+    LessOrEqual(Attribute, Value),
+    In(Attribute, Vec<Value>),
+    NotIn(Attribute, Vec<Value>),
+    Contains(Attribute, Value),
+    NotContains(Attribute, Value),
+    StartsWith(Attribute, Value),
+    NotStartsWith(Attribute, Value),
+    EndsWith(Attribute, Value),
+    NotEndsWith(Attribute, Value),
+}
+
+impl EntityFilter {
+    pub fn new_equal(
+        attribute_name: impl Into<Attribute>,
+        attribute_value: impl Into<Value>,
+    ) -> Self {
+        EntityFilter::Equal(attribute_name.into(), attribute_value.into())
+    }
+
+    /// Adds `other` to this filter, if it is present.
+    pub fn and_maybe(self, other: Option<Self>) -> Self {
+        use EntityFilter as f;
+        match other {
+            Some(other) => match self {
+                f::And(mut filters) => {
+                    filters.push(other);
+                    f::And(filters)
+                }
+                filter => f::And(vec![filter, other]),
+            },
+            None => self,
+        }
+    }
+}
+
+/// The order in which entities should be returned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EntityOrder {
+    Ascending(Attribute),
+    Descending(Attribute),
+    /// Order by the `id` of the entities.
+    Default,
+}
+
+/// How many entities to return and how many to skip.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityRange {
+    /// Limit on how many entities to return, `None` returns all of them.
+    pub first: Option<u32>,
+    /// How many entities to skip.
+    pub skip: u32,
+}
+
+impl EntityRange {
+    /// Query for the first `n` entities.
+    pub fn first(n: u32) -> Self {
+        Self {
+            first: Some(n),
+            skip: 0,
+        }
+    }
+}
+
+/// A query for the versions of entities of one type which were current at `block`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityQuery {
+    pub entity_type: EntityType,
+    pub filter: Option<EntityFilter>,
+    pub order: EntityOrder,
+    pub range: EntityRange,
+    pub block: BlockNumber,
+}
+
+impl EntityQuery {
+    pub fn new(entity_type: EntityType, block: BlockNumber) -> Self {
+        EntityQuery {
+            entity_type,
+            filter: None,
+            order: EntityOrder::Default,
+            range: EntityRange::first(100),
+            block,
+        }
+    }
+
+    pub fn filter(mut self, filter: EntityFilter) -> Self {
+        self.filter = Some(filter.and_maybe(self.filter));
+        self
+    }
+
+    pub fn order(mut self, order: EntityOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn range(mut self, range: EntityRange) -> Self {
+        self.range = range;
+        self
+    }
+}
+
+/// The kind of change made to an entity
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EntityChangeOperation {
+    /// An entity was added or updated
+    Set,
+    /// An existing entity was removed
+    Removed,
+}
+
+/// A change to an entity
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntityChange {
+    pub entity_type: EntityType,
+    pub entity_id: String,
+    pub operation: EntityChangeOperation,
+}
+
+impl EntityChange {
+    pub fn new(entity_type: &str, entity_id: &str, removed: bool) -> Self {
+        EntityChange {
+            entity_type: EntityType::new(entity_type.to_string()),
+            entity_id: entity_id.to_string(),
+            operation: if removed {
+                EntityChangeOperation::Removed
+            } else {
+                EntityChangeOperation::Set
+            },
+        }
+    }
+}
+
+impl From<&EntityModification> for EntityChange {
+    fn from(modification: &EntityModification) -> Self {
+        let key = modification.entity_key();
+        EntityChange::new(
+            key.entity_type.as_str(),
+            &key.entity_id,
+            modification.is_remove(),
+        )
+    }
+}
+
+/// The entities of an indexer that were changed by the block `block`
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StoreEvent {
+    /// Hash of the indexer whose entities changed
+    pub indexer: String,
+    pub block: BlockNumber,
+    pub changes: BTreeSet<EntityChange>,
+}
+
+impl StoreEvent {
+    pub fn from_mods<'a, I: IntoIterator<Item = &'a EntityModification>>(
+        indexer: impl Into<String>,
+        block: BlockNumber,
+        mods: I,
+    ) -> Self {
+        StoreEvent {
+            indexer: indexer.into(),
+            block,
+            changes: mods.into_iter().map(EntityChange::from).collect(),
+        }
+    }
+
+    /// Builds the event from the modifications of a store with its own entity types,
+    /// given as the type and id of each entity and whether it was removed.
+    pub fn from_changes<'a, I: IntoIterator<Item = (&'a str, &'a str, bool)>>(
+        indexer: impl Into<String>,
+        block: BlockNumber,
+        changes: I,
+    ) -> Self {
+        StoreEvent {
+            indexer: indexer.into(),
+            block,
+            changes: changes
+                .into_iter()
+                .map(|(entity_type, entity_id, removed)| {
+                    EntityChange::new(entity_type, entity_id, removed)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns true if the event changes an entity of `entity_type`, and that
+    /// entity is the one with `entity_id` if it is given.
+    pub fn touches(&self, entity_type: &EntityType, entity_id: Option<&str>) -> bool {
+        self.changes.iter().any(|change| {
+            &change.entity_type == entity_type
+                && entity_id.map_or(true, |id| change.entity_id == id)
+        })
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum StoreError {
     #[error("store error: {0}")]
@@ -613,7 +820,8 @@ pub trait WritableStore: Send + Sync + 'static {
     ) -> Result<BTreeMap<EntityType, Vec<Entity>>, StoreError>;
 
     /// Transact the entity changes from a single block atomically into the store, and update the
-    /// indexer block pointer to `block_ptr_to`.
+    /// indexer block pointer to `block_ptr_to`. The `deterministic_errors` of the handlers
+    /// that failed in the block are recorded with it.
     ///
     /// `block_ptr_to` must point to a child block of the current indexer block pointer.
     fn transact_block_operations(
@@ -621,8 +829,28 @@ pub trait WritableStore: Send + Sync + 'static {
         block_ptr_to: BlockPtr,
         mods: Vec<EntityModification>,
         data_sources: Vec<StoredDynamicDataSource>,
+        deterministic_errors: Vec<IndexerError>,
     ) -> Result<(), StoreError>;
 
+    /// Revert the entity changes of all blocks after `block_ptr_to` atomically, and move the
+    /// indexer block pointer back to `block_ptr_to`. Dynamic data sources created after
+    /// `block_ptr_to` are removed as well.
+    ///
+    /// `block_ptr_to` must point to an ancestor of the current indexer block pointer.
+    fn revert_block_operations(&self, block_ptr_to: BlockPtr) -> Result<(), StoreError>;
+
+    /// Mark the deployment as failed with `error`, replacing any earlier fatal error.
+    fn fail_indexer(&self, error: IndexerError) -> Result<(), StoreError>;
+
+    /// Clear the fatal error of a failed deployment once `block_ptr` has been processed
+    /// successfully past the block that failed, or in place of it after a reorg. Returns
+    /// `true` if the deployment is no longer failed.
+    fn unfail(&self, block_ptr: &BlockPtr) -> Result<bool, StoreError>;
+
+    /// The block that caused the fatal error of the deployment, if that error is
+    /// deterministic and the deployment can only resume once a reorg replaces the block.
+    fn deterministic_error_block(&self) -> Result<Option<BlockPtr>, StoreError>;
+
     /// Load the dynamic data sources for the given deployment
     async fn load_dynamic_data_sources(&self) -> Result<Vec<StoredDynamicDataSource>, StoreError>;
 }
@@ -663,4 +891,8 @@ pub trait IndexerStore: Send + Sync + 'static {
 
     /// Find the deployment locators for the subgraph with the given hash
     fn locators(&self, hash: &str) -> Result<Vec<DeploymentLocator>, StoreError>;
+
+    /// Return the pointer to the latest block that any instance of the
+    /// deployment `id` has processed
+    fn least_block_ptr(&self, id: &DeploymentHash) -> Result<Option<BlockPtr>, StoreError>;
 }
@@ -339,6 +339,8 @@ pub enum IndexerManifestValidationError {
     EthereumNetworkRequired,
     #[error("indexer data source has too many similar block handlers")]
     DataSourceBlockHandlerLimitExceeded,
+    #[error("indexer data source has more than one {0} handler")]
+    DataSourceHandlerLimitExceeded(String),
     #[error("the specified block must exist on the Ethereum network")]
     BlockNotFound(String),
     #[error("imported schema(s) are invalid: {0:?}")]
@@ -347,6 +349,8 @@ pub enum IndexerManifestValidationError {
     SchemaValidationError(Vec<SchemaValidationError>),
     #[error("indexer must use a single apiVersion across its data sources. Found: {}", format_versions(.0))]
     DifferentApiVersions(BTreeSet<Version>),
+    #[error("the graft base is invalid: {0}")]
+    GraftBaseInvalid(String),
 }
 
 #[derive(Error, Debug)]
@@ -413,6 +417,57 @@ pub struct Source {
     pub start_block: BlockNumber,
 }
 
+/// Optional behavior that an indexer enables in the `features` section of
+/// its manifest
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexerFeature {
+    /// Keep indexing when a handler fails with a deterministic error; only
+    /// the changes of the failed handler are discarded
+    NonFatalErrors,
+}
+
+impl fmt::Display for IndexerFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IndexerFeature::NonFatalErrors => write!(f, "nonFatalErrors"),
+        }
+    }
+}
+
+/// The deployment and block from which a new deployment copies its data,
+/// instead of indexing all blocks since its start block
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
+pub struct Graft {
+    pub base: DeploymentHash,
+    pub block: BlockNumber,
+}
+
+impl Graft {
+    fn validate<S: IndexerStore>(&self, store: Arc<S>) -> Vec<IndexerManifestValidationError> {
+        fn gbi(msg: String) -> Vec<IndexerManifestValidationError> {
+            vec![IndexerManifestValidationError::GraftBaseInvalid(msg)]
+        }
+
+        // We are being defensive here: we don't know which specific
+        // instance of a indexer we will use as the base for the graft,
+        // since the notion of which indexer instance is the 'active' one
+        // can change while the new indexer is being set up
+        match store.least_block_ptr(&self.base) {
+            Err(e) => gbi(e.to_string()),
+            Ok(None) => gbi(format!(
+                "failed to graft onto `{}` since it has not processed any blocks",
+                self.base
+            )),
+            Ok(Some(ptr)) if ptr.number < self.block => gbi(format!(
+                "failed to graft onto `{}` at block {} since it has only processed block {}",
+                self.base, self.block, ptr.number
+            )),
+            Ok(Some(_)) => vec![],
+        }
+    }
+}
+
 pub fn calls_host_fn(runtime: &[u8], host_fn: &str) -> anyhow::Result<bool> {
     use wasmparser::Payload;
 
@@ -441,6 +496,9 @@ pub struct BaseIndexerManifest<C, S, D, T> {
     pub schema: S,
     #[serde(default)]
     pub templates: Vec<T>,
+    #[serde(default)]
+    pub features: BTreeSet<IndexerFeature>,
+    pub graft: Option<Graft>,
     #[serde(skip_serializing, default)]
     pub chain: PhantomData<C>,
 }
@@ -498,6 +556,10 @@ impl<C: Blockchain> UnvalidatedIndexerManifest<C> {
             errors.extend(ds.validate());
         }
 
+        if let Some(graft) = &self.0.graft {
+            errors.extend(graft.validate(store.clone()));
+        }
+
         let mut networks = self
             .0
             .data_sources
@@ -599,6 +661,8 @@ impl<C: Blockchain> UnresolvedIndexerManifest<C> {
             schema,
             data_sources,
             templates,
+            features,
+            graft,
             chain,
         } = self;
 
@@ -635,6 +699,8 @@ impl<C: Blockchain> UnresolvedIndexerManifest<C> {
             schema,
             data_sources,
             templates,
+            features,
+            graft,
             chain,
         })
     }
@@ -652,3 +718,38 @@ fn display_vector(input: &Vec<impl std::fmt::Display>) -> impl std::fmt::Display
 fn format_versions(versions: &BTreeSet<Version>) -> String {
     versions.iter().map(ToString::to_string).join(", ")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Features {
+        #[serde(default)]
+        features: BTreeSet<IndexerFeature>,
+    }
+
+    #[test]
+    fn non_fatal_errors_feature() {
+        let manifest: Features = serde_yaml::from_str("features:\n  - nonFatalErrors\n").unwrap();
+        assert!(manifest.features.contains(&IndexerFeature::NonFatalErrors));
+        assert_eq!(IndexerFeature::NonFatalErrors.to_string(), "nonFatalErrors");
+        assert_eq!(
+            serde_json::to_string(&IndexerFeature::NonFatalErrors).unwrap(),
+            "\"nonFatalErrors\""
+        );
+    }
+
+    #[test]
+    fn features_default_to_none() {
+        let manifest: Features = serde_yaml::from_str("{}").unwrap();
+        assert!(manifest.features.is_empty());
+    }
+
+    #[test]
+    fn unknown_feature_is_rejected() {
+        assert!(serde_yaml::from_str::<Features>("features:\n  - fullTextSearch\n").is_err());
+        // Feature names are case sensitive, like the rest of the manifest
+        assert!(serde_yaml::from_str::<Features>("features:\n  - NonFatalErrors\n").is_err());
+    }
+}
@@ -12,6 +12,8 @@ pub struct IndexerDeploymentEntity {
     pub manifest: IndexerManifestEntity,
     pub earliest_block: Option<BlockPtr>,
     pub latest_block: Option<BlockPtr>,
+    pub graft_base: Option<DeploymentHash>,
+    pub graft_block: Option<BlockPtr>,
 }
 
 impl IndexerDeploymentEntity {
@@ -23,8 +25,21 @@ impl IndexerDeploymentEntity {
             manifest: IndexerManifestEntity::from(source_manifest),
             earliest_block: earliest_block.cheap_clone(),
             latest_block: earliest_block,
+            graft_base: None,
+            graft_block: None,
         }
     }
+
+    /// Copy the data of the deployment `base` up to and including `block`
+    /// instead of indexing those blocks; indexing continues after `block`
+    pub fn graft(mut self, base: Option<(DeploymentHash, BlockPtr)>) -> Self {
+        if let Some((base, block)) = base {
+            self.graft_base = Some(base);
+            self.latest_block = Some(block.cheap_clone());
+            self.graft_block = Some(block);
+        }
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -43,7 +58,11 @@ impl<'a, C: Blockchain> From<&'a super::IndexerManifest<C>> for IndexerManifestE
             description: manifest.description.clone(),
             repository: manifest.repository.clone(),
             schema: manifest.schema.document.clone().to_string(),
-            features: vec![],
+            features: manifest
+                .features
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect(),
         }
     }
 }
@@ -1,6 +1,9 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 use web3::types::H256;
 
-use crate::prelude::BlockPtr;
+use crate::prelude::{anyhow, BlockNumber, BlockPtr};
 
 /// Light wrapper around `EthereumBlockPointer` that is compatible with GraphQL values.
 #[derive(Debug)]
@@ -19,3 +22,68 @@ impl EthereumBlock {
         self.0.number
     }
 }
+
+/// The health of a deployment
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeploymentHealth {
+    /// Indexing without errors
+    Healthy,
+    /// Indexing, but some handlers failed and their changes are missing
+    Unhealthy,
+    /// Stopped by a fatal error until indexing is retried successfully
+    Failed,
+}
+
+impl DeploymentHealth {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeploymentHealth::Healthy => "healthy",
+            DeploymentHealth::Unhealthy => "unhealthy",
+            DeploymentHealth::Failed => "failed",
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self, DeploymentHealth::Failed)
+    }
+}
+
+impl fmt::Display for DeploymentHealth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for DeploymentHealth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "healthy" => Ok(DeploymentHealth::Healthy),
+            "unhealthy" => Ok(DeploymentHealth::Unhealthy),
+            "failed" => Ok(DeploymentHealth::Failed),
+            _ => Err(anyhow!("invalid deployment health `{}`", s)),
+        }
+    }
+}
+
+/// An error of a handler, or the error that stopped a deployment
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorStatus {
+    pub message: String,
+    pub block_number: Option<BlockNumber>,
+    pub block_hash: Option<String>,
+    pub handler: Option<String>,
+    pub deterministic: bool,
+}
+
+/// The health of a deployment, and why it failed if it did
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthStatus {
+    pub deployment: String,
+    pub health: DeploymentHealth,
+    pub fatal_error: Option<ErrorStatus>,
+}
@@ -95,8 +95,9 @@ pub mod prelude {
     pub use crate::components::link_resolver::{JsonStreamValue, JsonValueStream, LinkResolver};
     pub use crate::components::server::manager::JsonRpcServer;
     pub use crate::components::store::{
-        BlockNumber, EntityCache, EntityKey, EntityModification, IndexerStore, StoreError,
-        BLOCK_NUMBER_MAX,
+        BlockNumber, EntityCache, EntityChange, EntityChangeOperation, EntityFilter, EntityKey,
+        EntityModification, EntityOrder, EntityQuery, EntityRange, IndexerStore, StoreError,
+        StoreEvent, BLOCK_NUMBER_MAX,
     };
 
     pub use crate::data::indexer::{
@@ -190,6 +190,15 @@ pub enum IndexForAscTypeId {
     ArrayF32 = 49,
     ArrayF64 = 50,
     ArrayBigDecimal = 51,
+    SolanaBlock = 52,
+    SolanaTransaction = 53,
+    SolanaInstruction = 54,
+    SolanaLog = 55,
+    SolanaProgramAddressParams = 56,
+    SolanaProgramAddress = 57,
+    SolanaBorshDecodeParams = 58,
+    SolanaReadIntParams = 59,
+    SolanaSignatureParams = 60,
 }
 
 impl ToAscObj<u32> for IndexForAscTypeId {
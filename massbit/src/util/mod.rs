@@ -10,3 +10,5 @@ pub mod security;
 pub mod timed_rw_lock;
 
 pub mod timed_cache;
+
+pub mod backoff;
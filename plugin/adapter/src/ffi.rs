@@ -0,0 +1,90 @@
+//! Plugin side of the boundary of adapter plugins. It is the C boundary of the SDK:
+//! the manager calls the exported `handle` with a `HandlerCall` and gets the error of a
+//! failed handler in an `ErrorBuffer`, the handlers never leave the plugin.
+//! An adapter plugin only has the handler of all its data sources, which is called with
+//! an empty data source name.
+use crate::core::PluginRegistrar;
+use crate::solana::SolanaHandler;
+use massbit_chain_solana::data_type::{SolanaBlock, SolanaLogMessages, SolanaTransaction};
+use massbit_solana_sdk::plugin::ffi::{
+    catch_call, catch_has_handler, MessageKind, HANDLED, MESSAGE_FORMAT, NO_HANDLER,
+};
+pub use massbit_solana_sdk::plugin::ffi::{ErrorBuffer, HandlerCall, PluginHandlers};
+use std::error::Error;
+
+/// Handlers registered by the `register` function of an adapter plugin
+#[doc(hidden)]
+#[derive(Default)]
+pub struct AdapterHandlers {
+    handler: Option<Box<dyn SolanaHandler + Send + Sync>>,
+}
+
+impl PluginRegistrar for AdapterHandlers {
+    fn register_solana_handler(&mut self, handler: Box<dyn SolanaHandler + Send + Sync>) {
+        self.handler = Some(handler);
+    }
+}
+
+impl AdapterHandlers {
+    fn registered(&self, name: &str) -> Option<&(dyn SolanaHandler + Send + Sync)> {
+        match name {
+            "" => self.handler.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+fn get(
+    handlers: &PluginHandlers<AdapterHandlers>,
+    register: unsafe extern "C" fn(&mut dyn PluginRegistrar),
+) -> &AdapterHandlers {
+    handlers.get_or_register(|| {
+        let mut registered = AdapterHandlers::default();
+        unsafe { register(&mut registered) };
+        registered
+    })
+}
+
+/// Plugin side of a call, exported by `export_plugin!`
+#[doc(hidden)]
+pub unsafe fn handle(
+    handlers: &PluginHandlers<AdapterHandlers>,
+    register: unsafe extern "C" fn(&mut dyn PluginRegistrar),
+    call: *const HandlerCall,
+    error: *mut ErrorBuffer,
+) -> i32 {
+    catch_call(call, error, |name, kind, message| {
+        match get(handlers, register).registered(name) {
+            Some(handler) => dispatch(handler, kind, message).map(|_| HANDLED),
+            None => Ok(NO_HANDLER),
+        }
+    })
+}
+
+/// Plugin side of `HasHandlerFn`, exported by `export_plugin!`
+#[doc(hidden)]
+pub unsafe fn has_handler(
+    handlers: &PluginHandlers<AdapterHandlers>,
+    register: unsafe extern "C" fn(&mut dyn PluginRegistrar),
+    name: *const u8,
+    name_len: usize,
+) -> bool {
+    catch_has_handler(name, name_len, |name| {
+        get(handlers, register).registered(name).is_some()
+    })
+}
+
+fn dispatch(handler: &dyn SolanaHandler, kind: u32, message: &[u8]) -> Result<(), Box<dyn Error>> {
+    match kind {
+        kind if kind == MessageKind::Block as u32 => {
+            handler.handle_block(&MESSAGE_FORMAT.decode::<SolanaBlock>(message)?)
+        }
+        kind if kind == MessageKind::Transaction as u32 => {
+            handler.handle_transaction(&MESSAGE_FORMAT.decode::<SolanaTransaction>(message)?)
+        }
+        kind if kind == MessageKind::LogMessages as u32 => {
+            handler.handle_log_messages(&MESSAGE_FORMAT.decode::<SolanaLogMessages>(message)?)
+        }
+        kind => Err(format!("unknown message kind {}", kind).into()),
+    }
+}
@@ -2,9 +2,10 @@
 extern crate paste;
 
 pub mod core;
+pub mod ffi;
 //pub mod macros;
 pub mod setting;
-use crate::core::{AdapterHandler, MessageHandler};
+use crate::core::MessageHandler;
 use quote::quote;
 use std::{error::Error, sync::Arc};
 pub mod solana;
@@ -38,14 +39,40 @@ impl MessageHandler for HandlerProxyType {
         }
     }
 }
+/// Exports the declaration of a plugin, whose handlers are registered by `$register`
+/// on the first handler call.
 #[macro_export]
 macro_rules! export_plugin {
     ($register:expr) => {
+        #[doc(hidden)]
+        static PLUGIN_HANDLERS: $crate::ffi::PluginHandlers<$crate::ffi::AdapterHandlers> =
+            $crate::ffi::PluginHandlers::new();
+
+        #[doc(hidden)]
+        unsafe extern "C" fn plugin_handle(
+            call: *const $crate::ffi::HandlerCall,
+            error: *mut $crate::ffi::ErrorBuffer,
+        ) -> i32 {
+            $crate::ffi::handle(&PLUGIN_HANDLERS, $register, call, error)
+        }
+
+        #[doc(hidden)]
+        unsafe extern "C" fn plugin_has_handler(name: *const u8, name_len: usize) -> bool {
+            $crate::ffi::has_handler(&PLUGIN_HANDLERS, $register, name, name_len)
+        }
+
+        #[doc(hidden)]
+        #[no_mangle]
+        pub static plugin_abi_version: u32 = $crate::core::ADAPTER_ABI_VERSION;
+
         #[doc(hidden)]
         #[no_mangle]
         pub static adapter_declaration: $crate::core::AdapterDeclaration =
             $crate::core::AdapterDeclaration {
-                register: $register,
+                rustc_version: $crate::core::RUSTC_VERSION.as_ptr() as *const _,
+                core_version: $crate::core::CORE_VERSION.as_ptr() as *const _,
+                handle: plugin_handle,
+                has_handler: plugin_has_handler,
             };
     };
 }
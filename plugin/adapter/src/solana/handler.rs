@@ -1,4 +1,4 @@
-use crate::core::{AdapterManager, BlockResponse, MessageHandler};
+use crate::core::{BlockResponse, MessageHandler};
 use crate::solana::SolanaHandler;
 use index_store::Store;
 use libloading::Library;
@@ -43,7 +43,7 @@ impl MessageHandler for SolanaHandlerProxy {
         store: &mut dyn Store,
     ) -> Result<(), Box<dyn Error>> {
         //log::info!("handle_block_mapping data: {:?}", data);
-        let blocks: Vec<SolanaBlock> = decode(&mut data.payload).unwrap();
+        let blocks: Vec<SolanaBlock> = decode(&data.payload, data.encoding, data.compression)?;
         // Todo: Rewrite the flush so it will flush after finish the array of blocks for better performance. For now, we flush after each block.
         for block in blocks {
             log::info!(
@@ -86,7 +86,7 @@ impl MessageHandler for SolanaHandlerProxy {
                 // self.handler.handle_transaction(&transaction);
                 // self.handler.handle_log_messages(&log_messages);
             }
-            store.flush(&block.block.blockhash, block.block_number);
+            store.flush(&block.block.blockhash, block.block_number)?;
         }
         Ok(())
     }
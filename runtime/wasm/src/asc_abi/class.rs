@@ -306,6 +306,10 @@ impl AscIndexId for Array<Uint8Array> {
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::ArrayUint8Array;
 }
 
+impl AscIndexId for Array<AscPtr<Uint8Array>> {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::ArrayUint8Array;
+}
+
 impl AscIndexId for Array<AscPtr<AscEnum<EthereumValueKind>>> {
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::ArrayEthereumValue;
 }
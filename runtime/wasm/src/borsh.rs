@@ -0,0 +1,513 @@
+//! Decoding of Borsh serialized data, such as the instruction data and the
+//! accounts of Solana programs, into JSON. The layout of the data is given by
+//! a type descriptor:
+//!
+//! - `u8` .. `u128`, `i8` .. `i128`, `f32`, `f64`, `bool` and `string`
+//! - `pubkey`: 32 bytes, decoded to a base58 string
+//! - `bytes`: a `vec<u8>` decoded to a `0x` prefixed hex string
+//! - `option<T>`, `vec<T>` and `[T; N]`
+//! - `{ name: T, .. }`: a struct, decoded to an object
+//! - `enum { A, B: T, .. }`: decoded to `"A"`, or `{ "B": .. }` for variants
+//!   with a value
+//!
+//! Integers with more than 64 bits are decoded to decimal strings. Bytes after
+//! the decoded value are ignored, accounts are often bigger than their data.
+
+use anyhow::{anyhow, Error};
+use massbit::prelude::serde_json::{Map, Number, Value};
+use std::convert::TryInto;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum BorshType {
+    U8,
+    U16,
+    U32,
+    U64,
+    U128,
+    I8,
+    I16,
+    I32,
+    I64,
+    I128,
+    F32,
+    F64,
+    Bool,
+    String,
+    Pubkey,
+    Bytes,
+    Option(Box<BorshType>),
+    Vec(Box<BorshType>),
+    Array(Box<BorshType>, usize),
+    Struct(Vec<(String, BorshType)>),
+    Enum(Vec<(String, Option<BorshType>)>),
+}
+
+impl FromStr for BorshType {
+    type Err = Error;
+
+    fn from_str(descriptor: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            input: descriptor,
+            pos: 0,
+        };
+        let borsh_type = parser.parse_type()?;
+        parser.skip_whitespace();
+        if parser.pos < descriptor.len() {
+            return Err(parser.error("end of the descriptor"));
+        }
+        Ok(borsh_type)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, expected: &str) -> Error {
+        anyhow!(
+            "invalid type descriptor `{}`: expected {} at position {}",
+            self.input,
+            expected,
+            self.pos
+        )
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.input[self.pos..].starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), Error> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.error(&format!("`{}`", c))),
+        }
+    }
+
+    fn ident(&mut self) -> Result<&'a str, Error> {
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("a name"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn parse_type(&mut self) -> Result<BorshType, Error> {
+        if self.eat('{') {
+            return Ok(BorshType::Struct(self.parse_fields()?));
+        }
+        if self.eat('[') {
+            let item = self.parse_type()?;
+            self.expect(';')?;
+            let len = self
+                .ident()?
+                .parse()
+                .map_err(|_| self.error("the length of the array"))?;
+            self.expect(']')?;
+            return Ok(BorshType::Array(Box::new(item), len));
+        }
+        let borsh_type = match self.ident()? {
+            "u8" => BorshType::U8,
+            "u16" => BorshType::U16,
+            "u32" => BorshType::U32,
+            "u64" => BorshType::U64,
+            "u128" => BorshType::U128,
+            "i8" => BorshType::I8,
+            "i16" => BorshType::I16,
+            "i32" => BorshType::I32,
+            "i64" => BorshType::I64,
+            "i128" => BorshType::I128,
+            "f32" => BorshType::F32,
+            "f64" => BorshType::F64,
+            "bool" => BorshType::Bool,
+            "string" => BorshType::String,
+            "pubkey" => BorshType::Pubkey,
+            "bytes" => BorshType::Bytes,
+            "option" => BorshType::Option(Box::new(self.parse_type_argument()?)),
+            "vec" => BorshType::Vec(Box::new(self.parse_type_argument()?)),
+            "enum" => {
+                self.expect('{')?;
+                BorshType::Enum(self.parse_variants()?)
+            }
+            name => return Err(anyhow!("unknown type `{}` in `{}`", name, self.input)),
+        };
+        Ok(borsh_type)
+    }
+
+    fn parse_type_argument(&mut self) -> Result<BorshType, Error> {
+        self.expect('<')?;
+        let borsh_type = self.parse_type()?;
+        self.expect('>')?;
+        Ok(borsh_type)
+    }
+
+    /// Parses `name: type, ..}` after the opening brace of a struct
+    fn parse_fields(&mut self) -> Result<Vec<(String, BorshType)>, Error> {
+        let mut fields = vec![];
+        while !self.eat('}') {
+            let name = self.ident()?.to_string();
+            self.expect(':')?;
+            fields.push((name, self.parse_type()?));
+            if !self.eat(',') {
+                self.expect('}')?;
+                break;
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Parses `A, B: type, ..}` after the opening brace of an enum
+    fn parse_variants(&mut self) -> Result<Vec<(String, Option<BorshType>)>, Error> {
+        let mut variants = vec![];
+        while !self.eat('}') {
+            let name = self.ident()?.to_string();
+            let value = match self.eat(':') {
+                true => Some(self.parse_type()?),
+                false => None,
+            };
+            variants.push((name, value));
+            if !self.eat(',') {
+                self.expect('}')?;
+                break;
+            }
+        }
+        if variants.is_empty() {
+            return Err(anyhow!("enum without variants in `{}`", self.input));
+        }
+        Ok(variants)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.remaining() {
+            return Err(anyhow!(
+                "data too short: {} bytes needed at offset {} of {} bytes",
+                len,
+                self.pos,
+                self.data.len()
+            ));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn read_len(&mut self) -> Result<usize, Error> {
+        let len = u32::from_le_bytes(self.take_array()?) as usize;
+        // Every item takes at least one byte, a longer length is corrupt data
+        if len > self.remaining() {
+            return Err(anyhow!(
+                "length {} at offset {} exceeds the data",
+                len,
+                self.pos - 4
+            ));
+        }
+        Ok(len)
+    }
+}
+
+impl BorshType {
+    pub(crate) fn decode(&self, data: &[u8]) -> Result<Value, Error> {
+        self.read(&mut Reader { data, pos: 0 })
+    }
+
+    fn read(&self, reader: &mut Reader) -> Result<Value, Error> {
+        let value = match self {
+            BorshType::U8 => Value::from(reader.take_array::<1>()?[0]),
+            BorshType::U16 => Value::from(u16::from_le_bytes(reader.take_array()?)),
+            BorshType::U32 => Value::from(u32::from_le_bytes(reader.take_array()?)),
+            BorshType::U64 => Value::from(u64::from_le_bytes(reader.take_array()?)),
+            BorshType::U128 => Value::from(u128::from_le_bytes(reader.take_array()?).to_string()),
+            BorshType::I8 => Value::from(reader.take_array::<1>()?[0] as i8),
+            BorshType::I16 => Value::from(i16::from_le_bytes(reader.take_array()?)),
+            BorshType::I32 => Value::from(i32::from_le_bytes(reader.take_array()?)),
+            BorshType::I64 => Value::from(i64::from_le_bytes(reader.take_array()?)),
+            BorshType::I128 => Value::from(i128::from_le_bytes(reader.take_array()?).to_string()),
+            BorshType::F32 => float(f32::from_le_bytes(reader.take_array()?) as f64),
+            BorshType::F64 => float(f64::from_le_bytes(reader.take_array()?)),
+            BorshType::Bool => match reader.take_array::<1>()?[0] {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                byte => {
+                    return Err(anyhow!(
+                        "invalid bool {} at offset {}",
+                        byte,
+                        reader.pos - 1
+                    ))
+                }
+            },
+            BorshType::String => {
+                let len = reader.read_len()?;
+                let bytes = reader.take(len)?;
+                Value::String(
+                    String::from_utf8(bytes.to_vec())
+                        .map_err(|_| anyhow!("invalid UTF-8 string at offset {}", reader.pos))?,
+                )
+            }
+            BorshType::Pubkey => Value::String(bs58::encode(reader.take(32)?).into_string()),
+            BorshType::Bytes => {
+                let len = reader.read_len()?;
+                Value::String(format!("0x{}", hex::encode(reader.take(len)?)))
+            }
+            BorshType::Option(item) => match reader.take_array::<1>()?[0] {
+                0 => Value::Null,
+                1 => item.read(reader)?,
+                byte => {
+                    return Err(anyhow!(
+                        "invalid option {} at offset {}",
+                        byte,
+                        reader.pos - 1
+                    ))
+                }
+            },
+            BorshType::Vec(item) => {
+                let len = reader.read_len()?;
+                Value::Array(
+                    (0..len)
+                        .map(|_| item.read(reader))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            BorshType::Array(item, len) => Value::Array(
+                (0..*len)
+                    .map(|_| item.read(reader))
+                    .collect::<Result<_, _>>()?,
+            ),
+            BorshType::Struct(fields) => {
+                let mut object = Map::new();
+                for (name, field) in fields {
+                    object.insert(name.clone(), field.read(reader)?);
+                }
+                Value::Object(object)
+            }
+            BorshType::Enum(variants) => {
+                let index = reader.take_array::<1>()?[0] as usize;
+                match variants.get(index) {
+                    Some((name, None)) => Value::String(name.clone()),
+                    Some((name, Some(value))) => {
+                        let mut object = Map::new();
+                        object.insert(name.clone(), value.read(reader)?);
+                        Value::Object(object)
+                    }
+                    None => {
+                        return Err(anyhow!(
+                            "invalid enum variant {} at offset {}",
+                            index,
+                            reader.pos - 1
+                        ))
+                    }
+                }
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// NaN and infinities have no JSON representation
+fn float(value: f64) -> Value {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use massbit::prelude::serde_json::json;
+
+    fn decode(descriptor: &str, data: &[u8]) -> Result<Value, Error> {
+        descriptor.parse::<BorshType>()?.decode(data)
+    }
+
+    fn parse_error(descriptor: &str) -> String {
+        descriptor.parse::<BorshType>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parse_descriptor() {
+        let borsh_type: BorshType = "{ amount: u64, owner: option<pubkey>, tags: vec<[u8; 4]> }"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            borsh_type,
+            BorshType::Struct(vec![
+                ("amount".to_string(), BorshType::U64),
+                (
+                    "owner".to_string(),
+                    BorshType::Option(Box::new(BorshType::Pubkey))
+                ),
+                (
+                    "tags".to_string(),
+                    BorshType::Vec(Box::new(BorshType::Array(Box::new(BorshType::U8), 4)))
+                ),
+            ])
+        );
+        assert_eq!(
+            "enum { A, B: u8, }".parse::<BorshType>().unwrap(),
+            BorshType::Enum(vec![
+                ("A".to_string(), None),
+                ("B".to_string(), Some(BorshType::U8)),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_descriptor_errors() {
+        assert_eq!(parse_error("u256"), "unknown type `u256` in `u256`");
+        assert_eq!(
+            parse_error("u8 u8"),
+            "invalid type descriptor `u8 u8`: expected end of the descriptor at position 3"
+        );
+        assert_eq!(
+            parse_error("{ a u8 }"),
+            "invalid type descriptor `{ a u8 }`: expected `:` at position 4"
+        );
+        assert_eq!(
+            parse_error("vec<u8"),
+            "invalid type descriptor `vec<u8`: expected `>` at position 6"
+        );
+        assert_eq!(
+            parse_error("[u8; n]"),
+            "invalid type descriptor `[u8; n]`: expected the length of the array at position 6"
+        );
+        assert_eq!(parse_error("enum {}"), "enum without variants in `enum {}`");
+        assert_eq!(
+            parse_error(""),
+            "invalid type descriptor ``: expected a name at position 0"
+        );
+    }
+
+    #[test]
+    fn decode_primitives() {
+        assert_eq!(decode("u16", &[1, 2]).unwrap(), json!(0x0201));
+        assert_eq!(decode("i8", &[0xff]).unwrap(), json!(-1));
+        assert_eq!(
+            decode("u128", &[0xff; 16]).unwrap(),
+            json!(u128::MAX.to_string())
+        );
+        assert_eq!(decode("bool", &[1]).unwrap(), json!(true));
+        assert_eq!(
+            decode("string", &[2, 0, 0, 0, b'h', b'i']).unwrap(),
+            json!("hi")
+        );
+        assert_eq!(
+            decode("bytes", &[2, 0, 0, 0, 0xab, 0xcd]).unwrap(),
+            json!("0xabcd")
+        );
+        assert_eq!(
+            decode("pubkey", &[0; 32]).unwrap(),
+            json!("11111111111111111111111111111111")
+        );
+        // Bytes after the value are ignored
+        assert_eq!(decode("u8", &[7, 8, 9]).unwrap(), json!(7));
+    }
+
+    #[test]
+    fn decode_struct() {
+        let data = [5, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+        assert_eq!(
+            decode("{ amount: u64, frozen: bool, closed: bool }", &data).unwrap(),
+            json!({ "amount": 5, "frozen": false, "closed": true })
+        );
+    }
+
+    #[test]
+    fn decode_enum() {
+        let descriptor = "enum { Empty, Amount: u32 }";
+        assert_eq!(decode(descriptor, &[0]).unwrap(), json!("Empty"));
+        assert_eq!(
+            decode(descriptor, &[1, 3, 0, 0, 0]).unwrap(),
+            json!({ "Amount": 3 })
+        );
+        assert_eq!(
+            decode(descriptor, &[2]).unwrap_err().to_string(),
+            "invalid enum variant 2 at offset 0"
+        );
+    }
+
+    #[test]
+    fn decode_option() {
+        assert_eq!(decode("option<u8>", &[0]).unwrap(), json!(null));
+        assert_eq!(decode("option<u8>", &[1, 9]).unwrap(), json!(9));
+    }
+
+    #[test]
+    fn decode_vec_and_array() {
+        assert_eq!(
+            decode("vec<u16>", &[2, 0, 0, 0, 1, 0, 2, 0]).unwrap(),
+            json!([1, 2])
+        );
+        assert_eq!(decode("vec<u8>", &[0, 0, 0, 0]).unwrap(), json!([]));
+        assert_eq!(decode("[u8; 3]", &[1, 2, 3]).unwrap(), json!([1, 2, 3]));
+        assert_eq!(
+            decode("[option<u8>; 2]", &[1, 4, 0]).unwrap(),
+            json!([4, null])
+        );
+    }
+
+    #[test]
+    fn decode_short_data() {
+        assert_eq!(
+            decode("u32", &[1, 2]).unwrap_err().to_string(),
+            "data too short: 4 bytes needed at offset 0 of 2 bytes"
+        );
+        assert_eq!(
+            decode("{ a: u8, b: u16 }", &[1, 2])
+                .unwrap_err()
+                .to_string(),
+            "data too short: 2 bytes needed at offset 1 of 2 bytes"
+        );
+        assert_eq!(
+            decode("[u8; 4]", &[1, 2, 3]).unwrap_err().to_string(),
+            "data too short: 1 bytes needed at offset 3 of 3 bytes"
+        );
+        assert_eq!(
+            decode("vec<u8>", &[5, 0, 0, 0, 1]).unwrap_err().to_string(),
+            "length 5 at offset 0 exceeds the data"
+        );
+    }
+
+    #[test]
+    fn decode_invalid_tags() {
+        assert_eq!(
+            decode("bool", &[2]).unwrap_err().to_string(),
+            "invalid bool 2 at offset 0"
+        );
+        assert_eq!(
+            decode("option<u8>", &[3, 1]).unwrap_err().to_string(),
+            "invalid option 3 at offset 0"
+        );
+    }
+}
@@ -1,11 +1,15 @@
+use crate::borsh::BorshType;
 use crate::{error::DeterminismLevel, module::IntoTrap};
 use anyhow::ensure;
+use ed25519_dalek::Verifier;
 use ethabi::param_type::Reader;
 use ethabi::{decode, encode, Token};
 pub use massbit::runtime::{DeterministicHostError, HostExportError};
 use never::Never;
 use semver::Version;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 use wasmtime::Trap;
@@ -593,6 +597,99 @@ pub(crate) fn ethereum_decode(types: String, data: Vec<u8>) -> Result<Token, any
         .context("Failed to decode")
 }
 
+/// Decodes the base58 string `s`, e.g. a Solana address
+pub fn base58_decode(s: &str) -> Result<Vec<u8>, DeterministicHostError> {
+    ::bs58::decode(s)
+        .into_vec()
+        .with_context(|| format!("Failed to decode base58 string: '{}'", s))
+        .map_err(DeterministicHostError)
+}
+
+pub fn crypto_sha256(input: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Sha256::digest(input));
+    hash
+}
+
+/// Reads the little-endian integer of `size` bytes at `offset` in `data`, e.g.
+/// an argument in the data of a Solana instruction
+pub fn read_int_le(
+    data: &[u8],
+    offset: i32,
+    size: i32,
+    signed: bool,
+) -> Result<BigInt, DeterministicHostError> {
+    // Negative values and the end of the range are checked without overflow
+    let range = match (usize::try_from(offset), usize::try_from(size)) {
+        (Ok(offset), Ok(size)) if (1..=32).contains(&size) => {
+            offset.checked_add(size).map(|end| offset..end)
+        }
+        _ => None,
+    };
+    let bytes = range.and_then(|range| data.get(range)).ok_or_else(|| {
+        DeterministicHostError(anyhow!(
+            "Failed to read {} bytes at offset {} of {} bytes",
+            size,
+            offset,
+            data.len()
+        ))
+    })?;
+    Ok(match signed {
+        true => BigInt::from_signed_bytes_le(bytes),
+        false => BigInt::from_unsigned_bytes_le(bytes),
+    })
+}
+
+/// Whether `signature` is a valid ed25519 signature of `message` by `public_key`
+pub fn ed25519_verify(
+    public_key: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<bool, DeterministicHostError> {
+    let public_key = ed25519_dalek::PublicKey::from_bytes(public_key)
+        .map_err(|e| DeterministicHostError(anyhow!("Invalid ed25519 public key: {}", e)))?;
+    let signature = ed25519_dalek::Signature::try_from(signature)
+        .map_err(|e| DeterministicHostError(anyhow!("Invalid ed25519 signature: {}", e)))?;
+    Ok(public_key.verify(message, &signature).is_ok())
+}
+
+/// Decodes the Borsh serialized `data` laid out as `descriptor`, see `crate::borsh`
+pub fn borsh_decode(
+    descriptor: &str,
+    data: &[u8],
+) -> Result<serde_json::Value, DeterministicHostError> {
+    BorshType::from_str(descriptor)
+        .and_then(|borsh_type| borsh_type.decode(data))
+        .context("Failed to decode Borsh data")
+        .map_err(DeterministicHostError)
+}
+
 fn block_on03<T>(future: impl futures03::Future<Output = T> + Send) -> T {
     massbit::block_on(future)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_int_le_reads_in_bounds() {
+        let data = [0xff, 0xfe, 0x01];
+        assert_eq!(
+            read_int_le(&data, 1, 2, false).unwrap(),
+            BigInt::from(0x01fe)
+        );
+        assert_eq!(read_int_le(&data, 0, 1, true).unwrap(), BigInt::from(-1));
+    }
+
+    #[test]
+    fn read_int_le_rejects_out_of_bounds() {
+        let data = [0; 8];
+        assert!(read_int_le(&data, 4, 5, false).is_err());
+        assert!(read_int_le(&data, -1, 1, false).is_err());
+        assert!(read_int_le(&data, 0, 0, false).is_err());
+        assert!(read_int_le(&data, 0, 33, false).is_err());
+        // The end of the range does not fit in an i32
+        assert!(read_int_le(&data, i32::MAX, 4, false).is_err());
+    }
+}
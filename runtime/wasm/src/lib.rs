@@ -1,5 +1,6 @@
 pub mod asc_abi;
 
+mod borsh;
 mod host;
 pub mod to_from;
 
@@ -0,0 +1 @@
+drop table if exists indexer_schema_versions;
\ No newline at end of file
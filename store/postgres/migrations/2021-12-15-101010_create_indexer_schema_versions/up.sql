@@ -0,0 +1,9 @@
+create table if not exists indexer_schema_versions
+(
+    namespace varchar not null,     -- database schema of the indexer
+    version int not null,           -- increases with every change of the schema
+    schema text not null,           -- the GraphQL schema of the version
+    created_at timestamptz not null default now(),
+    constraint indexer_schema_versions_pk
+        primary key (namespace, version)
+);
@@ -0,0 +1,4 @@
+alter table indexer_deployment
+    drop column graft_base,
+    drop column graft_block_hash,
+    drop column graft_block_number;
@@ -0,0 +1,4 @@
+alter table indexer_deployment
+    add column graft_base         text,
+    add column graft_block_hash   bytea,
+    add column graft_block_number numeric;
@@ -0,0 +1 @@
+drop table if exists indexer_error;
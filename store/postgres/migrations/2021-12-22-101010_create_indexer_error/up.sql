@@ -0,0 +1,16 @@
+create table indexer_error
+(
+    vid           bigserial
+        constraint indexer_error_pkey
+            primary key,
+    deployment    text    not null,
+    message       text    not null,
+    block_hash    bytea,
+    block_number  integer,
+    handler       text,
+    deterministic boolean not null,
+    created_at    timestamp with time zone default now() not null
+);
+
+create index indexer_error_deployment_block_number
+    on indexer_error (deployment, block_number);
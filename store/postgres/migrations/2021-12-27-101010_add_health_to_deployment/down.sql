@@ -0,0 +1,3 @@
+alter table indexer_deployment
+    drop column health,
+    drop column fatal_error;
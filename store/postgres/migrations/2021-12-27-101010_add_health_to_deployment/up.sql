@@ -0,0 +1,7 @@
+alter table indexer_deployment
+    add column health      text   not null default 'healthy'
+        constraint indexer_deployment_health_check
+            check (health in ('healthy', 'unhealthy', 'failed')),
+    add column fatal_error bigint
+        constraint indexer_deployment_fatal_error_fkey
+            references indexer_error (vid) on delete set null;
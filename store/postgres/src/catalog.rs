@@ -44,6 +44,15 @@ impl Catalog {
         Ok(Catalog { site, text_columns })
     }
 
+    /// A catalog for a namespace without tables
+    #[cfg(test)]
+    pub(crate) fn make_empty(site: Arc<Site>) -> Self {
+        Catalog {
+            site,
+            text_columns: HashMap::new(),
+        }
+    }
+
     /// Return `true` if `table` exists and contains the given `column` and
     /// if that column is of data type `text`
     pub fn is_existing_text_column(&self, table: &SqlName, column: &SqlName) -> bool {
@@ -1,11 +1,15 @@
-use diesel::dsl::{insert_into, sql, update};
+use diesel::dsl::{delete, insert_into, sql, update};
 use diesel::{expression::SqlLiteral, pg::PgConnection, sql_types::Numeric};
 use diesel::{
     prelude::{ExpressionMethods, QueryDsl, RunQueryDsl},
     sql_types::Nullable,
 };
 
-use massbit::data::indexer::schema::{IndexerDeploymentEntity, IndexerManifestEntity};
+use massbit::blockchain::BlockHash;
+use massbit::data::indexer::schema::{
+    IndexerDeploymentEntity, IndexerError, IndexerManifestEntity,
+};
+use massbit::data::indexer::status::{DeploymentHealth, ErrorStatus, HealthStatus};
 use massbit::prelude::*;
 use massbit::prelude::{BlockPtr, DeploymentHash, Schema, StoreError};
 
@@ -24,6 +28,11 @@ table! {
         last_healthy_ethereum_block_hash -> Nullable<Binary>,
         last_healthy_ethereum_block_number -> Nullable<Numeric>,
         entity_count -> Numeric,
+        graft_base -> Nullable<Text>,
+        graft_block_hash -> Nullable<Binary>,
+        graft_block_number -> Nullable<Numeric>,
+        health -> Text,
+        fatal_error -> Nullable<BigInt>,
     }
 }
 
@@ -38,6 +47,18 @@ table! {
     }
 }
 
+table! {
+    indexer_error (vid) {
+        vid -> BigInt,
+        deployment -> Text,
+        message -> Text,
+        block_hash -> Nullable<Binary>,
+        block_number -> Nullable<Integer>,
+        handler -> Nullable<Text>,
+        deterministic -> Bool,
+    }
+}
+
 pub fn schema(conn: &PgConnection, site: &Site) -> Result<Schema, StoreError> {
     use indexer_manifest as sm;
     let s: String = sm::table
@@ -79,6 +100,8 @@ pub fn create_deployment(
             },
         earliest_block,
         latest_block,
+        graft_base,
+        graft_block,
     } = deployment;
 
     let deployment_values = (
@@ -89,6 +112,9 @@ pub fn create_deployment(
         d::latest_ethereum_block_hash.eq(b(&latest_block)),
         d::latest_ethereum_block_number.eq(n(&latest_block)),
         d::entity_count.eq(sql("0")),
+        d::graft_base.eq(graft_base.as_ref().map(|base| base.as_str())),
+        d::graft_block_hash.eq(b(&graft_block)),
+        d::graft_block_number.eq(n(&graft_block)),
     );
 
     let manifest_values = (
@@ -180,6 +206,42 @@ pub fn forward_block_ptr(
     }
 }
 
+/// Move the block pointer of the deployment back to `ptr`. This is the
+/// counterpart of `forward_block_ptr` and is only used when reverting blocks
+pub fn revert_block_ptr(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+    ptr: BlockPtr,
+) -> Result<(), StoreError> {
+    use indexer_deployment as d;
+
+    // Work around a Diesel issue with serializing BigDecimals to numeric
+    let number = format!("{}::numeric", ptr.number);
+
+    let row_count = update(d::table.filter(d::deployment.eq(id.as_str())).filter(
+        // Asserts that the processing direction is backward.
+        d::latest_ethereum_block_number.ge(sql(&number)),
+    ))
+    .set((
+        d::latest_ethereum_block_number.eq(sql(&number)),
+        d::latest_ethereum_block_hash.eq(ptr.hash_slice()),
+    ))
+    .execute(conn)
+    .map_err(StoreError::from)?;
+
+    match row_count {
+        1 => Ok(()),
+        0 => Err(StoreError::Unknown(anyhow!(
+            "can not revert deployment {} to block {} since it is not behind the deployment head",
+            id,
+            ptr.number
+        ))),
+        _ => Err(StoreError::ConstraintViolation(
+            "duplicate deployments in shard".to_owned(),
+        )),
+    }
+}
+
 pub fn block_ptr(conn: &PgConnection, id: &DeploymentHash) -> Result<Option<BlockPtr>, StoreError> {
     use indexer_deployment as d;
 
@@ -199,3 +261,341 @@ pub fn block_ptr(conn: &PgConnection, id: &DeploymentHash) -> Result<Option<Bloc
         .map(|block| block.to_ptr());
     Ok(ptr)
 }
+
+/// Record the errors of the handlers that failed in a block of the
+/// deployment `id`
+pub fn insert_indexer_errors(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+    errors: Vec<IndexerError>,
+) -> Result<(), StoreError> {
+    use indexer_deployment as d;
+    use indexer_error as e;
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    let values = errors
+        .into_iter()
+        .map(|error| {
+            let IndexerError {
+                indexer_id: _,
+                message,
+                block_ptr,
+                handler,
+                deterministic,
+            } = error;
+            (
+                e::deployment.eq(id.as_str()),
+                e::message.eq(message),
+                e::block_hash.eq(block_ptr.as_ref().map(|ptr| ptr.hash_slice().to_vec())),
+                e::block_number.eq(block_ptr.as_ref().map(|ptr| ptr.number)),
+                e::handler.eq(handler),
+                e::deterministic.eq(deterministic),
+            )
+        })
+        .collect::<Vec<_>>();
+    insert_into(e::table).values(values).execute(conn)?;
+
+    update(
+        d::table
+            .filter(d::deployment.eq(id.as_str()))
+            .filter(d::health.eq(DeploymentHealth::Healthy.as_str())),
+    )
+    .set(d::health.eq(DeploymentHealth::Unhealthy.as_str()))
+    .execute(conn)?;
+    Ok(())
+}
+
+/// Remove the errors that blocks after `block` caused. This is used when
+/// reverting blocks after a chain reorganization. The fatal error is kept
+/// since only processing the failed block again can clear it
+pub fn revert_indexer_errors(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+    block: BlockNumber,
+) -> Result<(), StoreError> {
+    use indexer_deployment as d;
+    use indexer_error as e;
+
+    let fatal = fatal_error_vid(conn, id.as_str())?.unwrap_or(-1);
+    delete(
+        e::table
+            .filter(e::deployment.eq(id.as_str()))
+            .filter(e::block_number.gt(block))
+            .filter(e::vid.ne(fatal)),
+    )
+    .execute(conn)?;
+
+    if !has_non_fatal_errors(conn, id.as_str())? {
+        update(
+            d::table
+                .filter(d::deployment.eq(id.as_str()))
+                .filter(d::health.eq(DeploymentHealth::Unhealthy.as_str())),
+        )
+        .set(d::health.eq(DeploymentHealth::Healthy.as_str()))
+        .execute(conn)?;
+    }
+    Ok(())
+}
+
+fn fatal_error_vid(conn: &PgConnection, id: &str) -> Result<Option<i64>, StoreError> {
+    use diesel::OptionalExtension;
+    use indexer_deployment as d;
+
+    let vid = d::table
+        .filter(d::deployment.eq(id))
+        .select(d::fatal_error)
+        .first::<Option<i64>>(conn)
+        .optional()?;
+    Ok(vid.flatten())
+}
+
+/// Returns `true` if handlers of the deployment `id` failed and their
+/// changes are missing from its data
+pub fn has_non_fatal_errors(conn: &PgConnection, id: &str) -> Result<bool, StoreError> {
+    let fatal = fatal_error_vid(conn, id)?;
+    has_errors_besides(conn, id, fatal)
+}
+
+/// Returns `true` if handlers of `id` failed, not counting the error
+/// `fatal` that stopped it
+pub fn has_errors_besides(
+    conn: &PgConnection,
+    id: &str,
+    fatal: Option<i64>,
+) -> Result<bool, StoreError> {
+    use indexer_error as e;
+
+    let count = e::table
+        .filter(e::deployment.eq(id))
+        .filter(e::deterministic.eq(true))
+        .filter(e::vid.ne(fatal.unwrap_or(-1)))
+        .count()
+        .get_result::<i64>(conn)?;
+    Ok(count > 0)
+}
+
+/// Record `error` for `id` and return its `vid`
+pub fn insert_error(conn: &PgConnection, id: &str, error: IndexerError) -> Result<i64, StoreError> {
+    use indexer_error as e;
+
+    let IndexerError {
+        indexer_id: _,
+        message,
+        block_ptr,
+        handler,
+        deterministic,
+    } = error;
+    let vid = insert_into(e::table)
+        .values((
+            e::deployment.eq(id),
+            e::message.eq(message),
+            e::block_hash.eq(block_ptr.as_ref().map(|ptr| ptr.hash_slice().to_vec())),
+            e::block_number.eq(block_ptr.as_ref().map(|ptr| ptr.number)),
+            e::handler.eq(handler),
+            e::deterministic.eq(deterministic),
+        ))
+        .returning(e::vid)
+        .get_result::<i64>(conn)?;
+    Ok(vid)
+}
+
+/// Return the error with the given `vid`
+pub fn find_error(conn: &PgConnection, vid: i64) -> Result<Option<ErrorStatus>, StoreError> {
+    use diesel::OptionalExtension;
+    use indexer_error as e;
+
+    let error = e::table
+        .filter(e::vid.eq(vid))
+        .select((
+            e::message,
+            e::block_number,
+            e::block_hash,
+            e::handler,
+            e::deterministic,
+        ))
+        .first::<ErrorRow>(conn)
+        .optional()?
+        .map(error_status);
+    Ok(error)
+}
+
+pub fn delete_error(conn: &PgConnection, vid: i64) -> Result<(), StoreError> {
+    use indexer_error as e;
+
+    delete(e::table.filter(e::vid.eq(vid))).execute(conn)?;
+    Ok(())
+}
+
+/// Mark the deployment `id` as failed because of `error`. An earlier
+/// fatal error is replaced by the new one
+pub fn fail(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+    error: IndexerError,
+) -> Result<(), StoreError> {
+    use indexer_deployment as d;
+
+    if let Some(vid) = fatal_error_vid(conn, id.as_str())? {
+        delete_error(conn, vid)?;
+    }
+    let vid = insert_error(conn, id.as_str(), error)?;
+
+    update(d::table.filter(d::deployment.eq(id.as_str())))
+        .set((
+            d::failed.eq(true),
+            d::health.eq(DeploymentHealth::Failed.as_str()),
+            d::fatal_error.eq(vid),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Return the block that caused the fatal error of the deployment `id` if
+/// that error is deterministic. Processing that block again fails the same
+/// way, so the deployment can only resume once a reorg replaced the block
+pub fn deterministic_error_block(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+) -> Result<Option<BlockPtr>, StoreError> {
+    use diesel::OptionalExtension;
+    use indexer_error as e;
+
+    let vid = match fatal_error_vid(conn, id.as_str())? {
+        Some(vid) => vid,
+        None => return Ok(None),
+    };
+    let (number, hash) = e::table
+        .filter(e::vid.eq(vid))
+        .filter(e::deterministic.eq(true))
+        .select((e::block_number, e::block_hash))
+        .first::<(Option<BlockNumber>, Option<Vec<u8>>)>(conn)
+        .optional()?
+        .unwrap_or((None, None));
+    Ok(number
+        .zip(hash)
+        .map(|(number, hash)| BlockPtr::from((hash, number))))
+}
+
+/// Clear the fatal error of the deployment `id` if `block_ptr` is past the
+/// block that caused it, or is a different block with the same number
+/// because a reorg replaced the failed block. A non-deterministic error is
+/// also cleared when the failed block itself was processed again. Errors
+/// without a block are cleared by any block. Returns `true` if the
+/// deployment is not failed anymore
+pub fn unfail(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+    block_ptr: &BlockPtr,
+) -> Result<bool, StoreError> {
+    use indexer_deployment as d;
+    use indexer_error as e;
+
+    let vid = match fatal_error_vid(conn, id.as_str())? {
+        Some(vid) => vid,
+        None => return Ok(true),
+    };
+    let (failed_at, failed_hash, deterministic) = e::table
+        .filter(e::vid.eq(vid))
+        .select((e::block_number, e::block_hash, e::deterministic))
+        .first::<(Option<BlockNumber>, Option<Vec<u8>>, bool)>(conn)?;
+    if let Some(failed_at) = failed_at {
+        let same_block = failed_hash.as_deref() == Some(block_ptr.hash_slice());
+        if block_ptr.number < failed_at
+            || (block_ptr.number == failed_at && same_block && deterministic)
+        {
+            return Ok(false);
+        }
+    }
+
+    update(d::table.filter(d::deployment.eq(id.as_str())))
+        .set(d::fatal_error.eq(None::<i64>))
+        .execute(conn)?;
+    delete(e::table.filter(e::vid.eq(vid))).execute(conn)?;
+
+    let health = if has_non_fatal_errors(conn, id.as_str())? {
+        DeploymentHealth::Unhealthy
+    } else {
+        DeploymentHealth::Healthy
+    };
+    update(d::table.filter(d::deployment.eq(id.as_str())))
+        .set((d::failed.eq(false), d::health.eq(health.as_str())))
+        .execute(conn)?;
+    Ok(true)
+}
+
+/// Return the most recent error of the deployment `id`, fatal or not
+pub fn last_error(conn: &PgConnection, id: &str) -> Result<Option<ErrorStatus>, StoreError> {
+    use diesel::OptionalExtension;
+    use indexer_error as e;
+
+    let error = e::table
+        .filter(e::deployment.eq(id))
+        .order_by(e::vid.desc())
+        .select((
+            e::message,
+            e::block_number,
+            e::block_hash,
+            e::handler,
+            e::deterministic,
+        ))
+        .first::<ErrorRow>(conn)
+        .optional()?
+        .map(error_status);
+    Ok(error)
+}
+
+type ErrorRow = (
+    String,
+    Option<BlockNumber>,
+    Option<Vec<u8>>,
+    Option<String>,
+    bool,
+);
+
+fn error_status(row: ErrorRow) -> ErrorStatus {
+    let (message, block_number, block_hash, handler, deterministic) = row;
+    ErrorStatus {
+        message,
+        block_number,
+        block_hash: block_hash.map(|hash| BlockHash::from(hash).to_string()),
+        handler,
+        deterministic,
+    }
+}
+
+/// Return the health of the deployment `id` together with the error that
+/// stopped it, or `None` if there is no such deployment
+pub fn deployment_health(
+    conn: &PgConnection,
+    id: &str,
+) -> Result<Option<HealthStatus>, StoreError> {
+    use diesel::OptionalExtension;
+    use indexer_deployment as d;
+
+    let (health, fatal) = match d::table
+        .filter(d::deployment.eq(id))
+        .select((d::health, d::fatal_error))
+        .first::<(String, Option<i64>)>(conn)
+        .optional()?
+    {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let health = health
+        .parse::<DeploymentHealth>()
+        .map_err(StoreError::Unknown)?;
+
+    let fatal_error = match fatal {
+        Some(vid) => find_error(conn, vid)?,
+        None => None,
+    };
+
+    Ok(Some(HealthStatus {
+        deployment: id.to_string(),
+        health,
+        fatal_error,
+    }))
+}
@@ -10,19 +10,23 @@ use std::ops::Deref;
 use std::str::FromStr;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
+use std::time::Instant;
 
+use massbit::components::metrics::{self, HistogramVec, IntCounterVec};
 use massbit::components::store::{EntityType, StoredDynamicDataSource, BLOCK_NUMBER_MAX};
-use massbit::data::indexer::schema::IndexerDeploymentEntity;
+use massbit::data::indexer::schema::{IndexerDeploymentEntity, IndexerError};
 use massbit::data::query::QueryExecutionError;
 use massbit::prelude::*;
 use massbit::prelude::{ApiSchema, DeploymentHash, Schema, StoreError};
 
 use crate::block_range::block_number;
 use crate::connection_pool::ConnectionPool;
+use crate::copy;
 use crate::deployment;
 use crate::dynds;
 use crate::primary::Site;
 use crate::relational::{Layout, LayoutCache};
+use crate::store_events::send_store_event;
 use massbit::prelude::reqwest::Client;
 lazy_static! {
     /// `QUERY_STATS_REFRESH_INTERVAL` is how long statistics that
@@ -40,6 +44,17 @@ lazy_static! {
     };
 
     static ref HASURA_URL: String = env::var("HASURA_URL").unwrap_or(String::from("http://localhost:8080/v1/query"));
+
+    static ref TRANSACT_DURATION: HistogramVec = metrics::histogram_vec(
+        "deployment_transact_block_operations_duration_seconds",
+        "Time to write the changes of a block to the store",
+        &["deployment"]
+    );
+    static ref ROWS_WRITTEN: IntCounterVec = metrics::int_counter_vec(
+        "deployment_entity_rows_written_total",
+        "Number of entity changes written to the store",
+        &["deployment"]
+    );
 }
 
 /// When connected to read replicas, this allows choosing which DB server to use for an operation.
@@ -156,9 +171,11 @@ impl DeploymentStore {
         schema: &Schema,
         deployment: IndexerDeploymentEntity,
         site: Arc<Site>,
+        graft_base: Option<Arc<Site>>,
         replace: bool,
     ) -> Result<(), StoreError> {
         let conn = self.get_conn()?;
+        let graft_block = deployment.graft_block.as_ref().map(|ptr| ptr.number);
         let result = conn.transaction(|| -> Result<_, StoreError> {
             let exists = deployment::exists(&conn, &site)?;
 
@@ -172,13 +189,20 @@ impl DeploymentStore {
                 let query = format!("create schema {}", &site.namespace);
                 conn.batch_execute(&query)?;
                 let layout = Layout::create_relational_schema(&conn, site.clone(), schema)?;
+
+                // Start with the data of the graft base as of the graft block
+                if let (Some(base), Some(block)) = (graft_base, graft_block) {
+                    let base_layout = self.layout(&conn, base.clone())?;
+                    copy::copy_deployment(&conn, &base_layout, &layout, block)?;
+                    dynds::copy(&conn, &base.deployment, &site.deployment, block)?;
+                }
                 Ok(Some(layout))
             } else {
                 Ok(None)
             }
         });
 
-        if let Ok(Some(layout)) = result {
+        if let Some(layout) = result? {
             let payload = layout.create_hasura_tracking();
             massbit::spawn(async move {
                 Client::new().post(&*HASURA_URL).json(&payload).send().await;
@@ -275,6 +299,7 @@ impl DeploymentStore {
         block_ptr_to: BlockPtr,
         mods: Vec<EntityModification>,
         data_sources: Vec<StoredDynamicDataSource>,
+        deterministic_errors: Vec<IndexerError>,
     ) -> Result<(), StoreError> {
         // All operations should apply only to data or metadata for this indexer
         if mods
@@ -288,20 +313,86 @@ impl DeploymentStore {
             );
         }
 
+        let start = Instant::now();
+        let rows = mods.len();
         let conn = self.get_conn()?;
 
         conn.transaction(|| -> Result<_, StoreError> {
+            // Subscribers are notified when the transaction commits
+            let event = StoreEvent::from_mods(
+                site.deployment.to_string(),
+                block_ptr_to.number,
+                mods.iter(),
+            );
+            send_store_event(&conn, &event)?;
+
             // Make the changes
             let layout = self.layout(&conn, site.clone())?;
             let _ = self.apply_entity_modifications(&conn, layout.as_ref(), mods, &block_ptr_to)?;
             dynds::insert(&conn, &site.deployment, data_sources, &block_ptr_to)?;
+            deployment::insert_indexer_errors(&conn, &site.deployment, deterministic_errors)?;
             deployment::forward_block_ptr(&conn, &site.deployment, block_ptr_to)?;
             Ok(())
         })?;
 
+        let deployment = site.deployment.as_str();
+        TRANSACT_DURATION
+            .with_label_values(&[deployment])
+            .observe(start.elapsed().as_secs_f64());
+        ROWS_WRITTEN
+            .with_label_values(&[deployment])
+            .inc_by(rows as u64);
         Ok(())
     }
 
+    pub(crate) fn revert_block_operations(
+        &self,
+        site: Arc<Site>,
+        block_ptr_to: BlockPtr,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+
+        conn.transaction(|| -> Result<_, StoreError> {
+            let block = block_number(&block_ptr_to);
+            let layout = self.layout(&conn, site.clone())?;
+            let count = layout.revert_block(&conn, block)?;
+            let removed_data_sources = dynds::revert(&conn, &site.deployment, block)?;
+            deployment::revert_indexer_errors(&conn, &site.deployment, block)?;
+            deployment::revert_block_ptr(&conn, &site.deployment, block_ptr_to)?;
+
+            info!(
+                self.logger,
+                "Reverted indexer to block {}", block;
+                "indexer" => site.deployment.to_string(),
+                "entity_count_change" => count,
+                "removed_data_sources" => removed_data_sources,
+            );
+            Ok(())
+        })
+    }
+
+    pub(crate) fn fail_indexer(
+        &self,
+        site: Arc<Site>,
+        error: IndexerError,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| deployment::fail(&conn, &site.deployment, error))
+    }
+
+    pub(crate) fn unfail(&self, site: Arc<Site>, block_ptr: &BlockPtr) -> Result<bool, StoreError> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| deployment::unfail(&conn, &site.deployment, block_ptr))
+    }
+
+    pub(crate) fn deterministic_error_block(
+        &self,
+        site: Arc<Site>,
+    ) -> Result<Option<BlockPtr>, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::deterministic_error_block(&conn, &site.deployment)
+    }
+
     fn apply_entity_modifications(
         &self,
         conn: &PgConnection,
@@ -345,6 +436,17 @@ impl DeploymentStore {
             count += self.insert_entities(&entity_type, &mut entities, conn, layout, ptr)? as i32
         }
 
+        // Overwrites:
+        for (entity_type, mut entities) in overwrites.into_iter() {
+            // we do not update the count since the number of entities remains the same
+            self.overwrite_entities(&entity_type, &mut entities, conn, layout, ptr)?;
+        }
+
+        // Removals:
+        for (entity_type, entity_keys) in removals.into_iter() {
+            count -= self.remove_entities(&entity_type, &entity_keys, conn, layout, ptr)? as i32;
+        }
+
         Ok(count)
     }
 
@@ -359,6 +461,28 @@ impl DeploymentStore {
         layout.insert(conn, entity_type, data, block_number(ptr))
     }
 
+    fn overwrite_entities(
+        &self,
+        entity_type: &EntityType,
+        data: &mut [(EntityKey, Entity)],
+        conn: &PgConnection,
+        layout: &Layout,
+        ptr: &BlockPtr,
+    ) -> Result<usize, StoreError> {
+        layout.update(conn, entity_type, data, block_number(ptr))
+    }
+
+    fn remove_entities(
+        &self,
+        entity_type: &EntityType,
+        entity_keys: &[String],
+        conn: &PgConnection,
+        layout: &Layout,
+        ptr: &BlockPtr,
+    ) -> Result<usize, StoreError> {
+        layout.delete(conn, entity_type, entity_keys, block_number(ptr))
+    }
+
     pub(crate) fn block_ptr(&self, site: &Site) -> Result<Option<BlockPtr>, Error> {
         let conn = self.get_conn()?;
         Self::block_ptr_with_conn(&site.deployment, &conn)
@@ -1,8 +1,9 @@
 use diesel::{
+    delete,
     dsl::sql,
     prelude::{ExpressionMethods, QueryDsl, RunQueryDsl},
 };
-use diesel::{insert_into, pg::PgConnection};
+use diesel::{insert_into, pg::PgConnection, sql_query, sql_types::Text};
 
 use massbit::{
     components::store::StoredDynamicDataSource,
@@ -156,3 +157,48 @@ pub(crate) fn insert(
         .execute(conn)
         .map_err(|e| e.into())
 }
+
+/// Copy the dynamic data sources that `src` created at or before `block`
+/// to `dst`
+pub(crate) fn copy(
+    conn: &PgConnection,
+    src: &DeploymentHash,
+    dst: &DeploymentHash,
+    block: BlockNumber,
+) -> Result<usize, StoreError> {
+    let query = format!(
+        "insert into dynamic_ethereum_contract_data_source(name, ethereum_block_hash, \
+           ethereum_block_number, deployment, context, address, abi, start_block)\n\
+         select name, ethereum_block_hash, ethereum_block_number, $2, context, \
+           address, abi, start_block\n  \
+           from dynamic_ethereum_contract_data_source\n \
+          where deployment = $1 and ethereum_block_number <= {}\n \
+          order by vid",
+        block
+    );
+    sql_query(query)
+        .bind::<Text, _>(src.as_str())
+        .bind::<Text, _>(dst.as_str())
+        .execute(conn)
+        .map_err(|e| e.into())
+}
+
+/// Remove the dynamic data sources that were created by blocks after
+/// `block`. This is used when reverting blocks after a chain reorganization
+pub(crate) fn revert(
+    conn: &PgConnection,
+    deployment: &DeploymentHash,
+    block: BlockNumber,
+) -> Result<usize, StoreError> {
+    use dynamic_ethereum_contract_data_source as decds;
+
+    let number = format!("{}::numeric", block);
+
+    delete(
+        decds::table
+            .filter(decds::deployment.eq(deployment.as_str()))
+            .filter(decds::ethereum_block_number.gt(sql(&number))),
+    )
+    .execute(conn)
+    .map_err(|e| e.into())
+}
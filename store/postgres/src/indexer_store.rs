@@ -9,7 +9,7 @@ use std::{fmt, io::Write};
 use std::{iter::FromIterator, time::Duration};
 
 use massbit::components::store::StoredDynamicDataSource;
-use massbit::data::indexer::schema::IndexerDeploymentEntity;
+use massbit::data::indexer::schema::{IndexerDeploymentEntity, IndexerError};
 use massbit::data::query::QueryExecutionError;
 use massbit::{
     components::store::{self, DeploymentLocator, EntityType, WritableStore as WritableStoreTrait},
@@ -259,7 +259,30 @@ impl IndexerStoreInner {
             .stores
             .get(&site.shard)
             .ok_or_else(|| StoreError::UnknownShard(site.shard.to_string()))?;
-        deployment_store.create_deployment(schema, deployment, site.clone(), replace)?;
+        // Grafting copies the data of the base inside the shard, and
+        // therefore only works if both deployments are in the same shard
+        let graft_base = match &deployment.graft_base {
+            Some(base) => {
+                let base = self.site(base)?;
+                if base.shard != site.shard {
+                    return Err(StoreError::Unknown(anyhow!(
+                        "can not graft onto `{}` since it is in shard `{}`, not in shard `{}`",
+                        base.deployment,
+                        base.shard,
+                        site.shard
+                    )));
+                }
+                Some(base)
+            }
+            None => None,
+        };
+        deployment_store.create_deployment(
+            schema,
+            deployment,
+            site.clone(),
+            graft_base,
+            replace,
+        )?;
 
         Ok(site.as_ref().into())
     }
@@ -308,6 +331,11 @@ impl IndexerStoreTrait for IndexerStore {
             .map(|site| site.into())
             .collect())
     }
+
+    fn least_block_ptr(&self, id: &DeploymentHash) -> Result<Option<BlockPtr>, StoreError> {
+        let (store, site) = self.store(id)?;
+        Ok(store.block_ptr(site.as_ref())?)
+    }
 }
 
 /// A wrapper around `IndexerStore` that only exposes functions that are
@@ -368,16 +396,35 @@ impl WritableStoreTrait for WritableStore {
         block_ptr_to: BlockPtr,
         mods: Vec<EntityModification>,
         data_sources: Vec<StoredDynamicDataSource>,
+        deterministic_errors: Vec<IndexerError>,
     ) -> Result<(), StoreError> {
         self.writable.transact_block_operations(
             self.site.clone(),
             block_ptr_to,
             mods,
             data_sources,
+            deterministic_errors,
         )?;
         Ok(())
     }
 
+    fn revert_block_operations(&self, block_ptr_to: BlockPtr) -> Result<(), StoreError> {
+        self.writable
+            .revert_block_operations(self.site.clone(), block_ptr_to)
+    }
+
+    fn fail_indexer(&self, error: IndexerError) -> Result<(), StoreError> {
+        self.writable.fail_indexer(self.site.clone(), error)
+    }
+
+    fn unfail(&self, block_ptr: &BlockPtr) -> Result<bool, StoreError> {
+        self.writable.unfail(self.site.clone(), block_ptr)
+    }
+
+    fn deterministic_error_block(&self) -> Result<Option<BlockPtr>, StoreError> {
+        self.writable.deterministic_error_block(self.site.clone())
+    }
+
     async fn load_dynamic_data_sources(&self) -> Result<Vec<StoredDynamicDataSource>, StoreError> {
         self.writable
             .load_dynamic_data_sources(self.site.deployment.clone())
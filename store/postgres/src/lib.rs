@@ -22,17 +22,25 @@ mod advisory_lock;
 pub mod block_range;
 mod catalog;
 pub mod connection_pool;
+mod copy;
 mod deployment;
 mod deployment_store;
 mod detail;
 mod dynds;
 pub mod helper;
 mod indexer_store;
+pub mod migration;
 mod primary;
 pub mod relational;
 pub mod relational_queries;
 pub mod sql_value;
+pub mod store_events;
+pub use self::deployment::{
+    delete_error, deployment_health, fail, find_error, has_errors_besides, has_non_fatal_errors,
+    insert_error, last_error,
+};
 pub use self::indexer_store::{IndexerStore, Shard, PRIMARY_SHARD};
+pub use self::store_events::{send_store_event, StoreEventListener};
 
 /// This module is only meant to support command line tooling. It must not
 /// be used in 'normal' code
@@ -0,0 +1,615 @@
+//! Migrate the tables of an indexer to a new version of its GraphQL schema.
+//!
+//! Every schema an indexer is deployed with is recorded in
+//! `indexer_schema_versions`. When the indexer is deployed again with a
+//! changed schema, we compare the `Layout` of the recorded schema with the
+//! `Layout` of the new one and only change what differs, so that the data
+//! that was already indexed is kept. Changes that lose data, or that can
+//! fail on the existing data, are only made when they are forced.
+
+use diesel::connection::SimpleConnection;
+use diesel::sql_types::{BigInt, Integer, Text};
+use diesel::{sql_query, Connection, OptionalExtension, PgConnection, RunQueryDsl};
+use std::fmt::{self, Write};
+
+use massbit::components::store::EntityType;
+use massbit::prelude::{anyhow, Schema, StoreError};
+
+use crate::relational::{Column, Layout, SqlName, Table};
+
+/// Creates `indexer_schema_versions` for the stores which do not run the
+/// migrations of this crate
+const CREATE_SCHEMA_VERSIONS: &str =
+    include_str!("../migrations/2021-12-15-101010_create_indexer_schema_versions/up.sql");
+
+/// A difference between the layouts of two versions of the schema of an
+/// indexer
+#[derive(Clone, Debug, PartialEq)]
+pub enum SchemaChange {
+    CreateEnum(String),
+    AddEnumValue(String, String),
+    /// Postgres can not remove values from an enum; the values stay in the
+    /// type, but the schema no longer allows them
+    RemoveEnumValue(String, String),
+    DropEnum(String),
+    CreateTable(EntityType),
+    DropTable(EntityType),
+    /// Columns are added as nullable since the existing entities have no
+    /// value for them
+    AddColumn(EntityType, SqlName),
+    DropColumn(EntityType, SqlName),
+    /// Changing the type of a column replaces it with an empty column
+    ChangeColumnType(EntityType, SqlName),
+    DropNotNull(EntityType, SqlName),
+    SetNotNull(EntityType, SqlName),
+    /// The column is indexed differently, e.g., because it became a reference
+    ReplaceIndex(EntityType, SqlName),
+}
+
+impl SchemaChange {
+    /// Returns true if the change loses data or can fail on existing data
+    pub fn is_destructive(&self) -> bool {
+        use SchemaChange::*;
+        match self {
+            RemoveEnumValue(..) | DropEnum(_) | DropTable(_) | DropColumn(..)
+            | ChangeColumnType(..) | SetNotNull(..) => true,
+            CreateEnum(_) | AddEnumValue(..) | CreateTable(_) | AddColumn(..) | DropNotNull(..)
+            | ReplaceIndex(..) => false,
+        }
+    }
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use SchemaChange::*;
+        match self {
+            CreateEnum(name) => write!(f, "create enum {}", name),
+            AddEnumValue(name, value) => write!(f, "add value {} to enum {}", value, name),
+            RemoveEnumValue(name, value) => write!(f, "remove value {} from enum {}", value, name),
+            DropEnum(name) => write!(f, "drop enum {}", name),
+            CreateTable(entity_type) => write!(f, "create table for {}", entity_type),
+            DropTable(entity_type) => write!(f, "drop table of {}", entity_type),
+            AddColumn(entity_type, column) => write!(f, "add column {}.{}", entity_type, column),
+            DropColumn(entity_type, column) => write!(f, "drop column {}.{}", entity_type, column),
+            ChangeColumnType(entity_type, column) => {
+                write!(f, "change type of column {}.{}", entity_type, column)
+            }
+            DropNotNull(entity_type, column) => {
+                write!(f, "make column {}.{} nullable", entity_type, column)
+            }
+            SetNotNull(entity_type, column) => {
+                write!(f, "make column {}.{} not null", entity_type, column)
+            }
+            ReplaceIndex(entity_type, column) => {
+                write!(f, "replace index on column {}.{}", entity_type, column)
+            }
+        }
+    }
+}
+
+/// The changes that turn the tables of layout `from` into the tables of
+/// layout `to`; both layouts must be for the same namespace
+pub struct SchemaMigration<'a> {
+    from: &'a Layout,
+    to: &'a Layout,
+    pub changes: Vec<SchemaChange>,
+}
+
+impl<'a> SchemaMigration<'a> {
+    pub fn new(from: &'a Layout, to: &'a Layout) -> Self {
+        use SchemaChange::*;
+        let mut changes = Vec::new();
+
+        for (name, values) in to.enums.iter() {
+            match from.enums.get(name) {
+                None => changes.push(CreateEnum(name.clone())),
+                Some(old_values) => {
+                    for value in values.difference(old_values) {
+                        changes.push(AddEnumValue(name.clone(), value.clone()));
+                    }
+                    for value in old_values.difference(values) {
+                        changes.push(RemoveEnumValue(name.clone(), value.clone()));
+                    }
+                }
+            }
+        }
+
+        let mut tables = to.tables.values().collect::<Vec<_>>();
+        tables.sort_by_key(|table| table.object.clone());
+        for table in tables {
+            let old_table = match from.tables.get(&table.object) {
+                Some(old_table) => old_table,
+                None => {
+                    changes.push(CreateTable(table.object.clone()));
+                    continue;
+                }
+            };
+            for column in table.columns.iter() {
+                let entity_type = table.object.clone();
+                let old_column = match old_table.column(&column.name) {
+                    Some(old_column) => old_column,
+                    None => {
+                        changes.push(AddColumn(entity_type, column.name.clone()));
+                        continue;
+                    }
+                };
+                if column_type(old_column) != column_type(column) {
+                    changes.push(ChangeColumnType(entity_type, column.name.clone()));
+                    continue;
+                }
+                if old_column.is_nullable() && !column.is_nullable() {
+                    changes.push(SetNotNull(entity_type.clone(), column.name.clone()));
+                } else if !old_column.is_nullable() && column.is_nullable() {
+                    changes.push(DropNotNull(entity_type.clone(), column.name.clone()));
+                }
+                if old_column.is_reference() != column.is_reference()
+                    || old_column.is_text() != column.is_text()
+                {
+                    changes.push(ReplaceIndex(entity_type, column.name.clone()));
+                }
+            }
+            for old_column in old_table.columns.iter() {
+                if table.column(&old_column.name).is_none() {
+                    changes.push(DropColumn(table.object.clone(), old_column.name.clone()));
+                }
+            }
+        }
+
+        let mut old_tables = from
+            .tables
+            .keys()
+            .filter(|entity_type| !to.tables.contains_key(*entity_type))
+            .cloned()
+            .collect::<Vec<_>>();
+        old_tables.sort();
+        changes.extend(old_tables.into_iter().map(DropTable));
+
+        changes.extend(
+            from.enums
+                .keys()
+                .filter(|name| !to.enums.contains_key(*name))
+                .map(|name| DropEnum(name.clone())),
+        );
+
+        SchemaMigration { from, to, changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn destructive_changes(&self) -> Vec<&SchemaChange> {
+        self.changes
+            .iter()
+            .filter(|change| change.is_destructive())
+            .collect()
+    }
+
+    /// Generate the statements that make the changes. Enums are changed
+    /// before the tables that use them, and dropped after them
+    pub fn as_ddl(&self) -> Result<String, StoreError> {
+        let mut out = String::new();
+        self.write_ddl(&mut out)?;
+        Ok(out)
+    }
+
+    fn write_ddl(&self, out: &mut String) -> fmt::Result {
+        use SchemaChange::*;
+        let namespace = &self.to.site.namespace;
+        let mut drop_enums = Vec::new();
+
+        for change in self.changes.iter() {
+            match change {
+                CreateEnum(name) => {
+                    let values = &self.to.enums[name];
+                    let values = values
+                        .iter()
+                        .map(|value| format!("'{}'", value))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    writeln!(
+                        out,
+                        "create type {}.{}\n    as enum ({});",
+                        namespace,
+                        SqlName::from(name.as_str()).quoted(),
+                        values
+                    )?;
+                }
+                AddEnumValue(name, value) => writeln!(
+                    out,
+                    "alter type {}.{} add value if not exists '{}';",
+                    namespace,
+                    SqlName::from(name.as_str()).quoted(),
+                    value
+                )?,
+                RemoveEnumValue(..) => {}
+                DropEnum(name) => drop_enums.push(name),
+                CreateTable(entity_type) => self.to.tables[entity_type].as_ddl(out, self.to)?,
+                DropTable(entity_type) => writeln!(
+                    out,
+                    "drop table {};",
+                    self.from.tables[entity_type].qualified_name
+                )?,
+                AddColumn(entity_type, column) => {
+                    let table = &self.to.tables[entity_type];
+                    self.add_column(out, table, table.column(column).unwrap())?;
+                }
+                DropColumn(entity_type, column) => writeln!(
+                    out,
+                    "alter table {} drop column {};",
+                    self.from.tables[entity_type].qualified_name,
+                    column.quoted()
+                )?,
+                ChangeColumnType(entity_type, column) => {
+                    let table = &self.to.tables[entity_type];
+                    writeln!(
+                        out,
+                        "alter table {} drop column {};",
+                        table.qualified_name,
+                        column.quoted()
+                    )?;
+                    self.add_column(out, table, table.column(column).unwrap())?;
+                }
+                DropNotNull(entity_type, column) => writeln!(
+                    out,
+                    "alter table {} alter column {} drop not null;",
+                    self.to.tables[entity_type].qualified_name,
+                    column.quoted()
+                )?,
+                SetNotNull(entity_type, column) => writeln!(
+                    out,
+                    "alter table {} alter column {} set not null;",
+                    self.to.tables[entity_type].qualified_name,
+                    column.quoted()
+                )?,
+                ReplaceIndex(entity_type, column) => {
+                    let old_table = &self.from.tables[entity_type];
+                    if let Some((name, _)) =
+                        old_table.attribute_index(old_table.column(column).unwrap(), namespace)
+                    {
+                        writeln!(out, "drop index if exists {}.{};", namespace, name)?;
+                    }
+                    let table = &self.to.tables[entity_type];
+                    if let Some((_, ddl)) =
+                        table.attribute_index(table.column(column).unwrap(), namespace)
+                    {
+                        write!(out, "{}", ddl)?;
+                    }
+                }
+            }
+        }
+        for name in drop_enums {
+            writeln!(
+                out,
+                "drop type {}.{};",
+                namespace,
+                SqlName::from(name.as_str()).quoted()
+            )?;
+        }
+        Ok(())
+    }
+
+    fn add_column(&self, out: &mut String, table: &Table, column: &Column) -> fmt::Result {
+        writeln!(
+            out,
+            "alter table {} add column {} {};",
+            table.qualified_name,
+            column.name.quoted(),
+            column_type(column)
+        )?;
+        if let Some((_, ddl)) = table.attribute_index(column, &self.to.site.namespace) {
+            write!(out, "{}", ddl)?;
+        }
+        Ok(())
+    }
+}
+
+/// The SQL type of `column`, including whether it is an array
+pub(crate) fn column_type(column: &Column) -> String {
+    if column.is_list() {
+        format!("{}[]", column.column_type.sql_type())
+    } else {
+        column.column_type.sql_type().to_string()
+    }
+}
+
+/// Create or migrate the tables in the namespace of `layout`, which was
+/// generated from the GraphQL schema `schema`, and record `schema` as the
+/// latest version for the namespace.
+///
+/// Tables that exist but have no recorded schema were created before
+/// schemas were recorded; they are assumed to match `schema`. Destructive
+/// changes are refused unless `force` is set. Returns the changes that were
+/// made to existing tables.
+pub fn migrate(
+    conn: &PgConnection,
+    layout: &Layout,
+    schema: &str,
+    force: bool,
+) -> Result<Vec<SchemaChange>, StoreError> {
+    let namespace = layout.site.namespace.as_str();
+    conn.transaction(|| {
+        conn.batch_execute(CREATE_SCHEMA_VERSIONS)?;
+        let (version, previous) = match latest_schema_version(conn, namespace)? {
+            Some((_, previous)) if previous == schema => return Ok(vec![]),
+            Some((version, previous)) => (version, previous),
+            None => {
+                if !has_tables(conn, namespace)? {
+                    conn.batch_execute(&layout.as_ddl()?)?;
+                }
+                record_schema_version(conn, namespace, 1, schema)?;
+                return Ok(vec![]);
+            }
+        };
+
+        let previous = Schema::parse(&previous, layout.site.deployment.clone())?;
+        let from = Layout::new(layout.site.clone(), &previous, layout.catalog.clone())?;
+        let migration = SchemaMigration::new(&from, layout);
+        let destructive = migration.destructive_changes();
+        if !force && !destructive.is_empty() {
+            let changes = destructive
+                .iter()
+                .map(|change| change.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Err(StoreError::Unknown(anyhow!(
+                "the new schema of `{}` requires destructive changes, which are only \
+                 made when forced: {}",
+                namespace,
+                changes
+            )));
+        }
+        if !migration.is_empty() {
+            conn.batch_execute(&migration.as_ddl()?)?;
+        }
+        record_schema_version(conn, namespace, version + 1, schema)?;
+        Ok(migration.changes)
+    })
+}
+
+/// The latest version of the schema recorded for `namespace`
+pub fn latest_schema_version(
+    conn: &PgConnection,
+    namespace: &str,
+) -> Result<Option<(i32, String)>, StoreError> {
+    #[derive(QueryableByName)]
+    struct SchemaVersion {
+        #[sql_type = "Integer"]
+        version: i32,
+        #[sql_type = "Text"]
+        schema: String,
+    }
+    let latest = sql_query(
+        "select version, schema from indexer_schema_versions \
+          where namespace = $1 order by version desc limit 1",
+    )
+    .bind::<Text, _>(namespace)
+    .get_result::<SchemaVersion>(conn)
+    .optional()?;
+    Ok(latest.map(|latest| (latest.version, latest.schema)))
+}
+
+fn record_schema_version(
+    conn: &PgConnection,
+    namespace: &str,
+    version: i32,
+    schema: &str,
+) -> Result<(), StoreError> {
+    sql_query(
+        "insert into indexer_schema_versions(namespace, version, schema) values ($1, $2, $3)",
+    )
+    .bind::<Text, _>(namespace)
+    .bind::<Integer, _>(version)
+    .bind::<Text, _>(schema)
+    .execute(conn)?;
+    Ok(())
+}
+
+/// Forget the schema versions of `namespace`, e.g., when it is dropped
+pub fn remove_schema_versions(conn: &PgConnection, namespace: &str) -> Result<(), StoreError> {
+    sql_query("delete from indexer_schema_versions where namespace = $1")
+        .bind::<Text, _>(namespace)
+        .execute(conn)?;
+    Ok(())
+}
+
+fn has_tables(conn: &PgConnection, namespace: &str) -> Result<bool, StoreError> {
+    #[derive(QueryableByName)]
+    struct Count {
+        #[sql_type = "BigInt"]
+        count: i64,
+    }
+    let tables = sql_query(
+        "select count(*) as count from information_schema.tables where table_schema = $1",
+    )
+    .bind::<Text, _>(namespace)
+    .get_result::<Count>(conn)?;
+    Ok(tables.count > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::primary::Site;
+    use massbit::prelude::DeploymentHash;
+    use std::sync::Arc;
+    use SchemaChange::*;
+
+    const SCHEMA: &str = "
+        enum Color { Red, Green }
+
+        type Token @entity {
+            id: ID!
+            name: String!
+            amount: BigInt!
+            color: Color
+        }
+
+        type Holder @entity {
+            id: ID!
+        }";
+
+    fn layout(schema: &str) -> Layout {
+        let deployment = DeploymentHash::new("migration").unwrap();
+        let site = Arc::new(Site::new(&deployment, "sgd1", "solana"));
+        let schema = Schema::parse(schema, deployment).unwrap();
+        Layout::new(site.clone(), &schema, Catalog::make_empty(site)).unwrap()
+    }
+
+    fn token() -> EntityType {
+        EntityType::new(String::from("Token"))
+    }
+
+    #[test]
+    fn same_schema() {
+        let from = layout(SCHEMA);
+        let to = layout(SCHEMA);
+        let migration = SchemaMigration::new(&from, &to);
+        assert!(migration.is_empty());
+        assert_eq!(migration.as_ddl().unwrap(), "");
+    }
+
+    #[test]
+    fn add_column() {
+        let from = layout(SCHEMA);
+        let to = layout(&SCHEMA.replace("color: Color", "color: Color\n symbol: String"));
+        let migration = SchemaMigration::new(&from, &to);
+        assert_eq!(
+            migration.changes,
+            vec![AddColumn(token(), SqlName::from("symbol"))]
+        );
+        assert!(migration.destructive_changes().is_empty());
+        let ddl = migration.as_ddl().unwrap();
+        assert!(ddl.contains("alter table \"sgd1\".\"token\" add column \"symbol\" text;"));
+    }
+
+    #[test]
+    fn drop_column() {
+        let from = layout(SCHEMA);
+        let to = layout(&SCHEMA.replace("name: String!", ""));
+        let migration = SchemaMigration::new(&from, &to);
+        assert_eq!(
+            migration.changes,
+            vec![DropColumn(token(), SqlName::from("name"))]
+        );
+        assert_eq!(migration.destructive_changes().len(), 1);
+        assert_eq!(
+            migration.as_ddl().unwrap(),
+            "alter table \"sgd1\".\"token\" drop column \"name\";\n"
+        );
+    }
+
+    #[test]
+    fn change_column_type() {
+        let from = layout(SCHEMA);
+        let to = layout(&SCHEMA.replace("amount: BigInt!", "amount: Int!"));
+        let migration = SchemaMigration::new(&from, &to);
+        assert_eq!(
+            migration.changes,
+            vec![ChangeColumnType(token(), SqlName::from("amount"))]
+        );
+        assert_eq!(migration.destructive_changes().len(), 1);
+        let ddl = migration.as_ddl().unwrap();
+        let drop = ddl
+            .find("alter table \"sgd1\".\"token\" drop column \"amount\";")
+            .unwrap();
+        let add = ddl
+            .find("alter table \"sgd1\".\"token\" add column \"amount\" integer;")
+            .unwrap();
+        assert!(drop < add);
+    }
+
+    #[test]
+    fn change_nullability() {
+        let from = layout(SCHEMA);
+        let to = layout(&SCHEMA.replace("name: String!", "name: String"));
+        let migration = SchemaMigration::new(&from, &to);
+        assert_eq!(
+            migration.changes,
+            vec![DropNotNull(token(), SqlName::from("name"))]
+        );
+        assert!(migration.destructive_changes().is_empty());
+        let migration = SchemaMigration::new(&to, &from);
+        assert_eq!(
+            migration.changes,
+            vec![SetNotNull(token(), SqlName::from("name"))]
+        );
+        assert_eq!(
+            migration.as_ddl().unwrap(),
+            "alter table \"sgd1\".\"token\" alter column \"name\" set not null;\n"
+        );
+    }
+
+    #[test]
+    fn change_enum() {
+        let from = layout(SCHEMA);
+        let to = layout(&SCHEMA.replace("Red, Green", "Red, Blue"));
+        let migration = SchemaMigration::new(&from, &to);
+        assert_eq!(
+            migration.changes,
+            vec![
+                AddEnumValue(String::from("Color"), String::from("Blue")),
+                RemoveEnumValue(String::from("Color"), String::from("Green")),
+            ]
+        );
+        assert_eq!(migration.destructive_changes().len(), 1);
+        // Postgres keeps the removed value
+        assert_eq!(
+            migration.as_ddl().unwrap(),
+            "alter type sgd1.\"color\" add value if not exists 'Blue';\n"
+        );
+    }
+
+    #[test]
+    fn create_and_drop_enum() {
+        let from = layout(
+            &SCHEMA
+                .replace("enum Color { Red, Green }", "")
+                .replace("color: Color", ""),
+        );
+        let to = layout(SCHEMA);
+        let migration = SchemaMigration::new(&from, &to);
+        assert_eq!(
+            migration.changes,
+            vec![
+                CreateEnum(String::from("Color")),
+                AddColumn(token(), SqlName::from("color")),
+            ]
+        );
+        let ddl = migration.as_ddl().unwrap();
+        let create = ddl.find("create type sgd1.\"color\"").unwrap();
+        let add = ddl.find("add column \"color\"").unwrap();
+        assert!(create < add);
+
+        let migration = SchemaMigration::new(&to, &from);
+        assert_eq!(
+            migration.changes,
+            vec![
+                DropColumn(token(), SqlName::from("color")),
+                DropEnum(String::from("Color")),
+            ]
+        );
+        let ddl = migration.as_ddl().unwrap();
+        let drop_column = ddl.find("drop column \"color\"").unwrap();
+        let drop_type = ddl.find("drop type sgd1.\"color\";").unwrap();
+        assert!(drop_column < drop_type);
+    }
+
+    #[test]
+    fn create_and_drop_table() {
+        let from = layout(SCHEMA);
+        let to = layout(&SCHEMA.replace("Holder", "Owner"));
+        let migration = SchemaMigration::new(&from, &to);
+        assert_eq!(
+            migration.changes,
+            vec![
+                CreateTable(EntityType::new(String::from("Owner"))),
+                DropTable(EntityType::new(String::from("Holder"))),
+            ]
+        );
+        let ddl = migration.as_ddl().unwrap();
+        assert!(ddl.contains("create table sgd1.\"owner\""));
+        assert!(ddl.contains("drop table \"sgd1\".\"holder\";"));
+    }
+}
@@ -18,7 +18,7 @@ use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use massbit::components::store::{EntityType, BLOCK_NUMBER_MAX};
+use massbit::components::store::{EntityQuery, EntityType, BLOCK_NUMBER_MAX};
 use massbit::data::graphql::ext::{DirectiveFinder, DocumentExt, ObjectTypeExt};
 use massbit::data::schema::{Schema, SCHEMA_TYPE_NAME};
 use massbit::prelude::{anyhow, BlockNumber, DeploymentHash, Entity, EntityKey, StoreError, *};
@@ -27,10 +27,15 @@ use crate::block_range::BLOCK_RANGE_COLUMN;
 pub use crate::catalog::Catalog;
 use crate::deployment;
 use crate::primary::{Namespace, Site};
-use crate::relational_queries::{EntityData, FindManyQuery, FindQuery, InsertQuery};
+use crate::relational_queries::{
+    ClampRangeQuery, EntityData, FilterQuery, FindManyQuery, FindQuery, FromEntityData,
+    InsertQuery, RevertClampQuery, RevertRemoveQuery,
+};
 
 const POSTGRES_MAX_PARAMETERS: usize = u16::MAX as usize; // 65535
 
+const DELETE_OPERATION_CHUNK_SIZE: usize = 1_000;
+
 /// The size of string prefixes that we index. This is chosen so that we
 /// will index strings that people will do string comparisons like
 /// `=` or `!=` on; if text longer than this is stored in a String attribute
@@ -461,6 +466,27 @@ impl Layout {
         Ok(entities_for_type)
     }
 
+    /// Return the entities that match `query`, in the order and the range
+    /// that it asks for
+    pub fn query<T: FromEntityData>(
+        &self,
+        conn: &PgConnection,
+        query: &EntityQuery,
+    ) -> Result<Vec<T>, StoreError> {
+        let table = self.table_for_entity(&query.entity_type)?;
+        FilterQuery::new(
+            table.as_ref(),
+            query.filter.as_ref(),
+            &query.order,
+            &query.range,
+            query.block,
+        )?
+        .load::<EntityData>(conn)?
+        .into_iter()
+        .map(|entity_data| entity_data.deserialize_with_layout(self))
+        .collect()
+    }
+
     pub fn insert(
         &self,
         conn: &PgConnection,
@@ -482,6 +508,85 @@ impl Layout {
         }
         Ok(count)
     }
+
+    /// Write new versions of `entities` that are valid from `block` on. The
+    /// current version of each entity has its block range clamped so that
+    /// it ends right before `block`
+    pub fn update(
+        &self,
+        conn: &PgConnection,
+        entity_type: &EntityType,
+        entities: &mut [(EntityKey, Entity)],
+        block: BlockNumber,
+    ) -> Result<usize, StoreError> {
+        let table = self.table_for_entity(entity_type)?;
+        let entity_keys: Vec<&str> = entities
+            .iter()
+            .map(|(key, _)| key.entity_id.as_str())
+            .collect();
+
+        for chunk in entity_keys.chunks(DELETE_OPERATION_CHUNK_SIZE) {
+            ClampRangeQuery::new(table, entity_type, chunk, block).execute(conn)?;
+        }
+
+        let mut count = 0;
+        // See `insert` for how the chunk size is determined
+        let chunk_size = POSTGRES_MAX_PARAMETERS / (table.columns.len() + 1);
+        for chunk in entities.chunks_mut(chunk_size) {
+            count += InsertQuery::new(table, chunk, block)?
+                .get_results(conn)
+                .map(|ids| ids.len())?
+        }
+        Ok(count)
+    }
+
+    /// Mark the entities with the given ids as deleted as of `block` by
+    /// clamping the block range of their current version. Older versions
+    /// stay around so that queries at earlier blocks still find them
+    pub fn delete(
+        &self,
+        conn: &PgConnection,
+        entity_type: &EntityType,
+        entity_ids: &[String],
+        block: BlockNumber,
+    ) -> Result<usize, StoreError> {
+        let table = self.table_for_entity(entity_type)?;
+        let mut count = 0;
+        for chunk in entity_ids.chunks(DELETE_OPERATION_CHUNK_SIZE) {
+            count += ClampRangeQuery::new(table, entity_type, chunk, block).execute(conn)?
+        }
+        Ok(count)
+    }
+
+    /// Revert the state of all tables to what it was at `block`: versions
+    /// written after `block` are deleted, and versions that were current at
+    /// `block` become current again. Returns the net change in the number
+    /// of current entities
+    pub fn revert_block(&self, conn: &PgConnection, block: BlockNumber) -> Result<i32, StoreError> {
+        let mut count: i32 = 0;
+
+        for table in self.tables.values() {
+            // Remove versions that were created after `block`; their ids
+            // tell us which entities were touched by the reverted blocks
+            let removed = RevertRemoveQuery::new(table, block)
+                .get_results(conn)?
+                .into_iter()
+                .map(|data| data.id)
+                .collect::<HashSet<_>>();
+            // Make the versions that were current at `block` current again
+            let unclamped = RevertClampQuery::new(table, block)
+                .get_results(conn)?
+                .into_iter()
+                .map(|data| data.id)
+                .collect::<HashSet<_>>();
+
+            // Entities that were only created after `block` are gone now,
+            // and entities that were only deleted after `block` are back
+            count -= removed.difference(&unclamped).count() as i32;
+            count += unclamped.difference(&removed).count() as i32;
+        }
+        Ok(count)
+    }
 }
 
 impl Layout {
@@ -920,6 +1025,14 @@ impl Table {
         self.columns.iter().find(|column| &column.name == name)
     }
 
+    /// Find the column for the GraphQL field `field`
+    pub fn column_for_field(&self, field: &str) -> Result<&Column, StoreError> {
+        self.columns
+            .iter()
+            .find(|column| column.field == field)
+            .ok_or_else(|| StoreError::UnknownField(field.to_string()))
+    }
+
     pub fn primary_key(&self) -> &Column {
         self.columns
             .iter()
@@ -932,7 +1045,7 @@ impl Table {
     ///
     /// See the unit tests at the end of this file for the actual DDL that
     /// gets generated
-    fn as_ddl(&self, out: &mut String, layout: &Layout) -> fmt::Result {
+    pub(crate) fn as_ddl(&self, out: &mut String, layout: &Layout) -> fmt::Result {
         writeln!(
             out,
             "create table {}.{} (",
@@ -994,50 +1107,66 @@ impl Table {
             block_max = BLOCK_NUMBER_MAX
         )?;
 
-        // Create indexes. Skip columns whose type is an array of enum,
-        // since there is no good way to index them with Postgres 9.6.
-        // Once we move to Postgres 11, we can enable that
-        // (tracked in graph-node issue #1330)
-        for (i, column) in self
+        // Create indexes
+        for column in self.columns.iter() {
+            if let Some((_, ddl)) = self.attribute_index(column, &layout.catalog.site.namespace) {
+                write!(out, "{}", ddl)?;
+            }
+        }
+        writeln!(out)
+    }
+
+    /// The name of the index on `column` and the statement that creates it.
+    /// Columns whose type is an array of enum are not indexed, since there
+    /// is no good way to index them with Postgres 9.6. Once we move to
+    /// Postgres 11, we can enable that (tracked in graph-node issue #1330)
+    pub(crate) fn attribute_index(
+        &self,
+        column: &Column,
+        namespace: &Namespace,
+    ) -> Option<(String, String)> {
+        let column_index = self
             .columns
             .iter()
             .filter(|col| !(col.is_list() && col.is_enum()))
-            .enumerate()
-        {
-            let (method, index_expr) = if column.is_reference() && !column.is_list() {
-                // For foreign keys, index the key together with the block range
-                // since we almost always also have a block_range clause in
-                // queries that look for specific foreign keys
-                let index_expr = format!("{}, {}", column.name.quoted(), BLOCK_RANGE_COLUMN);
-                ("gist", index_expr)
+            .position(|col| col.name == column.name)?;
+        let (method, index_expr) = if column.is_reference() && !column.is_list() {
+            // For foreign keys, index the key together with the block range
+            // since we almost always also have a block_range clause in
+            // queries that look for specific foreign keys
+            let index_expr = format!("{}, {}", column.name.quoted(), BLOCK_RANGE_COLUMN);
+            ("gist", index_expr)
+        } else {
+            // Attributes that are plain strings are indexed with a BTree; but
+            // they can be too large for Postgres' limit on values that can go
+            // into a BTree. For those attributes, only index the first
+            // STRING_PREFIX_SIZE characters
+            let index_expr = if column.is_text() {
+                format!("left({}, {})", column.name.quoted(), STRING_PREFIX_SIZE)
             } else {
-                // Attributes that are plain strings are indexed with a BTree; but
-                // they can be too large for Postgres' limit on values that can go
-                // into a BTree. For those attributes, only index the first
-                // STRING_PREFIX_SIZE characters
-                let index_expr = if column.is_text() {
-                    format!("left({}, {})", column.name.quoted(), STRING_PREFIX_SIZE)
-                } else {
-                    column.name.quoted()
-                };
+                column.name.quoted()
+            };
 
-                let method = if column.is_list() { "gin" } else { "btree" };
+            let method = if column.is_list() { "gin" } else { "btree" };
 
-                (method, index_expr)
-            };
-            write!(
-                out,
-                "create index attr_{table_index}_{column_index}_{table_name}_{column_name}\n    on {schema_name}.\"{table_name}\" using {method}({index_expr});\n",
-                table_index = self.position,
-                table_name = self.name,
-                column_index = i,
-                column_name = column.name,
-                schema_name = layout.catalog.site.namespace,
-                method = method,
-                index_expr = index_expr,
-            )?;
-        }
-        writeln!(out)
+            (method, index_expr)
+        };
+        let name = format!(
+            "attr_{table_index}_{column_index}_{table_name}_{column_name}",
+            table_index = self.position,
+            column_index = column_index,
+            table_name = self.name,
+            column_name = column.name,
+        );
+        let ddl = format!(
+            "create index {name}\n    on {schema_name}.\"{table_name}\" using {method}({index_expr});\n",
+            name = name,
+            table_name = self.name,
+            schema_name = namespace,
+            method = method,
+            index_expr = index_expr,
+        );
+        Some((name, ddl))
     }
 }
 
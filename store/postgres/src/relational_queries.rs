@@ -131,6 +131,348 @@ impl<'a> LoadQuery<PgConnection, EntityData> for FindManyQuery<'a> {
 
 impl<'a, Conn> RunQueryDsl<Conn> for FindManyQuery<'a> {}
 
+/// The condition for an `EntityFilter` on the columns of a table, where the
+/// table is aliased as `c`
+#[derive(Debug, Clone)]
+pub struct QueryFilter<'a> {
+    filter: &'a EntityFilter,
+    table: &'a Table,
+}
+
+impl<'a> QueryFilter<'a> {
+    pub fn new(filter: &'a EntityFilter, table: &'a Table) -> Result<Self, StoreError> {
+        Self::valid_attributes(filter, table)?;
+        Ok(QueryFilter { filter, table })
+    }
+
+    /// Check that all attributes in `filter` are columns of `table` and
+    /// that the string operations are only used on columns where they
+    /// make sense
+    fn valid_attributes(filter: &'a EntityFilter, table: &'a Table) -> Result<(), StoreError> {
+        use EntityFilter::*;
+
+        fn unsupported(op: &str, attribute: &str) -> StoreError {
+            StoreError::QueryExecutionError(format!(
+                "filter `{}` is not supported for attribute `{}`",
+                op, attribute
+            ))
+        }
+
+        fn is_text(column: &Column, value: &Value) -> bool {
+            column.column_type == ColumnType::String
+                && !column.is_list()
+                && matches!(value, Value::String(_))
+        }
+
+        match filter {
+            And(filters) | Or(filters) => {
+                for filter in filters {
+                    Self::valid_attributes(filter, table)?;
+                }
+            }
+            Contains(attr, value) | NotContains(attr, value) => {
+                let column = table.column_for_field(attr)?;
+                if !column.is_list() && !is_text(column, value) {
+                    return Err(unsupported("contains", attr));
+                }
+            }
+            StartsWith(attr, value)
+            | NotStartsWith(attr, value)
+            | EndsWith(attr, value)
+            | NotEndsWith(attr, value) => {
+                let column = table.column_for_field(attr)?;
+                if !is_text(column, value) {
+                    return Err(unsupported("starts_with/ends_with", attr));
+                }
+            }
+            Equal(attr, _)
+            | Not(attr, _)
+            | GreaterThan(attr, _)
+            | LessThan(attr, _)
+            | GreaterOrEqual(attr, _)
+            | LessOrEqual(attr, _)
+            | In(attr, _)
+            | NotIn(attr, _) => {
+                table.column_for_field(attr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn column(&self, attribute: &Attribute) -> &'a Column {
+        self.table
+            .column_for_field(attribute)
+            .expect("the constructor checked that all attributes are valid")
+    }
+
+    fn push_column(column: &Column, out: &mut AstPass<Pg>) -> QueryResult<()> {
+        out.push_sql("c.");
+        out.push_identifier(column.name.as_str())
+    }
+
+    fn binary_op(
+        &self,
+        filters: &[EntityFilter],
+        op: &str,
+        on_empty: &str,
+        mut out: AstPass<Pg>,
+    ) -> QueryResult<()> {
+        if filters.is_empty() {
+            out.push_sql(on_empty);
+            return Ok(());
+        }
+
+        out.push_sql("(");
+        for (i, filter) in filters.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(op);
+            }
+            QueryFilter {
+                filter,
+                table: self.table,
+            }
+            .walk_ast(out.reborrow())?;
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+
+    fn equals(
+        &self,
+        attribute: &Attribute,
+        value: &Value,
+        negated: bool,
+        mut out: AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let column = self.column(attribute);
+        Self::push_column(column, &mut out)?;
+        if value == &Value::Null {
+            out.push_sql(if negated { " is not null" } else { " is null" });
+            Ok(())
+        } else {
+            out.push_sql(if negated { " is distinct from " } else { " = " });
+            QueryValue(value, &column.column_type).walk_ast(out)
+        }
+    }
+
+    fn compare(
+        &self,
+        attribute: &Attribute,
+        value: &Value,
+        op: &str,
+        mut out: AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let column = self.column(attribute);
+        Self::push_column(column, &mut out)?;
+        out.push_sql(op);
+        QueryValue(value, &column.column_type).walk_ast(out)
+    }
+
+    fn in_values(
+        &self,
+        attribute: &Attribute,
+        values: &[Value],
+        negated: bool,
+        mut out: AstPass<Pg>,
+    ) -> QueryResult<()> {
+        if values.is_empty() {
+            out.push_sql(if negated { "true" } else { "false" });
+            return Ok(());
+        }
+
+        let column = self.column(attribute);
+        Self::push_column(column, &mut out)?;
+        out.push_sql(if negated { " not in (" } else { " in (" });
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                out.push_sql(", ");
+            }
+            QueryValue(value, &column.column_type).walk_ast(out.reborrow())?;
+        }
+        out.push_sql(")");
+        Ok(())
+    }
+
+    /// Generate `c.{attribute} like $pattern` for text columns, and an
+    /// array containment check for list columns. `pattern` turns the
+    /// escaped text into the pattern for `like`
+    fn matches(
+        &self,
+        attribute: &Attribute,
+        value: &Value,
+        negated: bool,
+        pattern: fn(&str) -> String,
+        mut out: AstPass<Pg>,
+    ) -> QueryResult<()> {
+        let column = self.column(attribute);
+        if negated {
+            out.push_sql("not ");
+        }
+        Self::push_column(column, &mut out)?;
+        if column.is_list() {
+            out.push_sql(" @> ");
+            match value {
+                Value::List(_) => QueryValue(value, &column.column_type).walk_ast(out),
+                value => {
+                    let values = Value::List(vec![value.clone()]);
+                    QueryValue(&values, &column.column_type).walk_ast(out)
+                }
+            }
+        } else {
+            let text = match value {
+                Value::String(text) => text,
+                _ => unreachable!("the constructor checked that only strings are matched"),
+            };
+            // Escape the characters that have a special meaning for `like`
+            let escaped = text
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            out.push_sql(" like ");
+            out.push_bind_param::<Text, _>(&pattern(&escaped))
+        }
+    }
+}
+
+impl<'a> QueryFragment<Pg> for QueryFilter<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        use EntityFilter::*;
+        match self.filter {
+            And(filters) => self.binary_op(filters, " and ", "true", out),
+            Or(filters) => self.binary_op(filters, " or ", "false", out),
+            Equal(attr, value) => self.equals(attr, value, false, out),
+            Not(attr, value) => self.equals(attr, value, true, out),
+            GreaterThan(attr, value) => self.compare(attr, value, " > ", out),
+            LessThan(attr, value) => self.compare(attr, value, " < ", out),
+            GreaterOrEqual(attr, value) => self.compare(attr, value, " >= ", out),
+            LessOrEqual(attr, value) => self.compare(attr, value, " <= ", out),
+            In(attr, values) => self.in_values(attr, values, false, out),
+            NotIn(attr, values) => self.in_values(attr, values, true, out),
+            Contains(attr, value) => {
+                self.matches(attr, value, false, |text| format!("%{}%", text), out)
+            }
+            NotContains(attr, value) => {
+                self.matches(attr, value, true, |text| format!("%{}%", text), out)
+            }
+            StartsWith(attr, value) => {
+                self.matches(attr, value, false, |text| format!("{}%", text), out)
+            }
+            NotStartsWith(attr, value) => {
+                self.matches(attr, value, true, |text| format!("{}%", text), out)
+            }
+            EndsWith(attr, value) => {
+                self.matches(attr, value, false, |text| format!("%{}", text), out)
+            }
+            NotEndsWith(attr, value) => {
+                self.matches(attr, value, true, |text| format!("%{}", text), out)
+            }
+        }
+    }
+}
+
+/// A query for the entities of one table that match a filter at a block,
+/// in the given order and range
+#[derive(Debug, Clone)]
+pub struct FilterQuery<'a> {
+    table: &'a Table,
+    filter: Option<QueryFilter<'a>>,
+    order: Option<(&'a Column, &'static str)>,
+    range: &'a EntityRange,
+    block: BlockNumber,
+}
+
+impl<'a> FilterQuery<'a> {
+    pub fn new(
+        table: &'a Table,
+        filter: Option<&'a EntityFilter>,
+        order: &'a EntityOrder,
+        range: &'a EntityRange,
+        block: BlockNumber,
+    ) -> Result<Self, StoreError> {
+        let filter = filter
+            .map(|filter| QueryFilter::new(filter, table))
+            .transpose()?;
+        let order = match order {
+            EntityOrder::Ascending(attr) => Some((table.column_for_field(attr)?, "asc")),
+            EntityOrder::Descending(attr) => Some((table.column_for_field(attr)?, "desc")),
+            EntityOrder::Default => None,
+        };
+        if let Some((column, _)) = order {
+            if column.is_list() {
+                return Err(StoreError::QueryExecutionError(format!(
+                    "can not order by the list attribute `{}`",
+                    column.field
+                )));
+            }
+        }
+        Ok(FilterQuery {
+            table,
+            filter,
+            order,
+            range,
+            block,
+        })
+    }
+}
+
+impl<'a> QueryFragment<Pg> for FilterQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        // Generate
+        //    select '..' as entity, to_jsonb(c.*) as data
+        //      from schema.table c
+        //     where c.block_range @> $block and {filter}
+        //     order by c.{order} {direction}, c.id
+        //     limit {first} offset {skip}
+        out.push_sql("select ");
+        out.push_bind_param::<Text, _>(&self.table.object.as_str())?;
+        out.push_sql(" as entity, to_jsonb(c.*) as data\n");
+        out.push_sql("  from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql(" c\n where ");
+        BlockRangeContainsClause::new(&self.table, "c.", self.block).walk_ast(out.reborrow())?;
+        if let Some(filter) = &self.filter {
+            out.push_sql(" and ");
+            filter.walk_ast(out.reborrow())?;
+        }
+        out.push_sql("\n order by ");
+        if let Some((column, direction)) = self.order {
+            QueryFilter::push_column(column, &mut out)?;
+            out.push_sql(" ");
+            out.push_sql(direction);
+            out.push_sql(", ");
+        }
+        out.push_sql("c.");
+        out.push_identifier(PRIMARY_KEY_COLUMN)?;
+        if let Some(first) = self.range.first {
+            out.push_sql("\n limit ");
+            out.push_sql(&first.to_string());
+        }
+        if self.range.skip > 0 {
+            out.push_sql(" offset ");
+            out.push_sql(&self.range.skip.to_string());
+        }
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for FilterQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, EntityData> for FilterQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<EntityData>> {
+        conn.query_by_name(&self)
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for FilterQuery<'a> {}
+
 #[derive(Debug)]
 pub struct InsertQuery<'a> {
     table: &'a Table,
@@ -303,6 +645,107 @@ where
 
 impl<'a, S, Conn> RunQueryDsl<Conn> for ClampRangeQuery<'a, S> {}
 
+/// Delete all entity versions whose block range starts after `block`,
+/// i.e., all versions that were written by a block that is being reverted
+#[derive(Debug, Clone, Constructor)]
+pub struct RevertRemoveQuery<'a> {
+    table: &'a Table,
+    block: BlockNumber,
+}
+
+impl<'a> QueryFragment<Pg> for RevertRemoveQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        // Construct a query
+        //   delete from table
+        //    where lower(block_range) > $block
+        //   returning id
+        out.push_sql("delete from ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql("\n where lower(");
+        out.push_identifier(BLOCK_RANGE_COLUMN)?;
+        out.push_sql(") > ");
+        out.push_bind_param::<Integer, _>(&self.block)?;
+        out.push_sql("\nreturning ");
+        out.push_sql(PRIMARY_KEY_COLUMN);
+        out.push_sql("::text");
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for RevertRemoveQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, ReturnedEntityData> for RevertRemoveQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<ReturnedEntityData>> {
+        conn.query_by_name(&self)
+            .map(|data| ReturnedEntityData::bytes_as_str(&self.table, data))
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for RevertRemoveQuery<'a> {}
+
+/// Reset the upper bound of the block range of entity versions that were
+/// clamped by a block after `block` to infinity, making the version that
+/// was current at `block` the current version again
+#[derive(Debug, Clone, Constructor)]
+pub struct RevertClampQuery<'a> {
+    table: &'a Table,
+    block: BlockNumber,
+}
+
+impl<'a> QueryFragment<Pg> for RevertClampQuery<'a> {
+    fn walk_ast(&self, mut out: AstPass<Pg>) -> QueryResult<()> {
+        out.unsafe_to_cache_prepared();
+
+        // Construct a query
+        //   update table
+        //     set block_range = int4range(lower(block_range), null)
+        //   where block_range @> $block
+        //     and not block_range @> INTMAX
+        //   returning id
+        //
+        // Any version that started after $block has already been deleted by
+        // `RevertRemoveQuery`, so the versions selected here are exactly the
+        // ones that were current at $block
+        out.push_sql("update ");
+        out.push_sql(self.table.qualified_name.as_str());
+        out.push_sql("\n   set ");
+        out.push_identifier(BLOCK_RANGE_COLUMN)?;
+        out.push_sql(" = int4range(lower(");
+        out.push_identifier(BLOCK_RANGE_COLUMN)?;
+        out.push_sql("), null)\n where ");
+        out.push_identifier(BLOCK_RANGE_COLUMN)?;
+        out.push_sql(" @> ");
+        out.push_bind_param::<Integer, _>(&self.block)?;
+        out.push_sql(" and not ");
+        out.push_sql(BLOCK_RANGE_CURRENT);
+        out.push_sql("\nreturning ");
+        out.push_sql(PRIMARY_KEY_COLUMN);
+        out.push_sql("::text");
+        Ok(())
+    }
+}
+
+impl<'a> QueryId for RevertClampQuery<'a> {
+    type QueryId = ();
+
+    const HAS_STATIC_QUERY_ID: bool = false;
+}
+
+impl<'a> LoadQuery<PgConnection, ReturnedEntityData> for RevertClampQuery<'a> {
+    fn internal_load(self, conn: &PgConnection) -> QueryResult<Vec<ReturnedEntityData>> {
+        conn.query_by_name(&self)
+            .map(|data| ReturnedEntityData::bytes_as_str(&self.table, data))
+    }
+}
+
+impl<'a, Conn> RunQueryDsl<Conn> for RevertClampQuery<'a> {}
+
 /// Helper struct for returning the id's touched by the RevertRemove and
 /// RevertExtend queries
 #[derive(QueryableByName, PartialEq, Eq, Hash)]
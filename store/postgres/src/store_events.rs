@@ -0,0 +1,165 @@
+//! Publish the entities that changed with each block, and listen for them.
+//!
+//! Writers send a `StoreEvent` with `NOTIFY` on the `store_events` channel in
+//! the transaction that writes the block, so that Postgres delivers it only
+//! once the block is committed. Readers run a `StoreEventListener`, which
+//! `LISTEN`s on a dedicated connection and hands the events to the
+//! subscribers in its process.
+
+use diesel::sql_types::Text;
+use diesel::{sql_query, PgConnection, RunQueryDsl};
+use fallible_iterator::FallibleIterator;
+use massbit::prelude::{
+    anyhow, error, serde_json, tokio::sync::broadcast, warn, Arc, Duration, Logger, StoreError,
+    StoreEvent,
+};
+use postgres::{Client, NoTls};
+use std::thread;
+
+/// The channel on which store events are sent
+pub const STORE_EVENTS_CHANNEL: &str = "store_events";
+
+/// Postgres rejects notifications with a payload of 8000 bytes or more;
+/// events that are larger are sent in several parts
+const MAX_PAYLOAD_SIZE: usize = 7900;
+
+/// How many events a subscriber can fall behind before it misses some
+const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// How long to wait before listening again after the connection was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Send `event` to the listeners of `STORE_EVENTS_CHANNEL`. Events without
+/// changes are not sent.
+pub fn send_store_event(conn: &PgConnection, event: &StoreEvent) -> Result<(), StoreError> {
+    if event.is_empty() {
+        return Ok(());
+    }
+    for payload in payloads(event)? {
+        sql_query("select pg_notify($1, $2)")
+            .bind::<Text, _>(STORE_EVENTS_CHANNEL)
+            .bind::<Text, _>(payload)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Serialize `event`, splitting its changes until every part fits into a
+/// notification.
+fn payloads(event: &StoreEvent) -> Result<Vec<String>, StoreError> {
+    let payload = serde_json::to_string(event)?;
+    if payload.len() < MAX_PAYLOAD_SIZE || event.changes.len() < 2 {
+        return Ok(vec![payload]);
+    }
+    let middle = event
+        .changes
+        .iter()
+        .nth(event.changes.len() / 2)
+        .cloned()
+        .unwrap();
+    let mut head = event.clone();
+    let tail = StoreEvent {
+        indexer: event.indexer.clone(),
+        block: event.block,
+        changes: head.changes.split_off(&middle),
+    };
+    let mut parts = payloads(&head)?;
+    parts.extend(payloads(&tail)?);
+    Ok(parts)
+}
+
+/// Receives the store events sent by all writers to the database and
+/// broadcasts them to its subscribers.
+pub struct StoreEventListener {
+    sender: broadcast::Sender<Arc<StoreEvent>>,
+}
+
+impl StoreEventListener {
+    /// Start listening on a connection to `postgres_url` in a background
+    /// thread. The listener reconnects when the connection is lost; events
+    /// sent while it is disconnected are missed.
+    pub fn start(logger: Logger, postgres_url: String) -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        let events = sender.clone();
+        thread::Builder::new()
+            .name("store-events".to_string())
+            .spawn(move || loop {
+                if let Err(err) = listen(&logger, &postgres_url, &events) {
+                    error!(logger, "Listening for store events failed";
+                           "error" => err.to_string());
+                }
+                thread::sleep(RECONNECT_DELAY);
+            })
+            .expect("failed to start the store event listener");
+        StoreEventListener { sender }
+    }
+
+    /// Receive the events that arrive from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<StoreEvent>> {
+        self.sender.subscribe()
+    }
+}
+
+fn listen(
+    logger: &Logger,
+    postgres_url: &str,
+    events: &broadcast::Sender<Arc<StoreEvent>>,
+) -> Result<(), anyhow::Error> {
+    let mut client = Client::connect(postgres_url, NoTls)?;
+    client.batch_execute(&format!("listen {}", STORE_EVENTS_CHANNEL))?;
+    let mut notifications = client.notifications();
+    let mut notifications = notifications.blocking_iter();
+    while let Some(notification) = notifications.next()? {
+        match serde_json::from_str::<StoreEvent>(notification.payload()) {
+            // Sending only fails when there are no subscribers
+            Ok(event) => {
+                let _ = events.send(Arc::new(event));
+            }
+            Err(err) => warn!(logger, "Received an invalid store event";
+                              "payload" => notification.payload(),
+                              "error" => err.to_string()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(count: usize) -> StoreEvent {
+        let ids: Vec<String> = (0..count).map(|id| format!("0x{:064x}", id)).collect();
+        StoreEvent::from_changes(
+            "QmIndexer",
+            42,
+            ids.iter().map(|id| ("Token", id.as_str(), false)),
+        )
+    }
+
+    #[test]
+    fn small_event_is_sent_whole() {
+        let event = event(3);
+        let payloads = payloads(&event).unwrap();
+        assert_eq!(payloads.len(), 1);
+        assert_eq!(
+            serde_json::from_str::<StoreEvent>(&payloads[0]).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn large_event_is_split() {
+        let event = event(1000);
+        let payloads = payloads(&event).unwrap();
+        assert!(payloads.len() > 1);
+        let mut changes = std::collections::BTreeSet::new();
+        for payload in payloads.iter() {
+            assert!(payload.len() < MAX_PAYLOAD_SIZE);
+            let part: StoreEvent = serde_json::from_str(payload).unwrap();
+            assert_eq!(part.indexer, event.indexer);
+            assert_eq!(part.block, event.block);
+            changes.extend(part.changes);
+        }
+        assert_eq!(changes, event.changes);
+    }
+}
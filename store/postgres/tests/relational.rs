@@ -0,0 +1,224 @@
+//! Test the block range versioning of the relational layout: overwrites and
+//! removals clamp the current version, and reverts restore the versions
+//! that were current at the revert block.
+//!
+//! The tests need a database, they are skipped unless `DATABASE_URL` is set.
+use diesel::connection::SimpleConnection;
+use diesel::{Connection, PgConnection};
+use lazy_static::lazy_static;
+use massbit::components::store::EntityType;
+use massbit::prelude::{
+    entity, BlockNumber, DeploymentHash, Entity, EntityKey, Schema, StoreError, Value,
+    BLOCK_NUMBER_MAX,
+};
+use massbit_store_postgres::command_support::catalog::Site;
+use massbit_store_postgres::relational::Layout;
+use std::env;
+use std::sync::Arc;
+
+const THINGS_GQL: &str = "
+    type Thing @entity {
+        id: ID!
+        name: String!
+        count: Int!
+    }";
+
+const NAMESPACE: &str = "sgd9999";
+
+lazy_static! {
+    static ref THINGS_INDEXER: DeploymentHash = DeploymentHash::new("things").unwrap();
+    static ref THING: EntityType = EntityType::from("Thing");
+}
+
+/// Runs `test` with a layout for `THINGS_GQL` in a transaction which is
+/// rolled back afterwards
+fn run_test<F>(test: F)
+where
+    F: FnOnce(&PgConnection, &Layout),
+{
+    let url = match env::var("DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => {
+            eprintln!("DATABASE_URL is not set, skipping relational test");
+            return;
+        }
+    };
+    let conn = PgConnection::establish(&url).expect("Failed to connect to Postgres");
+    conn.test_transaction::<_, StoreError, _>(|| {
+        conn.batch_execute(&format!(
+            "drop schema if exists {0} cascade; create schema {0}",
+            NAMESPACE
+        ))?;
+        let schema = Schema::parse(THINGS_GQL, THINGS_INDEXER.clone()).unwrap();
+        let site = Arc::new(Site::new(&THINGS_INDEXER, NAMESPACE, "solana"));
+        let layout = Layout::create_relational_schema(&conn, site, &schema)?;
+        test(&conn, &layout);
+        Ok(())
+    });
+}
+
+fn thing(id: &str, name: &str, count: i32) -> (EntityKey, Entity) {
+    let key = EntityKey {
+        indexer_id: THINGS_INDEXER.clone(),
+        entity_type: THING.clone(),
+        entity_id: id.to_owned(),
+    };
+    let data = entity! {
+        id: id.to_owned(),
+        name: name.to_owned(),
+        count: count,
+    };
+    (key, data)
+}
+
+fn insert(
+    conn: &PgConnection,
+    layout: &Layout,
+    things: Vec<(EntityKey, Entity)>,
+    block: BlockNumber,
+) {
+    let mut things = things;
+    let count = layout.insert(conn, &THING, &mut things, block).unwrap();
+    assert_eq!(things.len(), count);
+}
+
+fn update(
+    conn: &PgConnection,
+    layout: &Layout,
+    things: Vec<(EntityKey, Entity)>,
+    block: BlockNumber,
+) {
+    let mut things = things;
+    let count = layout.update(conn, &THING, &mut things, block).unwrap();
+    assert_eq!(things.len(), count);
+}
+
+/// The name of `id` at `block`, if it exists then
+fn name_at(conn: &PgConnection, layout: &Layout, id: &str, block: BlockNumber) -> Option<String> {
+    layout
+        .find(conn, &THING, id, block)
+        .unwrap()
+        .map(|entity| match entity.get("name") {
+            Some(Value::String(name)) => name.clone(),
+            name => panic!("unexpected name {:?}", name),
+        })
+}
+
+#[test]
+fn update_keeps_history() {
+    run_test(|conn, layout| {
+        insert(conn, layout, vec![thing("one", "first", 1)], 1);
+        update(conn, layout, vec![thing("one", "second", 2)], 5);
+
+        assert_eq!(None, name_at(conn, layout, "one", 0));
+        for block in 1..5 {
+            assert_eq!(
+                Some("first".to_owned()),
+                name_at(conn, layout, "one", block)
+            );
+        }
+        assert_eq!(Some("second".to_owned()), name_at(conn, layout, "one", 5));
+        assert_eq!(
+            Some("second".to_owned()),
+            name_at(conn, layout, "one", BLOCK_NUMBER_MAX)
+        );
+    })
+}
+
+#[test]
+fn update_in_chunks() {
+    run_test(|conn, layout| {
+        // More entities than the clamp of an update handles at once
+        let ids: Vec<String> = (0..2500).map(|id| format!("thing-{}", id)).collect();
+        let things = |name: &str, block: i32| -> Vec<(EntityKey, Entity)> {
+            ids.iter().map(|id| thing(id, name, block)).collect()
+        };
+        insert(conn, layout, things("old", 1), 1);
+        update(conn, layout, things("new", 3), 3);
+
+        for id in ids.iter() {
+            assert_eq!(Some("old".to_owned()), name_at(conn, layout, id, 2));
+            assert_eq!(Some("new".to_owned()), name_at(conn, layout, id, 3));
+        }
+    })
+}
+
+#[test]
+fn delete_clamps_current_version() {
+    run_test(|conn, layout| {
+        insert(
+            conn,
+            layout,
+            vec![thing("one", "first", 1), thing("two", "other", 1)],
+            1,
+        );
+        update(conn, layout, vec![thing("one", "second", 2)], 5);
+
+        let count = layout.delete(conn, &THING, &["one".to_owned()], 8).unwrap();
+        assert_eq!(1, count);
+
+        assert_eq!(Some("first".to_owned()), name_at(conn, layout, "one", 4));
+        assert_eq!(Some("second".to_owned()), name_at(conn, layout, "one", 7));
+        assert_eq!(None, name_at(conn, layout, "one", 8));
+        assert_eq!(None, name_at(conn, layout, "one", BLOCK_NUMBER_MAX));
+        assert_eq!(
+            Some("other".to_owned()),
+            name_at(conn, layout, "two", BLOCK_NUMBER_MAX)
+        );
+
+        // Deleting an entity twice changes nothing
+        let count = layout.delete(conn, &THING, &["one".to_owned()], 9).unwrap();
+        assert_eq!(0, count);
+        assert_eq!(Some("second".to_owned()), name_at(conn, layout, "one", 7));
+    })
+}
+
+#[test]
+fn revert_to_block_inside_range() {
+    run_test(|conn, layout| {
+        insert(conn, layout, vec![thing("one", "first", 1)], 1);
+        update(conn, layout, vec![thing("one", "second", 2)], 5);
+        layout.delete(conn, &THING, &["one".to_owned()], 8).unwrap();
+        insert(conn, layout, vec![thing("two", "late", 1)], 7);
+
+        // Block 6 is inside the range [5, 8) of the second version: the
+        // removal at 8 and the insert at 7 are undone
+        let count = layout.revert_block(conn, 6).unwrap();
+        assert_eq!(0, count);
+        assert_eq!(
+            Some("second".to_owned()),
+            name_at(conn, layout, "one", BLOCK_NUMBER_MAX)
+        );
+        assert_eq!(None, name_at(conn, layout, "two", BLOCK_NUMBER_MAX));
+
+        // Block 3 is inside the range [1, 5) of the first version
+        let count = layout.revert_block(conn, 3).unwrap();
+        assert_eq!(0, count);
+        assert_eq!(
+            Some("first".to_owned()),
+            name_at(conn, layout, "one", BLOCK_NUMBER_MAX)
+        );
+        assert_eq!(Some("first".to_owned()), name_at(conn, layout, "one", 1));
+
+        // Before the insert the entity did not exist
+        let count = layout.revert_block(conn, 0).unwrap();
+        assert_eq!(-1, count);
+        assert_eq!(None, name_at(conn, layout, "one", BLOCK_NUMBER_MAX));
+    })
+}
+
+#[test]
+fn revert_restores_deleted_entity() {
+    run_test(|conn, layout| {
+        insert(conn, layout, vec![thing("one", "first", 1)], 1);
+        layout.delete(conn, &THING, &["one".to_owned()], 4).unwrap();
+        assert_eq!(None, name_at(conn, layout, "one", BLOCK_NUMBER_MAX));
+
+        let count = layout.revert_block(conn, 3).unwrap();
+        assert_eq!(1, count);
+        assert_eq!(
+            Some("first".to_owned()),
+            name_at(conn, layout, "one", BLOCK_NUMBER_MAX)
+        );
+    })
+}